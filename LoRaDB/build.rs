@@ -0,0 +1,71 @@
+//! Embeds build metadata reported by `GET /version`:
+//! - `LORADB_GIT_SHA`: commit being built, or the `LORADB_GIT_SHA` environment
+//!   variable when set (Docker builds have no `.git`), "unknown" otherwise
+//! - `LORADB_BUILD_TIMESTAMP`: Unix time of the build, or `SOURCE_DATE_EPOCH`
+//!   for reproducible builds
+//! - `LORADB_FEATURES`: comma-separated Cargo features compiled in
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=LORADB_GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git_sha = env::var("LORADB_GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.trim().is_empty())
+        .or_else(git_head)
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=LORADB_GIT_SHA={}", git_sha.trim());
+
+    let build_timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or(0)
+        });
+    println!("cargo:rustc-env=LORADB_BUILD_TIMESTAMP={}", build_timestamp);
+
+    // Cargo passes each enabled feature as CARGO_FEATURE_<NAME>, uppercased with '-' as '_'
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|name| name.to_lowercase().replace('_', "-"))
+        })
+        .filter(|name| name != "default")
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=LORADB_FEATURES={}", features.join(","));
+}
+
+/// Short SHA of HEAD, None outside a git checkout or without git installed
+fn git_head() -> Option<String> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    let sha = git(&["rev-parse", "--short=12", "HEAD"])?;
+
+    // Rebuild when HEAD moves or the branch it points to gets a new commit
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        let git_dir = Path::new(&git_dir);
+        println!("cargo:rerun-if-changed={}", git_dir.join("HEAD").display());
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}", git_dir.join(head_ref).display());
+        }
+    }
+
+    Some(sha)
+}
@@ -42,6 +42,7 @@ echo ""
 
 # Build the Docker image
 echo "Building LoRaDB Docker image..."
+export LORADB_GIT_SHA="$(git rev-parse --short=12 HEAD 2>/dev/null || echo unknown)"
 docker compose build --no-cache
 
 echo -e "${GREEN}✓${NC} Build complete"
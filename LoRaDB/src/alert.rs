@@ -0,0 +1,272 @@
+use crate::model::device::DeviceRegistry;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use hyper::header::{CONTENT_TYPE, HOST, USER_AGENT};
+use hyper::{Body, Request, Uri};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tracing::{info, warn};
+
+/// Longest a single webhook delivery may take, connecting included
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// POSTs alerts as JSON to a configured URL
+pub struct AlertWebhook {
+    uri: Uri,
+    /// Present for https:// URLs, verifying against the system's root certificates
+    tls: Option<TlsConnector>,
+}
+
+impl AlertWebhook {
+    pub fn new(url: &str) -> Result<Self> {
+        let uri: Uri = url
+            .parse()
+            .with_context(|| format!("Invalid alert webhook URL '{}'", url))?;
+        if uri.host().is_none() {
+            return Err(anyhow!("Alert webhook URL '{}' has no host", url));
+        }
+
+        let tls = match uri.scheme_str() {
+            Some("http") => None,
+            Some("https") => {
+                let mut roots = RootCertStore::empty();
+                let (added, _) = roots.add_parsable_certificates(
+                    rustls_native_certs::load_native_certs()
+                        .context("Failed to load system root certificates")?,
+                );
+                if added == 0 {
+                    return Err(anyhow!("No system root certificates found for the alert webhook"));
+                }
+                let config = ClientConfig::builder()
+                    .with_root_certificates(roots)
+                    .with_no_client_auth();
+                Some(TlsConnector::from(Arc::new(config)))
+            }
+            _ => return Err(anyhow!("Alert webhook URL '{}' must use http:// or https://", url)),
+        };
+
+        Ok(Self { uri, tls })
+    }
+
+    /// POST `alert` as JSON, failing on a non-2xx response
+    pub async fn send<T: Serialize>(&self, alert: &T) -> Result<()> {
+        let body = serde_json::to_vec(alert)?;
+        tokio::time::timeout(WEBHOOK_TIMEOUT, self.post(body))
+            .await
+            .map_err(|_| anyhow!("Alert webhook timed out after {:?}", WEBHOOK_TIMEOUT))?
+    }
+
+    async fn post(&self, body: Vec<u8>) -> Result<()> {
+        // Checked in new()
+        let host = self.uri.host().unwrap_or_default();
+        let port = self
+            .uri
+            .port_u16()
+            .unwrap_or(if self.tls.is_some() { 443 } else { 80 });
+        let tcp = TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("Failed to connect to alert webhook {}:{}", host, port))?;
+
+        let status = match &self.tls {
+            Some(tls) => {
+                let server_name = ServerName::try_from(host.to_string())?;
+                let stream = tls.connect(server_name, tcp).await?;
+                self.request(stream, body).await?
+            }
+            None => self.request(tcp, body).await?,
+        };
+
+        if !status.is_success() {
+            return Err(anyhow!("Alert webhook responded with {}", status));
+        }
+        Ok(())
+    }
+
+    /// Send one HTTP/1.1 POST over an established connection
+    async fn request<S>(&self, io: S, body: Vec<u8>) -> Result<hyper::StatusCode>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sender, connection) = hyper::client::conn::handshake(io).await?;
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+
+        let path = self.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let authority = self.uri.authority().map(|a| a.as_str()).unwrap_or_default();
+        let request = Request::post(path)
+            .header(HOST, authority)
+            .header(CONTENT_TYPE, "application/json")
+            .header(USER_AGENT, format!("loradb/{}", crate::VERSION))
+            .body(Body::from(body))?;
+
+        Ok(sender.send_request(request).await?.status())
+    }
+}
+
+/// Webhook body sent when a device goes silent
+#[derive(Debug, Serialize)]
+pub struct OfflineAlert {
+    /// Always "device_offline"
+    pub event: &'static str,
+    pub dev_eui: String,
+    pub device_name: Option<String>,
+    pub application_id: String,
+    pub last_seen: DateTime<Utc>,
+    pub silent_secs: i64,
+    pub threshold_secs: i64,
+}
+
+/// Periodically alerts on devices that haven't been seen within a threshold,
+/// once per silence: a device alerts again only after it has been heard from
+pub struct OfflineWatchdog {
+    registry: Arc<DeviceRegistry>,
+    threshold: chrono::Duration,
+    webhook: Option<AlertWebhook>,
+    /// Normalized DevEUIs already alerted for their current silence
+    alerted: HashSet<String>,
+}
+
+impl OfflineWatchdog {
+    pub fn new(registry: Arc<DeviceRegistry>, threshold: chrono::Duration) -> Self {
+        Self {
+            registry,
+            threshold,
+            webhook: None,
+            alerted: HashSet::new(),
+        }
+    }
+
+    /// Deliver alerts to `webhook` as well as logging them
+    pub fn with_webhook(mut self, webhook: AlertWebhook) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Alert on devices that crossed the threshold since the last check,
+    /// returning how many were alerted
+    pub async fn check(&mut self) -> usize {
+        let now = Utc::now();
+        let offline = self.registry.list_offline(now - self.threshold);
+
+        // Devices heard from again alert again the next time they go silent
+        let offline_keys: HashSet<String> =
+            offline.iter().map(|device| device.dev_eui.normalized()).collect();
+        self.alerted.retain(|key| offline_keys.contains(key));
+
+        let mut alerted = 0;
+        for device in offline {
+            let key = device.dev_eui.normalized();
+            if self.alerted.contains(&key) {
+                continue;
+            }
+
+            let last_seen = device.last_seen.unwrap_or(device.first_seen);
+            let alert = OfflineAlert {
+                event: "device_offline",
+                dev_eui: device.dev_eui.as_str().to_string(),
+                device_name: device.device_name,
+                application_id: device.application_id,
+                last_seen,
+                silent_secs: (now - last_seen).num_seconds(),
+                threshold_secs: self.threshold.num_seconds(),
+            };
+            warn!(
+                dev_eui = %alert.dev_eui,
+                application_id = %alert.application_id,
+                last_seen = %alert.last_seen.to_rfc3339(),
+                "Device offline"
+            );
+
+            if let Some(webhook) = &self.webhook {
+                if let Err(e) = webhook.send(&alert).await {
+                    // Left unalerted, so it's retried with the rest on the next check
+                    warn!("Failed to deliver offline alert: {}", e);
+                    break;
+                }
+            }
+
+            self.alerted.insert(key);
+            alerted += 1;
+        }
+
+        alerted
+    }
+
+    pub fn start(mut self, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        info!(
+            "Starting offline device watchdog (threshold: {}s, interval: {:?}, webhook: {})",
+            self.threshold.num_seconds(),
+            interval,
+            if self.webhook.is_some() { "yes" } else { "no" }
+        );
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+                self.check().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::lorawan::DevEui;
+    use axum::{routing::post, Json, Router};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_watchdog_alerts_once_per_silence() {
+        let registry = Arc::new(DeviceRegistry::new());
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        registry.register_or_update(dev_eui.clone(), None, "test-app".to_string());
+
+        // Receives the webhook's POSTs
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/alerts",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                tx.send(body).unwrap();
+            }),
+        );
+        let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let url = format!("http://{}/alerts", server.local_addr());
+        tokio::spawn(server);
+
+        let mut watchdog = OfflineWatchdog::new(registry.clone(), chrono::Duration::milliseconds(200))
+            .with_webhook(AlertWebhook::new(&url).unwrap());
+
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        assert_eq!(watchdog.check().await, 1);
+        assert_eq!(watchdog.check().await, 0);
+
+        let alert = rx.recv().await.unwrap();
+        assert_eq!(alert["event"], "device_offline");
+        assert_eq!(alert["dev_eui"], "0123456789ABCDEF");
+        assert_eq!(alert["application_id"], "test-app");
+
+        // Heard from again, then silent again
+        registry.register_or_update(dev_eui, None, "test-app".to_string());
+        assert_eq!(watchdog.check().await, 0);
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        assert_eq!(watchdog.check().await, 1);
+    }
+
+    #[test]
+    fn test_webhook_rejects_other_schemes() {
+        assert!(AlertWebhook::new("ftp://example.com/alerts").is_err());
+        assert!(AlertWebhook::new("/alerts").is_err());
+        assert!(AlertWebhook::new("http://example.com/alerts").is_ok());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::error::LoraDbError;
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Serialization used for a response body
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl FromStr for ResponseFormat {
+    type Err = LoraDbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(ResponseFormat::Json),
+            "yaml" | "yml" => Ok(ResponseFormat::Yaml),
+            other => Err(LoraDbError::QueryParseError(format!(
+                "Unsupported format '{}' (expected 'json' or 'yaml')",
+                other
+            ))),
+        }
+    }
+}
+
+/// `?format=` query parameter for endpoints that can answer in YAML
+#[derive(Debug, Default, Deserialize)]
+pub struct FormatParams {
+    pub format: Option<String>,
+}
+
+impl FormatParams {
+    /// Requested format, JSON when the parameter is absent
+    pub fn response_format(&self) -> Result<ResponseFormat, LoraDbError> {
+        self.format
+            .as_deref()
+            .map_or(Ok(ResponseFormat::Json), str::parse)
+    }
+}
+
+/// Response body serialized as JSON or YAML
+#[derive(Debug)]
+pub struct Formatted<T> {
+    pub format: ResponseFormat,
+    pub body: T,
+}
+
+impl<T: Serialize> IntoResponse for Formatted<T> {
+    fn into_response(self) -> Response {
+        match self.format {
+            ResponseFormat::Json => Json(self.body).into_response(),
+            ResponseFormat::Yaml => match serde_json::to_value(&self.body) {
+                Ok(value) => (
+                    [(header::CONTENT_TYPE, "application/yaml")],
+                    to_yaml(&value),
+                )
+                    .into_response(),
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to serialize YAML response");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            },
+        }
+    }
+}
+
+/// Render a JSON value as a block-style YAML document
+pub fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => write_block(value, 0, &mut out),
+        Value::Array(items) if !items.is_empty() => write_block(value, 0, &mut out),
+        _ => {
+            out.push_str(&scalar(value));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Write a non-empty mapping or sequence, one entry per line at `indent`
+fn write_block(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                if is_block(child) {
+                    let _ = writeln!(out, "{}{}:", pad, string_scalar(key));
+                    write_block(child, indent + 2, out);
+                } else {
+                    let _ = writeln!(out, "{}{}: {}", pad, string_scalar(key), scalar(child));
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                if is_block(item) {
+                    // Render the item one level deeper, then hang its first line off the dash
+                    let mut nested = String::new();
+                    write_block(item, indent + 2, &mut nested);
+                    let _ = write!(out, "{}- {}", pad, &nested[indent + 2..]);
+                } else {
+                    let _ = writeln!(out, "{}- {}", pad, scalar(item));
+                }
+            }
+        }
+        _ => unreachable!("write_block is only called with non-empty containers"),
+    }
+}
+
+/// Whether a value is written as an indented block rather than inline
+fn is_block(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+/// Inline form of a scalar or empty container
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => string_scalar(s),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+    }
+}
+
+/// Plain string when YAML would read it back unchanged, double-quoted otherwise
+fn string_scalar(s: &str) -> String {
+    const RESERVED: &[&str] = &["null", "~", "true", "false", "yes", "no", "on", "off", "y", "n"];
+
+    let needs_quotes = s.is_empty()
+        || RESERVED.contains(&s.to_lowercase().as_str())
+        || s.starts_with(|c: char| {
+            c.is_ascii_digit() || c.is_whitespace() || "-?:,[]{}#&*!|>'\"%@`.+".contains(c)
+        })
+        || s.ends_with(|c: char| c.is_whitespace() || c == ':')
+        || s.contains(": ")
+        || s.contains(" #")
+        || s.chars().any(char::is_control);
+
+    if needs_quotes {
+        // JSON string literals are valid YAML double-quoted scalars
+        Value::String(s.to_string()).to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_response_format_from_str() {
+        assert_eq!("yaml".parse::<ResponseFormat>().unwrap(), ResponseFormat::Yaml);
+        assert_eq!("YML".parse::<ResponseFormat>().unwrap(), ResponseFormat::Yaml);
+        assert_eq!("json".parse::<ResponseFormat>().unwrap(), ResponseFormat::Json);
+        assert!("xml".parse::<ResponseFormat>().is_err());
+        assert_eq!(FormatParams::default().response_format().unwrap(), ResponseFormat::Json);
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        let value = json!({
+            "total_devices": 2,
+            "devices": [
+                {"dev_eui": "0123456789abcdef", "device_name": null, "tags": []},
+                {"dev_eui": "a84041c7a1881438", "device_name": "sensor: 2", "tags": ["x"]}
+            ],
+            "empty": {},
+            "flag": "yes"
+        });
+
+        let expected = "\
+devices:
+  - dev_eui: \"0123456789abcdef\"
+    device_name: null
+    tags: []
+  - dev_eui: a84041c7a1881438
+    device_name: \"sensor: 2\"
+    tags:
+      - x
+empty: {}
+flag: \"yes\"
+total_devices: 2
+";
+        assert_eq!(to_yaml(&value), expected);
+        assert_eq!(to_yaml(&json!([])), "[]\n");
+    }
+}
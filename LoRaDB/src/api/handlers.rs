@@ -1,19 +1,37 @@
-use crate::api::middleware::AuthContext;
-use crate::error::LoraDbError;
-use crate::ingest::chirpstack::ChirpStackParser;
-use crate::query::dsl::QueryResult;
+use crate::api::format::{FormatParams, Formatted};
+use crate::api::middleware::{current_request_id, AuthContext};
+use crate::engine::wal::ReplayReport;
+use crate::error::{FieldError, LoraDbError};
+use crate::ingest::chirpstack::{self, ChirpStackDownlink, ChirpStackParser};
+use crate::ingest::mqtt::{BrokerStatus, DownlinkPublisher, MqttStatus};
+use crate::model::gateway::GatewayInfo;
+use crate::query::cache::QueryCacheStats;
+use crate::query::dsl::{QueryEstimate, QueryResult};
 use crate::query::executor::QueryExecutor;
 use crate::query::parser::QueryParser;
 use crate::security::api_token::ApiTokenStore;
-use crate::storage::StorageEngine;
+use crate::security::tenant::TenantMap;
+use crate::storage::ingest_rate::IngestRateSnapshot;
+use crate::storage::ingestion_lag::IngestionLagSnapshot;
+use crate::storage::payload_profile_manager::PayloadProfile;
+use crate::storage::retention_manager::DefaultApplicationPolicy;
+use crate::storage::sampling_manager::SamplingScope;
+use crate::storage::schema_manager::{RequiredField, SchemaMode};
+use crate::storage::transform_manager::{Operand, Transform, TransformPipeline};
+use crate::storage::{
+    ClockSkewStats, DeviceStorageUsage, FlushSummary, OversizedPayloadStats, SSTableStats,
+    SelfTestReport, StorageEngine, UnknownApplicationStats,
+};
 use axum::{
     body::Bytes,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Json, Response},
     Extension,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Instant;
 use std::sync::Arc;
 
 // SECURITY: String length limits to prevent memory exhaustion attacks
@@ -22,17 +40,46 @@ const MAX_TOKEN_NAME_LENGTH: usize = 100;
 const MAX_DEV_EUI_LENGTH: usize = 32;
 const MAX_TOKEN_ID_LENGTH: usize = 64;
 const MAX_APP_ID_LENGTH: usize = 256;
-const MAX_PAYLOAD_SIZE: usize = 1_048_576; // 1MB max for webhook payloads
+const MAX_DEVICE_PROFILE_LENGTH: usize = 256;
+// 1MB max for a single webhook payload, checked after the router-wide
+// LORADB_API_MAX_BODY_BYTES limit, so raising that limit doesn't raise this one
+const MAX_PAYLOAD_SIZE: usize = 1_048_576;
+const MAX_SCHEMA_FIELDS: usize = 100;
+const MAX_FIELD_PATH_LENGTH: usize = 256;
+const MAX_ALLOWED_FIELDS: usize = 100;
+const MAX_TRANSFORMS: usize = 32;
+const MAX_GATEWAY_SITES: usize = 1_000;
+const DEFAULT_LATEST_PAGE_SIZE: usize = 100;
+const MAX_LATEST_PAGE_SIZE: usize = 500;
+const DEFAULT_APPLICATION_PAGE_SIZE: usize = 100;
+const MAX_APPLICATION_PAGE_SIZE: usize = 1_000;
+const DEFAULT_EXPORT_PAGE_SIZE: usize = 1_000;
+const MAX_EXPORT_PAGE_SIZE: usize = 10_000;
+const MAX_CURSOR_LENGTH: usize = 128;
+const MAX_EXISTS_BATCH: usize = 1_000;
+// Largest LoRaWAN application payload (DR with the biggest MACPayload)
+const MAX_DOWNLINK_PAYLOAD_BYTES: usize = 242;
 
 /// Validate string length
-fn validate_string_length(s: &str, max_len: usize, field_name: &str) -> Result<(), LoraDbError> {
+fn validate_string_length(
+    s: &str,
+    max_len: usize,
+    field: &str,
+    label: &str,
+) -> Result<(), LoraDbError> {
     if s.len() > max_len {
-        return Err(LoraDbError::QueryParseError(format!(
-            "{} exceeds maximum length of {} characters (got {})",
-            field_name,
-            max_len,
-            s.len()
-        )));
+        return Err(LoraDbError::ValidationError {
+            message: format!(
+                "{} exceeds maximum length of {} characters (got {})",
+                label,
+                max_len,
+                s.len()
+            ),
+            details: vec![FieldError {
+                field: field.to_string(),
+                issue: format!("must be at most {} characters", max_len),
+            }],
+        });
     }
     Ok(())
 }
@@ -44,14 +91,34 @@ pub struct AppState {
     pub query_executor: Arc<QueryExecutor>,
     pub query_parser: Arc<QueryParser>,
     pub api_token_store: Arc<ApiTokenStore>,
+    /// Queries taking at least this many milliseconds are logged at WARN (0 = never)
+    pub slow_query_ms: u64,
+    /// Whether query responses carry an `X-Query-Time-Ms` header
+    pub query_timing_header: bool,
+    /// Downlinks through the ChirpStack MQTT connection, None without a ChirpStack broker
+    pub downlink_publisher: Option<DownlinkPublisher>,
+    /// Broker connection state, None without MQTT ingestion
+    pub mqtt_status: Option<MqttStatus>,
+    /// Tenant each application belongs to, enforced for tenant-scoped principals
+    pub tenants: Arc<TenantMap>,
 }
 
+/// Response header with a query's total handling time in milliseconds
+pub const QUERY_TIME_HEADER: &str = "x-query-time-ms";
+
 /// Query request body
 #[derive(Debug, Deserialize)]
 pub struct QueryRequest {
     pub query: String,
 }
 
+/// Query string parameters for `GET /query`
+#[derive(Debug, Deserialize)]
+pub struct QueryParams {
+    /// The query, URL-encoded
+    pub q: String,
+}
+
 /// Health check response
 #[derive(Debug, Serialize)]
 pub struct HealthResponse {
@@ -59,6 +126,30 @@ pub struct HealthResponse {
     pub version: String,
 }
 
+/// Readiness response, `status` is "ready" or "degraded"
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub status: String,
+    /// The last flush or compaction ran out of disk space, new frames stay in the memtable
+    pub disk_full: bool,
+    /// The memtable reached its hard limit and writes are refused
+    pub memtable_full: bool,
+    /// MQTT brokers without a connection, frames from them aren't ingested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mqtt_disconnected: Vec<String>,
+}
+
+/// Build metadata response
+#[derive(Debug, Serialize)]
+pub struct VersionResponse {
+    pub version: String,
+    pub git_sha: String,
+    /// RFC 3339 build time
+    pub build_timestamp: String,
+    /// Cargo features compiled in
+    pub features: Vec<String>,
+}
+
 /// Device list response
 #[derive(Debug, Serialize)]
 pub struct DeviceListResponse {
@@ -66,6 +157,66 @@ pub struct DeviceListResponse {
     pub devices: Vec<DeviceInfo>,
 }
 
+/// Pagination for `GET /applications`
+#[derive(Debug, Deserialize)]
+pub struct ApplicationsQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// Applications with at least one registered device, ordered by ID
+#[derive(Debug, Serialize)]
+pub struct ApplicationListResponse {
+    pub total_applications: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub applications: Vec<ApplicationInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApplicationInfo {
+    pub application_id: String,
+    pub device_count: usize,
+    /// Frames registered since startup plus those found in storage when it started
+    pub frame_count: u64,
+    pub last_seen: Option<String>,
+    /// Days frames are kept, from the application's own policy or else the global one
+    /// (null = forever)
+    pub retention_days: Option<u32>,
+    /// Whether `retention_days` comes from an application policy
+    pub retention_policy_set: bool,
+}
+
+/// Gateway map response
+#[derive(Debug, Serialize)]
+pub struct GatewayMapResponse {
+    pub total_gateways: usize,
+    pub gateways: Vec<GatewayInfo>,
+}
+
+/// Query string parameters for `GET /devices/offline`
+#[derive(Debug, Deserialize)]
+pub struct OfflineDevicesQuery {
+    /// How long a device must have been silent, e.g. "2h"
+    pub threshold: Option<String>,
+}
+
+/// Devices that haven't sent a frame within the threshold
+#[derive(Debug, Serialize)]
+pub struct OfflineDevicesResponse {
+    pub threshold: String,
+    pub total_devices: usize,
+    pub devices: Vec<OfflineDevice>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OfflineDevice {
+    #[serde(flatten)]
+    pub device: DeviceInfo,
+    /// Seconds since the device was last seen
+    pub silent_secs: i64,
+}
+
 /// Device information
 #[derive(Debug, Serialize)]
 pub struct DeviceInfo {
@@ -75,11 +226,52 @@ pub struct DeviceInfo {
     pub last_seen: Option<String>,
 }
 
+/// Pagination for the application latest-frame listing
+#[derive(Debug, Deserialize)]
+pub struct LatestFramesQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// Latest frame for every device in an application
+#[derive(Debug, Serialize)]
+pub struct ApplicationLatestResponse {
+    pub application_id: String,
+    pub total_devices: usize,
+    pub offset: usize,
+    pub limit: usize,
+    /// Latest frame keyed by DevEUI (devices without stored frames are omitted)
+    pub frames: BTreeMap<String, serde_json::Value>,
+}
+
+/// Device history export request, continuing from `cursor` if given
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportRequest {
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// One page of a device history export
+#[derive(Debug, Serialize)]
+pub struct ExportResponse {
+    pub dev_eui: String,
+    pub frames: Vec<serde_json::Value>,
+    /// Pass back to fetch the next page, `null` once the export is complete
+    pub next_cursor: Option<String>,
+}
+
 /// API token creation request
 #[derive(Debug, Deserialize)]
 pub struct CreateTokenRequest {
     pub name: String,
     pub expires_in_days: Option<i64>,
+    /// Restrict the token to these frame fields (dot-separated paths)
+    #[serde(default)]
+    pub allowed_fields: Option<Vec<String>>,
+    /// Confine the token to one tenant's applications
+    /// Tokens created by a tenant-scoped caller always get the caller's tenant
+    #[serde(default)]
+    pub tenant_id: Option<String>,
 }
 
 /// API token response
@@ -90,6 +282,8 @@ pub struct TokenResponse {
     pub name: String,
     pub created_at: String,
     pub expires_at: Option<String>,
+    pub allowed_fields: Option<Vec<String>>,
+    pub tenant_id: Option<String>,
 }
 
 /// API token list item (without the actual token)
@@ -102,6 +296,8 @@ pub struct TokenInfo {
     pub last_used_at: Option<String>,
     pub expires_at: Option<String>,
     pub is_active: bool,
+    pub allowed_fields: Option<Vec<String>>,
+    pub tenant_id: Option<String>,
 }
 
 /// Token list response
@@ -114,7 +310,8 @@ pub struct TokenListResponse {
 /// ChirpStack ingestion query parameter
 #[derive(Debug, Deserialize)]
 pub struct IngestQuery {
-    pub event: String,  // "up", "join", or "status"
+    /// "up", "join", or "status", detected from the payload when absent
+    pub event: Option<String>,
 }
 
 /// ChirpStack ingestion response
@@ -130,15 +327,30 @@ pub struct IngestResponse {
 pub struct ErrorResponse {
     pub error: String,
     pub message: String,
+    /// Correlation ID of the failed request, matches the `X-Request-Id` header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Which input fields were invalid, for validation errors
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
 }
 
 impl IntoResponse for LoraDbError {
     fn into_response(self) -> Response {
+        let mut details = Vec::new();
         let (status, error_type, message) = match self {
             LoraDbError::QueryParseError(msg) => {
                 // User input error - safe to expose details
                 (StatusCode::BAD_REQUEST, "QueryParseError", msg)
             }
+            LoraDbError::ValidationError {
+                message,
+                details: fields,
+            } => {
+                // User input error - safe to expose details
+                details = fields;
+                (StatusCode::BAD_REQUEST, "ValidationError", message)
+            }
             LoraDbError::QueryExecutionError(msg) => {
                 // SECURITY: Log detailed error but return sanitized message to user
                 tracing::error!(error = %msg, "Query execution error");
@@ -152,10 +364,29 @@ impl IntoResponse for LoraDbError {
                 // Don't expose auth error details for security
                 (StatusCode::UNAUTHORIZED, "AuthError", "Authentication failed".to_string())
             }
+            LoraDbError::Forbidden(msg) => (StatusCode::FORBIDDEN, "Forbidden", msg),
             LoraDbError::InvalidDevEui(msg) => {
                 // User input error - safe to expose details
                 (StatusCode::BAD_REQUEST, "InvalidDevEui", msg)
             }
+            LoraDbError::InvalidFrame(msg) => {
+                // Frame rejected by validation - safe to expose details
+                (StatusCode::BAD_REQUEST, "InvalidFrame", msg)
+            }
+            LoraDbError::NotFound(msg) => (StatusCode::NOT_FOUND, "NotFound", msg),
+            LoraDbError::DeviceNotFound(dev_eui) => (
+                StatusCode::NOT_FOUND,
+                "DeviceNotFound",
+                format!("Device {} not found", dev_eui),
+            ),
+            LoraDbError::QuotaExceeded(msg) => (StatusCode::TOO_MANY_REQUESTS, "QuotaExceeded", msg),
+            LoraDbError::ReadOnly(msg) => (StatusCode::METHOD_NOT_ALLOWED, "ReadOnly", msg),
+            LoraDbError::Overloaded(msg) => (StatusCode::SERVICE_UNAVAILABLE, "Overloaded", msg),
+            LoraDbError::MqttError(msg) => {
+                // Only raised to HTTP clients when a downlink can't be handed to the broker
+                tracing::warn!(error = %msg, "MQTT error");
+                (StatusCode::SERVICE_UNAVAILABLE, "MqttError", msg)
+            }
             LoraDbError::StorageError(msg) => {
                 // SECURITY: Log detailed error but return generic message
                 tracing::error!(error = %msg, "Storage error");
@@ -180,12 +411,69 @@ impl IntoResponse for LoraDbError {
         let body = Json(ErrorResponse {
             error: error_type.to_string(),
             message,
+            request_id: current_request_id(),
+            details,
         });
 
         (status, body).into_response()
     }
 }
 
+/// Reject a device outside the caller's tenant as if it didn't exist
+fn authorize_device(
+    state: &AppState,
+    auth_context: &AuthContext,
+    dev_eui: &crate::model::lorawan::DevEui,
+) -> Result<(), LoraDbError> {
+    let Some(tenant) = auth_context.tenant() else {
+        return Ok(());
+    };
+    match state.storage.device_registry().application_of(dev_eui) {
+        Some(app_id) if state.tenants.allows(Some(tenant), &app_id) => Ok(()),
+        _ => Err(LoraDbError::DeviceNotFound(dev_eui.as_str().to_string())),
+    }
+}
+
+/// Reject an application outside the caller's tenant as if it had no data
+fn authorize_application(
+    state: &AppState,
+    auth_context: &AuthContext,
+    app_id: &str,
+) -> Result<(), LoraDbError> {
+    if state.tenants.allows(auth_context.tenant(), app_id) {
+        Ok(())
+    } else {
+        Err(LoraDbError::NotFound(format!("No data stored for application '{}'", app_id)))
+    }
+}
+
+/// Reject a query over a device or application outside the caller's tenant
+fn authorize_query(
+    state: &AppState,
+    auth_context: &AuthContext,
+    query: &crate::query::dsl::Query,
+) -> Result<(), LoraDbError> {
+    match &query.from {
+        crate::query::dsl::FromClause::Device(dev_eui) => {
+            let dev_eui = crate::model::lorawan::DevEui::new(dev_eui.clone())?;
+            authorize_device(state, auth_context, &dev_eui)
+        }
+        crate::query::dsl::FromClause::Application(app_id) => {
+            authorize_application(state, auth_context, app_id)
+        }
+    }
+}
+
+/// Errors the executor raises about the query itself (400) keep their message,
+/// anything else is reported as a sanitized execution failure
+fn query_execution_error(e: anyhow::Error) -> LoraDbError {
+    match e.downcast::<LoraDbError>() {
+        Ok(e @ LoraDbError::QueryParseError(_)) => e,
+        Ok(e) => LoraDbError::QueryExecutionError(e.to_string()),
+        Err(e) => LoraDbError::QueryExecutionError(e.to_string()),
+    }
+}
+
 /// Health check endpoint
 pub async fn health_check() -> Json<HealthResponse> {
     Json(HealthResponse {
@@ -194,46 +482,185 @@ pub async fn health_check() -> Json<HealthResponse> {
     })
 }
 
+/// Readiness check endpoint, 503 while the storage engine is degraded
+pub async fn readiness_check(State(state): State<AppState>) -> Response {
+    let disk_full = state.storage.is_disk_full();
+    let memtable_full = state.storage.is_memtable_full();
+    let mqtt_disconnected = state
+        .mqtt_status
+        .as_ref()
+        .map(MqttStatus::disconnected_brokers)
+        .unwrap_or_default();
+    let (status, text) = if disk_full || memtable_full || !mqtt_disconnected.is_empty() {
+        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+    } else {
+        (StatusCode::OK, "ready")
+    };
+
+    (
+        status,
+        Json(ReadinessResponse {
+            status: text.to_string(),
+            disk_full,
+            memtable_full,
+            mqtt_disconnected,
+        }),
+    )
+        .into_response()
+}
+
+/// Report exactly which build is running
+pub async fn version_info(Extension(_auth_context): Extension<AuthContext>) -> Json<VersionResponse> {
+    let build_timestamp = crate::BUILD_TIMESTAMP
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|dt| dt.to_rfc3339())
+        .unwrap_or_else(|| crate::BUILD_TIMESTAMP.to_string());
+
+    Json(VersionResponse {
+        version: crate::VERSION.to_string(),
+        git_sha: crate::GIT_SHA.to_string(),
+        build_timestamp,
+        features: crate::features().into_iter().map(String::from).collect(),
+    })
+}
+
 /// Execute a query
 pub async fn execute_query(
     State(state): State<AppState>,
     Extension(auth_context): Extension<AuthContext>,
     Json(request): Json<QueryRequest>,
-) -> Result<Json<QueryResult>, LoraDbError> {
+) -> Result<(HeaderMap, Json<QueryResult>), LoraDbError> {
+    run_query(&state, &auth_context, &request.query).await
+}
+
+/// Execute a query passed as `?q=`, so it can be shared as a URL
+pub async fn execute_query_get(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Query(params): Query<QueryParams>,
+) -> Result<(HeaderMap, Json<QueryResult>), LoraDbError> {
+    run_query(&state, &auth_context, &params.q).await
+}
+
+/// Parse and execute a query for `POST /query` and `GET /query`
+/// Logs how long each stage took, at WARN for queries over the slow query threshold
+async fn run_query(
+    state: &AppState,
+    auth_context: &AuthContext,
+    query_str: &str,
+) -> Result<(HeaderMap, Json<QueryResult>), LoraDbError> {
+    let started = Instant::now();
+
     // SECURITY: Validate query string length
-    validate_string_length(&request.query, MAX_QUERY_LENGTH, "Query")?;
+    validate_string_length(query_str, MAX_QUERY_LENGTH, "query", "Query")?;
 
     tracing::info!(
         user = auth_context.user_id(),
-        query = request.query,
+        query = query_str,
         "Executing query"
     );
 
     // Parse query
+    let query = {
+        let _span = tracing::info_span!("query.parse").entered();
+        state
+            .query_parser
+            .parse(query_str)
+            .map_err(|e| LoraDbError::QueryParseError(e.to_string()))?
+    };
+    let parse_time = started.elapsed();
+    authorize_query(state, auth_context, &query)?;
+
+    // Execute query, enforcing the token's field allowlist if any
+    let (result, timings) = state
+        .query_executor
+        .execute_timed(&query, auth_context.allowed_fields())
+        .await
+        .map_err(query_execution_error)?;
+
+    let total = started.elapsed();
+    let total_ms = total.as_secs_f64() * 1000.0;
+    let scan_ms = timings.scan.as_secs_f64() * 1000.0;
+    let serialize_ms = timings.serialize.as_secs_f64() * 1000.0;
+    let stats = timings.scan_stats;
+    tracing::info!(
+        user = auth_context.user_id(),
+        from = %query.from,
+        parse_ms = parse_time.as_secs_f64() * 1000.0,
+        scan_ms,
+        serialize_ms,
+        total_ms,
+        memtable_frames = stats.memtable_frames,
+        sstables_scanned = stats.sstables_scanned,
+        sstable_frames = stats.sstable_frames,
+        returned = result.total_frames,
+        cached = timings.cached,
+        "Query completed"
+    );
+    if state.slow_query_ms > 0 && total.as_millis() >= u128::from(state.slow_query_ms) {
+        tracing::warn!(
+            user = auth_context.user_id(),
+            query = query_str,
+            scan_ms,
+            serialize_ms,
+            total_ms,
+            frames_scanned = stats.memtable_frames + stats.sstable_frames,
+            "Slow query"
+        );
+    }
+
+    let mut headers = HeaderMap::new();
+    if state.query_timing_header {
+        headers.insert(QUERY_TIME_HEADER, HeaderValue::from(total.as_millis() as u64));
+    }
+
+    Ok((headers, Json(result)))
+}
+
+/// Estimate a query's frame count and response size without returning the frames
+pub async fn estimate_query(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<QueryEstimate>, LoraDbError> {
+    // SECURITY: Validate query string length
+    validate_string_length(&request.query, MAX_QUERY_LENGTH, "query", "Query")?;
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        query = request.query,
+        "Estimating query"
+    );
+
     let query = state
         .query_parser
         .parse(&request.query)
         .map_err(|e| LoraDbError::QueryParseError(e.to_string()))?;
+    authorize_query(&state, &auth_context, &query)?;
 
-    // Execute query
-    let result = state
+    let estimate = state
         .query_executor
-        .execute(&query)
+        .estimate_restricted(&query, auth_context.allowed_fields())
         .await
-        .map_err(|e| LoraDbError::QueryExecutionError(e.to_string()))?;
+        .map_err(query_execution_error)?;
 
-    Ok(Json(result))
+    Ok(Json(estimate))
 }
 
 /// List all devices
 pub async fn list_devices(
     State(state): State<AppState>,
-    Extension(_auth_context): Extension<AuthContext>,
-) -> Json<DeviceListResponse> {
+    Extension(auth_context): Extension<AuthContext>,
+    Query(params): Query<FormatParams>,
+) -> Result<Formatted<DeviceListResponse>, LoraDbError> {
+    let format = params.response_format()?;
     let registry = state.storage.device_registry();
     let devices: Vec<DeviceInfo> = registry
         .list_devices()
         .into_iter()
+        .filter(|device| state.tenants.allows(auth_context.tenant(), &device.application_id))
         .map(|device| DeviceInfo {
             dev_eui: device.dev_eui.as_str().to_string(),
             device_name: device.device_name,
@@ -242,36 +669,224 @@ pub async fn list_devices(
         })
         .collect();
 
-    Json(DeviceListResponse {
+    Ok(Formatted {
+        format,
+        body: DeviceListResponse {
+            total_devices: devices.len(),
+            devices,
+        },
+    })
+}
+
+/// List applications with their device and frame counts and retention, paginated
+pub async fn list_applications(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Query(page): Query<ApplicationsQuery>,
+) -> Json<ApplicationListResponse> {
+    let offset = page.offset.unwrap_or(0);
+    let limit = page
+        .limit
+        .unwrap_or(DEFAULT_APPLICATION_PAGE_SIZE)
+        .clamp(1, MAX_APPLICATION_PAGE_SIZE);
+
+    let summaries: Vec<_> = state
+        .storage
+        .device_registry()
+        .application_summaries()
+        .into_iter()
+        .filter(|summary| state.tenants.allows(auth_context.tenant(), &summary.application_id))
+        .collect();
+
+    let policies = state.storage.retention_manager().get_policies().await;
+    let applications = summaries
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|summary| {
+            let policy = policies.applications.get(&summary.application_id);
+            ApplicationInfo {
+                application_id: summary.application_id.clone(),
+                device_count: summary.device_count,
+                frame_count: summary.frame_count,
+                last_seen: summary.last_seen.map(|dt| dt.to_rfc3339()),
+                retention_days: policy.map_or(policies.global_days, |policy| policy.days),
+                retention_policy_set: policy.is_some(),
+            }
+        })
+        .collect();
+
+    Json(ApplicationListResponse {
+        total_applications: summaries.len(),
+        offset,
+        limit,
+        applications,
+    })
+}
+
+/// List devices whose last frame is older than the threshold, longest silent first
+pub async fn list_offline_devices(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Query(params): Query<OfflineDevicesQuery>,
+) -> Result<Json<OfflineDevicesResponse>, LoraDbError> {
+    let threshold = params.threshold.ok_or_else(|| {
+        LoraDbError::QueryParseError("threshold is required, e.g. ?threshold=2h".to_string())
+    })?;
+    validate_string_length(&threshold, 32, "threshold", "threshold")?;
+    let window = crate::query::parser::parse_duration(&threshold)
+        .map_err(|e| LoraDbError::QueryParseError(format!("Invalid threshold: {}", e)))?;
+    if window <= chrono::Duration::zero() {
+        return Err(LoraDbError::QueryParseError(
+            "threshold must be greater than 0".to_string(),
+        ));
+    }
+
+    let now = chrono::Utc::now();
+    let devices: Vec<OfflineDevice> = state
+        .storage
+        .device_registry()
+        .list_offline(now - window)
+        .into_iter()
+        .filter(|device| state.tenants.allows(auth_context.tenant(), &device.application_id))
+        .map(|device| OfflineDevice {
+            silent_secs: (now - device.last_seen.unwrap_or(device.first_seen)).num_seconds(),
+            device: DeviceInfo {
+                dev_eui: device.dev_eui.as_str().to_string(),
+                device_name: device.device_name,
+                application_id: device.application_id,
+                last_seen: device.last_seen.map(|dt| dt.to_rfc3339()),
+            },
+        })
+        .collect();
+
+    Ok(Json(OfflineDevicesResponse {
+        threshold,
         total_devices: devices.len(),
         devices,
+    }))
+}
+
+/// List every gateway seen in reception info, with its last-known location
+/// and the number of distinct devices it has heard
+/// Locations are left out for tokens whose allowlist hides them in frames
+pub async fn get_gateway_map(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Json<GatewayMapResponse> {
+    let mut gateways = state.storage.gateway_registry().list_all();
+    if !auth_context.can_read_field("rx_info.location") {
+        for gateway in &mut gateways {
+            gateway.location = None;
+        }
+    }
+    Json(GatewayMapResponse {
+        total_gateways: gateways.len(),
+        gateways,
     })
 }
 
+/// Report which of a list of DevEUIs are registered
+pub async fn devices_exist(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(dev_euis): Json<Vec<String>>,
+) -> Result<Json<BTreeMap<String, bool>>, LoraDbError> {
+    // SECURITY: Cap the batch size and validate each DevEUI to prevent memory exhaustion
+    if dev_euis.len() > MAX_EXISTS_BATCH {
+        return Err(LoraDbError::QueryParseError(format!(
+            "Too many DevEUIs (max: {}, got: {})",
+            MAX_EXISTS_BATCH,
+            dev_euis.len()
+        )));
+    }
+    for dev_eui in &dev_euis {
+        validate_string_length(dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+    }
+
+    // Registry keys are normalized to lowercase, but answer with the EUIs as sent
+    let registry = state.storage.device_registry();
+    let exists = dev_euis
+        .into_iter()
+        .map(|dev_eui| {
+            let found = registry
+                .get_device(&dev_eui.to_lowercase())
+                .is_some_and(|device| {
+                    state.tenants.allows(auth_context.tenant(), &device.application_id)
+                });
+            (dev_eui, found)
+        })
+        .collect();
+
+    Ok(Json(exists))
+}
+
 /// Get device information
 pub async fn get_device(
     State(state): State<AppState>,
-    Extension(_auth_context): Extension<AuthContext>,
+    Extension(auth_context): Extension<AuthContext>,
     Path(dev_eui): Path<String>,
-) -> Result<Json<DeviceInfo>, LoraDbError> {
+    Query(params): Query<FormatParams>,
+) -> Result<Formatted<DeviceInfo>, LoraDbError> {
+    let format = params.response_format()?;
+
     // SECURITY: Validate dev_eui string length
-    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "DevEUI")?;
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+
+    // Malformed EUIs are a bad request, well-formed but unknown ones are not found
+    let parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())?;
+    authorize_device(&state, &auth_context, &parsed)?;
 
     let registry = state.storage.device_registry();
 
     if let Some(device) = registry.get_device(&dev_eui) {
-        Ok(Json(DeviceInfo {
-            dev_eui: device.dev_eui.as_str().to_string(),
-            device_name: device.device_name,
-            application_id: device.application_id,
-            last_seen: device.last_seen.map(|dt| dt.to_rfc3339()),
-        }))
+        Ok(Formatted {
+            format,
+            body: DeviceInfo {
+                dev_eui: device.dev_eui.as_str().to_string(),
+                device_name: device.device_name,
+                application_id: device.application_id,
+                last_seen: device.last_seen.map(|dt| dt.to_rfc3339()),
+            },
+        })
     } else {
-        Err(LoraDbError::InvalidDevEui(format!(
-            "Device {} not found",
-            dev_eui
-        )))
+        Err(LoraDbError::DeviceNotFound(dev_eui))
+    }
+}
+
+/// Storage footprint of a device's flushed frames
+#[derive(Debug, Serialize)]
+pub struct DeviceStorageResponse {
+    pub dev_eui: String,
+    #[serde(flatten)]
+    pub usage: DeviceStorageUsage,
+}
+
+/// Get how many frames and compressed bytes a device occupies in SSTables
+pub async fn get_device_storage(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Path(dev_eui): Path<String>,
+) -> Result<Json<DeviceStorageResponse>, LoraDbError> {
+    // SECURITY: Validate dev_eui string length
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+
+    let parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())?;
+    authorize_device(&state, &auth_context, &parsed)?;
+
+    if state.storage.device_registry().get_device(&dev_eui).is_none() {
+        return Err(LoraDbError::DeviceNotFound(dev_eui));
     }
+
+    let usage = state
+        .storage
+        .device_storage_usage(&parsed)
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to read SSTable index: {}", e)))?;
+
+    Ok(Json(DeviceStorageResponse {
+        dev_eui: parsed.normalized(),
+        usage,
+    }))
 }
 
 /// Delete device and all its data
@@ -280,8 +895,10 @@ pub async fn delete_device(
     Extension(auth_context): Extension<AuthContext>,
     Path(dev_eui): Path<String>,
 ) -> Result<Json<DeleteDeviceResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
     // SECURITY: Validate dev_eui string length
-    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "DevEUI")?;
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
 
     let user_id = auth_context.user_id();
 
@@ -291,18 +908,38 @@ pub async fn delete_device(
         "Deleting device and all its data"
     );
 
+    // Parse DevEUI
+    let dev_eui_parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())
+        .map_err(|e| LoraDbError::InvalidDevEui(e.to_string()))?;
+    authorize_device(&state, &auth_context, &dev_eui_parsed)?;
+
     // Check if device exists
     let registry = state.storage.device_registry();
     if registry.get_device(&dev_eui).is_none() {
-        return Err(LoraDbError::InvalidDevEui(format!(
-            "Device {} not found",
-            dev_eui
-        )));
+        return Err(LoraDbError::DeviceNotFound(dev_eui));
     }
 
-    // Parse DevEUI
-    let dev_eui_parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())
-        .map_err(|e| LoraDbError::InvalidDevEui(e.to_string()))?;
+    // With a grace period, only hide the device, the retention task purges it later
+    if let Some(grace) = state.storage.device_delete_grace() {
+        let tombstone = state
+            .storage
+            .soft_delete_device(&dev_eui_parsed, grace)
+            .await
+            .map_err(|e| LoraDbError::StorageError(format!("Failed to delete device: {}", e)))?;
+
+        tracing::info!(
+            user = user_id,
+            dev_eui = dev_eui,
+            purge_after = %tombstone.purge_after,
+            "Device marked deleted"
+        );
+
+        return Ok(Json(DeleteDeviceResponse {
+            dev_eui,
+            deleted_frames: 0,
+            purge_after: Some(tombstone.purge_after.to_rfc3339()),
+        }));
+    }
 
     // Delete all data for the device
     let deleted_count = state
@@ -321,6 +958,7 @@ pub async fn delete_device(
     Ok(Json(DeleteDeviceResponse {
         dev_eui,
         deleted_frames: deleted_count,
+        purge_after: None,
     }))
 }
 
@@ -328,61 +966,655 @@ pub async fn delete_device(
 #[derive(Debug, Serialize)]
 pub struct DeleteDeviceResponse {
     pub dev_eui: String,
+    /// Frames removed, 0 for a soft delete whose data is still kept
     pub deleted_frames: usize,
+    /// When a soft-deleted device's data will be removed (RFC3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge_after: Option<String>,
 }
 
-/// Create a new API token
-pub async fn create_token(
+/// Delete every device of an application and all their data, along with its retention policy
+pub async fn delete_application(
     State(state): State<AppState>,
     Extension(auth_context): Extension<AuthContext>,
-    Json(request): Json<CreateTokenRequest>,
-) -> Result<Json<TokenResponse>, LoraDbError> {
-    // SECURITY: Validate token name length
-    validate_string_length(&request.name, MAX_TOKEN_NAME_LENGTH, "Token name")?;
+    Path(app_id): Path<String>,
+) -> Result<Json<DeleteApplicationResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+    authorize_application(&state, &auth_context, &app_id)?;
 
     let user_id = auth_context.user_id();
 
     tracing::info!(
         user = user_id,
-        token_name = request.name,
-        "Creating API token"
+        application_id = app_id,
+        "Deleting application and all its data"
     );
 
-    // Create the token
-    let (token_string, api_token) = state
-        .api_token_store
-        .create_token(
-            request.name,
-            user_id.to_string(),
-            request.expires_in_days,
-        )
-        .map_err(|e| LoraDbError::StorageError(format!("Failed to create token: {}", e)))?;
+    let deleted = state
+        .storage
+        .delete_application(&app_id)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to delete application: {}", e)))?;
 
-    Ok(Json(TokenResponse {
-        token: token_string,
-        id: api_token.id,
-        name: api_token.name,
-        created_at: api_token.created_at.to_rfc3339(),
-        expires_at: api_token.expires_at.map(|dt| dt.to_rfc3339()),
+    if deleted.deleted_devices == 0 && deleted.deleted_frames == 0 {
+        return Err(LoraDbError::NotFound(format!("No data stored for application '{}'", app_id)));
+    }
+
+    tracing::info!(
+        user = user_id,
+        application_id = app_id,
+        deleted_devices = deleted.deleted_devices,
+        deleted_frames = deleted.deleted_frames,
+        "Application deleted successfully"
+    );
+
+    Ok(Json(DeleteApplicationResponse {
+        application_id: app_id,
+        deleted_devices: deleted.deleted_devices,
+        deleted_frames: deleted.deleted_frames,
     }))
 }
 
-/// List all API tokens for the authenticated user
-pub async fn list_tokens(
+/// Delete application response
+#[derive(Debug, Serialize)]
+pub struct DeleteApplicationResponse {
+    pub application_id: String,
+    pub deleted_devices: usize,
+    pub deleted_frames: usize,
+}
+
+/// Restore a soft-deleted device before its data is purged
+pub async fn undelete_device(
     State(state): State<AppState>,
     Extension(auth_context): Extension<AuthContext>,
-) -> Result<Json<TokenListResponse>, LoraDbError> {
-    let user_id = auth_context.user_id();
+    Path(dev_eui): Path<String>,
+) -> Result<Json<UndeleteDeviceResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
 
-    tracing::info!(user = user_id, "Listing API tokens");
+    // SECURITY: Validate dev_eui string length
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+
+    let dev_eui_parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())
+        .map_err(|e| LoraDbError::InvalidDevEui(e.to_string()))?;
+    authorize_device(&state, &auth_context, &dev_eui_parsed)?;
+
+    let restored = state
+        .storage
+        .undelete_device(&dev_eui_parsed)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to undelete device: {}", e)))?;
+
+    if !restored {
+        return Err(LoraDbError::NotFound(format!(
+            "Device {} is not pending deletion",
+            dev_eui
+        )));
+    }
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        dev_eui = dev_eui,
+        "Device undeleted"
+    );
+
+    Ok(Json(UndeleteDeviceResponse {
+        dev_eui,
+        restored: true,
+    }))
+}
+
+/// Undelete device response
+#[derive(Debug, Serialize)]
+pub struct UndeleteDeviceResponse {
+    pub dev_eui: String,
+    pub restored: bool,
+}
+
+/// Maximum length of a user-assigned device name
+const MAX_DEVICE_NAME_LENGTH: usize = 128;
+
+/// Body for `PUT /devices/:dev_eui/name`
+#[derive(Debug, Deserialize)]
+pub struct SetDeviceNameRequest {
+    /// Shown instead of the network server's name, null to go back to it
+    pub name: Option<String>,
+}
+
+/// Give a device a name that takes precedence over the network server's
+pub async fn set_device_name(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Path(dev_eui): Path<String>,
+    Json(request): Json<SetDeviceNameRequest>,
+) -> Result<Json<DeviceInfo>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate dev_eui string length
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+
+    let name = match request.name.as_deref().map(str::trim) {
+        Some("") => {
+            return Err(LoraDbError::QueryParseError(
+                "Device name must not be empty, use null to clear it".to_string(),
+            ))
+        }
+        Some(name) => {
+            validate_string_length(name, MAX_DEVICE_NAME_LENGTH, "name", "Device name")?;
+            Some(name)
+        }
+        None => None,
+    };
+
+    let parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())
+        .map_err(|e| LoraDbError::InvalidDevEui(e.to_string()))?;
+    authorize_device(&state, &auth_context, &parsed)?;
+    if state.storage.device_registry().get(&parsed).is_none() {
+        return Err(LoraDbError::DeviceNotFound(dev_eui));
+    }
+
+    state
+        .storage
+        .set_device_name(&parsed, name)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to save device name: {}", e)))?;
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        dev_eui = dev_eui,
+        name = name,
+        "Device name set"
+    );
+
+    let device = state
+        .storage
+        .device_registry()
+        .get(&parsed)
+        .ok_or_else(|| LoraDbError::DeviceNotFound(dev_eui.clone()))?;
+    Ok(Json(DeviceInfo {
+        dev_eui: device.dev_eui.as_str().to_string(),
+        device_name: device.device_name,
+        application_id: device.application_id,
+        last_seen: device.last_seen.map(|dt| dt.to_rfc3339()),
+    }))
+}
+
+/// Get the most recent frame stored for a device
+pub async fn get_latest_frame(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Path(dev_eui): Path<String>,
+) -> Result<Json<serde_json::Value>, LoraDbError> {
+    // SECURITY: Validate dev_eui string length
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+
+    let dev_eui_parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())
+        .map_err(|e| LoraDbError::InvalidDevEui(e.to_string()))?;
+    authorize_device(&state, &auth_context, &dev_eui_parsed)?;
+
+    let frame = state
+        .storage
+        .latest_frame(&dev_eui_parsed)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to read latest frame: {}", e)))?
+        .ok_or_else(|| LoraDbError::NotFound(format!("No frames stored for device {}", dev_eui)))?;
+
+    let executor = &state.query_executor;
+    Ok(Json(executor.restrict_fields(
+        executor.frame_to_json(&frame),
+        auth_context.allowed_fields(),
+    )))
+}
+
+/// Reception quality of a device's most recent uplink
+#[derive(Debug, Serialize)]
+pub struct SignalSnapshot {
+    pub received_at: String,
+    pub dr_label: String,
+    /// Gateways that received the uplink
+    pub gateways: usize,
+    /// Best RSSI and SNR across those gateways (None when the uplink has no rx_info)
+    pub best_rssi: Option<i16>,
+    pub best_snr: Option<f32>,
+}
+
+impl SignalSnapshot {
+    fn from_uplink(uplink: &crate::model::frames::UplinkFrame) -> Self {
+        Self {
+            received_at: uplink.received_at.to_rfc3339(),
+            dr_label: uplink.dr.label(),
+            gateways: uplink.rx_info.len(),
+            best_rssi: uplink.rx_info.iter().map(|rx| rx.rssi).max(),
+            best_snr: uplink.rx_info.iter().map(|rx| rx.snr).reduce(f32::max),
+        }
+    }
+}
+
+/// Everything a device detail view needs in one response
+#[derive(Debug, Serialize)]
+pub struct DeviceSummaryResponse {
+    #[serde(flatten)]
+    pub device: DeviceInfo,
+    pub latest_frame: Option<serde_json::Value>,
+    /// Frames in SSTables, counted as for `/devices/:dev_eui/storage`, plus unflushed ones
+    pub stored_frames: u64,
+    /// Frames received in the last 24 hours, including unflushed ones
+    pub frames_last_24h: usize,
+    /// From the latest uplink, or the newest uplink of the last 24 hours; left out for
+    /// tokens whose field allowlist hides `received_at`, `dr` or `rx_info`
+    pub signal: Option<SignalSnapshot>,
+}
+
+/// Get a device's metadata, latest frame, frame counts and signal in one call
+pub async fn get_device_summary(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Path(dev_eui): Path<String>,
+) -> Result<Json<DeviceSummaryResponse>, LoraDbError> {
+    // SECURITY: Validate dev_eui string length
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+
+    let parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())?;
+    authorize_device(&state, &auth_context, &parsed)?;
+
+    let device = state
+        .storage
+        .device_registry()
+        .get(&parsed)
+        .ok_or_else(|| LoraDbError::DeviceNotFound(dev_eui.clone()))?;
+
+    let latest = state
+        .storage
+        .latest_frame(&parsed)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to read latest frame: {}", e)))?;
+
+    let usage = state
+        .storage
+        .device_storage_usage(&parsed)
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to read SSTable index: {}", e)))?;
+
+    let recent = state
+        .storage
+        .query(&parsed, Some(chrono::Utc::now() - chrono::Duration::hours(24)), None)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to read recent frames: {}", e)))?;
+
+    use crate::model::frames::Frame;
+    let can_read_signal =
+        ["received_at", "dr", "rx_info"].iter().all(|field| auth_context.can_read_field(field));
+    let signal = match &latest {
+        _ if !can_read_signal => None,
+        Some(Frame::Uplink(uplink)) => Some(SignalSnapshot::from_uplink(uplink)),
+        _ => recent.iter().rev().find_map(|frame| match frame {
+            Frame::Uplink(uplink) => Some(SignalSnapshot::from_uplink(uplink)),
+            _ => None,
+        }),
+    };
+
+    let executor = &state.query_executor;
+    Ok(Json(DeviceSummaryResponse {
+        device: DeviceInfo {
+            dev_eui: device.dev_eui.as_str().to_string(),
+            device_name: device.device_name,
+            application_id: device.application_id,
+            last_seen: device.last_seen.map(|dt| dt.to_rfc3339()),
+        },
+        latest_frame: latest.map(|frame| {
+            executor.restrict_fields(executor.frame_to_json(&frame), auth_context.allowed_fields())
+        }),
+        stored_frames: usage.frames + state.storage.device_memtable_frames(&parsed) as u64,
+        frames_last_24h: recent.len(),
+        signal,
+    }))
+}
+
+/// Downlink request body
+#[derive(Debug, Deserialize)]
+pub struct DownlinkRequest {
+    /// ChirpStack application ID (UUID) the device belongs to; LoRaDB stores the
+    /// application name when ChirpStack sends one, so it can't be looked up here
+    pub application_id: String,
+    pub f_port: u8,
+    /// Base64 encoded payload
+    pub data: String,
+    #[serde(default)]
+    pub confirmed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownlinkResponse {
+    pub dev_eui: String,
+    pub application_id: String,
+    /// MQTT topic the enqueue command was published to
+    pub topic: String,
+}
+
+/// Enqueue a downlink in ChirpStack by publishing to its MQTT command topic
+/// Accepted means the broker took the message, ChirpStack may still reject it
+pub async fn send_downlink(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Path(dev_eui): Path<String>,
+    Json(request): Json<DownlinkRequest>,
+) -> Result<(StatusCode, Json<DownlinkResponse>), LoraDbError> {
+    // SECURITY: Validate string lengths
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+    validate_string_length(
+        &request.application_id,
+        MAX_APP_ID_LENGTH,
+        "application_id",
+        "Application ID",
+    )?;
+
+    let dev_eui = crate::model::lorawan::DevEui::new(dev_eui)?;
+    authorize_device(&state, &auth_context, &dev_eui)?;
+
+    // The application ID becomes a topic level, so it can't contain separators or wildcards
+    if request.application_id.is_empty()
+        || request.application_id.contains(['/', '+', '#'])
+    {
+        return Err(LoraDbError::QueryParseError(
+            "application_id must be a non-empty ChirpStack application ID".to_string(),
+        ));
+    }
+    if !(1..=223).contains(&request.f_port) {
+        return Err(LoraDbError::QueryParseError(
+            "f_port must be between 1 and 223".to_string(),
+        ));
+    }
+    let payload = {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD
+            .decode(&request.data)
+            .map_err(|_| LoraDbError::QueryParseError("data must be valid base64".to_string()))?
+    };
+    if payload.len() > MAX_DOWNLINK_PAYLOAD_BYTES {
+        return Err(LoraDbError::QueryParseError(format!(
+            "data must decode to at most {} bytes",
+            MAX_DOWNLINK_PAYLOAD_BYTES
+        )));
+    }
+
+    let publisher = state.downlink_publisher.as_ref().ok_or_else(|| {
+        LoraDbError::MqttError("Downlinks require a ChirpStack MQTT broker".to_string())
+    })?;
+
+    let downlink = ChirpStackDownlink::new(&dev_eui, request.f_port, request.data, request.confirmed);
+    let topic = publisher.publish(&request.application_id, &downlink).await?;
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        dev_eui = dev_eui.as_str(),
+        f_port = request.f_port,
+        confirmed = request.confirmed,
+        bytes = payload.len(),
+        topic = %topic,
+        "Downlink enqueued"
+    );
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(DownlinkResponse {
+            dev_eui: dev_eui.as_str().to_string(),
+            application_id: request.application_id,
+            topic,
+        }),
+    ))
+}
+
+/// Export a device's full history one page at a time
+/// Unlike /query there is no time filter or result cap, the cursor resumes where the last page ended
+pub async fn export_device(
+    State(state): State<AppState>,
+    Path(dev_eui): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<ExportRequest>,
+) -> Result<Json<ExportResponse>, LoraDbError> {
+    // SECURITY: Validate dev_eui string length
+    validate_string_length(&dev_eui, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+
+    let dev_eui_parsed = crate::model::lorawan::DevEui::new(dev_eui.clone())
+        .map_err(|e| LoraDbError::InvalidDevEui(e.to_string()))?;
+    authorize_device(&state, &auth_context, &dev_eui_parsed)?;
+
+    let after_micros = match &request.cursor {
+        Some(cursor) => {
+            validate_string_length(cursor, MAX_CURSOR_LENGTH, "cursor", "Cursor")?;
+            Some(decode_export_cursor(cursor, &dev_eui_parsed)?)
+        }
+        None => None,
+    };
+
+    let limit = request
+        .limit
+        .unwrap_or(DEFAULT_EXPORT_PAGE_SIZE)
+        .clamp(1, MAX_EXPORT_PAGE_SIZE);
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        dev_eui = dev_eui,
+        "Exporting device history page"
+    );
+
+    let page = state
+        .storage
+        .history_page(&dev_eui_parsed, after_micros, limit)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to export device history: {}", e)))?;
+
+    let executor = &state.query_executor;
+    let frames = page
+        .frames
+        .iter()
+        .map(|frame| {
+            executor.restrict_fields(executor.frame_to_json(frame), auth_context.allowed_fields())
+        })
+        .collect();
+
+    Ok(Json(ExportResponse {
+        dev_eui,
+        frames,
+        next_cursor: page
+            .resume_after
+            .map(|micros| encode_export_cursor(&dev_eui_parsed, micros)),
+    }))
+}
+
+/// Export cursors are opaque to clients: the DevEUI and last exported timestamp, base64 encoded
+fn encode_export_cursor(dev_eui: &crate::model::lorawan::DevEui, after_micros: i64) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .encode(format!("{}:{}", dev_eui.normalized(), after_micros))
+}
+
+/// Decode an export cursor, rejecting cursors issued for a different device
+fn decode_export_cursor(
+    cursor: &str,
+    dev_eui: &crate::model::lorawan::DevEui,
+) -> Result<i64, LoraDbError> {
+    use base64::Engine;
+    let invalid = || LoraDbError::QueryParseError("Invalid export cursor".to_string());
+
+    let decoded = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|_| invalid())?;
+    let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+    let (cursor_dev_eui, after_micros) = decoded.split_once(':').ok_or_else(invalid)?;
+
+    if cursor_dev_eui != dev_eui.normalized() {
+        return Err(invalid());
+    }
+
+    after_micros.parse().map_err(|_| invalid())
+}
+
+/// Get the most recent frame for every device in an application
+pub async fn get_application_latest(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Path(app_id): Path<String>,
+    Query(page): Query<LatestFramesQuery>,
+) -> Result<Json<ApplicationLatestResponse>, LoraDbError> {
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+    authorize_application(&state, &auth_context, &app_id)?;
+
+    let offset = page.offset.unwrap_or(0);
+    let limit = page
+        .limit
+        .unwrap_or(DEFAULT_LATEST_PAGE_SIZE)
+        .clamp(1, MAX_LATEST_PAGE_SIZE);
+
+    // Sort by DevEUI so pages are stable between requests
+    let mut dev_euis: Vec<_> = state
+        .storage
+        .device_registry()
+        .list_devices()
+        .into_iter()
+        .filter(|device| device.application_id == app_id)
+        .map(|device| device.dev_eui)
+        .collect();
+    dev_euis.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+    let mut frames = BTreeMap::new();
+    for dev_eui in dev_euis.iter().skip(offset).take(limit) {
+        let latest = state
+            .storage
+            .latest_frame(dev_eui)
+            .await
+            .map_err(|e| LoraDbError::StorageError(format!("Failed to read latest frame: {}", e)))?;
+
+        if let Some(frame) = latest {
+            let executor = &state.query_executor;
+            frames.insert(
+                dev_eui.as_str().to_string(),
+                executor.restrict_fields(executor.frame_to_json(&frame), auth_context.allowed_fields()),
+            );
+        }
+    }
+
+    Ok(Json(ApplicationLatestResponse {
+        application_id: app_id,
+        total_devices: dev_euis.len(),
+        offset,
+        limit,
+        frames,
+    }))
+}
+
+/// Create a new API token
+pub async fn create_token(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<CreateTokenRequest>,
+) -> Result<Json<TokenResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate token name length and field allowlist size
+    validate_string_length(&request.name, MAX_TOKEN_NAME_LENGTH, "name", "Token name")?;
+    if let Some(fields) = &request.allowed_fields {
+        if fields.len() > MAX_ALLOWED_FIELDS {
+            return Err(LoraDbError::QueryParseError(format!(
+                "Too many allowed fields (max: {}, got: {})",
+                MAX_ALLOWED_FIELDS,
+                fields.len()
+            )));
+        }
+        for field in fields {
+            validate_string_length(field, MAX_FIELD_PATH_LENGTH, "allowed_fields", "Allowed field")?;
+        }
+    }
+
+    // A tenant-scoped caller can only hand out tokens for its own tenant
+    let tenant_id = match (auth_context.tenant(), request.tenant_id) {
+        (Some(own), Some(requested)) if requested != own => {
+            return Err(LoraDbError::Forbidden(format!(
+                "Cannot create a token for tenant '{}' from tenant '{}'",
+                requested, own
+            )));
+        }
+        (Some(own), _) => Some(own.to_string()),
+        (None, requested) => requested,
+    };
+    if let Some(tenant) = &tenant_id {
+        validate_string_length(tenant, MAX_APP_ID_LENGTH, "tenant_id", "Tenant ID")?;
+        if !state.tenants.has_tenant(tenant) {
+            return Err(LoraDbError::QueryParseError(format!(
+                "Unknown tenant '{}', no application is assigned to it",
+                tenant
+            )));
+        }
+    }
+
+    let user_id = auth_context.user_id();
+
+    tracing::info!(
+        user = user_id,
+        token_name = request.name,
+        tenant = tenant_id.as_deref(),
+        "Creating API token"
+    );
+
+    // Create the token
+    let (token_string, mut api_token) = state
+        .api_token_store
+        .create_token(
+            request.name,
+            user_id.to_string(),
+            request.expires_in_days,
+        )
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to create token: {}", e)))?;
+
+    // Apply the field allowlist before the token is handed out
+    if request.allowed_fields.is_some() {
+        api_token = state
+            .api_token_store
+            .set_allowed_fields(&api_token.id, user_id, request.allowed_fields)
+            .map_err(|e| LoraDbError::StorageError(format!("Failed to create token: {}", e)))?;
+    }
+    if tenant_id.is_some() {
+        api_token = state
+            .api_token_store
+            .set_tenant(&api_token.id, user_id, tenant_id)
+            .map_err(|e| LoraDbError::StorageError(format!("Failed to create token: {}", e)))?;
+    }
+
+    Ok(Json(TokenResponse {
+        token: token_string,
+        id: api_token.id,
+        name: api_token.name,
+        created_at: api_token.created_at.to_rfc3339(),
+        expires_at: api_token.expires_at.map(|dt| dt.to_rfc3339()),
+        allowed_fields: api_token.allowed_fields,
+        tenant_id: api_token.tenant_id,
+    }))
+}
+
+/// List all API tokens for the authenticated user
+pub async fn list_tokens(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<Json<TokenListResponse>, LoraDbError> {
+    let user_id = auth_context.user_id();
+
+    tracing::info!(user = user_id, "Listing API tokens");
 
     let tokens = state
         .api_token_store
         .list_tokens(user_id)
         .map_err(|e| LoraDbError::StorageError(format!("Failed to list tokens: {}", e)))?;
 
+    // A tenant-scoped caller only sees its tenant's tokens, not the rest of its owner's
     let token_infos: Vec<TokenInfo> = tokens
         .into_iter()
+        .filter(|t| {
+            auth_context
+                .tenant()
+                .map_or(true, |tenant| t.tenant_id.as_deref() == Some(tenant))
+        })
         .map(|t| TokenInfo {
             id: t.id,
             name: t.name,
@@ -391,6 +1623,8 @@ pub async fn list_tokens(
             last_used_at: t.last_used_at.map(|dt| dt.to_rfc3339()),
             expires_at: t.expires_at.map(|dt| dt.to_rfc3339()),
             is_active: t.is_active,
+            allowed_fields: t.allowed_fields,
+            tenant_id: t.tenant_id,
         })
         .collect();
 
@@ -406,8 +1640,10 @@ pub async fn revoke_token(
     Extension(auth_context): Extension<AuthContext>,
     Path(token_id): Path<String>,
 ) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
     // SECURITY: Validate token ID length
-    validate_string_length(&token_id, MAX_TOKEN_ID_LENGTH, "Token ID")?;
+    validate_string_length(&token_id, MAX_TOKEN_ID_LENGTH, "token_id", "Token ID")?;
 
     let user_id = auth_context.user_id();
 
@@ -417,6 +1653,21 @@ pub async fn revoke_token(
         "Revoking API token"
     );
 
+    // A tenant-scoped caller can only revoke its tenant's tokens
+    if let Some(tenant) = auth_context.tenant() {
+        let same_tenant = state
+            .api_token_store
+            .list_tokens(user_id)
+            .map_err(|e| LoraDbError::AuthError(format!("Failed to revoke token: {}", e)))?
+            .iter()
+            .any(|t| t.id == token_id && t.tenant_id.as_deref() == Some(tenant));
+        if !same_tenant {
+            return Err(LoraDbError::AuthError(
+                "Failed to revoke token: Token not found".to_string(),
+            ));
+        }
+    }
+
     state
         .api_token_store
         .revoke_token(&token_id, user_id)
@@ -432,6 +1683,9 @@ pub async fn revoke_token(
 pub struct RetentionPolicyListResponse {
     pub global_days: Option<u32>,
     pub check_interval_hours: u64,
+    pub max_total_bytes: Option<u64>,
+    /// Given to applications without a policy when they first send data
+    pub default_application_policy: Option<DefaultApplicationPolicy>,
     pub applications: Vec<ApplicationRetentionPolicy>,
 }
 
@@ -446,6 +1700,13 @@ pub struct ApplicationRetentionPolicy {
 #[derive(Debug, Serialize)]
 pub struct GlobalRetentionResponse {
     pub global_days: Option<u32>,
+    pub max_total_bytes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRetentionSizeRequest {
+    /// Combined SSTable size budget in bytes (null = no size limit)
+    pub max_total_bytes: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -470,7 +1731,9 @@ pub struct ApplicationRetentionResponse {
 pub async fn list_retention_policies(
     State(state): State<AppState>,
     Extension(_auth_context): Extension<AuthContext>,
-) -> Result<Json<RetentionPolicyListResponse>, LoraDbError> {
+    Query(params): Query<FormatParams>,
+) -> Result<Formatted<RetentionPolicyListResponse>, LoraDbError> {
+    let format = params.response_format()?;
     let retention_manager = state.storage.retention_manager();
     let policies = retention_manager.get_policies().await;
 
@@ -485,11 +1748,16 @@ pub async fn list_retention_policies(
         })
         .collect();
 
-    Ok(Json(RetentionPolicyListResponse {
-        global_days: policies.global_days,
-        check_interval_hours: policies.check_interval_hours,
-        applications,
-    }))
+    Ok(Formatted {
+        format,
+        body: RetentionPolicyListResponse {
+            global_days: policies.global_days,
+            check_interval_hours: policies.check_interval_hours,
+            max_total_bytes: policies.max_total_bytes,
+            default_application_policy: policies.default_application_policy,
+            applications,
+        },
+    })
 }
 
 /// Get global retention policy
@@ -499,16 +1767,102 @@ pub async fn get_global_retention(
 ) -> Result<Json<GlobalRetentionResponse>, LoraDbError> {
     let retention_manager = state.storage.retention_manager();
     let global_days = retention_manager.get_global().await;
+    let max_total_bytes = retention_manager.get_max_total_bytes().await;
 
-    Ok(Json(GlobalRetentionResponse { global_days }))
+    Ok(Json(GlobalRetentionResponse {
+        global_days,
+        max_total_bytes,
+    }))
 }
 
-/// Set global retention policy
-pub async fn set_global_retention(
+/// Set the combined SSTable size budget enforced alongside age-based retention
+pub async fn set_retention_size(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetRetentionSizeRequest>,
+) -> Result<Json<GlobalRetentionResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    if request.max_total_bytes == Some(0) {
+        return Err(LoraDbError::QueryParseError(
+            "max_total_bytes must be greater than 0 (use null for no limit)".to_string(),
+        ));
+    }
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        max_total_bytes = ?request.max_total_bytes,
+        "Setting retention size budget"
+    );
+
+    let retention_manager = state.storage.retention_manager();
+    retention_manager
+        .set_max_total_bytes(request.max_total_bytes)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to set retention policy: {}", e)))?;
+
+    Ok(Json(GlobalRetentionResponse {
+        global_days: retention_manager.get_global().await,
+        max_total_bytes: request.max_total_bytes,
+    }))
+}
+
+/// Set the policy copied to applications without one when they first send data,
+/// body `{"days": 30}` or `{"days": null}` for keep forever
+pub async fn set_default_application_retention(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetApplicationRetentionRequest>,
+) -> Result<Json<DefaultApplicationPolicy>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        days = ?request.days,
+        "Setting default application retention policy"
+    );
+
+    let policy = DefaultApplicationPolicy { days: request.days };
+    state
+        .storage
+        .retention_manager()
+        .set_default_application_policy(Some(policy))
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to set retention policy: {}", e)))?;
+
+    Ok(Json(policy))
+}
+
+/// Stop giving new applications a policy, they use the global policy again
+pub async fn delete_default_application_retention(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        "Removing default application retention policy"
+    );
+
+    state
+        .storage
+        .retention_manager()
+        .set_default_application_policy(None)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to set retention policy: {}", e)))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Set global retention policy
+pub async fn set_global_retention(
     State(state): State<AppState>,
     Extension(auth_context): Extension<AuthContext>,
     Json(request): Json<SetGlobalRetentionRequest>,
 ) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
     let user_id = auth_context.user_id();
 
     tracing::info!(
@@ -533,7 +1887,7 @@ pub async fn get_application_retention(
     Path(app_id): Path<String>,
 ) -> Result<Json<ApplicationRetentionResponse>, LoraDbError> {
     // SECURITY: Validate app_id string length
-    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "Application ID")?;
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
 
     let retention_manager = state.storage.retention_manager();
 
@@ -559,8 +1913,10 @@ pub async fn set_application_retention(
     Extension(auth_context): Extension<AuthContext>,
     Json(request): Json<SetApplicationRetentionRequest>,
 ) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
     // SECURITY: Validate app_id string length
-    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "Application ID")?;
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
 
     let user_id = auth_context.user_id();
 
@@ -586,8 +1942,10 @@ pub async fn delete_application_retention(
     Path(app_id): Path<String>,
     Extension(auth_context): Extension<AuthContext>,
 ) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
     // SECURITY: Validate app_id string length
-    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "Application ID")?;
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
 
     let user_id = auth_context.user_id();
 
@@ -618,6 +1976,8 @@ pub async fn enforce_retention(
     State(state): State<AppState>,
     Extension(auth_context): Extension<AuthContext>,
 ) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
     let user_id = auth_context.user_id();
 
     tracing::info!(
@@ -634,174 +1994,2542 @@ pub async fn enforce_retention(
     Ok(StatusCode::OK)
 }
 
-/// Ingest ChirpStack webhook event
-pub async fn ingest_chirpstack(
+// ===== Application Schema Handlers =====
+
+#[derive(Debug, Serialize)]
+pub struct ApplicationSchemaResponse {
+    pub application_id: String,
+    pub required_fields: Vec<RequiredField>,
+    pub mode: SchemaMode,
+    pub violations: u64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchemaListResponse {
+    pub total: usize,
+    pub schemas: Vec<ApplicationSchemaResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetSchemaRequest {
+    pub required_fields: Vec<RequiredField>,
+    #[serde(default)]
+    pub mode: SchemaMode,
+}
+
+/// List all application schemas
+pub async fn list_schemas(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+) -> Json<SchemaListResponse> {
+    let schema_manager = state.storage.schema_manager();
+    let schemas: Vec<ApplicationSchemaResponse> = schema_manager
+        .list()
+        .await
+        .into_iter()
+        .map(|(app_id, schema)| ApplicationSchemaResponse {
+            violations: schema_manager.violation_count(&app_id),
+            application_id: app_id,
+            required_fields: schema.required_fields,
+            mode: schema.mode,
+            created_at: schema.created_at.to_rfc3339(),
+            updated_at: schema.updated_at.to_rfc3339(),
+        })
+        .collect();
+
+    Json(SchemaListResponse {
+        total: schemas.len(),
+        schemas,
+    })
+}
+
+/// Get the schema for an application
+pub async fn get_schema(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+    Path(app_id): Path<String>,
+) -> Result<Json<ApplicationSchemaResponse>, LoraDbError> {
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    let schema_manager = state.storage.schema_manager();
+
+    if let Some(schema) = schema_manager.get(&app_id).await {
+        Ok(Json(ApplicationSchemaResponse {
+            violations: schema_manager.violation_count(&app_id),
+            application_id: app_id,
+            required_fields: schema.required_fields,
+            mode: schema.mode,
+            created_at: schema.created_at.to_rfc3339(),
+            updated_at: schema.updated_at.to_rfc3339(),
+        }))
+    } else {
+        Err(LoraDbError::StorageError(format!(
+            "No schema found for application '{}'",
+            app_id
+        )))
+    }
+}
+
+/// Create or replace the schema for an application
+pub async fn set_schema(
     State(state): State<AppState>,
+    Path(app_id): Path<String>,
     Extension(auth_context): Extension<AuthContext>,
-    Query(query): Query<IngestQuery>,
-    payload: Bytes,
-) -> Result<Json<IngestResponse>, LoraDbError> {
-    // SECURITY: Validate payload size (1MB max)
-    if payload.len() > MAX_PAYLOAD_SIZE {
-        return Err(LoraDbError::MqttParseError(
-            format!("Payload exceeds maximum size of {} bytes", MAX_PAYLOAD_SIZE)
-        ));
+    Json(request): Json<SetSchemaRequest>,
+) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate app_id and field paths to prevent memory exhaustion
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+    if request.required_fields.len() > MAX_SCHEMA_FIELDS {
+        return Err(LoraDbError::QueryParseError(format!(
+            "Too many required fields (max: {}, got: {})",
+            MAX_SCHEMA_FIELDS,
+            request.required_fields.len()
+        )));
+    }
+    for field in &request.required_fields {
+        validate_string_length(&field.path, MAX_FIELD_PATH_LENGTH, "required_fields", "Field path")?;
+        if field.path.is_empty() {
+            return Err(LoraDbError::QueryParseError(
+                "Field path must not be empty".to_string(),
+            ));
+        }
     }
 
-    // Log ingestion attempt with user_id for audit trail
     let user_id = auth_context.user_id();
+
     tracing::info!(
         user = user_id,
-        event_type = query.event,
-        payload_size = payload.len(),
-        "Received ChirpStack webhook event"
+        application_id = app_id,
+        fields = request.required_fields.len(),
+        mode = ?request.mode,
+        "Setting application schema"
     );
 
-    // Create parser
-    let parser = ChirpStackParser::new();
+    state
+        .storage
+        .schema_manager()
+        .set(app_id, request.required_fields, request.mode)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to set schema: {}", e)))?;
 
-    // Parse based on event type
-    let frame = match query.event.as_str() {
-        "up" => parser.parse_uplink(&payload)
-            .map_err(|e| LoraDbError::MqttParseError(format!("Failed to parse uplink: {}", e)))?,
-        "join" => parser.parse_join(&payload)
-            .map_err(|e| LoraDbError::MqttParseError(format!("Failed to parse join: {}", e)))?,
-        "status" => parser.parse_status(&payload)
-            .map_err(|e| LoraDbError::MqttParseError(format!("Failed to parse status: {}", e)))?,
-        other => {
-            tracing::warn!(event_type = other, "Unsupported event type");
-            return Err(LoraDbError::QueryParseError(
-                format!("Unsupported event type: {}. Supported: up, join, status", other)
-            ));
-        }
-    };
+    Ok(StatusCode::OK)
+}
 
-    let dev_eui = frame.dev_eui().to_string();
+/// Delete the schema for an application
+pub async fn delete_schema(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
 
-    // Write directly to storage (async, no channel needed)
-    state.storage.write(frame).await
-        .map_err(|e| LoraDbError::StorageError(format!("Failed to write frame: {}", e)))?;
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    let user_id = auth_context.user_id();
 
     tracing::info!(
         user = user_id,
-        event_type = query.event,
-        dev_eui = dev_eui,
-        "Successfully ingested event"
+        application_id = app_id,
+        "Deleting application schema"
     );
 
-    Ok(Json(IngestResponse {
-        success: true,
-        dev_eui,
-        event_type: query.event,
-    }))
+    let removed = state
+        .storage
+        .schema_manager()
+        .remove(&app_id)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to delete schema: {}", e)))?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(LoraDbError::StorageError(format!(
+            "No schema found for application '{}'",
+            app_id
+        )))
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
-    use super::*;
-    use crate::config::StorageConfig;
-    use crate::model::frames::UplinkFrame;
-    use crate::model::lorawan::*;
-    use crate::security::jwt::Claims;
-    use chrono::Utc;
-    use tempfile::TempDir;
+// ===== Admin Handlers =====
 
-    async fn create_test_state() -> AppState {
-        let temp_dir = TempDir::new().unwrap();
-        let config = StorageConfig {
-            data_dir: temp_dir.path().to_path_buf(),
-            wal_sync_interval_ms: 1000,
-            memtable_size_mb: 1,
-            memtable_flush_interval_secs: 300,
-            compaction_threshold: 3,
-            enable_encryption: false,
-            encryption_key: None,
-            retention_days: None,
-            retention_apps: HashMap::new(),
-            retention_check_interval_hours: 24,
-        };
+/// Get the WAL recovery report from the last startup
+pub async fn get_recovery_report(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+) -> Json<ReplayReport> {
+    Json(state.storage.recovery_report())
+}
 
-        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
-        let query_executor = Arc::new(QueryExecutor::new(storage.clone()));
-        let query_parser = Arc::new(QueryParser::new());
-        let api_token_store = Arc::new(
-            ApiTokenStore::new(temp_dir.path().join("tokens.json")).unwrap(),
-        );
+/// SSTable listing response
+#[derive(Debug, Serialize)]
+pub struct SSTableListResponse {
+    pub total_sstables: usize,
+    pub sstables: Vec<SSTableStats>,
+}
 
-        AppState {
-            storage,
-            query_executor,
-            query_parser,
-            api_token_store,
-        }
+/// List live SSTables with their size and bloom filter saturation
+pub async fn list_sstables(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<Json<SSTableListResponse>, LoraDbError> {
+    // Exposes the physical storage layout, including every application ID
+    if !auth_context.is_admin() {
+        return Err(LoraDbError::Forbidden(
+            "Listing SSTables requires the admin role".to_string(),
+        ));
     }
 
-    fn create_test_uplink(dev_eui: &str) -> crate::model::frames::Frame {
-        crate::model::frames::Frame::Uplink(UplinkFrame {
-            dev_eui: DevEui::new(dev_eui.to_string()).unwrap(),
-            application_id: ApplicationId::new("test-app".to_string()),
-            device_name: Some("test-device".to_string()),
-            received_at: Utc::now(),
-            f_port: 1,
-            f_cnt: 42,
-            confirmed: false,
-            adr: true,
-            dr: DataRate::new_lora(125000, 7),
-            frequency: 868100000,
-            rx_info: vec![],
-            decoded_payload: None,
-            raw_payload: Some("aGVsbG8=".to_string()),
-        })
-    }
+    let sstables = state.storage.sstable_stats();
+    Ok(Json(SSTableListResponse {
+        total_sstables: sstables.len(),
+        sstables,
+    }))
+}
 
-    #[tokio::test]
-    async fn test_health_check() {
-        let response = health_check().await;
-        assert_eq!(response.0.status, "ok");
+#[derive(Debug, Serialize)]
+pub struct FlushResponse {
+    #[serde(flatten)]
+    pub summary: FlushSummary,
+    /// Why nothing was flushed, if it wasn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Flush the memtable to SSTables now, e.g. before a backup or to test recovery
+pub async fn flush_memtable(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<Json<FlushResponse>, LoraDbError> {
+    if !auth_context.is_admin() {
+        return Err(LoraDbError::Forbidden(
+            "Flushing the memtable requires the admin role".to_string(),
+        ));
     }
+    state.storage.ensure_writable()?;
 
-    #[tokio::test]
-    async fn test_execute_query() {
-        let state = create_test_state().await;
-        let claims = Claims::new("test-user".to_string());
-        let auth_context = AuthContext::Jwt(claims);
+    tracing::info!(user = auth_context.user_id(), "Flushing memtable on request");
 
-        // Write a test frame
-        let dev_eui = "0123456789ABCDEF";
-        let frame = create_test_uplink(dev_eui);
-        state.storage.write(frame).await.unwrap();
+    let summary = state
+        .storage
+        .flush()
+        .await
+        .map_err(|e| match e.downcast::<LoraDbError>() {
+            Ok(e) => e,
+            Err(e) => LoraDbError::StorageError(format!("Failed to flush memtable: {}", e)),
+        })?;
 
-        // Execute query
-        let request = QueryRequest {
-            query: format!("SELECT * FROM device '{}' WHERE LAST '1h'", dev_eui),
-        };
+    let note = if state.storage.is_in_memory() {
+        Some("Running in memory, frames are never flushed to SSTables".to_string())
+    } else if summary.entries == 0 {
+        Some("Memtable is empty, nothing to flush".to_string())
+    } else {
+        None
+    };
 
-        let result = execute_query(
-            State(state),
-            Extension(auth_context),
-            Json(request),
-        )
-        .await
-        .unwrap();
+    Ok(Json(FlushResponse { summary, note }))
+}
 
-        assert_eq!(result.0.total_frames, 1);
-    }
+/// Background task intervals, adjustable at runtime
+#[derive(Debug, Serialize)]
+pub struct IntervalConfigResponse {
+    /// Seconds between periodic memtable flushes
+    pub flush_interval_secs: u64,
+    /// Hours between retention policy checks
+    pub retention_check_interval_hours: u64,
+}
 
-    #[tokio::test]
-    async fn test_list_devices() {
-        let state = create_test_state().await;
-        let claims = Claims::new("test-user".to_string());
-        let auth_context = AuthContext::Jwt(claims);
+#[derive(Debug, Deserialize)]
+pub struct SetFlushIntervalRequest {
+    pub seconds: u64,
+}
 
-        // Write test frames for different devices
+#[derive(Debug, Deserialize)]
+pub struct SetRetentionIntervalRequest {
+    pub hours: u64,
+}
+
+async fn interval_config(state: &AppState) -> IntervalConfigResponse {
+    IntervalConfigResponse {
+        flush_interval_secs: state.storage.flush_interval_secs(),
+        retention_check_interval_hours: state.storage.retention_check_interval_hours().await,
+    }
+}
+
+/// Query string parameters for `POST /admin/selftest`
+#[derive(Debug, Deserialize)]
+pub struct SelfTestQuery {
+    /// Delete the canary's frames afterwards
+    #[serde(default)]
+    pub cleanup: bool,
+}
+
+/// Write a canary frame and read it back, exercising WAL, memtable and query together
+/// Responds 503 when the canary isn't read back, so it can be used as a probe
+pub async fn run_selftest(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Query(params): Query<SelfTestQuery>,
+) -> Result<Response, LoraDbError> {
+    if !auth_context.is_admin() {
+        return Err(LoraDbError::Forbidden(
+            "Running the self-test requires the admin role".to_string(),
+        ));
+    }
+    state.storage.ensure_writable()?;
+
+    let report = state
+        .storage
+        .self_test(params.cleanup)
+        .await
+        .map_err(|e| match e.downcast::<LoraDbError>() {
+            Ok(e) => e,
+            Err(e) => LoraDbError::StorageError(format!("Self-test failed: {}", e)),
+        })?;
+
+    let status = if report.success {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok((status, Json::<SelfTestReport>(report)).into_response())
+}
+
+/// Get the current background task intervals
+pub async fn get_interval_config(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+) -> Json<IntervalConfigResponse> {
+    Json(interval_config(&state).await)
+}
+
+/// Change the periodic memtable flush interval without a restart
+/// Not persisted, `LORADB_STORAGE_MEMTABLE_FLUSH_INTERVAL_SECS` applies again after a restart
+pub async fn set_flush_interval(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetFlushIntervalRequest>,
+) -> Result<Json<IntervalConfigResponse>, LoraDbError> {
+    if !auth_context.is_admin() {
+        return Err(LoraDbError::Forbidden(
+            "Changing the flush interval requires the admin role".to_string(),
+        ));
+    }
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        seconds = request.seconds,
+        "Setting periodic flush interval"
+    );
+
+    state.storage.set_flush_interval_secs(request.seconds)?;
+
+    Ok(Json(interval_config(&state).await))
+}
+
+/// Change the retention check interval without a restart
+/// Saved with the retention policies, so it survives restarts
+pub async fn set_retention_interval(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetRetentionIntervalRequest>,
+) -> Result<Json<IntervalConfigResponse>, LoraDbError> {
+    if !auth_context.is_admin() {
+        return Err(LoraDbError::Forbidden(
+            "Changing the retention check interval requires the admin role".to_string(),
+        ));
+    }
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        hours = request.hours,
+        "Setting retention check interval"
+    );
+
+    state
+        .storage
+        .set_retention_check_interval_hours(request.hours)
+        .await
+        .map_err(|e| match e.downcast::<LoraDbError>() {
+            Ok(e) => e,
+            Err(e) => LoraDbError::StorageError(format!(
+                "Failed to set retention check interval: {}",
+                e
+            )),
+        })?;
+
+    Ok(Json(interval_config(&state).await))
+}
+
+/// Runtime statistics response
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    /// Time between a frame's network timestamp and it being stored
+    pub ingestion_lag: IngestionLagSnapshot,
+    /// Frames stored per second over recent windows
+    pub ingest_rate: IngestRateSnapshot,
+    /// Frames rejected or clamped for timestamps outside the clock skew tolerance
+    pub clock_skew: ClockSkewStats,
+    /// Frames without an application ID, rejected or stored under the fallback application
+    pub unknown_application: UnknownApplicationStats,
+    /// Frames whose decoded payload was too deep or too large
+    pub oversized_payload: OversizedPayloadStats,
+    /// Query results served from the cache instead of a scan
+    pub query_cache: QueryCacheStats,
+    /// Highest SSTable fragmentation score of any directory, from 0 to 1
+    pub fragmentation_score: f64,
+    /// Connection state of each MQTT broker, empty without MQTT ingestion
+    pub mqtt: Vec<BrokerStatus>,
+}
+
+/// Get runtime statistics
+pub async fn get_stats(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+) -> Json<StatsResponse> {
+    Json(StatsResponse {
+        ingestion_lag: state.storage.ingestion_lag().snapshot(),
+        ingest_rate: state.storage.ingest_rate().snapshot(),
+        clock_skew: state.storage.clock_skew_stats(),
+        unknown_application: state.storage.unknown_application_stats(),
+        oversized_payload: state.storage.oversized_payload_stats(),
+        query_cache: state.query_executor.cache_stats(),
+        fragmentation_score: state.storage.fragmentation_score(),
+        mqtt: state.mqtt_status.as_ref().map(MqttStatus::brokers).unwrap_or_default(),
+    })
+}
+
+// ===== Application Ingestion Handlers =====
+
+#[derive(Debug, Serialize)]
+pub struct IngestionStatusResponse {
+    pub application_id: String,
+    pub enabled: bool,
+    pub disabled_at: Option<String>,
+    pub dropped_frames: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetIngestionRequest {
+    pub enabled: bool,
+}
+
+/// Get the ingestion status for an application
+pub async fn get_application_ingestion(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+    Path(app_id): Path<String>,
+) -> Result<Json<IngestionStatusResponse>, LoraDbError> {
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    let ingestion_manager = state.storage.ingestion_manager();
+    let disabled_at = ingestion_manager.disabled_since(&app_id);
+
+    Ok(Json(IngestionStatusResponse {
+        enabled: disabled_at.is_none(),
+        disabled_at: disabled_at.map(|dt| dt.to_rfc3339()),
+        dropped_frames: ingestion_manager.dropped_count(&app_id),
+        application_id: app_id,
+    }))
+}
+
+/// Enable or disable ingestion for an application
+pub async fn set_application_ingestion(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetIngestionRequest>,
+) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    let user_id = auth_context.user_id();
+
+    tracing::info!(
+        user = user_id,
+        application_id = app_id,
+        enabled = request.enabled,
+        "Updating application ingestion"
+    );
+
+    state
+        .storage
+        .ingestion_manager()
+        .set_enabled(&app_id, request.enabled)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to update ingestion: {}", e)))?;
+
+    Ok(StatusCode::OK)
+}
+
+// ===== Application Quota Handlers =====
+
+#[derive(Debug, Serialize)]
+pub struct QuotaStatusResponse {
+    pub application_id: String,
+    pub max_frames_per_day: Option<u64>,
+    pub max_bytes_per_day: Option<u64>,
+    /// UTC day the usage counters cover
+    pub period_start: String,
+    pub frames_used: u64,
+    pub bytes_used: u64,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetQuotaRequest {
+    pub max_frames_per_day: Option<u64>,
+    pub max_bytes_per_day: Option<u64>,
+}
+
+/// Get the daily ingest quota and current usage for an application
+pub async fn get_application_quota(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+    Path(app_id): Path<String>,
+) -> Result<Json<QuotaStatusResponse>, LoraDbError> {
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    let quota = state.storage.quota_manager().get(&app_id).ok_or_else(|| {
+        LoraDbError::NotFound(format!("No quota set for application '{}'", app_id))
+    })?;
+
+    Ok(Json(QuotaStatusResponse {
+        application_id: app_id,
+        max_frames_per_day: quota.max_frames_per_day,
+        max_bytes_per_day: quota.max_bytes_per_day,
+        period_start: quota.usage.period_start.to_string(),
+        frames_used: quota.usage.frames,
+        bytes_used: quota.usage.bytes,
+        updated_at: quota.updated_at.to_rfc3339(),
+    }))
+}
+
+/// Set the daily ingest quota for an application
+pub async fn set_application_quota(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetQuotaRequest>,
+) -> Result<Json<QuotaStatusResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    if request.max_frames_per_day.is_none() && request.max_bytes_per_day.is_none() {
+        return Err(LoraDbError::QueryParseError(
+            "Set max_frames_per_day and/or max_bytes_per_day, or DELETE the quota to remove it"
+                .to_string(),
+        ));
+    }
+
+    let user_id = auth_context.user_id();
+
+    tracing::info!(
+        user = user_id,
+        application_id = app_id,
+        max_frames_per_day = request.max_frames_per_day,
+        max_bytes_per_day = request.max_bytes_per_day,
+        "Updating application quota"
+    );
+
+    state
+        .storage
+        .quota_manager()
+        .set(&app_id, request.max_frames_per_day, request.max_bytes_per_day)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to update quota: {}", e)))?;
+
+    get_application_quota(State(state), Extension(auth_context), Path(app_id)).await
+}
+
+/// Remove the daily ingest quota for an application
+pub async fn delete_application_quota(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    let user_id = auth_context.user_id();
+
+    tracing::info!(
+        user = user_id,
+        application_id = app_id,
+        "Deleting application quota"
+    );
+
+    let removed = state
+        .storage
+        .quota_manager()
+        .remove(&app_id)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to delete quota: {}", e)))?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(LoraDbError::NotFound(format!(
+            "No quota set for application '{}'",
+            app_id
+        )))
+    }
+}
+
+// ===== Sampling Rule Handlers =====
+
+#[derive(Debug, Serialize)]
+pub struct SamplingRuleResponse {
+    pub scope: SamplingScope,
+    /// Application ID or DevEUI, normalized to lowercase
+    pub id: String,
+    pub keep_one_in: Option<u32>,
+    pub min_interval_secs: Option<u64>,
+    /// Uplinks this rule has dropped since startup
+    pub dropped_frames: u64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SamplingRuleListResponse {
+    pub total_rules: usize,
+    pub rules: Vec<SamplingRuleResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetSamplingRuleRequest {
+    pub keep_one_in: Option<u32>,
+    pub min_interval_secs: Option<u64>,
+}
+
+fn scope_name(scope: SamplingScope) -> &'static str {
+    match scope {
+        SamplingScope::Application => "application",
+        SamplingScope::Device => "device",
+    }
+}
+
+/// Validate and normalize the ID of a sampling rule target
+fn sampling_target(scope: SamplingScope, id: &str) -> Result<String, LoraDbError> {
+    match scope {
+        SamplingScope::Application => {
+            // SECURITY: Validate app_id string length
+            validate_string_length(id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+            Ok(crate::model::lorawan::ApplicationId::normalize(id))
+        }
+        SamplingScope::Device => {
+            // SECURITY: Validate dev_eui string length
+            validate_string_length(id, MAX_DEV_EUI_LENGTH, "dev_eui", "DevEUI")?;
+            Ok(crate::model::lorawan::DevEui::new(id.to_string())?.normalized())
+        }
+    }
+}
+
+fn sampling_rule_response(
+    state: &AppState,
+    scope: SamplingScope,
+    id: &str,
+) -> Result<SamplingRuleResponse, LoraDbError> {
+    let sampling = state.storage.sampling_manager();
+    let rule = sampling.get(scope, id).ok_or_else(|| {
+        LoraDbError::NotFound(format!("No sampling rule set for {} '{}'", scope_name(scope), id))
+    })?;
+
+    Ok(SamplingRuleResponse {
+        scope,
+        id: id.to_string(),
+        keep_one_in: rule.keep_one_in,
+        min_interval_secs: rule.min_interval_secs,
+        dropped_frames: sampling.dropped_count(scope, id),
+        created_at: rule.created_at.to_rfc3339(),
+        updated_at: rule.updated_at.to_rfc3339(),
+    })
+}
+
+async fn set_sampling_rule(
+    state: &AppState,
+    auth_context: &AuthContext,
+    scope: SamplingScope,
+    id: &str,
+    request: SetSamplingRuleRequest,
+) -> Result<Json<SamplingRuleResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
+    let id = sampling_target(scope, id)?;
+
+    if request.keep_one_in.is_none() && request.min_interval_secs.is_none() {
+        return Err(LoraDbError::QueryParseError(
+            "Set keep_one_in and/or min_interval_secs, or DELETE the rule to remove it".to_string(),
+        ));
+    }
+    if request.keep_one_in == Some(0) || request.min_interval_secs == Some(0) {
+        return Err(LoraDbError::QueryParseError(
+            "keep_one_in and min_interval_secs must be greater than 0".to_string(),
+        ));
+    }
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        scope = scope_name(scope),
+        id = id,
+        keep_one_in = request.keep_one_in,
+        min_interval_secs = request.min_interval_secs,
+        "Updating sampling rule"
+    );
+
+    state
+        .storage
+        .sampling_manager()
+        .set(scope, &id, request.keep_one_in, request.min_interval_secs)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to update sampling rule: {}", e)))?;
+
+    sampling_rule_response(state, scope, &id).map(Json)
+}
+
+async fn delete_sampling_rule(
+    state: &AppState,
+    auth_context: &AuthContext,
+    scope: SamplingScope,
+    id: &str,
+) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+    let id = sampling_target(scope, id)?;
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        scope = scope_name(scope),
+        id = id,
+        "Deleting sampling rule"
+    );
+
+    let removed = state
+        .storage
+        .sampling_manager()
+        .remove(scope, &id)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to delete sampling rule: {}", e)))?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(LoraDbError::NotFound(format!(
+            "No sampling rule set for {} '{}'",
+            scope_name(scope),
+            id
+        )))
+    }
+}
+
+/// List all sampling rules with their dropped frame counts
+pub async fn list_sampling_rules(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+) -> Json<SamplingRuleListResponse> {
+    let sampling = state.storage.sampling_manager();
+    let rules: Vec<SamplingRuleResponse> = sampling
+        .list()
+        .into_iter()
+        .map(|(scope, id, rule)| SamplingRuleResponse {
+            scope,
+            dropped_frames: sampling.dropped_count(scope, &id),
+            id,
+            keep_one_in: rule.keep_one_in,
+            min_interval_secs: rule.min_interval_secs,
+            created_at: rule.created_at.to_rfc3339(),
+            updated_at: rule.updated_at.to_rfc3339(),
+        })
+        .collect();
+
+    Json(SamplingRuleListResponse {
+        total_rules: rules.len(),
+        rules,
+    })
+}
+
+/// Get the uplink sampling rule for an application
+pub async fn get_application_sampling(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+    Path(app_id): Path<String>,
+) -> Result<Json<SamplingRuleResponse>, LoraDbError> {
+    let id = sampling_target(SamplingScope::Application, &app_id)?;
+    sampling_rule_response(&state, SamplingScope::Application, &id).map(Json)
+}
+
+/// Set the uplink sampling rule for an application
+pub async fn set_application_sampling(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetSamplingRuleRequest>,
+) -> Result<Json<SamplingRuleResponse>, LoraDbError> {
+    set_sampling_rule(&state, &auth_context, SamplingScope::Application, &app_id, request).await
+}
+
+/// Remove the uplink sampling rule for an application
+pub async fn delete_application_sampling(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<StatusCode, LoraDbError> {
+    delete_sampling_rule(&state, &auth_context, SamplingScope::Application, &app_id).await
+}
+
+/// Get the uplink sampling rule for a device
+pub async fn get_device_sampling(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+    Path(dev_eui): Path<String>,
+) -> Result<Json<SamplingRuleResponse>, LoraDbError> {
+    let id = sampling_target(SamplingScope::Device, &dev_eui)?;
+    sampling_rule_response(&state, SamplingScope::Device, &id).map(Json)
+}
+
+/// Set the uplink sampling rule for a device, replacing its application's rule
+pub async fn set_device_sampling(
+    State(state): State<AppState>,
+    Path(dev_eui): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetSamplingRuleRequest>,
+) -> Result<Json<SamplingRuleResponse>, LoraDbError> {
+    set_sampling_rule(&state, &auth_context, SamplingScope::Device, &dev_eui, request).await
+}
+
+/// Remove the uplink sampling rule for a device
+pub async fn delete_device_sampling(
+    State(state): State<AppState>,
+    Path(dev_eui): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<StatusCode, LoraDbError> {
+    delete_sampling_rule(&state, &auth_context, SamplingScope::Device, &dev_eui).await
+}
+
+// ===== Payload Profile Handlers =====
+
+#[derive(Debug, Serialize)]
+pub struct PayloadProfileResponse {
+    /// ChirpStack device profile ID or name
+    pub profile: String,
+    /// Vendor field path -> canonical field name
+    pub fields: BTreeMap<String, String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PayloadProfileListResponse {
+    pub total: usize,
+    pub profiles: Vec<PayloadProfileResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetPayloadProfileRequest {
+    pub fields: BTreeMap<String, String>,
+}
+
+impl PayloadProfileResponse {
+    fn new(profile: String, payload_profile: PayloadProfile) -> Self {
+        Self {
+            profile,
+            fields: payload_profile.fields,
+            created_at: payload_profile.created_at.to_rfc3339(),
+            updated_at: payload_profile.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// List all decoded payload profiles
+pub async fn list_payload_profiles(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+) -> Json<PayloadProfileListResponse> {
+    let profiles: Vec<PayloadProfileResponse> = state
+        .storage
+        .payload_profiles()
+        .list()
+        .into_iter()
+        .map(|(profile, payload_profile)| PayloadProfileResponse::new(profile, payload_profile))
+        .collect();
+
+    Json(PayloadProfileListResponse {
+        total: profiles.len(),
+        profiles,
+    })
+}
+
+/// Get the field mapping for a device profile
+pub async fn get_payload_profile(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+    Path(profile): Path<String>,
+) -> Result<Json<PayloadProfileResponse>, LoraDbError> {
+    // SECURITY: Validate profile string length
+    validate_string_length(&profile, MAX_DEVICE_PROFILE_LENGTH, "profile", "Device profile")?;
+
+    match state.storage.payload_profiles().get(&profile) {
+        Some(payload_profile) => Ok(Json(PayloadProfileResponse::new(profile, payload_profile))),
+        None => Err(LoraDbError::NotFound(format!(
+            "No payload profile set for device profile '{}'",
+            profile
+        ))),
+    }
+}
+
+/// Create or replace the field mapping for a device profile
+pub async fn set_payload_profile(
+    State(state): State<AppState>,
+    Path(profile): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetPayloadProfileRequest>,
+) -> Result<Json<PayloadProfileResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate profile and field names to prevent memory exhaustion
+    validate_string_length(&profile, MAX_DEVICE_PROFILE_LENGTH, "profile", "Device profile")?;
+    if profile.trim().is_empty() {
+        return Err(LoraDbError::QueryParseError(
+            "Device profile must not be empty".to_string(),
+        ));
+    }
+    if request.fields.len() > MAX_SCHEMA_FIELDS {
+        return Err(LoraDbError::QueryParseError(format!(
+            "Too many field mappings (max: {}, got: {})",
+            MAX_SCHEMA_FIELDS,
+            request.fields.len()
+        )));
+    }
+    for (vendor, canonical) in &request.fields {
+        validate_string_length(vendor, MAX_FIELD_PATH_LENGTH, "fields", "Vendor field")?;
+        validate_string_length(canonical, MAX_FIELD_PATH_LENGTH, "fields", "Canonical field")?;
+        if vendor.is_empty() || canonical.is_empty() {
+            return Err(LoraDbError::QueryParseError(
+                "Field names must not be empty".to_string(),
+            ));
+        }
+        // Canonical fields are added at the top level of the decoded payload
+        if canonical.contains('.') {
+            return Err(LoraDbError::QueryParseError(format!(
+                "Canonical field '{}' must be a top-level name without '.'",
+                canonical
+            )));
+        }
+    }
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        profile = profile,
+        fields = request.fields.len(),
+        "Setting payload profile"
+    );
+
+    let payload_profiles = state.storage.payload_profiles();
+    payload_profiles
+        .set(&profile, request.fields)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to set payload profile: {}", e)))?;
+
+    let profile = profile.trim().to_string();
+    let payload_profile = payload_profiles
+        .get(&profile)
+        .ok_or_else(|| LoraDbError::StorageError("Payload profile vanished after set".to_string()))?;
+    Ok(Json(PayloadProfileResponse::new(profile, payload_profile)))
+}
+
+/// Delete the field mapping for a device profile
+pub async fn delete_payload_profile(
+    State(state): State<AppState>,
+    Path(profile): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate profile string length
+    validate_string_length(&profile, MAX_DEVICE_PROFILE_LENGTH, "profile", "Device profile")?;
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        profile = profile,
+        "Deleting payload profile"
+    );
+
+    let removed = state
+        .storage
+        .payload_profiles()
+        .remove(&profile)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to delete payload profile: {}", e)))?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(LoraDbError::NotFound(format!(
+            "No payload profile set for device profile '{}'",
+            profile
+        )))
+    }
+}
+
+// ===== Transform Pipeline Handlers =====
+
+#[derive(Debug, Serialize)]
+pub struct TransformPipelineResponse {
+    /// Application ID, normalized to lowercase
+    pub application_id: String,
+    pub transforms: Vec<Transform>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransformPipelineListResponse {
+    pub total: usize,
+    pub pipelines: Vec<TransformPipelineResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetTransformsRequest {
+    pub transforms: Vec<Transform>,
+}
+
+impl TransformPipelineResponse {
+    fn new(application_id: String, pipeline: TransformPipeline) -> Self {
+        Self {
+            application_id,
+            transforms: pipeline.transforms,
+            created_at: pipeline.created_at.to_rfc3339(),
+            updated_at: pipeline.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Check the field names a transform reads and writes
+fn validate_transform(transform: &Transform) -> Result<(), LoraDbError> {
+    let field = transform.field();
+    validate_string_length(field, MAX_FIELD_PATH_LENGTH, "transforms", "Transform field")?;
+    // Results are added at the top level of the decoded payload
+    if field.is_empty() || field.contains('.') {
+        return Err(LoraDbError::QueryParseError(format!(
+            "Transform field '{}' must be a non-empty top-level name without '.'",
+            field
+        )));
+    }
+
+    let inputs: Vec<&str> = match transform {
+        Transform::Arithmetic { left, right, .. } => [left, right]
+            .into_iter()
+            .filter_map(|operand| match operand {
+                Operand::Field(path) => Some(path.as_str()),
+                Operand::Number(_) => None,
+            })
+            .collect(),
+        Transform::DewPoint { temperature, humidity, .. } => vec![temperature, humidity],
+        Transform::Constant { .. } => vec![],
+        Transform::GatewaySite { sites, .. } => {
+            if sites.len() > MAX_GATEWAY_SITES {
+                return Err(LoraDbError::QueryParseError(format!(
+                    "Too many gateway sites (max: {}, got: {})",
+                    MAX_GATEWAY_SITES,
+                    sites.len()
+                )));
+            }
+            sites.keys().map(String::as_str).collect()
+        }
+    };
+    for input in inputs {
+        validate_string_length(input, MAX_FIELD_PATH_LENGTH, "transforms", "Transform input")?;
+        if input.is_empty() {
+            return Err(LoraDbError::QueryParseError(
+                "Transform inputs must not be empty".to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// List all frame transform pipelines
+pub async fn list_transforms(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+) -> Json<TransformPipelineListResponse> {
+    let pipelines: Vec<TransformPipelineResponse> = state
+        .storage
+        .transforms()
+        .list()
+        .into_iter()
+        .map(|(app_id, pipeline)| TransformPipelineResponse::new(app_id, pipeline))
+        .collect();
+
+    Json(TransformPipelineListResponse {
+        total: pipelines.len(),
+        pipelines,
+    })
+}
+
+/// Get the frame transform pipeline for an application
+pub async fn get_application_transforms(
+    State(state): State<AppState>,
+    Extension(_auth_context): Extension<AuthContext>,
+    Path(app_id): Path<String>,
+) -> Result<Json<TransformPipelineResponse>, LoraDbError> {
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    let pipeline = state.storage.transforms().get(&app_id).ok_or_else(|| {
+        LoraDbError::NotFound(format!("No transforms set for application '{}'", app_id))
+    })?;
+
+    Ok(Json(TransformPipelineResponse::new(
+        crate::model::lorawan::ApplicationId::normalize(&app_id),
+        pipeline,
+    )))
+}
+
+/// Set the frame transform pipeline for an application, replacing any previous one
+pub async fn set_application_transforms(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+    Json(request): Json<SetTransformsRequest>,
+) -> Result<Json<TransformPipelineResponse>, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate app_id and transforms to prevent memory exhaustion
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+    if request.transforms.is_empty() {
+        return Err(LoraDbError::QueryParseError(
+            "Set at least one transform, or DELETE the pipeline to remove it".to_string(),
+        ));
+    }
+    if request.transforms.len() > MAX_TRANSFORMS {
+        return Err(LoraDbError::QueryParseError(format!(
+            "Too many transforms (max: {}, got: {})",
+            MAX_TRANSFORMS,
+            request.transforms.len()
+        )));
+    }
+    for transform in &request.transforms {
+        validate_transform(transform)?;
+    }
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        application_id = app_id,
+        transforms = request.transforms.len(),
+        "Setting transform pipeline"
+    );
+
+    state
+        .storage
+        .transforms()
+        .set(&app_id, request.transforms)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to set transforms: {}", e)))?;
+
+    get_application_transforms(State(state), Extension(auth_context), Path(app_id)).await
+}
+
+/// Remove the frame transform pipeline for an application
+pub async fn delete_application_transforms(
+    State(state): State<AppState>,
+    Path(app_id): Path<String>,
+    Extension(auth_context): Extension<AuthContext>,
+) -> Result<StatusCode, LoraDbError> {
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate app_id string length
+    validate_string_length(&app_id, MAX_APP_ID_LENGTH, "app_id", "Application ID")?;
+
+    tracing::info!(
+        user = auth_context.user_id(),
+        application_id = app_id,
+        "Deleting transform pipeline"
+    );
+
+    let removed = state
+        .storage
+        .transforms()
+        .remove(&app_id)
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Failed to delete transforms: {}", e)))?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(LoraDbError::NotFound(format!(
+            "No transforms set for application '{}'",
+            app_id
+        )))
+    }
+}
+
+/// Ingest ChirpStack webhook event
+pub async fn ingest_chirpstack(
+    State(state): State<AppState>,
+    Extension(auth_context): Extension<AuthContext>,
+    Query(query): Query<IngestQuery>,
+    payload: Bytes,
+) -> Result<Json<IngestResponse>, LoraDbError> {
+    // A read-only replica rejects every write, before doing any work
+    state.storage.ensure_writable()?;
+
+    // SECURITY: Validate payload size (1MB max)
+    if payload.len() > MAX_PAYLOAD_SIZE {
+        return Err(LoraDbError::MqttParseError(
+            format!("Payload exceeds maximum size of {} bytes", MAX_PAYLOAD_SIZE)
+        ));
+    }
+
+    // An explicit event overrides detection, for integrations sending every event to one URL
+    let detected = query.event.is_none();
+    let event = match query.event {
+        Some(event) => event,
+        None => chirpstack::detect_event(&payload)
+            .ok_or_else(|| {
+                LoraDbError::QueryParseError(
+                    "Could not detect the event type from the payload, pass ?event=up|join|status"
+                        .to_string(),
+                )
+            })?
+            .to_string(),
+    };
+
+    // Log ingestion attempt with user_id for audit trail
+    let user_id = auth_context.user_id();
+    tracing::info!(
+        user = user_id,
+        event_type = event,
+        detected,
+        payload_size = payload.len(),
+        "Received ChirpStack webhook event"
+    );
+
+    // Create parser, keyed like MQTT ingest
+    let parser = ChirpStackParser::with_application_key(state.storage.application_key())
+        .with_payload_profiles(state.storage.payload_profiles().clone());
+
+    // Parse based on event type
+    let frame = {
+        let _span = tracing::info_span!("ingest.parse", event_type = %event).entered();
+        match event.as_str() {
+            "up" => parser.parse_uplink(&payload)
+                .map_err(|e| LoraDbError::MqttParseError(format!("Failed to parse uplink: {}", e)))?,
+            "join" => parser.parse_join(&payload)
+                .map_err(|e| LoraDbError::MqttParseError(format!("Failed to parse join: {}", e)))?,
+            "status" => parser.parse_status(&payload)
+                .map_err(|e| LoraDbError::MqttParseError(format!("Failed to parse status: {}", e)))?,
+            other => {
+                tracing::warn!(event_type = other, "Unsupported event type");
+                return Err(LoraDbError::QueryParseError(
+                    format!("Unsupported event type: {}. Supported: up, join, status", other)
+                ));
+            }
+        }
+    };
+
+    let dev_eui = frame.dev_eui().to_string();
+
+    // A tenant can only write into its own applications
+    if let Some(tenant) = auth_context.tenant() {
+        let app_id = frame.application_id().map(|id| id.as_str()).unwrap_or_default();
+        if !state.tenants.allows(Some(tenant), app_id) {
+            tracing::warn!(
+                user = user_id,
+                tenant,
+                application_id = app_id,
+                "Rejecting event for an application outside the tenant"
+            );
+            return Err(LoraDbError::Forbidden(format!(
+                "Application '{}' does not belong to tenant '{}'",
+                app_id, tenant
+            )));
+        }
+    }
+
+    // Acknowledge but drop frames from applications with ingestion disabled,
+    // so the network server does not keep retrying them
+    if !state.storage.ingestion_manager().admit(&frame) {
+        tracing::debug!(
+            dev_eui = dev_eui,
+            "Dropping event: application ingestion disabled"
+        );
+        return Ok(Json(IngestResponse {
+            success: false,
+            dev_eui,
+            event_type: event,
+        }));
+    }
+
+    // Write directly to storage (async, no channel needed)
+    state.storage.write(frame).await.map_err(|e| match e.downcast::<LoraDbError>() {
+        // Schema violations and exhausted quotas are the sender's fault, surface them as-is
+        Ok(err @ (LoraDbError::InvalidFrame(_) | LoraDbError::QuotaExceeded(_))) => err,
+        Ok(err) => LoraDbError::StorageError(format!("Failed to write frame: {}", err)),
+        Err(e) => LoraDbError::StorageError(format!("Failed to write frame: {}", e)),
+    })?;
+
+    tracing::info!(
+        user = user_id,
+        event_type = event,
+        dev_eui = dev_eui,
+        "Successfully ingested event"
+    );
+
+    Ok(Json(IngestResponse {
+        success: true,
+        dev_eui,
+        event_type: event,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+    use crate::config::{
+        ApplicationKey, ClockSkewAction, OversizedPayloadAction, StorageConfig, StorageLayout,
+        TimestampSource, UnknownApplicationAction, WalDurability,
+    };
+    use crate::model::frames::UplinkFrame;
+    use crate::model::lorawan::*;
+    use crate::security::jwt::Claims;
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    async fn create_test_state() -> (AppState, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            wal_durability: WalDurability::Interval(1000),
+            memtable_size_mb: 1,
+            memtable_hard_limit_factor: 0,
+            memtable_flush_interval_secs: 300,
+            compaction_threshold: 3,
+            max_open_sstables: 128,
+            warmup_sstables: 0,
+            warmup_read_data: false,
+            compaction_max_bytes_per_sec: 0,
+            compaction_fragmentation_threshold: 0.75,
+            compaction_target_sstable_entries: 10_000,
+            max_sstable_bytes: 0,
+            layout: StorageLayout::Flat,
+            max_clock_skew_secs: 300,
+            clock_skew_action: ClockSkewAction::Reject,
+            timestamp_source: TimestampSource::Network,
+            application_key: ApplicationKey::Id,
+            unknown_application_action: UnknownApplicationAction::Fallback,
+            fallback_application_id: "unknown".to_string(),
+            max_payload_depth: 16,
+            max_payload_bytes: 64 * 1024,
+            oversized_payload_action: OversizedPayloadAction::Reject,
+            selftest_dev_eui: "fffffffffffffffe".to_string(),
+            maintenance_window: None,
+            read_only: false,
+            replica_refresh_secs: 30,
+            in_memory: false,
+            enable_encryption: false,
+            encryption_key: None,
+            retention_days: None,
+            retention_apps: HashMap::new(),
+            retention_check_interval_hours: 24,
+            retention_max_total_bytes: None,
+            retention_default_app_policy: None,
+            device_delete_grace_hours: 0,
+        };
+
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let query_executor = Arc::new(QueryExecutor::new(storage.clone()));
+        let query_parser = Arc::new(QueryParser::new());
+        let api_token_store = Arc::new(
+            ApiTokenStore::new(temp_dir.path().join("tokens.json")).unwrap(),
+        );
+
+        (
+            AppState {
+                storage,
+                query_executor,
+                query_parser,
+                api_token_store,
+                slow_query_ms: 1000,
+                query_timing_header: false,
+                downlink_publisher: None,
+                mqtt_status: None,
+            tenants: Arc::new(TenantMap::default()),
+            },
+            temp_dir,
+        )
+    }
+
+    fn create_test_uplink(dev_eui: &str) -> crate::model::frames::Frame {
+        crate::model::frames::Frame::Uplink(UplinkFrame {
+            dev_eui: DevEui::new(dev_eui.to_string()).unwrap(),
+            application_id: ApplicationId::new("test-app".to_string()),
+            device_name: Some("test-device".to_string()),
+            received_at: Utc::now(),
+            stored_at: None,
+            f_port: 1,
+            f_cnt: 42,
+            confirmed: false,
+            adr: true,
+            dr: DataRate::new_lora(125000, 7),
+            frequency: 868100000,
+            rx_info: vec![],
+            decoded_payload: None,
+            raw_payload: Some("aGVsbG8=".to_string()),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let response = health_check().await;
+        assert_eq!(response.0.status, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_get_latest_frame() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let dev_eui = "0123456789ABCDEF";
+
+        let result = get_latest_frame(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Path(dev_eui.to_string()),
+        )
+        .await;
+        assert!(matches!(result, Err(LoraDbError::NotFound(_))));
+
+        state.storage.write(create_test_uplink(dev_eui)).await.unwrap();
+
+        let frame = get_latest_frame(State(state), Extension(auth_context), Path(dev_eui.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(frame.0["dev_eui"], dev_eui);
+        assert_eq!(frame.0["frame_type"], "Uplink");
+    }
+
+    #[tokio::test]
+    async fn test_get_device_summary() {
+        use crate::model::gateway::GatewayRxInfo;
+
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let dev_eui = "0123456789ABCDEF";
+
+        let missing = get_device_summary(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Path(dev_eui.to_string()),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(missing, LoraDbError::DeviceNotFound(_)));
+
+        // One flushed frame and one unflushed uplink heard by two gateways
+        state.storage.write(create_test_uplink(dev_eui)).await.unwrap();
+        state.storage.shutdown().await.unwrap();
+        let mut frame = create_test_uplink(dev_eui);
+        if let crate::model::frames::Frame::Uplink(ref mut uplink) = frame {
+            uplink.rx_info = [(-110, 2.5), (-95, -1.0)]
+                .into_iter()
+                .map(|(rssi, snr)| GatewayRxInfo {
+                    gateway_id: GatewayEui::new("0016c001ff10a235".to_string()),
+                    rssi,
+                    snr,
+                    channel: 0,
+                    rf_chain: 0,
+                    location: None,
+                })
+                .collect();
+        }
+        state.storage.write(frame).await.unwrap();
+
+        let Json(summary) = get_device_summary(
+            State(state.clone()),
+            Extension(auth_context),
+            Path(dev_eui.to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(summary.device.dev_eui, dev_eui);
+        assert_eq!(summary.latest_frame.as_ref().unwrap()["frame_type"], "Uplink");
+        assert_eq!(summary.stored_frames, 2);
+        assert_eq!(summary.frames_last_24h, 2);
+
+        let signal = summary.signal.unwrap();
+        assert_eq!(signal.gateways, 2);
+        assert_eq!(signal.best_rssi, Some(-95));
+        assert_eq!(signal.best_snr, Some(2.5));
+        assert_eq!(signal.dr_label, "SF7BW125");
+
+        // A token that can't read reception info gets no signal either
+        let token = AuthContext::ApiToken {
+            user_id: "test-user".to_string(),
+            token_id: "token".to_string(),
+            allowed_fields: Some(vec!["received_at".to_string(), "dr".to_string()]),
+            tenant_id: None,
+        };
+        let Json(summary) =
+            get_device_summary(State(state), Extension(token), Path(dev_eui.to_string()))
+                .await
+                .unwrap();
+        assert!(summary.latest_frame.as_ref().unwrap().get("rx_info").is_none());
+        assert!(summary.signal.is_none());
+        assert_eq!(summary.stored_frames, 2);
+    }
+
+    #[tokio::test]
+    async fn test_set_device_name() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let dev_eui = "0123456789ABCDEF";
+
+        let set = |name: Option<&str>| {
+            set_device_name(
+                State(state.clone()),
+                Extension(auth_context.clone()),
+                Path(dev_eui.to_string()),
+                Json(SetDeviceNameRequest {
+                    name: name.map(str::to_string),
+                }),
+            )
+        };
+
+        assert!(matches!(
+            set(Some("Boiler room")).await.unwrap_err(),
+            LoraDbError::DeviceNotFound(_)
+        ));
+
+        state.storage.write(create_test_uplink(dev_eui)).await.unwrap();
+        let Json(device) = set(Some("  Boiler room ")).await.unwrap();
+        assert_eq!(device.device_name.as_deref(), Some("Boiler room"));
+        assert!(matches!(set(Some(" ")).await.unwrap_err(), LoraDbError::QueryParseError(_)));
+
+        // Query output shows the assigned name too
+        let query = state
+            .query_parser
+            .parse(&format!("SELECT device_name FROM device '{}' WHERE LAST '1h'", dev_eui))
+            .unwrap();
+        let result = state.query_executor.execute(&query).await.unwrap();
+        assert_eq!(result.frames[0]["device_name"], "Boiler room");
+
+        let Json(device) = set(None).await.unwrap();
+        assert_ne!(device.device_name.as_deref(), Some("Boiler room"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_application() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        let delete = || {
+            delete_application(
+                State(state.clone()),
+                Extension(auth_context.clone()),
+                Path("test-app".to_string()),
+            )
+        };
+
+        assert!(matches!(delete().await.unwrap_err(), LoraDbError::NotFound(_)));
+
+        state.storage.write(create_test_uplink("0123456789ABCDEF")).await.unwrap();
+        let Json(deleted) = delete().await.unwrap();
+        assert_eq!(deleted.deleted_devices, 1);
+        assert_eq!(deleted.deleted_frames, 1);
+        assert!(state.storage.device_registry().list_devices().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_offline_devices() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        state.storage.write(create_test_uplink("0123456789ABCDEF")).await.unwrap();
+
+        let list = |threshold: Option<&str>| {
+            list_offline_devices(
+                State(state.clone()),
+                Extension(auth_context.clone()),
+                Query(OfflineDevicesQuery {
+                    threshold: threshold.map(str::to_string),
+                }),
+            )
+        };
+
+        let Json(response) = list(Some("2h")).await.unwrap();
+        assert_eq!(response.threshold, "2h");
+        assert_eq!(response.total_devices, 0);
+
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        let Json(response) = list(Some("10ms")).await.unwrap();
+        assert_eq!(response.total_devices, 1);
+        assert_eq!(response.devices[0].device.dev_eui, "0123456789ABCDEF");
+
+        for threshold in [None, Some("soon"), Some("0s")] {
+            assert!(matches!(
+                list(threshold).await.unwrap_err(),
+                LoraDbError::QueryParseError(_)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_gateway_map() {
+        use crate::model::gateway::{GatewayLocation, GatewayRxInfo};
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        for dev_eui in ["0123456789ABCDEF", "FEDCBA9876543210"] {
+            let mut frame = create_test_uplink(dev_eui);
+            if let crate::model::frames::Frame::Uplink(ref mut uplink) = frame {
+                uplink.rx_info = vec![GatewayRxInfo {
+                    gateway_id: GatewayEui::new("0016c001ff10a235".to_string()),
+                    rssi: -90,
+                    snr: 5.0,
+                    channel: 0,
+                    rf_chain: 0,
+                    location: Some(GatewayLocation {
+                        latitude: 52.37,
+                        longitude: 4.89,
+                        altitude: Some(12.0),
+                    }),
+                }];
+            }
+            state.storage.write(frame).await.unwrap();
+        }
+
+        let Json(map) = get_gateway_map(State(state.clone()), Extension(auth_context)).await;
+        assert_eq!(map.total_gateways, 1);
+        assert_eq!(map.gateways[0].gateway_id, "0016c001ff10a235");
+        assert_eq!(map.gateways[0].device_count, 2);
+        assert_eq!(map.gateways[0].location.as_ref().unwrap().latitude, 52.37);
+
+        // Locations only reach tokens allowed to read them in frames
+        let token = |allowed_fields: &[&str]| AuthContext::ApiToken {
+            user_id: "test-user".to_string(),
+            token_id: "token".to_string(),
+            allowed_fields: Some(allowed_fields.iter().map(|f| f.to_string()).collect()),
+            tenant_id: None,
+        };
+        let Json(map) = get_gateway_map(State(state.clone()), Extension(token(&["f_cnt"]))).await;
+        assert_eq!(map.gateways[0].device_count, 2);
+        assert!(map.gateways[0].location.is_none());
+        let Json(map) = get_gateway_map(State(state), Extension(token(&["rx_info"]))).await;
+        assert!(map.gateways[0].location.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_application_latest_paginates() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        for dev_eui in ["0000000000000001", "0000000000000002", "0000000000000003"] {
+            state.storage.write(create_test_uplink(dev_eui)).await.unwrap();
+        }
+
+        let response = get_application_latest(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Path("test-app".to_string()),
+            Query(LatestFramesQuery { offset: Some(1), limit: Some(1) }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.0.total_devices, 3);
+        assert_eq!(
+            response.0.frames.keys().collect::<Vec<_>>(),
+            vec!["0000000000000002"]
+        );
+
+        let response = get_application_latest(
+            State(state),
+            Extension(auth_context),
+            Path("other-app".to_string()),
+            Query(LatestFramesQuery { offset: None, limit: None }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.0.total_devices, 0);
+        assert!(response.0.frames.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_applications() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        for (dev_eui, app_id) in [
+            ("0000000000000001", "test-app"),
+            ("0000000000000002", "test-app"),
+            ("0000000000000003", "other-app"),
+        ] {
+            let mut frame = create_test_uplink(dev_eui);
+            if let crate::model::frames::Frame::Uplink(ref mut uplink) = frame {
+                uplink.application_id = ApplicationId::new(app_id.to_string());
+            }
+            state.storage.write(frame).await.unwrap();
+        }
+        state
+            .storage
+            .retention_manager()
+            .set_application("other-app".to_string(), Some(7))
+            .await
+            .unwrap();
+
+        let list = |offset, limit| {
+            list_applications(
+                State(state.clone()),
+                Extension(auth_context.clone()),
+                Query(ApplicationsQuery { offset, limit }),
+            )
+        };
+
+        let Json(response) = list(None, None).await;
+        assert_eq!(response.total_applications, 2);
+        let ids: Vec<_> = response.applications.iter().map(|a| a.application_id.as_str()).collect();
+        assert_eq!(ids, vec!["other-app", "test-app"]);
+        assert_eq!(response.applications[0].retention_days, Some(7));
+        assert!(response.applications[0].retention_policy_set);
+        assert!(!response.applications[1].retention_policy_set);
+        assert_eq!(response.applications[1].device_count, 2);
+        assert_eq!(response.applications[1].frame_count, 2);
+
+        let Json(response) = list(Some(1), Some(1)).await;
+        assert_eq!(response.total_applications, 2);
+        assert_eq!(response.applications.len(), 1);
+        assert_eq!(response.applications[0].application_id, "test-app");
+    }
+
+    #[tokio::test]
+    async fn test_get_recovery_report() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        let report = get_recovery_report(State(state), Extension(auth_context)).await;
+        assert_eq!(report.0, ReplayReport::default());
+    }
+
+    #[tokio::test]
+    async fn test_version_info() {
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let response = version_info(Extension(auth_context)).await;
+
+        assert_eq!(response.0.version, crate::VERSION);
+        assert!(!response.0.git_sha.is_empty());
+        assert!(chrono::DateTime::parse_from_rfc3339(&response.0.build_timestamp).is_ok());
+        assert!(!response.0.features.contains(&"default".to_string()));
+        #[cfg(feature = "encryption-aes")]
+        assert!(response.0.features.contains(&"encryption-aes".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_list_sstables() {
+        let (state, _temp_dir) = create_test_state().await;
+        let admin = AuthContext::Jwt(Claims::with_role("test-user".to_string(), "admin".to_string()));
+
+        let response = list_sstables(State(state.clone()), Extension(admin.clone())).await.unwrap();
+        assert_eq!(response.0.total_sstables, 0);
+        assert!(response.0.sstables.is_empty());
+
+        state.storage.write(create_test_uplink("0123456789ABCDEF")).await.unwrap();
+        state.storage.write(create_test_uplink("FEDCBA9876543210")).await.unwrap();
+        state.storage.shutdown().await.unwrap();
+
+        let response = list_sstables(State(state.clone()), Extension(admin)).await.unwrap();
+        assert_eq!(response.0.total_sstables, 1);
+        let sstable = &response.0.sstables[0];
+        assert_eq!(sstable.num_entries, 2);
+        assert_eq!(sstable.min_dev_eui, "0123456789abcdef");
+        assert_eq!(sstable.max_dev_eui, "fedcba9876543210");
+        assert!(sstable.min_timestamp.is_some() && sstable.max_timestamp.is_some());
+        assert_eq!(sstable.application_ids, vec!["test-app".to_string()]);
+
+        // Plain JWTs and API tokens are refused
+        let user = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let err = list_sstables(State(state.clone()), Extension(user)).await.unwrap_err();
+        assert_eq!(err.into_response().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_flush_memtable() {
+        let (state, _temp_dir) = create_test_state().await;
+        let admin = AuthContext::Jwt(Claims::with_role("test-user".to_string(), "admin".to_string()));
+
+        let response = flush_memtable(State(state.clone()), Extension(admin.clone())).await.unwrap();
+        assert_eq!(response.0.summary, FlushSummary::default());
+        assert!(response.0.note.is_some());
+
+        state.storage.write(create_test_uplink("0123456789ABCDEF")).await.unwrap();
+        state.storage.write(create_test_uplink("FEDCBA9876543210")).await.unwrap();
+
+        let response = flush_memtable(State(state.clone()), Extension(admin)).await.unwrap();
+        assert_eq!(response.0.summary.entries, 2);
+        assert_eq!(response.0.summary.sstable_ids.len(), 1);
+        assert!(response.0.note.is_none());
+        assert_eq!(state.storage.sstable_stats()[0].id, response.0.summary.sstable_ids[0]);
+
+        let user = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let err = flush_memtable(State(state.clone()), Extension(user)).await.unwrap_err();
+        assert_eq!(err.into_response().status(), StatusCode::FORBIDDEN);
+        let token = AuthContext::ApiToken {
+            user_id: "test-user".to_string(),
+            token_id: "token".to_string(),
+            allowed_fields: None,
+            tenant_id: None,
+        };
+        assert!(list_sstables(State(state), Extension(token)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_downlink_validation() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let request = |f_port: u8, data: &str| DownlinkRequest {
+            application_id: "3f8a2c1e-0000-4000-8000-000000000001".to_string(),
+            f_port,
+            data: data.to_string(),
+            confirmed: false,
+        };
+
+        for (f_port, data) in [(0, "AQI="), (224, "AQI="), (10, "not base64!")] {
+            let result = send_downlink(
+                State(state.clone()),
+                Extension(auth_context.clone()),
+                Path("0123456789ABCDEF".to_string()),
+                Json(request(f_port, data)),
+            )
+            .await;
+            assert!(matches!(result, Err(LoraDbError::QueryParseError(_))));
+        }
+
+        let mut wildcard = request(10, "AQI=");
+        wildcard.application_id = "+".to_string();
+        let result = send_downlink(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Path("0123456789ABCDEF".to_string()),
+            Json(wildcard),
+        )
+        .await;
+        assert!(matches!(result, Err(LoraDbError::QueryParseError(_))));
+
+        // Valid, but no ChirpStack broker to publish through
+        let result = send_downlink(
+            State(state),
+            Extension(auth_context),
+            Path("0123456789ABCDEF".to_string()),
+            Json(request(10, "AQI=")),
+        )
+        .await;
+        assert!(matches!(result, Err(LoraDbError::MqttError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_sampling_rule_handlers() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        let response = set_device_sampling(
+            State(state.clone()),
+            Path("0123456789ABCDEF".to_string()),
+            Extension(auth_context.clone()),
+            Json(SetSamplingRuleRequest { keep_one_in: Some(2), min_interval_secs: None }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.0.id, "0123456789abcdef");
+        assert_eq!(response.0.keep_one_in, Some(2));
+
+        // Every second uplink is acknowledged but not stored
+        for _ in 0..4 {
+            state.storage.write(create_test_uplink("0123456789ABCDEF")).await.unwrap();
+        }
+        let response = get_device_sampling(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Path("0123456789abcdef".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.0.dropped_frames, 2);
+
+        let result = set_application_sampling(
+            State(state.clone()),
+            Path("test-app".to_string()),
+            Extension(auth_context.clone()),
+            Json(SetSamplingRuleRequest { keep_one_in: None, min_interval_secs: None }),
+        )
+        .await;
+        assert!(matches!(result, Err(LoraDbError::QueryParseError(_))));
+
+        let listed = list_sampling_rules(State(state.clone()), Extension(auth_context.clone())).await;
+        assert_eq!(listed.0.total_rules, 1);
+
+        let status = delete_device_sampling(
+            State(state.clone()),
+            Path("0123456789ABCDEF".to_string()),
+            Extension(auth_context.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(matches!(
+            get_device_sampling(
+                State(state),
+                Extension(auth_context),
+                Path("0123456789ABCDEF".to_string()),
+            )
+            .await,
+            Err(LoraDbError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_background_intervals() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let admin = AuthContext::Jwt(Claims::with_role("test-user".to_string(), "admin".to_string()));
+
+        let response = get_interval_config(State(state.clone()), Extension(auth_context))
+            .await;
+        assert_eq!(response.0.flush_interval_secs, 300);
+        assert_eq!(response.0.retention_check_interval_hours, 24);
+
+        let response = set_flush_interval(
+            State(state.clone()),
+            Extension(admin.clone()),
+            Json(SetFlushIntervalRequest { seconds: 30 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.0.flush_interval_secs, 30);
+
+        let response = set_retention_interval(
+            State(state.clone()),
+            Extension(admin.clone()),
+            Json(SetRetentionIntervalRequest { hours: 6 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.0.retention_check_interval_hours, 6);
+        assert_eq!(state.storage.retention_manager().get_check_interval_hours().await, 6);
+
+        let result = set_flush_interval(
+            State(state.clone()),
+            Extension(admin.clone()),
+            Json(SetFlushIntervalRequest { seconds: 0 }),
+        )
+        .await;
+        assert!(matches!(result, Err(LoraDbError::QueryParseError(_))));
+
+        let result = set_retention_interval(
+            State(state),
+            Extension(admin),
+            Json(SetRetentionIntervalRequest { hours: 0 }),
+        )
+        .await;
+        assert!(matches!(result, Err(LoraDbError::QueryParseError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_set_background_intervals_requires_admin() {
+        let (state, _temp_dir) = create_test_state().await;
+        let user = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let token = AuthContext::ApiToken {
+            user_id: "test-user".to_string(),
+            token_id: "token".to_string(),
+            allowed_fields: None,
+            tenant_id: None,
+        };
+
+        for auth_context in [user, token] {
+            let result = set_flush_interval(
+                State(state.clone()),
+                Extension(auth_context.clone()),
+                Json(SetFlushIntervalRequest { seconds: 30 }),
+            )
+            .await;
+            assert!(matches!(result, Err(LoraDbError::Forbidden(_))));
+
+            let result = set_retention_interval(
+                State(state.clone()),
+                Extension(auth_context),
+                Json(SetRetentionIntervalRequest { hours: 6 }),
+            )
+            .await;
+            assert!(matches!(result, Err(LoraDbError::Forbidden(_))));
+        }
+
+        // Nothing changed
+        assert_eq!(state.storage.flush_interval_secs(), 300);
+        assert_eq!(state.storage.retention_manager().get_check_interval_hours().await, 24);
+    }
+
+    #[tokio::test]
+    async fn test_get_stats_reports_ingestion_lag() {
+        let (state, _temp_dir) = create_test_state().await;
+        state.storage.write(create_test_uplink("0123456789ABCDEF")).await.unwrap();
+
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        let stats = get_stats(State(state), Extension(auth_context)).await;
+        assert_eq!(stats.0.ingestion_lag.samples, 1);
+        assert_eq!(stats.0.ingest_rate.total, 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_enforces_token_allowed_fields() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::ApiToken {
+            user_id: "test-user".to_string(),
+            token_id: "token-1".to_string(),
+            allowed_fields: Some(vec!["dev_eui".to_string(), "f_cnt".to_string()]),
+            tenant_id: None,
+        };
+
+        let dev_eui = "0123456789ABCDEF";
+        state.storage.write(create_test_uplink(dev_eui)).await.unwrap();
+
+        // Asking for a denied field explicitly still doesn't return it
+        let request = QueryRequest {
+            query: format!(
+                "SELECT dev_eui, f_cnt, rx_info FROM device '{}' WHERE LAST '1h'",
+                dev_eui
+            ),
+        };
+
+        let result = execute_query(State(state), Extension(auth_context), Json(request))
+            .await
+            .unwrap();
+
+        let frame = result.1.frames[0].as_object().unwrap();
+        assert_eq!(frame.len(), 2);
+        assert_eq!(frame["dev_eui"], dev_eui);
+        assert_eq!(frame["f_cnt"], 42);
+    }
+
+    #[tokio::test]
+    async fn test_execute_query() {
+        let (state, _temp_dir) = create_test_state().await;
+        let claims = Claims::new("test-user".to_string());
+        let auth_context = AuthContext::Jwt(claims);
+
+        // Write a test frame
+        let dev_eui = "0123456789ABCDEF";
+        let frame = create_test_uplink(dev_eui);
+        state.storage.write(frame).await.unwrap();
+
+        // Execute query
+        let request = QueryRequest {
+            query: format!("SELECT * FROM device '{}' WHERE LAST '1h'", dev_eui),
+        };
+
+        let result = execute_query(
+            State(state),
+            Extension(auth_context),
+            Json(request),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.1.total_frames, 1);
+        assert!(result.0.get(QUERY_TIME_HEADER).is_none());
+    }
+
+    #[test]
+    fn test_query_execution_error_keeps_query_errors() {
+        let err = query_execution_error(
+            LoraDbError::QueryParseError("Query time range of 91d exceeds the maximum of 90d".to_string())
+                .into(),
+        );
+        assert!(matches!(err, LoraDbError::QueryParseError(ref msg) if msg.contains("90d")));
+        assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let err = query_execution_error(anyhow::anyhow!("corrupt block"));
+        assert_eq!(err.into_response().status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_timing_header() {
+        let (mut state, _temp_dir) = create_test_state().await;
+        state.query_timing_header = true;
+        let dev_eui = "0123456789ABCDEF";
+        state.storage.write(create_test_uplink(dev_eui)).await.unwrap();
+
+        let result = execute_query_get(
+            State(state),
+            Extension(AuthContext::Jwt(Claims::new("test-user".to_string()))),
+            Query(QueryParams {
+                q: format!("SELECT * FROM device '{}' WHERE LAST '1h'", dev_eui),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let millis = result.0.get(QUERY_TIME_HEADER).unwrap().to_str().unwrap();
+        assert!(millis.parse::<u64>().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_query_matches_result_size() {
+        let (state, _temp_dir) = create_test_state().await;
+        let dev_eui = "0123456789ABCDEF";
+        for _ in 0..3 {
+            state.storage.write(create_test_uplink(dev_eui)).await.unwrap();
+        }
+
+        let query = format!("SELECT * FROM device '{}' WHERE LAST '1h'", dev_eui);
+        let estimate = estimate_query(
+            State(state.clone()),
+            Extension(AuthContext::Jwt(Claims::new("test-user".to_string()))),
+            Json(QueryRequest { query: query.clone() }),
+        )
+        .await
+        .unwrap();
+
+        let result = execute_query(
+            State(state),
+            Extension(AuthContext::Jwt(Claims::new("test-user".to_string()))),
+            Json(QueryRequest { query }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(estimate.0.total_frames, 3);
+        assert_eq!(estimate.0.estimated_bytes, result.1.estimated_bytes);
+        assert_eq!(
+            result.1.estimated_bytes,
+            serde_json::to_vec(&result.1.frames).unwrap().len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_device_pages_with_cursor() {
+        let (state, _temp_dir) = create_test_state().await;
+        let dev_eui = "0123456789ABCDEF";
+        let base = Utc::now() - chrono::Duration::days(30);
+        for i in 0..5 {
+            let mut frame = create_test_uplink(dev_eui);
+            if let crate::model::frames::Frame::Uplink(ref mut uplink) = frame {
+                uplink.received_at = base + chrono::Duration::seconds(i);
+            }
+            state.storage.write(frame).await.unwrap();
+        }
+
+        let auth = || Extension(AuthContext::Jwt(Claims::new("test-user".to_string())));
+        let mut cursor = None;
+        let mut pages = 0;
+        let mut total = 0;
+        loop {
+            let page = export_device(
+                State(state.clone()),
+                Path(dev_eui.to_string()),
+                auth(),
+                Json(ExportRequest { cursor, limit: Some(2) }),
+            )
+            .await
+            .unwrap();
+            pages += 1;
+            total += page.0.frames.len();
+            cursor = page.0.next_cursor.clone();
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(total, 5);
+        assert_eq!(pages, 3);
+
+        // Cursors are bound to the device they were issued for
+        let first = export_device(
+            State(state.clone()),
+            Path(dev_eui.to_string()),
+            auth(),
+            Json(ExportRequest { cursor: None, limit: Some(1) }),
+        )
+        .await
+        .unwrap();
+        let result = export_device(
+            State(state),
+            Path("FEDCBA9876543210".to_string()),
+            auth(),
+            Json(ExportRequest { cursor: first.0.next_cursor.clone(), limit: None }),
+        )
+        .await;
+        assert!(matches!(result, Err(LoraDbError::QueryParseError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_list_devices() {
+        let (state, _temp_dir) = create_test_state().await;
+        let claims = Claims::new("test-user".to_string());
+        let auth_context = AuthContext::Jwt(claims);
+
+        // Write test frames for different devices
         for i in 0..3 {
             let dev_eui = format!("012345678{:07X}", i);
             let frame = create_test_uplink(&dev_eui);
             state.storage.write(frame).await.unwrap();
         }
 
-        let response = list_devices(State(state), Extension(auth_context)).await;
-        assert_eq!(response.0.total_devices, 3);
+        let response = list_devices(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Query(FormatParams::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.body.total_devices, 3);
+
+        let yaml = list_devices(
+            State(state),
+            Extension(auth_context),
+            Query(FormatParams {
+                format: Some("yaml".to_string()),
+            }),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        assert_eq!(yaml.headers()[axum::http::header::CONTENT_TYPE], "application/yaml");
+        let body = hyper::body::to_bytes(yaml.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("total_devices: 3\n"));
+        assert!(body.contains("    dev_eui: \"0123456780000000\"\n"));
+    }
+
+    #[tokio::test]
+    async fn test_devices_exist() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        state.storage.write(create_test_uplink("0123456789ABCDEF")).await.unwrap();
+
+        let result = devices_exist(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Json(vec!["0123456789ABCDEF".to_string(), "FEDCBA9876543210".to_string()]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.0.len(), 2);
+        assert!(result.0["0123456789ABCDEF"]);
+        assert!(!result.0["FEDCBA9876543210"]);
+
+        let too_many = vec!["0123456789ABCDEF".to_string(); MAX_EXISTS_BATCH + 1];
+        let result = devices_exist(State(state), Extension(auth_context), Json(too_many)).await;
+        assert!(matches!(result, Err(LoraDbError::QueryParseError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_device_not_found_vs_malformed() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        let missing = get_device(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Path("0123456789ABCDEF".to_string()),
+            Query(FormatParams::default()),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(missing, LoraDbError::DeviceNotFound(_)));
+        assert_eq!(missing.into_response().status(), StatusCode::NOT_FOUND);
+
+        let malformed = get_device(
+            State(state),
+            Extension(auth_context),
+            Path("not-a-dev-eui".to_string()),
+            Query(FormatParams::default()),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(malformed, LoraDbError::InvalidDevEui(_)));
+        assert_eq!(malformed.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_schema_violation() {
+        use crate::storage::schema_manager::FieldType;
+
+        let (state, _temp_dir) = create_test_state().await;
+        let claims = Claims::new("test-user".to_string());
+        let auth_context = AuthContext::Jwt(claims);
+
+        state
+            .storage
+            .schema_manager()
+            .set(
+                "env".to_string(),
+                vec![RequiredField {
+                    path: "temperature".to_string(),
+                    field_type: FieldType::Number,
+                }],
+                SchemaMode::Reject,
+            )
+            .await
+            .unwrap();
+
+        let payload = r#"{
+            "deviceInfo": {
+                "devEui": "0123456789abcdef",
+                "applicationId": "env"
+            },
+            "fPort": 1,
+            "object": { "humidity": 40 }
+        }"#;
+
+        let result = ingest_chirpstack(
+            State(state.clone()),
+            Extension(auth_context),
+            Query(IngestQuery { event: Some("up".to_string()) }),
+            Bytes::from(payload),
+        )
+        .await;
+
+        assert!(matches!(result, Err(LoraDbError::InvalidFrame(_))));
+        assert_eq!(state.storage.device_registry().device_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_isolation() {
+        let (mut state, _temp_dir) = create_test_state().await;
+        state.tenants = Arc::new(TenantMap::new(HashMap::from([
+            ("test-app".to_string(), "acme".to_string()),
+            ("other-app".to_string(), "globex".to_string()),
+        ])));
+
+        let own = "0123456789ABCDEF";
+        let other = "FEDCBA9876543210";
+        state.storage.write(create_test_uplink(own)).await.unwrap();
+        let mut frame = create_test_uplink(other);
+        if let crate::model::frames::Frame::Uplink(ref mut uplink) = frame {
+            uplink.application_id = ApplicationId::new("other-app".to_string());
+        }
+        state.storage.write(frame).await.unwrap();
+
+        let mut claims = Claims::new("test-user".to_string());
+        claims.tenant = Some("acme".to_string());
+        let acme = AuthContext::Jwt(claims);
+
+        let Formatted { body: list, .. } = list_devices(
+            State(state.clone()),
+            Extension(acme.clone()),
+            Query(FormatParams::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(list.total_devices, 1);
+        assert_eq!(list.devices[0].dev_eui, own);
+
+        // Another tenant's device looks like it doesn't exist
+        let err = get_device(
+            State(state.clone()),
+            Extension(acme.clone()),
+            Path(other.to_string()),
+            Query(FormatParams::default()),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, LoraDbError::DeviceNotFound(_)));
+
+        let Json(exists) = devices_exist(
+            State(state.clone()),
+            Extension(acme.clone()),
+            Json(vec![own.to_string(), other.to_string()]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(exists[own], true);
+        assert_eq!(exists[other], false);
+
+        let query = |dev_eui: &str| format!("SELECT * FROM device '{}' WHERE LAST '1h'", dev_eui);
+        assert!(run_query(&state, &acme, &query(own)).await.is_ok());
+        assert!(run_query(&state, &acme, &query(other)).await.is_err());
+        let unscoped = AuthContext::Jwt(Claims::new("test-user".to_string()));
+        assert!(run_query(&state, &unscoped, &query(other)).await.is_ok());
+
+        let payload = r#"{
+            "deviceInfo": { "devEui": "1111111111111111", "applicationId": "other-app" },
+            "fPort": 1
+        }"#;
+        let err = ingest_chirpstack(
+            State(state.clone()),
+            Extension(acme.clone()),
+            Query(IngestQuery { event: Some("up".to_string()) }),
+            Bytes::from(payload),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, LoraDbError::Forbidden(_)));
+
+        // Tokens created by a tenant inherit it and can't escape it
+        let request = |tenant_id: Option<&str>| CreateTokenRequest {
+            name: "tenant token".to_string(),
+            expires_in_days: None,
+            allowed_fields: None,
+            tenant_id: tenant_id.map(str::to_string),
+        };
+        let Json(token) =
+            create_token(State(state.clone()), Extension(acme.clone()), Json(request(None)))
+                .await
+                .unwrap();
+        assert_eq!(token.tenant_id.as_deref(), Some("acme"));
+        let err = create_token(State(state.clone()), Extension(acme), Json(request(Some("globex"))))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LoraDbError::Forbidden(_)));
+        let err = create_token(State(state), Extension(unscoped), Json(request(Some("initech"))))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LoraDbError::QueryParseError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_ingest_detects_event_type() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        let status = r#"{
+            "deviceInfo": {
+                "devEui": "0123456789abcdef",
+                "applicationId": "env"
+            },
+            "margin": 7,
+            "batteryLevel": 80
+        }"#;
+        let response = ingest_chirpstack(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Query(IngestQuery { event: None }),
+            Bytes::from(status),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.0.event_type, "status");
+
+        let result = ingest_chirpstack(
+            State(state),
+            Extension(auth_context),
+            Query(IngestQuery { event: None }),
+            Bytes::from(r#"{"acknowledged": true}"#),
+        )
+        .await;
+        assert!(matches!(result, Err(LoraDbError::QueryParseError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_ingest_drops_disabled_application() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        set_application_ingestion(
+            State(state.clone()),
+            Path("noisy".to_string()),
+            Extension(auth_context.clone()),
+            Json(SetIngestionRequest { enabled: false }),
+        )
+        .await
+        .unwrap();
+
+        let payload = r#"{
+            "deviceInfo": {
+                "devEui": "0123456789abcdef",
+                "applicationId": "noisy"
+            },
+            "fPort": 1
+        }"#;
+
+        let response = ingest_chirpstack(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Query(IngestQuery { event: Some("up".to_string()) }),
+            Bytes::from(payload),
+        )
+        .await
+        .unwrap();
+
+        assert!(!response.0.success);
+        assert_eq!(state.storage.device_registry().device_count(), 0);
+
+        let status = get_application_ingestion(
+            State(state),
+            Extension(auth_context),
+            Path("noisy".to_string()),
+        )
+        .await
+        .unwrap();
+        assert!(!status.0.enabled);
+        assert_eq!(status.0.dropped_frames, 1);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_over_quota() {
+        let (state, _temp_dir) = create_test_state().await;
+        let auth_context = AuthContext::Jwt(Claims::new("test-user".to_string()));
+
+        let status = set_application_quota(
+            State(state.clone()),
+            Path("tenant".to_string()),
+            Extension(auth_context.clone()),
+            Json(SetQuotaRequest {
+                max_frames_per_day: Some(1),
+                max_bytes_per_day: None,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status.0.frames_used, 0);
+
+        let payload = r#"{
+            "deviceInfo": {
+                "devEui": "0123456789abcdef",
+                "applicationId": "tenant"
+            },
+            "fPort": 1
+        }"#;
+        let ingest = || {
+            ingest_chirpstack(
+                State(state.clone()),
+                Extension(auth_context.clone()),
+                Query(IngestQuery { event: Some("up".to_string()) }),
+                Bytes::from(payload),
+            )
+        };
+
+        assert!(ingest().await.unwrap().0.success);
+        let err = ingest().await.unwrap_err();
+        assert!(matches!(err, LoraDbError::QuotaExceeded(_)));
+        assert_eq!(err.into_response().status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let status = get_application_quota(
+            State(state.clone()),
+            Extension(auth_context.clone()),
+            Path("tenant".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status.0.frames_used, 1);
+        assert!(status.0.bytes_used > 0);
+
+        delete_application_quota(
+            State(state.clone()),
+            Path("tenant".to_string()),
+            Extension(auth_context.clone()),
+        )
+        .await
+        .unwrap();
+        assert!(ingest().await.unwrap().0.success);
     }
 }
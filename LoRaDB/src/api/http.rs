@@ -1,26 +1,66 @@
 use crate::api::handlers::{
-    create_token, delete_device, enforce_retention, execute_query,
-    get_application_retention, get_device, get_global_retention, health_check, ingest_chirpstack,
-    list_devices, list_retention_policies, list_tokens, revoke_token, AppState,
+    create_token, delete_application, delete_application_quota, delete_application_sampling,
+    delete_application_transforms, delete_default_application_retention, delete_device,
+    delete_device_sampling, delete_payload_profile, delete_schema, devices_exist, enforce_retention,
+    estimate_query, execute_query, execute_query_get, export_device, flush_memtable,
+    get_application_ingestion, get_application_latest, get_application_quota,
+    get_application_retention, get_application_sampling, get_application_transforms, get_device,
+    get_device_sampling, get_device_storage, get_device_summary, get_gateway_map,
+    get_global_retention, get_interval_config, get_latest_frame, get_payload_profile,
+    get_recovery_report, get_schema, get_stats, health_check, ingest_chirpstack, list_applications,
+    list_devices, list_offline_devices, list_payload_profiles, list_retention_policies,
+    list_sampling_rules, list_schemas, list_sstables, list_tokens, list_transforms, readiness_check,
+    revoke_token, run_selftest, send_downlink, set_application_ingestion, set_application_quota,
+    set_application_sampling, set_application_transforms, set_default_application_retention,
+    set_device_name, set_device_sampling, set_flush_interval, set_payload_profile,
+    set_retention_interval, set_retention_size, set_schema, undelete_device, version_info, AppState,
+    QUERY_TIME_HEADER,
+};
+use crate::api::middleware::{
+    client_ip, deny_tenants, jwt_auth, overloaded, request_id, security_headers, AuthMiddleware,
+    TrustedProxies, REQUEST_ID_HEADER,
 };
-use crate::api::middleware::{jwt_auth, security_headers, AuthMiddleware};
 use crate::config::ApiConfig;
+use crate::ingest::mqtt::{DownlinkPublisher, MqttStatus};
+use crate::query::cache::QueryCache;
 use crate::query::executor::QueryExecutor;
 use crate::query::parser::QueryParser;
 use crate::security::api_token::ApiTokenStore;
 use crate::security::jwt::JwtService;
+use crate::security::tenant::TenantMap;
 use crate::storage::StorageEngine;
 use anyhow::Result;
 use axum::{
+    error_handling::HandleErrorLayer,
+    extract::DefaultBodyLimit,
     middleware,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Router,
 };
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::ServiceBuilder;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::info;
 
+/// Answer requests beyond `max` in flight on these routes with 503 (0 = unlimited)
+/// The permits are shared by all the routes, not counted per route
+fn limit_concurrency(routes: Router<AppState>, max: usize) -> Router<AppState> {
+    if max == 0 {
+        return routes;
+    }
+    routes.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(overloaded))
+            .layer(LoadShedLayer::new())
+            .layer(GlobalConcurrencyLimitLayer::new(max)),
+    )
+}
+
 /// HTTP/HTTPS API server
 pub struct HttpServer {
     app_state: AppState,
@@ -30,6 +70,13 @@ pub struct HttpServer {
     tls_cert_path: Option<String>,
     tls_key_path: Option<String>,
     cors_allowed_origins: Vec<String>,
+    cors_allow_credentials: bool,
+    cors_exposed_headers: Vec<String>,
+    cors_max_age_secs: u64,
+    trusted_proxies: TrustedProxies,
+    max_body_bytes: usize,
+    max_concurrent_queries: usize,
+    max_concurrent_ingests: usize,
     #[allow(dead_code)]
     rate_limit_per_minute: u32,
 }
@@ -41,7 +88,18 @@ impl HttpServer {
         api_token_store: Arc<ApiTokenStore>,
         config: ApiConfig,
     ) -> Self {
-        let query_executor = Arc::new(QueryExecutor::new(storage.clone()));
+        let query_executor = Arc::new(
+            QueryExecutor::new(storage.clone())
+                .with_default_window(config.default_query_window)
+                .with_max_window(config.max_query_window)
+                .with_cache((config.query_cache_ttl_secs > 0).then(|| {
+                    QueryCache::new(
+                        Duration::from_secs(config.query_cache_ttl_secs),
+                        config.query_cache_max_entries,
+                        config.query_cache_strict_freshness,
+                    )
+                })),
+        );
         let query_parser = Arc::new(QueryParser::new());
 
         let app_state = AppState {
@@ -49,6 +107,11 @@ impl HttpServer {
             query_executor,
             query_parser,
             api_token_store: api_token_store.clone(),
+            slow_query_ms: config.slow_query_ms,
+            query_timing_header: config.query_timing_header,
+            downlink_publisher: None,
+            mqtt_status: None,
+            tenants: Arc::new(TenantMap::new(config.tenants)),
         };
 
         let auth_middleware = AuthMiddleware::new(jwt_service, api_token_store);
@@ -61,36 +124,124 @@ impl HttpServer {
             tls_cert_path: config.tls_cert.map(|p| p.to_string_lossy().to_string()),
             tls_key_path: config.tls_key.map(|p| p.to_string_lossy().to_string()),
             cors_allowed_origins: config.cors_allowed_origins,
+            cors_allow_credentials: config.cors_allow_credentials,
+            cors_exposed_headers: config.cors_exposed_headers,
+            cors_max_age_secs: config.cors_max_age_secs,
+            trusted_proxies: TrustedProxies::new(config.trusted_proxies),
+            max_body_bytes: config.max_body_bytes,
+            max_concurrent_queries: config.max_concurrent_queries,
+            max_concurrent_ingests: config.max_concurrent_ingests,
             rate_limit_per_minute: config.rate_limit_per_minute,
         }
     }
 
+    /// Send `POST /devices/:dev_eui/downlink` through the ChirpStack MQTT connection
+    pub fn with_downlink_publisher(mut self, publisher: DownlinkPublisher) -> Self {
+        self.app_state.downlink_publisher = Some(publisher);
+        self
+    }
+
+    /// Report broker connections in `GET /stats` and fail `/health/ready` while one is down
+    pub fn with_mqtt_status(mut self, status: MqttStatus) -> Self {
+        self.app_state.mqtt_status = Some(status);
+        self
+    }
+
     /// Build the Axum router with all routes and middleware
     fn build_router(&self) -> Router {
-        // NOTE: Rate limiting and body size limiting have been temporarily disabled
-        // due to compatibility issues with Axum 0.6. Axum 0.6 has a default 2MB body limit.
-        // For rate limiting, consider upgrading to Axum 0.7+ or using a custom middleware.
+        // NOTE: Rate limiting has been temporarily disabled due to compatibility issues
+        // with Axum 0.6. For rate limiting, consider upgrading to Axum 0.7+ or using a custom middleware.
 
         // Public routes (no authentication required)
-        let public_routes = Router::new().route("/health", get(health_check));
-
-        // Protected routes (authentication required)
-        let protected_routes = Router::new()
-            // ChirpStack webhook ingestion endpoint
-            // NOTE: Rate limiting should be added when upgrading to Axum 0.7+
-            // For now, relies on authentication and default 2MB body limit
-            .route("/ingest", post(ingest_chirpstack))
-            .route("/query", post(execute_query))
+        let public_routes = Router::new()
+            .route("/health", get(health_check))
+            .route("/health/ready", get(readiness_check));
+
+        // Protected routes (authentication required) open to tenant-scoped principals,
+        // the handlers only let them reach their own tenant's devices and applications
+        // ChirpStack webhook ingestion and queries get separate concurrency limits,
+        // so a burst of expensive queries can't starve ingestion or the other way around
+        let ingest_routes = limit_concurrency(
+            Router::new().route("/ingest", post(ingest_chirpstack)),
+            self.max_concurrent_ingests,
+        );
+        let query_routes = limit_concurrency(
+            Router::new()
+                .route("/query", post(execute_query))
+                .route("/query", get(execute_query_get))
+                .route("/query/estimate", post(estimate_query))
+                .route("/devices/:dev_eui/export", post(export_device)),
+            self.max_concurrent_queries,
+        );
+        let tenant_routes = Router::new()
+            .merge(ingest_routes)
+            .merge(query_routes)
             .route("/devices", get(list_devices))
+            .route("/devices/exists", post(devices_exist))
+            .route("/devices/offline", get(list_offline_devices))
             .route("/devices/:dev_eui", get(get_device))
             .route("/devices/:dev_eui", delete(delete_device))
+            .route("/devices/:dev_eui/undelete", post(undelete_device))
+            .route("/devices/:dev_eui/latest", get(get_latest_frame))
+            .route("/devices/:dev_eui/storage", get(get_device_storage))
+            .route("/devices/:dev_eui/summary", get(get_device_summary))
+            .route("/devices/:dev_eui/name", put(set_device_name))
+            .route("/devices/:dev_eui/downlink", post(send_downlink))
+            .route("/applications", get(list_applications))
+            .route("/applications/:app_id", delete(delete_application))
+            .route("/applications/:app_id/latest", get(get_application_latest))
+            .route("/version", get(version_info))
             // API token management routes
             .route("/tokens", post(create_token))
             .route("/tokens", get(list_tokens))
-            .route("/tokens/:token_id", delete(revoke_token))
+            .route("/tokens/:token_id", delete(revoke_token));
+
+        // Protected routes spanning or configuring every tenant,
+        // refused to tenant-scoped principals
+        let shared_routes = Router::new()
+            .route("/gateways/map", get(get_gateway_map))
+            // Admin routes
+            .route("/admin/recovery", get(get_recovery_report))
+            .route("/admin/sstables", get(list_sstables))
+            .route("/admin/flush", post(flush_memtable))
+            .route("/admin/selftest", post(run_selftest))
+            .route("/admin/config/intervals", get(get_interval_config))
+            .route("/admin/config/flush-interval", put(set_flush_interval))
+            .route("/admin/config/retention-interval", put(set_retention_interval))
+            .route("/stats", get(get_stats))
+            // Application ingestion kill-switch routes
+            .route("/applications/:app_id/ingestion", get(get_application_ingestion))
+            .route("/applications/:app_id/ingestion", put(set_application_ingestion))
+            // Application ingest quota routes
+            .route("/applications/:app_id/quota", get(get_application_quota))
+            .route("/applications/:app_id/quota", put(set_application_quota))
+            .route("/applications/:app_id/quota", delete(delete_application_quota))
+            // Uplink sampling routes
+            .route("/sampling/rules", get(list_sampling_rules))
+            .route("/applications/:app_id/sampling", get(get_application_sampling))
+            .route("/applications/:app_id/sampling", put(set_application_sampling))
+            .route("/applications/:app_id/sampling", delete(delete_application_sampling))
+            .route("/devices/:dev_eui/sampling", get(get_device_sampling))
+            .route("/devices/:dev_eui/sampling", put(set_device_sampling))
+            .route("/devices/:dev_eui/sampling", delete(delete_device_sampling))
+            // Frame transform pipeline routes
+            .route("/transforms", get(list_transforms))
+            .route("/applications/:app_id/transforms", get(get_application_transforms))
+            .route("/applications/:app_id/transforms", put(set_application_transforms))
+            .route("/applications/:app_id/transforms", delete(delete_application_transforms))
+            // Decoded payload profile routes
+            .route("/payload-profiles", get(list_payload_profiles))
+            .route("/payload-profiles/:profile", get(get_payload_profile))
+            .route("/payload-profiles/:profile", put(set_payload_profile))
+            .route("/payload-profiles/:profile", delete(delete_payload_profile))
             // Retention policy management routes
             .route("/retention/policies", get(list_retention_policies))
             .route("/retention/policies/global", get(get_global_retention))
+            .route("/retention/policies/size", put(set_retention_size))
+            .route(
+                "/retention/policies/default-application",
+                put(set_default_application_retention).delete(delete_default_application_retention),
+            )
             // TODO: Fix Handler trait issues with State+Extension+Json combination
             // .route("/retention/policies/global", axum::routing::put(set_global_retention))
             .route("/retention/policies/:app_id", get(get_application_retention))
@@ -98,6 +249,16 @@ impl HttpServer {
             // .route("/retention/policies/:app_id", axum::routing::put(set_application_retention))
             // .route("/retention/policies/:app_id", delete(delete_application_retention))
             .route("/retention/enforce", post(enforce_retention))
+            // Application decoded payload schema routes
+            .route("/schemas", get(list_schemas))
+            .route("/schemas/:app_id", get(get_schema))
+            .route("/schemas/:app_id", put(set_schema))
+            .route("/schemas/:app_id", delete(delete_schema))
+            .layer(middleware::from_fn(deny_tenants));
+
+        let protected_routes = Router::new()
+            .merge(tenant_routes)
+            .merge(shared_routes)
             .layer(middleware::from_fn_with_state(
                 self.auth_middleware.clone(),
                 jwt_auth,
@@ -119,8 +280,20 @@ impl HttpServer {
                 .filter_map(|origin| origin.parse().ok())
                 .collect();
 
+            // Validated in config, so nothing is dropped here
+            let mut exposed_headers = vec![
+                axum::http::HeaderName::from_static(REQUEST_ID_HEADER),
+                axum::http::HeaderName::from_static(QUERY_TIME_HEADER),
+            ];
+            exposed_headers.extend(
+                self.cors_exposed_headers
+                    .iter()
+                    .filter_map(|name| axum::http::HeaderName::from_bytes(name.as_bytes()).ok()),
+            );
+
             CorsLayer::new()
                 .allow_origin(AllowOrigin::list(origins))
+                .allow_credentials(self.cors_allow_credentials)
                 .allow_methods([
                     axum::http::Method::GET,
                     axum::http::Method::POST,
@@ -131,16 +304,35 @@ impl HttpServer {
                 .allow_headers([
                     axum::http::header::CONTENT_TYPE,
                     axum::http::header::AUTHORIZATION,
+                    axum::http::HeaderName::from_static(REQUEST_ID_HEADER),
                 ])
+                .expose_headers(exposed_headers)
+        };
+        let cors = if self.cors_max_age_secs > 0 {
+            cors.max_age(std::time::Duration::from_secs(self.cors_max_age_secs))
+        } else {
+            cors
         };
 
         // Combine routes and apply global middleware
-        // NOTE: Axum 0.6 has a default 2MB body limit which is reasonable for our API
+        // Replaces Axum's implicit 2MB body limit with the configured one. tower-http's
+        // RequestBodyLimitLayer changes the body type, which Axum 0.6 routers can't accept.
+        // /ingest additionally caps each webhook payload at MAX_PAYLOAD_SIZE
         Router::new()
             .merge(public_routes)
             .merge(protected_routes)
+            .layer(DefaultBodyLimit::max(self.max_body_bytes))
+            // gzip or zstd per Accept-Encoding, bodies under 32 bytes are sent as is
+            .layer(CompressionLayer::new())
             .layer(cors)
             .layer(middleware::from_fn(security_headers))
+            // Outermost so auth rejections and CORS preflights also carry the ID
+            .layer(middleware::from_fn(request_id))
+            // Before everything else, so the request span and all middleware see the client IP
+            .layer(middleware::from_fn_with_state(
+                self.trusted_proxies.clone(),
+                client_ip,
+            ))
             .with_state(self.app_state.clone())
     }
 
@@ -168,7 +360,7 @@ impl HttpServer {
             .await?;
 
             axum_server::bind_rustls(self.bind_addr, config)
-                .serve(app.into_make_service())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                 .await?;
         } else {
             info!(
@@ -177,7 +369,7 @@ impl HttpServer {
             );
 
             axum_server::bind(self.bind_addr)
-                .serve(app.into_make_service())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                 .await?;
         }
 
@@ -189,7 +381,10 @@ impl HttpServer {
 mod tests {
     use std::collections::HashMap;
     use super::*;
-    use crate::config::StorageConfig;
+    use crate::config::{
+        ApplicationKey, ClockSkewAction, OversizedPayloadAction, StorageConfig, StorageLayout,
+        TimestampSource, UnknownApplicationAction, WalDurability,
+    };
     use crate::security::jwt::Claims;
     use axum::{
         body::Body,
@@ -203,15 +398,41 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let storage_config = StorageConfig {
             data_dir: temp_dir.path().to_path_buf(),
-            wal_sync_interval_ms: 1000,
+            wal_durability: WalDurability::Interval(1000),
             memtable_size_mb: 1,
+            memtable_hard_limit_factor: 0,
             memtable_flush_interval_secs: 300,
             compaction_threshold: 3,
+            max_open_sstables: 128,
+            warmup_sstables: 0,
+            warmup_read_data: false,
+            compaction_max_bytes_per_sec: 0,
+            compaction_fragmentation_threshold: 0.75,
+            compaction_target_sstable_entries: 10_000,
+            max_sstable_bytes: 0,
+            layout: StorageLayout::Flat,
+            max_clock_skew_secs: 300,
+            clock_skew_action: ClockSkewAction::Reject,
+            timestamp_source: TimestampSource::Network,
+            application_key: ApplicationKey::Id,
+            unknown_application_action: UnknownApplicationAction::Fallback,
+            fallback_application_id: "unknown".to_string(),
+            max_payload_depth: 16,
+            max_payload_bytes: 64 * 1024,
+            oversized_payload_action: OversizedPayloadAction::Reject,
+            selftest_dev_eui: "fffffffffffffffe".to_string(),
+            maintenance_window: None,
+            read_only: false,
+            replica_refresh_secs: 30,
+            in_memory: false,
             enable_encryption: false,
             encryption_key: None,
             retention_days: None,
             retention_apps: HashMap::new(),
             retention_check_interval_hours: 24,
+            retention_max_total_bytes: None,
+            retention_default_app_policy: None,
+            device_delete_grace_hours: 0,
         };
 
         let storage = Arc::new(StorageEngine::new(storage_config).await.unwrap());
@@ -228,9 +449,28 @@ mod tests {
             tls_cert: None,
             tls_key: None,
             jwt_secret: "this-is-a-very-secure-secret-key-for-testing".to_string(),
+            jwt_verification_secrets: Vec::new(),
             jwt_expiration_hours: 1,
             rate_limit_per_minute: 100,
             cors_allowed_origins: vec!["*".to_string()],
+            cors_allow_credentials: false,
+            cors_exposed_headers: Vec::new(),
+            cors_max_age_secs: 0,
+            trusted_proxies: Vec::new(),
+            max_body_bytes: 2 * 1024 * 1024,
+            worker_threads: 0,
+            max_concurrent_queries: 0,
+            max_concurrent_ingests: 0,
+            token_retention_days: Some(30),
+            token_cleanup_interval_hours: 24,
+            default_query_window: None,
+            max_query_window: None,
+            slow_query_ms: 1000,
+            query_timing_header: false,
+            query_cache_ttl_secs: 0,
+            query_cache_max_entries: 256,
+            query_cache_strict_freshness: false,
+            tenants: HashMap::new(),
         };
 
         HttpServer::new(storage, jwt_service, api_token_store, api_config)
@@ -250,6 +490,155 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_readiness_endpoint() {
+        let server = create_test_server().await;
+        let app = server.build_router();
+
+        // Public like /health, so probes don't need a token
+        let request = Request::builder()
+            .uri("/health/ready")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "ready");
+        assert_eq!(json["disk_full"], false);
+        assert_eq!(json["memtable_full"], false);
+        assert!(json.get("mqtt_disconnected").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_readiness_with_disconnected_broker() {
+        let mqtt_config = crate::config::MqttConfig {
+            brokers: vec![crate::config::BrokerConfig {
+                name: "chirpstack".to_string(),
+                broker_url: "mqtt://localhost:1883".to_string(),
+                topic_prefix: "application".to_string(),
+                parser: crate::config::BrokerParser::ChirpStack,
+                username: None,
+                password: None,
+            }],
+            client_id: None,
+            clean_session: true,
+            tls_ca_cert: None,
+            tls_client_cert: None,
+            tls_client_key: None,
+            reconnect_interval_secs: 5,
+            max_reconnect_interval_secs: 300,
+            qos: crate::config::MqttQos::AtLeastOnce,
+        };
+        let server = create_test_server()
+            .await
+            .with_mqtt_status(MqttStatus::new(&mqtt_config));
+        let app = server.build_router();
+
+        // Not connected yet
+        let request = Request::builder()
+            .uri("/health/ready")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "degraded");
+        assert_eq!(json["mqtt_disconnected"], serde_json::json!(["chirpstack"]));
+    }
+
+    #[tokio::test]
+    async fn test_body_limit() {
+        let mut server = create_test_server().await;
+        server.max_body_bytes = 1024;
+        let app = server.build_router();
+
+        let jwt_service = JwtService::new("this-is-a-very-secure-secret-key-for-testing").unwrap();
+        let token = jwt_service
+            .generate_token(Claims::new("test-user".to_string()))
+            .unwrap();
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/query")
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(vec![b' '; 2048]))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_sheds_with_retry_after() {
+        let server = create_test_server().await;
+        let release = Arc::new(tokio::sync::Notify::new());
+        let held = release.clone();
+        let slow = move || {
+            let held = held.clone();
+            async move {
+                held.notified().await;
+                "done"
+            }
+        };
+        let app = limit_concurrency(Router::new().route("/slow", get(slow)), 1)
+            .with_state(server.app_state.clone());
+        let request = || Request::builder().uri("/slow").body(Body::empty()).unwrap();
+
+        // The first request holds the only permit until released
+        let first = tokio::spawn(app.clone().oneshot(request()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[http::header::RETRY_AFTER], "1");
+
+        release.notify_one();
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_response_compression() {
+        use std::io::Read;
+
+        let server = create_test_server().await;
+        let app = server.build_router();
+        let jwt_service = JwtService::new("this-is-a-very-secure-secret-key-for-testing").unwrap();
+        let token = jwt_service
+            .generate_token(Claims::new("test-user".to_string()))
+            .unwrap();
+
+        let request = |accept_encoding: Option<&str>| {
+            let mut builder = Request::builder()
+                .uri("/stats")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+            if let Some(encoding) = accept_encoding {
+                builder = builder.header(http::header::ACCEPT_ENCODING, encoding);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(request(Some("gzip"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[http::header::CONTENT_ENCODING], "gzip");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_string(&mut json)
+            .unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(&json).is_ok());
+
+        let response = app.clone().oneshot(request(Some("zstd"))).await.unwrap();
+        assert_eq!(response.headers()[http::header::CONTENT_ENCODING], "zstd");
+
+        let response = app.oneshot(request(None)).await.unwrap();
+        assert!(response.headers().get(http::header::CONTENT_ENCODING).is_none());
+    }
+
     #[tokio::test]
     async fn test_protected_endpoint_without_auth() {
         let server = create_test_server().await;
@@ -284,6 +673,73 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_tenant_scoped_token_is_kept_off_shared_routes() {
+        let server = create_test_server().await;
+        let app = server.build_router();
+
+        let jwt_service = JwtService::new("this-is-a-very-secure-secret-key-for-testing").unwrap();
+        let mut claims = Claims::new("test-user".to_string());
+        claims.tenant = Some("acme".to_string());
+        let token = jwt_service.generate_token(claims).unwrap();
+
+        let get = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(get("/devices")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        for uri in ["/stats", "/gateways/map", "/retention/policies"] {
+            let response = app.clone().oneshot(get(uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_via_get() {
+        let server = create_test_server().await;
+        let app = server.build_router();
+
+        let jwt_service = JwtService::new("this-is-a-very-secure-secret-key-for-testing").unwrap();
+        let token = jwt_service
+            .generate_token(Claims::new("test-user".to_string()))
+            .unwrap();
+
+        let get = |uri: String| {
+            Request::builder()
+                .uri(uri)
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // SELECT * FROM device '0123456789ABCDEF' WHERE LAST '1h'
+        let uri = "/query?q=SELECT%20*%20FROM%20device%20%270123456789ABCDEF%27%20WHERE%20LAST%20%271h%27";
+        let response = app.clone().oneshot(get(uri.to_string())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Same length limit as POST
+        let uri = format!("/query?q={}", "x".repeat(10_001));
+        let response = app.clone().oneshot(get(uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["error"], "ValidationError");
+        assert_eq!(error["details"][0]["field"], "query");
+
+        // Still needs auth
+        let request = Request::builder()
+            .uri("/query?q=SELECT%20*")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
     #[tokio::test]
     async fn test_security_headers_present() {
         let server = create_test_server().await;
@@ -328,15 +784,41 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let storage_config = StorageConfig {
             data_dir: temp_dir.path().to_path_buf(),
-            wal_sync_interval_ms: 1000,
+            wal_durability: WalDurability::Interval(1000),
             memtable_size_mb: 1,
+            memtable_hard_limit_factor: 0,
             memtable_flush_interval_secs: 300,
             compaction_threshold: 3,
+            max_open_sstables: 128,
+            warmup_sstables: 0,
+            warmup_read_data: false,
+            compaction_max_bytes_per_sec: 0,
+            compaction_fragmentation_threshold: 0.75,
+            compaction_target_sstable_entries: 10_000,
+            max_sstable_bytes: 0,
+            layout: StorageLayout::Flat,
+            max_clock_skew_secs: 300,
+            clock_skew_action: ClockSkewAction::Reject,
+            timestamp_source: TimestampSource::Network,
+            application_key: ApplicationKey::Id,
+            unknown_application_action: UnknownApplicationAction::Fallback,
+            fallback_application_id: "unknown".to_string(),
+            max_payload_depth: 16,
+            max_payload_bytes: 64 * 1024,
+            oversized_payload_action: OversizedPayloadAction::Reject,
+            selftest_dev_eui: "fffffffffffffffe".to_string(),
+            maintenance_window: None,
+            read_only: false,
+            replica_refresh_secs: 30,
+            in_memory: false,
             enable_encryption: false,
             encryption_key: None,
             retention_days: None,
             retention_apps: HashMap::new(),
             retention_check_interval_hours: 24,
+            retention_max_total_bytes: None,
+            retention_default_app_policy: None,
+            device_delete_grace_hours: 0,
         };
 
         let storage = Arc::new(StorageEngine::new(storage_config).await.unwrap());
@@ -354,12 +836,31 @@ mod tests {
             tls_cert: None,
             tls_key: None,
             jwt_secret: "this-is-a-very-secure-secret-key-for-testing".to_string(),
+            jwt_verification_secrets: Vec::new(),
             jwt_expiration_hours: 1,
             rate_limit_per_minute: 100,
             cors_allowed_origins: vec![
                 "https://dashboard.example.com".to_string(),
                 "https://admin.example.com".to_string(),
             ],
+            cors_allow_credentials: false,
+            cors_exposed_headers: Vec::new(),
+            cors_max_age_secs: 0,
+            trusted_proxies: Vec::new(),
+            max_body_bytes: 2 * 1024 * 1024,
+            worker_threads: 0,
+            max_concurrent_queries: 0,
+            max_concurrent_ingests: 0,
+            token_retention_days: Some(30),
+            token_cleanup_interval_hours: 24,
+            default_query_window: None,
+            max_query_window: None,
+            slow_query_ms: 1000,
+            query_timing_header: false,
+            query_cache_ttl_secs: 0,
+            query_cache_max_entries: 256,
+            query_cache_strict_freshness: false,
+            tenants: HashMap::new(),
         };
 
         let server = HttpServer::new(storage, jwt_service, api_token_store, api_config);
@@ -379,4 +880,44 @@ mod tests {
         let headers = response.headers();
         assert!(headers.contains_key(http::header::ACCESS_CONTROL_ALLOW_ORIGIN));
     }
+
+    #[tokio::test]
+    async fn test_cors_credentials_exposed_headers_and_max_age() {
+        let mut server = create_test_server().await;
+        server.cors_allowed_origins = vec!["https://dashboard.example.com".to_string()];
+        server.cors_allow_credentials = true;
+        server.cors_exposed_headers = vec!["X-Custom-Total".to_string()];
+        server.cors_max_age_secs = 600;
+        let app = server.build_router();
+
+        let preflight = Request::builder()
+            .method(http::Method::OPTIONS)
+            .uri("/query")
+            .header(http::header::ORIGIN, "https://dashboard.example.com")
+            .header(http::header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(preflight).await.unwrap();
+        let headers = response.headers();
+        assert_eq!(headers[http::header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[http::header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let request = Request::builder()
+            .uri("/health")
+            .header(http::header::ORIGIN, "https://dashboard.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let headers = response.headers();
+        assert_eq!(
+            headers[http::header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://dashboard.example.com"
+        );
+        let exposed = headers[http::header::ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap()
+            .to_ascii_lowercase();
+        assert!(exposed.contains("x-query-time-ms"));
+        assert!(exposed.contains("x-custom-total"));
+    }
 }
@@ -1,14 +1,118 @@
 use crate::security::jwt::{Claims, JwtService};
 use crate::security::api_token::ApiTokenStore;
+use crate::error::LoraDbError;
+use crate::util::net::IpCidr;
 use axum::{
     body::Body,
-    extract::State,
+    extract::{ConnectInfo, State},
     http::{header, HeaderMap, HeaderValue, Request, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
+    BoxError,
 };
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tracing::warn;
+use tracing::{warn, Instrument};
+
+/// Header carrying the per-request correlation ID
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request ID we accept before generating our own
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    /// Request ID of the request currently being handled on this task
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// Correlation ID for a request, available to handlers as an extension
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Request ID of the request being handled, if called from inside `request_id`
+pub fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Header a reverse proxy appends each hop's client address to
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Header nginx-style proxies set to the address they received the request from
+pub const REAL_IP_HEADER: &str = "x-real-ip";
+
+/// Address of the client that made a request, resolved through trusted proxies by `client_ip`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Reverse proxies whose forwarding headers are believed
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Arc<Vec<IpCidr>>);
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpCidr>) -> Self {
+        Self(Arc::new(networks))
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+
+    /// The client address for a request received from `peer`
+    ///
+    /// Headers are ignored unless `peer` is trusted, anyone can send them. Otherwise
+    /// `X-Forwarded-For` is read right to left, skipping trusted proxies, so a client
+    /// can't pose as another address by sending its own header. `X-Real-IP` is used
+    /// when there is no `X-Forwarded-For`
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.contains(&peer) {
+            return peer;
+        }
+
+        let forwarded: Vec<IpAddr> = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+        if let Some(first) = forwarded.first() {
+            // When every hop is a trusted proxy, the first one is as close to the client as it gets
+            return *forwarded
+                .iter()
+                .rev()
+                .find(|hop| !self.contains(hop))
+                .unwrap_or(first);
+        }
+
+        headers
+            .get(REAL_IP_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(peer)
+    }
+}
+
+/// Client IP middleware
+///
+/// Resolves the client address from the connection peer and, behind trusted proxies,
+/// the forwarding headers, and stores it as a `ClientIp` extension. Without connection
+/// info (e.g. in tests) no `ClientIp` is added
+pub async fn client_ip(
+    State(trusted): State<TrustedProxies>,
+    mut request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Some(peer) = peer {
+        let ip = trusted.resolve(peer, request.headers());
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+
+    next.run(request).await
+}
 
 /// Authentication context that can be extracted by handlers
 #[derive(Clone, Debug)]
@@ -16,7 +120,14 @@ pub enum AuthContext {
     /// JWT-based authentication
     Jwt(Claims),
     /// API token-based authentication
-    ApiToken { user_id: String, token_id: String },
+    ApiToken {
+        user_id: String,
+        token_id: String,
+        /// Frame fields this token may see (None = unrestricted)
+        allowed_fields: Option<Vec<String>>,
+        /// Tenant whose applications this token is confined to (None = every tenant)
+        tenant_id: Option<String>,
+    },
 }
 
 impl AuthContext {
@@ -27,6 +138,43 @@ impl AuthContext {
             AuthContext::ApiToken { user_id, .. } => user_id,
         }
     }
+
+    /// Whether this is a JWT carrying the admin role, API tokens never are
+    /// A JWT confined to a tenant is never an admin either, whatever its role
+    pub fn is_admin(&self) -> bool {
+        match self {
+            AuthContext::Jwt(claims) => {
+                claims.role.as_deref() == Some("admin") && claims.tenant.is_none()
+            }
+            AuthContext::ApiToken { .. } => false,
+        }
+    }
+
+    /// Tenant this context is confined to, None for access to every tenant
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            AuthContext::Jwt(claims) => claims.tenant.as_deref(),
+            AuthContext::ApiToken { tenant_id, .. } => tenant_id.as_deref(),
+        }
+    }
+
+    /// Get the frame field allowlist, if this context is restricted
+    pub fn allowed_fields(&self) -> Option<&[String]> {
+        match self {
+            AuthContext::Jwt(_) => None,
+            AuthContext::ApiToken { allowed_fields, .. } => allowed_fields.as_deref(),
+        }
+    }
+
+    /// Whether the field allowlist covers the whole frame field at `path` (dot-separated),
+    /// always true without an allowlist
+    pub fn can_read_field(&self, path: &str) -> bool {
+        self.allowed_fields().map_or(true, |fields| {
+            fields
+                .iter()
+                .any(|field| path == field || path.starts_with(&format!("{}.", field)))
+        })
+    }
 }
 
 /// Authentication middleware state
@@ -73,6 +221,8 @@ pub async fn jwt_auth(
             Ok(api_token) => AuthContext::ApiToken {
                 user_id: api_token.created_by.clone(),
                 token_id: api_token.id.clone(),
+                allowed_fields: api_token.allowed_fields.clone(),
+                tenant_id: api_token.tenant_id.clone(),
             },
             Err(e) => {
                 warn!("API token validation failed: {}", e);
@@ -100,6 +250,40 @@ pub async fn jwt_auth(
     Ok(next.run(request).await)
 }
 
+/// Keep tenant-scoped principals out of routes that span or configure every tenant
+/// Must run after `jwt_auth`, which provides the `AuthContext`
+pub async fn deny_tenants(
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, StatusCode> {
+    let tenant = request
+        .extensions()
+        .get::<AuthContext>()
+        .and_then(AuthContext::tenant);
+    if let Some(tenant) = tenant {
+        warn!(tenant, path = request.uri().path(), "Tenant-scoped request to a shared route");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Seconds clients turned away by a concurrency limit are asked to wait
+pub const OVERLOAD_RETRY_AFTER_SECS: u64 = 1;
+
+/// Error handler for routes behind a concurrency limit, which sheds requests beyond it
+pub async fn overloaded(err: BoxError) -> Response {
+    warn!(error = %err, "Concurrency limit reached, shedding request");
+    let mut response = LoraDbError::Overloaded(
+        "Too many requests in flight, retry shortly".to_string(),
+    )
+    .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(OVERLOAD_RETRY_AFTER_SECS));
+    response
+}
+
 /// Security headers middleware
 pub async fn security_headers(request: Request<Body>, next: Next<Body>) -> Response {
     let mut response = next.run(request).await;
@@ -145,6 +329,52 @@ pub async fn security_headers(request: Request<Body>, next: Next<Body>) -> Respo
     response
 }
 
+/// Request ID middleware
+///
+/// Reuses a well-formed incoming `X-Request-Id` or generates a UUID, records it on the
+/// tracing span for the request and echoes it back in the response header
+pub async fn request_id(mut request: Request<Body>, next: Next<Body>) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_request_id(value))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        client_ip = tracing::field::Empty,
+    );
+    if let Some(ClientIp(ip)) = request.extensions().get::<ClientIp>() {
+        span.record("client_ip", tracing::field::display(ip));
+    }
+    let mut response = CURRENT_REQUEST_ID
+        .scope(id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+/// Client-supplied IDs end up in logs and headers, so keep them short and plain
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
 /// CORS headers (for development/testing - should be restricted in production)
 pub fn cors_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
@@ -336,4 +566,117 @@ mod tests {
         assert!(headers.contains_key(header::X_FRAME_OPTIONS));
         assert!(headers.contains_key(header::X_CONTENT_TYPE_OPTIONS));
     }
+
+    #[test]
+    fn test_trusted_proxies_resolve() {
+        let trusted = TrustedProxies::new(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "fd00::/8".parse().unwrap(),
+        ]);
+        let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut map = HeaderMap::new();
+            for (name, value) in pairs {
+                map.append(*name, HeaderValue::from_str(value).unwrap());
+            }
+            map
+        };
+
+        // An untrusted peer's headers are ignored
+        let spoofed = headers(&[(FORWARDED_FOR_HEADER, "1.2.3.4"), (REAL_IP_HEADER, "5.6.7.8")]);
+        assert_eq!(trusted.resolve(ip("203.0.113.9"), &spoofed), ip("203.0.113.9"));
+
+        // Behind a trusted proxy the nearest untrusted hop wins, not a value the client prepended
+        let chain = headers(&[(FORWARDED_FOR_HEADER, "1.2.3.4, 198.51.100.7, 10.0.0.2")]);
+        assert_eq!(trusted.resolve(ip("10.0.0.1"), &chain), ip("198.51.100.7"));
+
+        // Hops split across repeated headers, IPv6 proxy peer
+        let repeated = headers(&[
+            (FORWARDED_FOR_HEADER, "198.51.100.7"),
+            (FORWARDED_FOR_HEADER, "10.0.0.2"),
+        ]);
+        assert_eq!(trusted.resolve(ip("fd00::1"), &repeated), ip("198.51.100.7"));
+
+        // Only trusted hops: the first one
+        let internal = headers(&[(FORWARDED_FOR_HEADER, "10.0.0.5, 10.0.0.2")]);
+        assert_eq!(trusted.resolve(ip("10.0.0.1"), &internal), ip("10.0.0.5"));
+
+        // X-Real-IP without X-Forwarded-For, and the peer when neither parses
+        let real_ip = headers(&[(REAL_IP_HEADER, "198.51.100.7")]);
+        assert_eq!(trusted.resolve(ip("10.0.0.1"), &real_ip), ip("198.51.100.7"));
+        let garbage = headers(&[(FORWARDED_FOR_HEADER, "unknown")]);
+        assert_eq!(trusted.resolve(ip("10.0.0.1"), &garbage), ip("10.0.0.1"));
+
+        // Nothing trusted by default
+        let default = TrustedProxies::default();
+        assert_eq!(default.resolve(ip("10.0.0.1"), &chain), ip("10.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_middleware() {
+        async fn show_ip(client: Option<Extension<ClientIp>>) -> String {
+            client.map_or("none".to_string(), |Extension(ClientIp(ip))| ip.to_string())
+        }
+
+        let trusted = TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()]);
+        let app = Router::new()
+            .route("/ip", get(show_ip))
+            .layer(middleware::from_fn_with_state(trusted, client_ip));
+
+        let call = |peer: &str| {
+            let mut request = Request::builder()
+                .uri("/ip")
+                .header(FORWARDED_FOR_HEADER, "198.51.100.7")
+                .body(Body::empty())
+                .unwrap();
+            if !peer.is_empty() {
+                let addr: SocketAddr = peer.parse().unwrap();
+                request.extensions_mut().insert(ConnectInfo(addr));
+            }
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        assert_eq!(call("10.0.0.1:40000").await, "198.51.100.7");
+        assert_eq!(call("203.0.113.9:40000").await, "203.0.113.9");
+        assert_eq!(call("").await, "none");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_propagation() {
+        async fn failing_handler() -> Result<&'static str, crate::error::LoraDbError> {
+            Err(crate::error::LoraDbError::QueryParseError("bad query".to_string()))
+        }
+
+        let app = Router::new()
+            .route("/fail", get(failing_handler))
+            .layer(middleware::from_fn(request_id));
+
+        // Incoming ID is echoed in the header and the error body
+        let request = Request::builder()
+            .uri("/fail")
+            .header(REQUEST_ID_HEADER, "client-abc.123")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-abc.123");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["request_id"], "client-abc.123");
+
+        // Malformed IDs are replaced with a generated one
+        let request = Request::builder()
+            .uri("/fail")
+            .header(REQUEST_ID_HEADER, "bad id with spaces")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let generated = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+    }
 }
@@ -1,3 +1,4 @@
 pub mod http;
 pub mod handlers;
 pub mod middleware;
+pub mod format;
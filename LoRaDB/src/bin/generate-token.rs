@@ -5,9 +5,11 @@ fn main() -> anyhow::Result<()> {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        eprintln!("Usage: {} <username> [jwt_secret] [expiration_hours]", args[0]);
+        eprintln!("Usage: {} <username> [jwt_secret] [expiration_hours] [role] [tenant]", args[0]);
         eprintln!("\nIf jwt_secret is not provided, it will be read from LORADB_API_JWT_SECRET env var");
         eprintln!("If expiration_hours is not provided, it will be read from LORADB_API_JWT_EXPIRATION_HOURS env var (default: 1)");
+        eprintln!("Pass 'admin' as the role for tokens that may use admin-only endpoints such as GET /admin/sstables");
+        eprintln!("Pass a tenant to confine the token to that tenant's applications (LORADB_API_TENANTS), use '' as the role for none");
         std::process::exit(1);
     }
 
@@ -36,13 +38,22 @@ fn main() -> anyhow::Result<()> {
     let jwt_service = JwtService::new(&jwt_secret)?;
 
     // Create claims with configured expiration
-    let claims = Claims::with_expiration_hours(username.to_string(), expiration_hours);
+    let role = args.get(4).cloned();
+    let mut claims = Claims::with_expiration_hours(username.to_string(), expiration_hours);
+    claims.role = role.clone().filter(|role| !role.is_empty());
+    claims.tenant = args.get(5).cloned().filter(|tenant| !tenant.is_empty());
 
     // Generate token
-    let token = jwt_service.generate_token(claims)?;
+    let token = jwt_service.generate_token(claims.clone())?;
 
     println!("Generated JWT token for user '{}':", username);
     println!("Expiration: {} hour{}", expiration_hours, if expiration_hours == 1 { "" } else { "s" });
+    if let Some(role) = &claims.role {
+        println!("Role: {}", role);
+    }
+    if let Some(tenant) = &claims.tenant {
+        println!("Tenant: {}", tenant);
+    }
     println!("\n{}\n", token);
     println!("Use this token in API requests:");
     println!("curl -H 'Authorization: Bearer {}' https://your-domain.com/devices", token);
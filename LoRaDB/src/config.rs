@@ -1,4 +1,8 @@
 use crate::error::LoraDbError;
+use crate::model::lorawan::DevEui;
+use crate::storage::retention_manager::DefaultApplicationPolicy;
+use crate::util::clock::MaintenanceWindow;
+use crate::util::net::IpCidr;
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::env;
@@ -10,34 +14,365 @@ pub struct Config {
     pub mqtt: MqttConfig,
     pub storage: StorageConfig,
     pub api: ApiConfig,
+    pub alerts: AlertConfig,
 }
 
 #[derive(Debug, Clone)]
 pub struct MqttConfig {
-    pub chirpstack_broker: Option<String>,
-    pub ttn_broker: Option<String>,
-    pub client_id: String,
-    pub username: Option<String>,
-    pub password: Option<String>,
+    /// One client is started per broker (empty = MQTT ingestion disabled)
+    pub brokers: Vec<BrokerConfig>,
+    /// Stable client ID prefix, each broker connection appends its name
+    /// (None = a fresh random ID on every connection)
+    pub client_id: Option<String>,
+    /// Start a clean session on connect; when false the broker keeps the
+    /// subscription and queues QoS 1/2 messages while LoRaDB is disconnected
+    pub clean_session: bool,
     pub tls_ca_cert: Option<PathBuf>,
     pub tls_client_cert: Option<PathBuf>,
     pub tls_client_key: Option<PathBuf>,
     pub reconnect_interval_secs: u64,
     pub max_reconnect_interval_secs: u64,
+    /// QoS level requested when subscribing to broker topics
+    pub qos: MqttQos,
+}
+
+/// One MQTT broker connection
+#[derive(Debug, Clone)]
+pub struct BrokerConfig {
+    /// Unique name, used in logs and appended to the client ID
+    pub name: String,
+    pub broker_url: String,
+    /// Subscribed to as `{topic_prefix}/#`
+    pub topic_prefix: String,
+    pub parser: BrokerParser,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Message format of a broker's uplinks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrokerParser {
+    ChirpStack,
+    Ttn,
+}
+
+impl BrokerParser {
+    /// Topic prefix the network server publishes device events under
+    pub fn default_topic_prefix(self) -> &'static str {
+        match self {
+            BrokerParser::ChirpStack => "application/+/device/+/event",
+            BrokerParser::Ttn => "v3/+/devices/+",
+        }
+    }
+}
+
+impl std::str::FromStr for BrokerParser {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "chirpstack" => Ok(BrokerParser::ChirpStack),
+            "ttn" => Ok(BrokerParser::Ttn),
+            other => Err(format!(
+                "unknown broker parser '{}', expected chirpstack or ttn",
+                other
+            )),
+        }
+    }
+}
+
+/// MQTT delivery guarantee for subscriptions
+///
+/// At most once never redelivers, so it avoids redelivery storms on busy
+/// brokers at the cost of losing messages when a connection drops. At least
+/// once redelivers anything unacknowledged, which can store a frame twice.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MqttQos {
+    /// QoS 0
+    AtMostOnce,
+    /// QoS 1 (default)
+    #[default]
+    AtLeastOnce,
+    /// QoS 2
+    ExactlyOnce,
+}
+
+impl std::str::FromStr for MqttQos {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "0" | "at_most_once" => Ok(MqttQos::AtMostOnce),
+            "1" | "at_least_once" => Ok(MqttQos::AtLeastOnce),
+            "2" | "exactly_once" => Ok(MqttQos::ExactlyOnce),
+            other => Err(format!(
+                "unknown MQTT QoS '{}', expected 0, 1 or 2",
+                other
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct StorageConfig {
     pub data_dir: PathBuf,
-    pub wal_sync_interval_ms: u64,
+    pub wal_durability: WalDurability,
     pub memtable_size_mb: usize,
+    /// Writes are refused once the memtable reaches this many times `memtable_size_mb`,
+    /// e.g. while flushes keep failing (0 = no limit, never applies in memory)
+    pub memtable_hard_limit_factor: usize,
     pub memtable_flush_interval_secs: u64,
     pub compaction_threshold: usize,
+    /// SSTables whose index and file are kept open at once, others are reopened on demand
+    pub max_open_sstables: usize,
+    /// Newest SSTables whose index is loaded into the open-table cache on startup (0 = none)
+    pub warmup_sstables: usize,
+    /// Also read the warmed-up SSTables' files once, so their data is in the OS page cache
+    pub warmup_read_data: bool,
+    /// Compaction IO cap in bytes per second (0 = unlimited)
+    pub compaction_max_bytes_per_sec: u64,
+    /// Fragmentation score (0 to 1) at which a directory is compacted before it
+    /// reaches `compaction_threshold` SSTables (0 = count only)
+    pub compaction_fragmentation_threshold: f64,
+    /// Entries an SSTable is expected to hold, below which it counts as fragmented
+    pub compaction_target_sstable_entries: u64,
+    /// Data size at which a flush starts a new SSTable (0 = one SSTable per flush)
+    pub max_sstable_bytes: u64,
+    /// How SSTables are arranged under `data_dir`
+    pub layout: StorageLayout,
+    /// How far a frame timestamp may be ahead of the local clock (0 = no limit)
+    pub max_clock_skew_secs: u64,
+    /// What to do with frames outside the clock skew tolerance
+    pub clock_skew_action: ClockSkewAction,
+    /// Which clock frames are keyed and queried by
+    pub timestamp_source: TimestampSource,
+    /// Which ChirpStack field frames are stored under as their application ID
+    pub application_key: ApplicationKey,
+    /// What to do with frames that carry no application ID
+    pub unknown_application_action: UnknownApplicationAction,
+    /// Application ID given to frames without one when the action is `fallback`
+    pub fallback_application_id: String,
+    /// Deepest nesting allowed in a decoded payload (0 = no limit)
+    pub max_payload_depth: usize,
+    /// Largest decoded payload allowed, in bytes of JSON (0 = no limit)
+    pub max_payload_bytes: usize,
+    /// What to do with frames whose decoded payload exceeds either limit
+    pub oversized_payload_action: OversizedPayloadAction,
+    /// DevEUI `POST /admin/selftest` writes its canary frames under, hidden from device listings
+    pub selftest_dev_eui: String,
+    /// UTC window compaction and retention are restricted to (None = any time)
+    pub maintenance_window: Option<MaintenanceWindow>,
+    /// Serve reads from a data directory another instance writes to, never writing to it
+    pub read_only: bool,
+    /// How often a read-only instance rescans the data directory for new SSTables
+    pub replica_refresh_secs: u64,
+    /// Keep everything in memory: no data directory, WAL or SSTables, data is lost on exit
+    pub in_memory: bool,
     pub enable_encryption: bool,
     pub encryption_key: Option<String>,
     pub retention_days: Option<u32>,
     pub retention_apps: HashMap<String, Option<u32>>,
     pub retention_check_interval_hours: u64,
+    /// Combined SSTable size above which the oldest SSTables are deleted (None = no limit)
+    pub retention_max_total_bytes: Option<u64>,
+    /// Policy given to applications without one when they first send data (None = global policy)
+    pub retention_default_app_policy: Option<DefaultApplicationPolicy>,
+    /// Hours a deleted device stays recoverable before its data is removed (0 = delete immediately)
+    pub device_delete_grace_hours: u64,
+}
+
+/// When WAL writes are fsynced to disk
+///
+/// Data-loss window on power failure or kernel crash (a process crash alone
+/// loses nothing in any mode, writes are always handed to the OS):
+/// - `None`: whatever the OS hasn't written back yet, typically up to ~30 seconds
+/// - `Interval(ms)`: up to `ms` milliseconds of writes
+/// - `Always`: nothing that was acknowledged, at the cost of one fsync per frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalDurability {
+    /// Never fsync explicitly, rely on the OS to write back
+    None,
+    /// fsync from a background task every N milliseconds
+    Interval(u64),
+    /// fsync after every append
+    Always,
+}
+
+impl WalDurability {
+    /// Parse a durability mode name, using `sync_interval_ms` for interval mode
+    pub fn parse(mode: &str, sync_interval_ms: u64) -> std::result::Result<Self, String> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(WalDurability::None),
+            "interval" => Ok(WalDurability::Interval(sync_interval_ms)),
+            "always" => Ok(WalDurability::Always),
+            other => Err(format!(
+                "unknown WAL durability '{}', expected 'none', 'interval' or 'always'",
+                other
+            )),
+        }
+    }
+}
+
+/// Handling of frames whose timestamp is too far in the future or implausibly old
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClockSkewAction {
+    /// Refuse the frame (default)
+    #[default]
+    Reject,
+    /// Store the frame with its timestamp replaced by the time it was received
+    Clamp,
+}
+
+impl std::str::FromStr for ClockSkewAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(ClockSkewAction::Reject),
+            "clamp" => Ok(ClockSkewAction::Clamp),
+            other => Err(format!(
+                "unknown clock skew action '{}', expected 'reject' or 'clamp'",
+                other
+            )),
+        }
+    }
+}
+
+/// Which clock a frame's timestamp comes from
+///
+/// Network time keeps the order frames were received in upstream, even when they
+/// arrive late or out of order, but trusts the network server and gateway clocks.
+/// Ingest time only trusts this server's clock and never runs backwards across
+/// writes, but a backlog replayed after an outage is stamped with the replay time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// The timestamp reported by the network server (default)
+    #[default]
+    Network,
+    /// The time this server stored the frame
+    Ingest,
+}
+
+impl std::str::FromStr for TimestampSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "network" => Ok(TimestampSource::Network),
+            "ingest" => Ok(TimestampSource::Ingest),
+            other => Err(format!(
+                "unknown timestamp source '{}', expected 'network' or 'ingest'",
+                other
+            )),
+        }
+    }
+}
+
+/// Handling of frames the network server sent without an application ID
+///
+/// Such frames match no per-application retention policy, schema or quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownApplicationAction {
+    /// Store the frame under the configured fallback application ID (default)
+    #[default]
+    Fallback,
+    /// Refuse the frame
+    Reject,
+}
+
+impl std::str::FromStr for UnknownApplicationAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fallback" => Ok(UnknownApplicationAction::Fallback),
+            "reject" => Ok(UnknownApplicationAction::Reject),
+            other => Err(format!(
+                "unknown application action '{}', expected 'fallback' or 'reject'",
+                other
+            )),
+        }
+    }
+}
+
+/// Handling of frames whose decoded payload is nested too deeply or too large
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizedPayloadAction {
+    /// Refuse the frame (default)
+    #[default]
+    Reject,
+    /// Store the frame, cutting the payload down to the depth limit and dropping it
+    /// entirely if it is still too large. The raw payload is kept
+    Truncate,
+}
+
+impl std::str::FromStr for OversizedPayloadAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(OversizedPayloadAction::Reject),
+            "truncate" => Ok(OversizedPayloadAction::Truncate),
+            other => Err(format!(
+                "oversized payload action '{}', expected 'reject' or 'truncate'",
+                other
+            )),
+        }
+    }
+}
+
+/// Which ChirpStack device info field becomes a frame's application ID at ingest
+///
+/// Retention policies, schemas, quotas, sampling rules and queries all match on the
+/// resulting ID, so they must name applications the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApplicationKey {
+    /// The application UUID, stable across renames and unique per tenant (default)
+    #[default]
+    Id,
+    /// The application display name, readable but split by renames and shared by
+    /// same-named applications
+    Name,
+}
+
+impl std::str::FromStr for ApplicationKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "id" => Ok(ApplicationKey::Id),
+            "name" => Ok(ApplicationKey::Name),
+            other => Err(format!(
+                "unknown application key '{}', expected 'id' or 'name'",
+                other
+            )),
+        }
+    }
+}
+
+/// SSTable directory layout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageLayout {
+    /// All SSTables directly in `data_dir` (default)
+    #[default]
+    Flat,
+    /// SSTables partitioned by application under `data_dir/<app_id>/`
+    PerApplication,
+}
+
+impl std::str::FromStr for StorageLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "flat" => Ok(StorageLayout::Flat),
+            "per-application" | "per_application" => Ok(StorageLayout::PerApplication),
+            other => Err(format!(
+                "unknown storage layout '{}', expected 'flat' or 'per-application'",
+                other
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -47,9 +382,59 @@ pub struct ApiConfig {
     pub tls_cert: Option<PathBuf>,
     pub tls_key: Option<PathBuf>,
     pub jwt_secret: String,
+    /// Previous secrets still accepted for validation during rotation (never used to sign)
+    pub jwt_verification_secrets: Vec<String>,
     pub jwt_expiration_hours: i64,
     pub rate_limit_per_minute: u32,
     pub cors_allowed_origins: Vec<String>,
+    /// Let browsers send cookies and Authorization headers cross-origin (needs specific origins)
+    pub cors_allow_credentials: bool,
+    /// Response headers scripts may read, on top of X-Request-Id and X-Query-Time-Ms
+    pub cors_exposed_headers: Vec<String>,
+    /// How long browsers may cache a preflight response, in seconds (0 = no Access-Control-Max-Age)
+    pub cors_max_age_secs: u64,
+    /// Reverse proxies whose X-Forwarded-For / X-Real-IP headers give the client IP
+    /// (empty = always use the connection's peer address)
+    pub trusted_proxies: Vec<IpCidr>,
+    /// Largest request body accepted on any route, in bytes
+    pub max_body_bytes: usize,
+    /// Tokio worker threads, shared by the API server, ingestion and background tasks
+    /// (0 = one per CPU core)
+    pub worker_threads: usize,
+    /// Most `/query` and export requests handled at once, others get 503 (0 = unlimited)
+    pub max_concurrent_queries: usize,
+    /// Most `/ingest` requests handled at once, others get 503 (0 = unlimited)
+    pub max_concurrent_ingests: usize,
+    /// Days expired or revoked API tokens are kept before removal (None = keep forever)
+    pub token_retention_days: Option<u32>,
+    /// How often expired and revoked API tokens are cleaned up, in hours
+    pub token_cleanup_interval_hours: u64,
+    /// Time window used for queries without LAST/SINCE/BETWEEN (None = reject them)
+    pub default_query_window: Option<chrono::Duration>,
+    /// Longest time range a query may cover (None = unlimited), exports aren't limited
+    pub max_query_window: Option<chrono::Duration>,
+    /// Queries taking at least this long are logged at WARN (0 = never)
+    pub slow_query_ms: u64,
+    /// Return each query's total time in an `X-Query-Time-Ms` response header
+    pub query_timing_header: bool,
+    /// How long repeated query results are served from the cache, in seconds (0 = no cache)
+    pub query_cache_ttl_secs: u64,
+    /// Most query results the cache holds at once
+    pub query_cache_max_entries: usize,
+    /// Only cache BETWEEN queries that end in the past, so results are never stale
+    pub query_cache_strict_freshness: bool,
+    /// Tenant each application belongs to, keyed by application ID (empty = no tenants)
+    pub tenants: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertConfig {
+    /// URL alerts are POSTed to as JSON, http:// or https:// (None = only log them)
+    pub webhook_url: Option<String>,
+    /// Alert once a device has been silent this long (None = no offline watchdog)
+    pub offline_threshold: Option<chrono::Duration>,
+    /// How often the offline watchdog checks the device registry, in seconds
+    pub offline_check_interval_secs: u64,
 }
 
 impl Config {
@@ -58,13 +443,11 @@ impl Config {
         dotenvy::dotenv().ok();
 
         let mqtt = MqttConfig {
-            chirpstack_broker: env::var("LORADB_MQTT_CHIRPSTACK_BROKER").ok(),
-            ttn_broker: env::var("LORADB_MQTT_TTN_BROKER").ok(),
-            client_id: env::var("LORADB_MQTT_CLIENT_ID").unwrap_or_else(|_| {
-                format!("loradb-{}", uuid::Uuid::new_v4())
-            }),
-            username: env::var("LORADB_MQTT_USERNAME").ok(),
-            password: env::var("LORADB_MQTT_PASSWORD").ok(),
+            brokers: parse_mqtt_brokers(|key| env::var(key).ok())?,
+            client_id: env::var("LORADB_MQTT_CLIENT_ID")
+                .ok()
+                .filter(|s| !s.trim().is_empty()),
+            clean_session: parse_env("LORADB_MQTT_CLEAN_SESSION", true)?,
             tls_ca_cert: env::var("LORADB_MQTT_CA_CERT").ok().map(PathBuf::from),
             tls_client_cert: env::var("LORADB_MQTT_CLIENT_CERT")
                 .ok()
@@ -80,8 +463,17 @@ impl Config {
                 "LORADB_MQTT_MAX_RECONNECT_INTERVAL_SECS",
                 300,
             )?,
+            qos: parse_env("LORADB_MQTT_QOS", MqttQos::AtLeastOnce)?,
         };
 
+        // The broker finds a persistent session by client ID, so it has to be stable
+        if !mqtt.clean_session && mqtt.client_id.is_none() {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_MQTT_CLEAN_SESSION=false requires LORADB_MQTT_CLIENT_ID to be set".to_string(),
+            )
+            .into());
+        }
+
         // Parse retention policy (optional - None means keep data forever)
         let retention_days = env::var("LORADB_STORAGE_RETENTION_DAYS")
             .ok()
@@ -111,16 +503,20 @@ impl Config {
             })
             .unwrap_or_default();
 
+        let wal_durability = WalDurability::parse(
+            &env::var("LORADB_STORAGE_WAL_DURABILITY").unwrap_or_else(|_| "interval".to_string()),
+            parse_env("LORADB_STORAGE_WAL_SYNC_INTERVAL_MS", 1000)?,
+        )
+        .map_err(LoraDbError::ConfigError)?;
+
         let storage = StorageConfig {
             data_dir: parse_env_path(
                 "LORADB_STORAGE_DATA_DIR",
                 "/var/lib/loradb",
             )?,
-            wal_sync_interval_ms: parse_env(
-                "LORADB_STORAGE_WAL_SYNC_INTERVAL_MS",
-                1000,
-            )?,
+            wal_durability,
             memtable_size_mb: parse_env("LORADB_STORAGE_MEMTABLE_SIZE_MB", 64)?,
+            memtable_hard_limit_factor: parse_env("LORADB_STORAGE_MEMTABLE_HARD_LIMIT_FACTOR", 4)?,
             memtable_flush_interval_secs: parse_env(
                 "LORADB_STORAGE_MEMTABLE_FLUSH_INTERVAL_SECS",
                 300,  // 5 minutes default
@@ -129,6 +525,60 @@ impl Config {
                 "LORADB_STORAGE_COMPACTION_THRESHOLD",
                 10,
             )?,
+            max_open_sstables: parse_env("LORADB_STORAGE_MAX_OPEN_SSTABLES", 128)?,
+            warmup_sstables: parse_env("LORADB_STORAGE_WARMUP_SSTABLES", 0)?,
+            warmup_read_data: parse_env("LORADB_STORAGE_WARMUP_READ_DATA", false)?,
+            compaction_max_bytes_per_sec: parse_env(
+                "LORADB_STORAGE_COMPACTION_MAX_BYTES_PER_SEC",
+                0,  // Unlimited by default
+            )?,
+            compaction_fragmentation_threshold: parse_env(
+                "LORADB_STORAGE_COMPACTION_FRAGMENTATION_THRESHOLD",
+                0.75,
+            )?,
+            compaction_target_sstable_entries: parse_env(
+                "LORADB_STORAGE_COMPACTION_TARGET_SSTABLE_ENTRIES",
+                10_000,
+            )?,
+            max_sstable_bytes: parse_env("LORADB_STORAGE_MAX_SSTABLE_BYTES", 0)?,
+            layout: parse_env("LORADB_STORAGE_LAYOUT", StorageLayout::Flat)?,
+            max_clock_skew_secs: parse_env(
+                "LORADB_STORAGE_MAX_CLOCK_SKEW_SECS",
+                300,  // 5 minutes default
+            )?,
+            clock_skew_action: parse_env(
+                "LORADB_STORAGE_CLOCK_SKEW_ACTION",
+                ClockSkewAction::Reject,
+            )?,
+            timestamp_source: parse_env(
+                "LORADB_STORAGE_TIMESTAMP_SOURCE",
+                TimestampSource::Network,
+            )?,
+            application_key: parse_env("LORADB_STORAGE_APPLICATION_KEY", ApplicationKey::Id)?,
+            unknown_application_action: parse_env(
+                "LORADB_STORAGE_UNKNOWN_APPLICATION_ACTION",
+                UnknownApplicationAction::Fallback,
+            )?,
+            fallback_application_id: env::var("LORADB_STORAGE_FALLBACK_APPLICATION_ID")
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(|_| "unknown".to_string()),
+            max_payload_depth: parse_env("LORADB_STORAGE_MAX_PAYLOAD_DEPTH", 16)?,
+            max_payload_bytes: parse_env("LORADB_STORAGE_MAX_PAYLOAD_BYTES", 64 * 1024)?,
+            oversized_payload_action: parse_env(
+                "LORADB_STORAGE_OVERSIZED_PAYLOAD_ACTION",
+                OversizedPayloadAction::Reject,
+            )?,
+            selftest_dev_eui: env::var("LORADB_STORAGE_SELFTEST_DEV_EUI")
+                .map(|s| s.trim().to_lowercase())
+                .unwrap_or_else(|_| "fffffffffffffffe".to_string()),
+            maintenance_window: env::var("LORADB_STORAGE_MAINTENANCE_WINDOW")
+                .ok()
+                .map(|s| s.parse::<MaintenanceWindow>())
+                .transpose()
+                .map_err(LoraDbError::ConfigError)?,
+            read_only: parse_env("LORADB_STORAGE_READ_ONLY", false)?,
+            replica_refresh_secs: parse_env("LORADB_STORAGE_REPLICA_REFRESH_SECS", 30)?,
+            in_memory: parse_env("LORADB_STORAGE_IN_MEMORY", false)?,
             enable_encryption: parse_env(
                 "LORADB_STORAGE_ENABLE_ENCRYPTION",
                 false,
@@ -140,8 +590,70 @@ impl Config {
                 "LORADB_STORAGE_RETENTION_CHECK_INTERVAL_HOURS",
                 24,  // Check once per day by default
             )?,
+            retention_max_total_bytes: env::var("LORADB_STORAGE_RETENTION_MAX_TOTAL_BYTES")
+                .ok()
+                .map(|s| {
+                    s.trim().parse::<u64>().map_err(|e| {
+                        LoraDbError::ConfigError(format!(
+                            "Invalid LORADB_STORAGE_RETENTION_MAX_TOTAL_BYTES '{}': {}",
+                            s, e
+                        ))
+                    })
+                })
+                .transpose()?,
+            // Days or "never", like the entries of LORADB_STORAGE_RETENTION_APPS
+            retention_default_app_policy: env::var("LORADB_STORAGE_RETENTION_DEFAULT_APP_DAYS")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| {
+                    if s.trim().eq_ignore_ascii_case("never") {
+                        return Ok(DefaultApplicationPolicy { days: None });
+                    }
+                    s.trim()
+                        .parse::<u32>()
+                        .map(|days| DefaultApplicationPolicy { days: Some(days) })
+                        .map_err(|e| {
+                            LoraDbError::ConfigError(format!(
+                                "Invalid LORADB_STORAGE_RETENTION_DEFAULT_APP_DAYS '{}': {} (expected days or 'never')",
+                                s, e
+                            ))
+                        })
+                })
+                .transpose()?,
+            device_delete_grace_hours: parse_env("LORADB_STORAGE_DEVICE_DELETE_GRACE_HOURS", 0)?,
         };
 
+        if storage.max_open_sstables == 0 {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_STORAGE_MAX_OPEN_SSTABLES must be greater than 0".to_string(),
+            )
+            .into());
+        }
+
+        if storage.in_memory && storage.read_only {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_STORAGE_IN_MEMORY and LORADB_STORAGE_READ_ONLY can't both be set, \
+                 a replica reads the primary's data directory"
+                    .to_string(),
+            )
+            .into());
+        }
+
+        if storage.fallback_application_id.is_empty() {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_STORAGE_FALLBACK_APPLICATION_ID must not be empty".to_string(),
+            )
+            .into());
+        }
+
+        if let Err(e) = DevEui::new(storage.selftest_dev_eui.clone()) {
+            return Err(LoraDbError::ConfigError(format!(
+                "Invalid LORADB_STORAGE_SELFTEST_DEV_EUI: {}",
+                e
+            ))
+            .into());
+        }
+
         // Validate encryption configuration
         if storage.enable_encryption && storage.encryption_key.is_none() {
             return Err(LoraDbError::ConfigError(
@@ -161,6 +673,33 @@ impl Config {
             .filter(|s| !s.is_empty())
             .collect::<Vec<String>>();
 
+        let cors_allow_credentials = parse_env("LORADB_API_CORS_ALLOW_CREDENTIALS", false)?;
+        // Browsers ignore credentialed responses that allow any origin
+        if cors_allow_credentials && cors_allowed_origins.iter().any(|origin| origin == "*") {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_API_CORS_ALLOW_CREDENTIALS requires specific LORADB_API_CORS_ALLOWED_ORIGINS, not *"
+                    .to_string(),
+            )
+            .into());
+        }
+
+        let cors_exposed_headers = env::var("LORADB_API_CORS_EXPOSED_HEADERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<String>>();
+        if let Some(invalid) = cors_exposed_headers
+            .iter()
+            .find(|name| axum::http::HeaderName::from_bytes(name.as_bytes()).is_err())
+        {
+            return Err(LoraDbError::ConfigError(format!(
+                "Invalid header name '{}' in LORADB_API_CORS_EXPOSED_HEADERS",
+                invalid
+            ))
+            .into());
+        }
+
         let api = ApiConfig {
             bind_addr: parse_env(
                 "LORADB_API_BIND_ADDR",
@@ -180,6 +719,15 @@ impl Config {
             jwt_secret: env::var("LORADB_API_JWT_SECRET").context(
                 "LORADB_API_JWT_SECRET must be set",
             )?,
+            jwt_verification_secrets: env::var("LORADB_API_JWT_VERIFICATION_SECRETS")
+                .ok()
+                .map(|s| {
+                    s.split(',')
+                        .map(|secret| secret.trim().to_string())
+                        .filter(|secret| !secret.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
             jwt_expiration_hours: parse_env(
                 "LORADB_API_JWT_EXPIRATION_HOURS",
                 1,
@@ -189,8 +737,58 @@ impl Config {
                 60,
             )?,
             cors_allowed_origins,
+            cors_allow_credentials,
+            cors_exposed_headers,
+            cors_max_age_secs: parse_env("LORADB_API_CORS_MAX_AGE_SECS", 0)?,
+            trusted_proxies: env::var("LORADB_API_TRUSTED_PROXIES")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse::<IpCidr>())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|e| {
+                    LoraDbError::ConfigError(format!("Invalid LORADB_API_TRUSTED_PROXIES: {}", e))
+                })?,
+            max_body_bytes: parse_env("LORADB_API_MAX_BODY_BYTES", 2 * 1024 * 1024)?,
+            worker_threads: parse_env("LORADB_API_WORKER_THREADS", 0)?,
+            max_concurrent_queries: parse_env("LORADB_API_MAX_CONCURRENT_QUERIES", 0)?,
+            max_concurrent_ingests: parse_env("LORADB_API_MAX_CONCURRENT_INGESTS", 0)?,
+            token_retention_days: match env::var("LORADB_API_TOKEN_RETENTION_DAYS") {
+                Ok(s) if s.trim().eq_ignore_ascii_case("never") => None,
+                Ok(s) => Some(s.trim().parse::<u32>().map_err(|e| {
+                    LoraDbError::ConfigError(format!(
+                        "Invalid LORADB_API_TOKEN_RETENTION_DAYS '{}': {} (expected days or 'never')",
+                        s, e
+                    ))
+                })?),
+                Err(_) => Some(30),
+            },
+            token_cleanup_interval_hours: parse_env("LORADB_API_TOKEN_CLEANUP_INTERVAL_HOURS", 24)?,
+            default_query_window: parse_env_window("LORADB_API_DEFAULT_QUERY_WINDOW")?,
+            max_query_window: parse_env_window("LORADB_API_MAX_QUERY_WINDOW")?,
+            slow_query_ms: parse_env("LORADB_API_SLOW_QUERY_MS", 1000)?,
+            query_timing_header: parse_env("LORADB_API_QUERY_TIMING_HEADER", false)?,
+            query_cache_ttl_secs: parse_env("LORADB_API_QUERY_CACHE_TTL_SECS", 0)?,
+            query_cache_max_entries: parse_env("LORADB_API_QUERY_CACHE_MAX_ENTRIES", 256)?,
+            query_cache_strict_freshness: parse_env("LORADB_API_QUERY_CACHE_STRICT_FRESHNESS", false)?,
+            tenants: parse_tenants(&env::var("LORADB_API_TENANTS").unwrap_or_default())?,
         };
 
+        if api.token_cleanup_interval_hours == 0 {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_API_TOKEN_CLEANUP_INTERVAL_HOURS must be greater than 0".to_string(),
+            )
+            .into());
+        }
+
+        if api.max_body_bytes == 0 {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_API_MAX_BODY_BYTES must be greater than 0".to_string(),
+            )
+            .into());
+        }
+
         // Validate JWT secret length
         if api.jwt_secret.len() < 32 {
             return Err(LoraDbError::ConfigError(
@@ -199,16 +797,49 @@ impl Config {
             .into());
         }
 
+        if api.jwt_verification_secrets.iter().any(|s| s.len() < 32) {
+            return Err(LoraDbError::ConfigError(
+                "JWT verification secrets must be at least 32 characters".to_string(),
+            )
+            .into());
+        }
+
+        let alerts = AlertConfig {
+            webhook_url: env::var("LORADB_ALERT_WEBHOOK_URL")
+                .ok()
+                .filter(|url| !url.trim().is_empty()),
+            offline_threshold: parse_env_window("LORADB_ALERT_OFFLINE_THRESHOLD")?,
+            offline_check_interval_secs: parse_env("LORADB_ALERT_OFFLINE_CHECK_INTERVAL_SECS", 60)?,
+        };
+
+        if let Some(url) = &alerts.webhook_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(LoraDbError::ConfigError(format!(
+                    "LORADB_ALERT_WEBHOOK_URL must be an http:// or https:// URL, got '{}'",
+                    url
+                ))
+                .into());
+            }
+        }
+
+        if alerts.offline_check_interval_secs == 0 {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_ALERT_OFFLINE_CHECK_INTERVAL_SECS must be greater than 0".to_string(),
+            )
+            .into());
+        }
+
         Ok(Config {
             mqtt,
             storage,
             api,
+            alerts,
         })
     }
 
     pub fn validate(&self) -> Result<()> {
         // MQTT ingestion is now optional - HTTP ingestion can be used instead
-        // No validation required for MQTT brokers (both can be None)
+        // No validation required for MQTT brokers (the list can be empty)
 
         // Validate TLS certificate paths exist if TLS is enabled
         if self.api.enable_tls {
@@ -243,6 +874,24 @@ impl Config {
             }
         }
 
+        if let (Some(default), Some(max)) = (self.api.default_query_window, self.api.max_query_window) {
+            if default > max {
+                return Err(LoraDbError::ConfigError(
+                    "LORADB_API_DEFAULT_QUERY_WINDOW must not exceed LORADB_API_MAX_QUERY_WINDOW"
+                        .to_string(),
+                )
+                .into());
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.storage.compaction_fragmentation_threshold) {
+            return Err(LoraDbError::ConfigError(
+                "LORADB_STORAGE_COMPACTION_FRAGMENTATION_THRESHOLD must be between 0 and 1"
+                    .to_string(),
+            )
+            .into());
+        }
+
         // Validate MQTT CA cert if provided
         if let Some(ref ca_cert) = self.mqtt.tls_ca_cert {
             if !ca_cert.exists() {
@@ -272,6 +921,75 @@ impl Config {
     }
 }
 
+/// MQTT brokers from `LORADB_MQTT_CHIRPSTACK_BROKER`, `LORADB_MQTT_TTN_BROKER` and the
+/// named brokers in `LORADB_MQTT_BROKERS`, each configured by `LORADB_MQTT_BROKER_<NAME>_*`
+fn parse_mqtt_brokers(var: impl Fn(&str) -> Option<String>) -> Result<Vec<BrokerConfig>> {
+    let username = var("LORADB_MQTT_USERNAME");
+    let password = var("LORADB_MQTT_PASSWORD");
+    let mut brokers = Vec::new();
+
+    // Single-broker variables, kept as brokers named after their parser
+    for (key, name, parser) in [
+        ("LORADB_MQTT_CHIRPSTACK_BROKER", "chirpstack", BrokerParser::ChirpStack),
+        ("LORADB_MQTT_TTN_BROKER", "ttn", BrokerParser::Ttn),
+    ] {
+        if let Some(broker_url) = var(key) {
+            brokers.push(BrokerConfig {
+                name: name.to_string(),
+                broker_url,
+                topic_prefix: parser.default_topic_prefix().to_string(),
+                parser,
+                username: username.clone(),
+                password: password.clone(),
+            });
+        }
+    }
+
+    let names = var("LORADB_MQTT_BROKERS").unwrap_or_default();
+    for name in names.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(LoraDbError::ConfigError(format!(
+                "Invalid broker name '{}' in LORADB_MQTT_BROKERS (use letters, digits, - and _)",
+                name
+            ))
+            .into());
+        }
+        if brokers.iter().any(|broker| broker.name.eq_ignore_ascii_case(name)) {
+            return Err(LoraDbError::ConfigError(format!(
+                "MQTT broker '{}' is configured more than once",
+                name
+            ))
+            .into());
+        }
+
+        let prefix = format!(
+            "LORADB_MQTT_BROKER_{}_",
+            name.to_ascii_uppercase().replace('-', "_")
+        );
+        let broker_url = var(&format!("{}URL", prefix)).ok_or_else(|| {
+            LoraDbError::ConfigError(format!("{}URL must be set for broker '{}'", prefix, name))
+        })?;
+        let parser = match var(&format!("{}PARSER", prefix)) {
+            Some(s) => s.parse::<BrokerParser>().map_err(|e| {
+                LoraDbError::ConfigError(format!("Invalid {}PARSER: {}", prefix, e))
+            })?,
+            None => BrokerParser::ChirpStack,
+        };
+
+        brokers.push(BrokerConfig {
+            name: name.to_string(),
+            broker_url,
+            topic_prefix: var(&format!("{}TOPIC_PREFIX", prefix))
+                .unwrap_or_else(|| parser.default_topic_prefix().to_string()),
+            parser,
+            username: var(&format!("{}USERNAME", prefix)).or_else(|| username.clone()),
+            password: var(&format!("{}PASSWORD", prefix)).or_else(|| password.clone()),
+        });
+    }
+
+    Ok(brokers)
+}
+
 fn parse_env<T: std::str::FromStr>(key: &str, default: T) -> Result<T>
 where
     T::Err: std::fmt::Display,
@@ -287,6 +1005,52 @@ where
         .map(|opt| opt.unwrap_or(default))
 }
 
+/// Positive query duration such as "1h" or "90d" (unset or empty = None)
+fn parse_env_window(key: &str) -> Result<Option<chrono::Duration>> {
+    match env::var(key) {
+        Ok(s) if !s.trim().is_empty() => {
+            let window = crate::query::parser::parse_duration(&s).map_err(|e| {
+                LoraDbError::ConfigError(format!(
+                    "Invalid {} '{}': {} (expected e.g. '1h', '7d')",
+                    key, s, e
+                ))
+            })?;
+            if window <= chrono::Duration::zero() {
+                return Err(
+                    LoraDbError::ConfigError(format!("{} must be greater than 0", key)).into(),
+                );
+            }
+            Ok(Some(window))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Parse `app-id:tenant-id` pairs, comma separated, into tenants keyed by application
+fn parse_tenants(value: &str) -> Result<HashMap<String, String>> {
+    let mut tenants = HashMap::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (app_id, tenant) = entry
+            .rsplit_once(':')
+            .map(|(app_id, tenant)| (app_id.trim(), tenant.trim()))
+            .filter(|(app_id, tenant)| !app_id.is_empty() && !tenant.is_empty())
+            .ok_or_else(|| {
+                LoraDbError::ConfigError(format!(
+                    "Invalid LORADB_API_TENANTS entry '{}' (expected app-id:tenant-id)",
+                    entry
+                ))
+            })?;
+        if tenants.insert(app_id.to_string(), tenant.to_string()).is_some() {
+            return Err(LoraDbError::ConfigError(format!(
+                "Application '{}' is assigned to more than one tenant in LORADB_API_TENANTS",
+                app_id
+            ))
+            .into());
+        }
+    }
+    Ok(tenants)
+}
+
 fn parse_env_path(key: &str, default: &str) -> Result<PathBuf> {
     Ok(env::var(key).unwrap_or_else(|_| default.to_string()).into())
 }
@@ -296,3 +1060,69 @@ fn parse_env_path_required(key: &str) -> Result<PathBuf> {
         .context(format!("{} must be set", key))
         .map(PathBuf::from)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brokers(vars: &[(&str, &str)]) -> Result<Vec<BrokerConfig>> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        parse_mqtt_brokers(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn test_parse_mqtt_brokers() {
+        // Single-broker variables map to a one-element list
+        let list = brokers(&[
+            ("LORADB_MQTT_CHIRPSTACK_BROKER", "mqtt://localhost:1883"),
+            ("LORADB_MQTT_USERNAME", "loradb"),
+        ])
+        .unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].name, "chirpstack");
+        assert_eq!(list[0].topic_prefix, "application/+/device/+/event");
+        assert_eq!(list[0].username.as_deref(), Some("loradb"));
+
+        let list = brokers(&[
+            ("LORADB_MQTT_CHIRPSTACK_BROKER", "mqtt://localhost:1883"),
+            ("LORADB_MQTT_USERNAME", "loradb"),
+            ("LORADB_MQTT_BROKERS", "eu-west, us"),
+            ("LORADB_MQTT_BROKER_EU_WEST_URL", "mqtts://eu.example.com:8883"),
+            ("LORADB_MQTT_BROKER_EU_WEST_USERNAME", "eu-user"),
+            ("LORADB_MQTT_BROKER_US_URL", "mqtts://us.example.com:8883"),
+            ("LORADB_MQTT_BROKER_US_PARSER", "ttn"),
+        ])
+        .unwrap();
+        let names: Vec<_> = list.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["chirpstack", "eu-west", "us"]);
+        assert_eq!(list[1].username.as_deref(), Some("eu-user"));
+        assert_eq!(list[2].parser, BrokerParser::Ttn);
+        assert_eq!(list[2].topic_prefix, "v3/+/devices/+");
+        // Falls back to the shared credentials
+        assert_eq!(list[2].username.as_deref(), Some("loradb"));
+
+        assert!(brokers(&[("LORADB_MQTT_BROKERS", "eu")]).is_err());
+        assert!(brokers(&[
+            ("LORADB_MQTT_CHIRPSTACK_BROKER", "mqtt://localhost:1883"),
+            ("LORADB_MQTT_BROKERS", "chirpstack"),
+            ("LORADB_MQTT_BROKER_CHIRPSTACK_URL", "mqtt://other:1883"),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_tenants() {
+        let tenants = parse_tenants(" app-1:acme, app-2:acme,urn:app:3:globex ").unwrap();
+        assert_eq!(tenants.len(), 3);
+        assert_eq!(tenants["app-1"], "acme");
+        assert_eq!(tenants["urn:app:3"], "globex");
+        assert!(parse_tenants("").unwrap().is_empty());
+
+        assert!(parse_tenants("app-1").is_err());
+        assert!(parse_tenants("app-1:").is_err());
+        assert!(parse_tenants("app-1:acme,app-1:globex").is_err());
+    }
+}
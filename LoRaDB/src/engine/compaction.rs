@@ -1,18 +1,66 @@
+use crate::config::StorageLayout;
 use crate::engine::memtable::MemtableKey;
-use crate::engine::sstable::{SSTableMetadata, SSTableReader, SSTableWriter};
+use crate::engine::sstable::{SSTableCache, SSTableMetadata, SSTableReader, SSTableWriter};
 use crate::error::LoraDbError;
 use crate::model::frames::Frame;
 use anyhow::Result;
-use std::collections::BTreeMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
 use std::fs;
-use std::path::PathBuf;
-use tracing::{info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
 
 /// Compaction strategy: merge multiple SSTables when count exceeds threshold
 pub struct CompactionManager {
     data_dir: PathBuf,
     threshold: usize,
     next_sstable_id: u64,
+    max_bytes_per_sec: u64,
+    fragmentation_threshold: f64,
+    target_sstable_entries: u64,
+    layout: StorageLayout,
+    read_only: bool,
+}
+
+/// Fewest SSTables in a directory that fragmentation alone compacts
+const MIN_FRAGMENTED_SSTABLES: usize = 4;
+
+/// Simple IO rate limiter for compaction
+/// Sleeps whenever the bytes processed so far run ahead of the configured rate
+pub struct IoThrottle {
+    bytes_per_sec: u64,
+    started: Instant,
+    consumed: u64,
+}
+
+impl IoThrottle {
+    /// Create a throttle, `0` disables rate limiting
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            started: Instant::now(),
+            consumed: 0,
+        }
+    }
+
+    /// Account for `bytes` of IO, blocking the current thread if over budget
+    pub fn consume(&mut self, bytes: u64) {
+        // Give other threads (e.g. foreground queries) a chance between entries
+        std::thread::yield_now();
+
+        if self.bytes_per_sec == 0 {
+            return;
+        }
+
+        self.consumed += bytes;
+        let expected = Duration::from_secs_f64(self.consumed as f64 / self.bytes_per_sec as f64);
+        let elapsed = self.started.elapsed();
+        if expected > elapsed {
+            std::thread::sleep(expected - elapsed);
+        }
+    }
 }
 
 impl CompactionManager {
@@ -21,6 +69,56 @@ impl CompactionManager {
             data_dir,
             threshold,
             next_sstable_id: 0,
+            max_bytes_per_sec: 0,
+            fragmentation_threshold: 0.0,
+            target_sstable_entries: 0,
+            layout: StorageLayout::Flat,
+            read_only: false,
+        }
+    }
+
+    /// Leave SSTable files alone when opening them
+    /// A read-only replica can see a file the primary is still writing, which looks partial
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Set the directory layout new SSTables are written with
+    /// Existing SSTables are found in either layout regardless
+    pub fn set_layout(&mut self, layout: StorageLayout) {
+        self.layout = layout;
+    }
+
+    /// Set the compaction IO cap in bytes per second (0 = unlimited)
+    pub fn set_max_bytes_per_sec(&mut self, max_bytes_per_sec: u64) {
+        self.max_bytes_per_sec = max_bytes_per_sec;
+    }
+
+    /// Also compact a directory once its fragmentation score reaches `threshold`
+    /// (0 = count only), tables under `target_sstable_entries` counting as fragmented
+    pub fn set_fragmentation_threshold(&mut self, threshold: f64, target_sstable_entries: u64) {
+        self.fragmentation_threshold = threshold;
+        self.target_sstable_entries = target_sstable_entries;
+    }
+
+    /// Get the compaction IO cap in bytes per second
+    pub fn max_bytes_per_sec(&self) -> u64 {
+        self.max_bytes_per_sec
+    }
+
+    /// Get the data directory SSTables are written to
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// Directory an SSTable holding frames from `app_id` belongs in
+    /// Frames without an application stay in the data directory itself
+    pub fn sstable_dir(&self, app_id: Option<&str>) -> PathBuf {
+        match (self.layout, app_id) {
+            (StorageLayout::PerApplication, Some(app_id)) => {
+                self.data_dir.join(application_dir_name(app_id))
+            }
+            _ => self.data_dir.clone(),
         }
     }
 
@@ -29,6 +127,71 @@ impl CompactionManager {
         sstable_count > self.threshold
     }
 
+    /// Fragmentation score of one directory's SSTables, given their entry counts
+    /// 0 when the entries couldn't fit in fewer tables of the target size, approaching 1
+    /// as they are spread over many more tables than needed
+    pub fn fragmentation_score(&self, entries: &[u64]) -> f64 {
+        if entries.len() < 2 || self.target_sstable_entries == 0 {
+            return 0.0;
+        }
+
+        let total: u64 = entries.iter().sum();
+        let needed = total.div_ceil(self.target_sstable_entries).max(1);
+        (1.0 - needed as f64 / entries.len() as f64).max(0.0)
+    }
+
+    /// Check if a directory's SSTables are over the count threshold or too fragmented
+    fn should_compact_group(&self, entries: &[u64]) -> bool {
+        self.should_compact(entries.len())
+            || (self.fragmentation_threshold > 0.0
+                && entries.len() >= MIN_FRAGMENTED_SSTABLES
+                && self.fragmentation_score(entries) >= self.fragmentation_threshold)
+    }
+
+    /// Group SSTables, given as path and entry count, by directory, returning the
+    /// groups that should be compacted
+    /// SSTables are only ever merged with others in the same directory,
+    /// so the per-application layout survives compaction
+    pub fn groups_to_compact(
+        &self,
+        sstables: Vec<(PathBuf, u64)>,
+    ) -> Vec<(PathBuf, Vec<PathBuf>)> {
+        self.group_by_dir(sstables)
+            .into_iter()
+            .filter(|(_, tables)| {
+                let entries: Vec<u64> = tables.iter().map(|(_, entries)| *entries).collect();
+                self.should_compact_group(&entries)
+            })
+            .map(|(dir, tables)| (dir, tables.into_iter().map(|(path, _)| path).collect()))
+            .collect()
+    }
+
+    /// Highest fragmentation score of any directory's SSTables
+    pub fn max_fragmentation_score(&self, sstables: Vec<(PathBuf, u64)>) -> f64 {
+        self.group_by_dir(sstables)
+            .values()
+            .map(|tables| {
+                let entries: Vec<u64> = tables.iter().map(|(_, entries)| *entries).collect();
+                self.fragmentation_score(&entries)
+            })
+            .fold(0.0, f64::max)
+    }
+
+    fn group_by_dir(
+        &self,
+        sstables: Vec<(PathBuf, u64)>,
+    ) -> BTreeMap<PathBuf, Vec<(PathBuf, u64)>> {
+        let mut groups: BTreeMap<PathBuf, Vec<(PathBuf, u64)>> = BTreeMap::new();
+        for (path, entries) in sstables {
+            let dir = path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| self.data_dir.clone());
+            groups.entry(dir).or_default().push((path, entries));
+        }
+        groups
+    }
+
     /// Set the next SSTable ID (used for recovery)
     pub fn set_next_sstable_id(&mut self, id: u64) {
         self.next_sstable_id = id;
@@ -51,6 +214,19 @@ impl CompactionManager {
     pub fn compact(
         &mut self,
         sstables: Vec<SSTableReader>,
+    ) -> Result<(SSTableMetadata, Vec<PathBuf>)> {
+        let new_id = self.allocate_sstable_id();
+        Self::merge(&self.data_dir, new_id, sstables, self.max_bytes_per_sec)
+    }
+
+    /// Merge SSTables into a new SSTable with the given ID, rate limited to
+    /// `max_bytes_per_sec` (0 = unlimited)
+    /// Does not need the manager itself so it can run without holding its lock
+    pub fn merge(
+        data_dir: &Path,
+        new_id: u64,
+        sstables: Vec<SSTableReader>,
+        max_bytes_per_sec: u64,
     ) -> Result<(SSTableMetadata, Vec<PathBuf>)> {
         if sstables.is_empty() {
             return Err(LoraDbError::StorageError(
@@ -60,49 +236,42 @@ impl CompactionManager {
         }
 
         info!("Starting compaction of {} SSTables", sstables.len());
-
-        // Use a BTreeMap to merge and deduplicate entries
-        // Key: MemtableKey (sorted), Value: Frame
-        // Later entries with same dev_eui/timestamp but higher sequence number override earlier ones
-        let mut merged_data: BTreeMap<MemtableKey, Frame> = BTreeMap::new();
-
-        // Collect all entries from all SSTables using iter_all()
-        let mut all_entries: Vec<(MemtableKey, Frame)> = Vec::new();
-
-        for reader in &sstables {
-            // Extract all frames from this SSTable
-            let frames = reader.iter_all()?;
-
-            // Add frames with their keys
-            for frame in frames {
-                let key = MemtableKey::new(
-                    frame.dev_eui(),
-                    frame.timestamp(),
-                    0, // Sequence number not preserved during compaction (will be deduplicated by dev_eui+timestamp)
-                );
-                all_entries.push((key, frame));
-            }
+        let started = Instant::now();
+        let mut throttle = IoThrottle::new(max_bytes_per_sec);
+
+        // Streaming k-way merge: only the next entry of each input is held in memory
+        // Entries are deduplicated by dev_eui+timestamp (sequence numbers aren't preserved);
+        // on a tie the entry from the later input wins, inputs being in ID order (oldest first)
+        let mut inputs: Vec<_> = sstables.iter().map(|reader| reader.iter_entries()).collect();
+        let mut heads: Vec<Option<Frame>> = Vec::with_capacity(inputs.len());
+        let mut heap = BinaryHeap::new();
+        for (i, input) in inputs.iter_mut().enumerate() {
+            heads.push(Self::advance(input, i, &mut heap, &mut throttle)?);
         }
 
-        // Sort and deduplicate
-        all_entries.sort_by(|a, b| a.0.cmp(&b.0));
-
-        // Merge into BTreeMap (automatically deduplicates by key, keeping last value)
-        for (key, frame) in all_entries {
-            merged_data.insert(key, frame);
-        }
+        let mut writer = SSTableWriter::new(new_id, data_dir);
+        let mut pending: Option<(MemtableKey, Frame)> = None;
 
-        info!("Merged {} entries after deduplication", merged_data.len());
+        while let Some(Reverse((key, i))) = heap.pop() {
+            let frame = heads[i].take().expect("heap entries have a head frame");
+            heads[i] = Self::advance(&mut inputs[i], i, &mut heap, &mut throttle)?;
 
-        // Write new SSTable
-        let new_id = self.allocate_sstable_id();
-        let mut writer = SSTableWriter::new(new_id, &self.data_dir);
+            if let Some((pending_key, pending_frame)) = pending.take() {
+                if pending_key != key {
+                    throttle.consume(bincode::serialized_size(&pending_frame).unwrap_or(0));
+                    writer.add(pending_key, pending_frame)?;
+                }
+            }
+            pending = Some((key, frame));
+        }
 
-        for (key, frame) in merged_data {
+        if let Some((key, frame)) = pending {
+            throttle.consume(bincode::serialized_size(&frame).unwrap_or(0));
             writer.add(key, frame)?;
         }
 
         let metadata = writer.finish()?;
+        info!("Merged {} entries after deduplication", metadata.num_entries);
 
         // Collect old SSTable paths for deletion
         let old_paths: Vec<PathBuf> = sstables
@@ -110,6 +279,7 @@ impl CompactionManager {
             .map(|r| r.path().to_path_buf())
             .collect();
 
+        debug!("Compaction IO took {:?}", started.elapsed());
         info!(
             "Compaction complete: created SSTable {} with {} entries, will delete {} old SSTables",
             new_id,
@@ -120,6 +290,30 @@ impl CompactionManager {
         Ok((metadata, old_paths))
     }
 
+    /// Read the next entry of merge input `i` and push its key onto the heap
+    fn advance(
+        input: &mut impl Iterator<Item = Result<(MemtableKey, Frame, u64)>>,
+        i: usize,
+        heap: &mut BinaryHeap<Reverse<(MemtableKey, usize)>>,
+        throttle: &mut IoThrottle,
+    ) -> Result<Option<Frame>> {
+        match input.next() {
+            Some(entry) => {
+                let (key, frame, size) = entry?;
+                throttle.consume(size);
+                heap.push(Reverse((
+                    MemtableKey {
+                        sequence: 0,
+                        ..key
+                    },
+                    i,
+                )));
+                Ok(Some(frame))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Delete old SSTables after successful compaction
     pub fn delete_old_sstables(&self, paths: Vec<PathBuf>) -> Result<()> {
         for path in paths {
@@ -132,48 +326,49 @@ impl CompactionManager {
         Ok(())
     }
 
-    /// Find all SSTable files in the data directory
+    /// Find all SSTable files in the data directory and its per-application subdirectories
     pub fn find_sstables(&self) -> Result<Vec<PathBuf>> {
-        let mut sstables = Vec::new();
-
-        if !self.data_dir.exists() {
-            return Ok(sstables);
-        }
-
-        for entry in fs::read_dir(&self.data_dir)? {
-            let entry = entry?;
-            let path = entry.path();
+        find_sstables(&self.data_dir)
+    }
 
-            if path.is_file() {
-                if let Some(name) = path.file_name() {
-                    let name_str = name.to_string_lossy();
-                    if name_str.starts_with("sstable-") && name_str.ends_with(".sst") {
-                        sstables.push(path);
-                    }
-                }
-            }
+    /// Open all SSTables in the data directory, reading their index through `cache`
+    /// Partially written SSTables left by a crash are removed (unless read-only), and the
+    /// next ID is set past every ID in use, including files that couldn't be opened
+    pub fn open_all_sstables(&mut self, cache: &Arc<SSTableCache>) -> Result<Vec<SSTableReader>> {
+        if !self.read_only && self.data_dir.exists() {
+            remove_temp_sstables(&self.data_dir, true)?;
         }
 
-        // Sort by filename (which includes the ID)
-        sstables.sort();
-
-        Ok(sstables)
-    }
-
-    /// Open all SSTables in the data directory
-    pub fn open_all_sstables(&mut self) -> Result<Vec<SSTableReader>> {
         let paths = self.find_sstables()?;
-        let mut readers = Vec::new();
+        let mut readers: Vec<SSTableReader> = Vec::new();
         let mut max_id = 0u64;
 
         for path in paths {
-            match SSTableReader::open(path.clone()) {
+            let file_id = sstable_id_from_path(&path);
+            match SSTableReader::open_cached(path.clone(), cache) {
                 Ok(reader) => {
-                    max_id = max_id.max(reader.id());
+                    if let Some(other) = readers.iter().find(|r| r.id() == reader.id()) {
+                        warn!(
+                            "SSTables {:?} and {:?} share ID {}",
+                            other.path(),
+                            path,
+                            reader.id()
+                        );
+                    }
+                    max_id = max_id.max(reader.id()).max(file_id.unwrap_or(0));
                     readers.push(reader);
                 }
+                Err(e) if !self.read_only && matches!(SSTableReader::is_complete(&path), Ok(false)) => {
+                    warn!("Removing partially written SSTable {:?}: {}", path, e);
+                    if let Err(e) = fs::remove_file(&path) {
+                        warn!("Failed to remove partial SSTable {:?}: {}", path, e);
+                        max_id = max_id.max(file_id.unwrap_or(0));
+                    }
+                }
                 Err(e) => {
                     warn!("Failed to open SSTable {:?}: {}", path, e);
+                    // Never reuse the ID, a new SSTable would overwrite this file
+                    max_id = max_id.max(file_id.unwrap_or(0));
                 }
             }
         }
@@ -187,6 +382,98 @@ impl CompactionManager {
     }
 }
 
+/// Find all SSTable files in `data_dir` and its per-application subdirectories
+pub fn find_sstables(data_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut sstables = Vec::new();
+
+    if !data_dir.exists() {
+        return Ok(sstables);
+    }
+
+    collect_sstables(data_dir, &mut sstables, true)?;
+
+    // Sort by filename (which includes the ID), wherever the file lives
+    sstables.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    Ok(sstables)
+}
+
+/// Collect `sstable-*.sst` files in `dir`, descending one level if `recurse`
+fn collect_sstables(dir: &Path, sstables: &mut Vec<PathBuf>, recurse: bool) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.is_dir() {
+            if recurse {
+                collect_sstables(&path, sstables, false)?;
+            }
+        } else if let Some(name) = path.file_name() {
+            let name_str = name.to_string_lossy();
+            if name_str.starts_with("sstable-") && name_str.ends_with(".sst") {
+                sstables.push(path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove `sstable-*.sst.tmp` files left by writes interrupted by a crash,
+/// descending one level if `recurse`
+fn remove_temp_sstables(dir: &Path, recurse: bool) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            if recurse {
+                remove_temp_sstables(&path, false)?;
+            }
+        } else if let Some(name) = path.file_name() {
+            let name_str = name.to_string_lossy();
+            if name_str.starts_with("sstable-") && name_str.ends_with(".sst.tmp") {
+                warn!("Removing unfinished SSTable {:?}", path);
+                if let Err(e) = fs::remove_file(&path) {
+                    warn!("Failed to remove unfinished SSTable {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// ID in an `sstable-NNNNNNNN.sst` file name
+fn sstable_id_from_path(path: &Path) -> Option<u64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("sstable-")?
+        .strip_suffix(".sst")?
+        .parse()
+        .ok()
+}
+
+/// Map an application ID to a safe single path component
+/// Anything other than ASCII alphanumerics, `-`, `_` and `.` becomes `_`
+fn application_dir_name(app_id: &str) -> String {
+    let name: String = app_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if name.is_empty() || name.chars().all(|c| c == '.') {
+        "_".repeat(name.len().max(1))
+    } else {
+        name
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -202,6 +489,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("test-device".to_string()),
             received_at: timestamp,
+            stored_at: None,
             f_port: 1,
             f_cnt: 42,
             confirmed: false,
@@ -225,6 +513,105 @@ mod tests {
         assert!(manager.should_compact(20));
     }
 
+    #[test]
+    fn test_fragmentation_triggers_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut manager = CompactionManager::new(temp_dir.path().to_path_buf(), 10);
+        manager.set_fragmentation_threshold(0.75, 1000);
+
+        // Four full tables can't be merged into fewer
+        assert_eq!(manager.fragmentation_score(&[1000, 1000, 1000, 1000]), 0.0);
+        // Four tiny tables fit in one
+        assert_eq!(manager.fragmentation_score(&[10, 10, 10, 10]), 0.75);
+        // One large table and a few recent flushes still need three tables
+        assert!(manager.fragmentation_score(&[2500, 10, 10]) < 0.75);
+
+        let tables = |entries: &[u64]| -> Vec<(PathBuf, u64)> {
+            entries
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (temp_dir.path().join(format!("sstable-{:08}.sst", i)), *e))
+                .collect()
+        };
+        assert!(manager.groups_to_compact(tables(&[10, 10, 10])).is_empty());
+        assert_eq!(manager.groups_to_compact(tables(&[10, 10, 10, 10])).len(), 1);
+        assert!(manager.groups_to_compact(tables(&[1000, 1000, 1000, 1000])).is_empty());
+        assert_eq!(manager.max_fragmentation_score(tables(&[10, 10, 10, 10])), 0.75);
+
+        // Disabled, only the count threshold applies
+        manager.set_fragmentation_threshold(0.0, 1000);
+        assert!(manager.groups_to_compact(tables(&[10, 10, 10, 10])).is_empty());
+    }
+
+    #[test]
+    fn test_io_throttle_limits_rate() {
+        let mut throttle = IoThrottle::new(1000);
+        let started = Instant::now();
+
+        // 200 bytes at 1000 bytes/sec should take roughly 200ms
+        for _ in 0..4 {
+            throttle.consume(50);
+        }
+
+        assert!(started.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[test]
+    fn test_merge_streams_and_deduplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = Utc::now();
+        let at = |secs: i64| now + chrono::Duration::seconds(secs);
+
+        // (SSTable, device, second, f_cnt): device B at second 1 is in all three tables
+        let tables: [&[(&str, i64, u32)]; 3] = [
+            &[("AAAAAAAAAAAAAAAA", 0, 1), ("BBBBBBBBBBBBBBBB", 1, 1)],
+            &[("AAAAAAAAAAAAAAAA", 2, 2), ("BBBBBBBBBBBBBBBB", 1, 2), ("BBBBBBBBBBBBBBBB", 3, 2)],
+            &[("BBBBBBBBBBBBBBBB", 1, 3)],
+        ];
+        let mut readers = Vec::new();
+        for (id, entries) in tables.iter().enumerate() {
+            let mut writer = SSTableWriter::new(id as u64, temp_dir.path());
+            for (sequence, (dev_eui, secs, f_cnt)) in entries.iter().enumerate() {
+                let mut frame = create_test_frame(dev_eui, at(*secs));
+                if let Frame::Uplink(uplink) = &mut frame {
+                    uplink.f_cnt = *f_cnt;
+                }
+                let key = MemtableKey::new(frame.dev_eui(), at(*secs), sequence as u64);
+                writer.add(key, frame).unwrap();
+            }
+            writer.finish().unwrap();
+            readers.push(SSTableReader::open(temp_dir.path().join(format!("sstable-{:08}.sst", id))).unwrap());
+        }
+
+        let (metadata, old_paths) = CompactionManager::merge(temp_dir.path(), 10, readers, 0).unwrap();
+        assert_eq!(metadata.num_entries, 4);
+        assert_eq!(old_paths.len(), 3);
+
+        let merged = SSTableReader::open(temp_dir.path().join("sstable-00000010.sst")).unwrap();
+        let entries: Vec<(String, i64, u32)> = merged
+            .iter_entries()
+            .map(|entry| {
+                let (key, frame, _) = entry.unwrap();
+                let f_cnt = match frame {
+                    Frame::Uplink(uplink) => uplink.f_cnt,
+                    _ => unreachable!(),
+                };
+                (key.dev_eui, key.timestamp, f_cnt)
+            })
+            .collect();
+        let micros = |secs: i64| at(secs).timestamp_micros();
+        assert_eq!(
+            entries,
+            vec![
+                ("aaaaaaaaaaaaaaaa".to_string(), micros(0), 1),
+                ("aaaaaaaaaaaaaaaa".to_string(), micros(2), 2),
+                // The newest SSTable's copy wins
+                ("bbbbbbbbbbbbbbbb".to_string(), micros(1), 3),
+                ("bbbbbbbbbbbbbbbb".to_string(), micros(3), 2),
+            ]
+        );
+    }
+
     #[test]
     fn test_sstable_id_allocation() {
         let temp_dir = TempDir::new().unwrap();
@@ -258,8 +645,86 @@ mod tests {
         let sstables = manager.find_sstables().unwrap();
         assert_eq!(sstables.len(), 3);
 
-        let readers = manager.open_all_sstables().unwrap();
+        let readers = manager.open_all_sstables(&Arc::new(SSTableCache::new(8))).unwrap();
         assert_eq!(readers.len(), 3);
         assert_eq!(manager.next_sstable_id(), 3);
     }
+
+    #[test]
+    fn test_open_all_sstables_removes_partial_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        for i in 0..2 {
+            let mut writer = SSTableWriter::new(i, temp_dir.path());
+            let key = MemtableKey::new(&dev_eui, now, i);
+            writer.add(key, create_test_frame("0123456789ABCDEF", now)).unwrap();
+            writer.finish().unwrap();
+        }
+
+        // A crash mid-write: a truncated copy with the next ID, and an empty file after it
+        let complete = fs::read(temp_dir.path().join("sstable-00000001.sst")).unwrap();
+        let truncated = temp_dir.path().join("sstable-00000002.sst");
+        fs::write(&truncated, &complete[..complete.len() / 2]).unwrap();
+        let empty = temp_dir.path().join("sstable-00000003.sst");
+        fs::write(&empty, b"").unwrap();
+        let unfinished = temp_dir.path().join("sstable-00000004.sst.tmp");
+        fs::write(&unfinished, &complete[..10]).unwrap();
+
+        // A read-only instance skips them but leaves them in place
+        let cache = Arc::new(SSTableCache::new(8));
+        let mut replica = CompactionManager::new(temp_dir.path().to_path_buf(), 10);
+        replica.set_read_only(true);
+        assert_eq!(replica.open_all_sstables(&cache).unwrap().len(), 2);
+        assert_eq!(replica.next_sstable_id(), 4);
+        assert!(truncated.exists() && empty.exists() && unfinished.exists());
+
+        let mut manager = CompactionManager::new(temp_dir.path().to_path_buf(), 10);
+        assert_eq!(manager.open_all_sstables(&cache).unwrap().len(), 2);
+        assert!(!truncated.exists() && !empty.exists() && !unfinished.exists());
+        assert_eq!(manager.next_sstable_id(), 2);
+    }
+
+    #[test]
+    fn test_per_application_layout() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut manager = CompactionManager::new(temp_dir.path().to_path_buf(), 1);
+
+        assert_eq!(manager.sstable_dir(Some("app-1")), temp_dir.path());
+
+        manager.set_layout(StorageLayout::PerApplication);
+        assert_eq!(manager.sstable_dir(Some("app-1")), temp_dir.path().join("app-1"));
+        assert_eq!(manager.sstable_dir(Some("../etc")), temp_dir.path().join(".._etc"));
+        assert_eq!(manager.sstable_dir(Some("..")), temp_dir.path().join("__"));
+        assert_eq!(manager.sstable_dir(None), temp_dir.path());
+
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        // One SSTable at the top level, two in an application directory
+        let dirs = [
+            temp_dir.path().to_path_buf(),
+            manager.sstable_dir(Some("app-1")),
+            manager.sstable_dir(Some("app-1")),
+        ];
+        for (i, dir) in dirs.iter().enumerate() {
+            fs::create_dir_all(dir).unwrap();
+            let mut writer = SSTableWriter::new(i as u64, dir);
+            let key = MemtableKey::new(&dev_eui, now, i as u64);
+            writer.add(key, create_test_frame("0123456789ABCDEF", now)).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let paths = manager.find_sstables().unwrap();
+        assert_eq!(paths.len(), 3);
+        assert_eq!(manager.open_all_sstables(&Arc::new(SSTableCache::new(8))).unwrap().len(), 3);
+        assert_eq!(manager.next_sstable_id(), 3);
+
+        // Only the application directory is over the threshold of 1
+        let groups = manager.groups_to_compact(paths.into_iter().map(|p| (p, 1)).collect());
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].0, temp_dir.path().join("app-1"));
+        assert_eq!(groups[0].1.len(), 2);
+    }
 }
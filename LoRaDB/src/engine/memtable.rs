@@ -1,5 +1,5 @@
 use crate::model::frames::Frame;
-use crate::model::lorawan::DevEui;
+use crate::model::lorawan::{ApplicationId, DevEui};
 use chrono::{DateTime, Utc};
 use crossbeam_skiplist::SkipMap;
 use serde::{Deserialize, Serialize};
@@ -34,6 +34,18 @@ impl MemtableKey {
         }
     }
 
+    /// First key strictly after every entry at `after_micros` (or the device's first key)
+    /// Sequence numbers aren't preserved by compaction, so resuming is by whole timestamp
+    pub fn resume_after(dev_eui: &DevEui, after_micros: Option<i64>) -> Self {
+        Self {
+            dev_eui: dev_eui.normalized(),
+            timestamp: after_micros
+                .map(|t| t.saturating_add(1))
+                .unwrap_or(i64::MIN),
+            sequence: 0,
+        }
+    }
+
     pub fn range_end(dev_eui: &DevEui, end_time: Option<DateTime<Utc>>) -> Self {
         Self {
             dev_eui: dev_eui.normalized(),
@@ -116,6 +128,53 @@ impl Memtable {
             .collect()
     }
 
+    /// Number of frames held for a device
+    pub fn device_frame_count(&self, dev_eui: &DevEui) -> usize {
+        let start_key = MemtableKey::range_start(dev_eui, None);
+        let end_key = MemtableKey::range_end(dev_eui, None);
+        self.data.range(start_key..=end_key).count()
+    }
+
+    /// Range scan like `scan_device_range`, keeping each frame's key
+    pub fn scan_device_entries(
+        &self,
+        dev_eui: &DevEui,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+    ) -> Vec<(MemtableKey, Frame)> {
+        let start_key = MemtableKey::range_start(dev_eui, start_time);
+        let end_key = MemtableKey::range_end(dev_eui, end_time);
+
+        self.data
+            .range(start_key..=end_key)
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Scan a device's frames after `after_micros`, returning at least `limit` frames
+    /// (finishing the last timestamp) and whether the device has no more frames
+    pub fn scan_after(
+        &self,
+        dev_eui: &DevEui,
+        after_micros: Option<i64>,
+        limit: usize,
+    ) -> (Vec<Frame>, bool) {
+        let start_key = MemtableKey::resume_after(dev_eui, after_micros);
+        let end_key = MemtableKey::range_end(dev_eui, None);
+
+        let mut results = Vec::new();
+        let mut last_timestamp = None;
+        for entry in self.data.range(start_key..=end_key) {
+            if results.len() >= limit && last_timestamp != Some(entry.key().timestamp) {
+                return (results, false);
+            }
+            last_timestamp = Some(entry.key().timestamp);
+            results.push(entry.value().clone());
+        }
+
+        (results, true)
+    }
+
     /// Get all frames (for flushing to SSTable)
     pub fn iter(&self) -> impl Iterator<Item = (MemtableKey, Frame)> + '_ {
         self.data
@@ -153,11 +212,14 @@ impl Memtable {
         self.sequence.store(0, Ordering::Relaxed);
     }
 
+    /// Remove the entries a flush wrote to SSTables, keeping any inserted since its snapshot
+    pub fn remove_flushed(&self, keys: Vec<MemtableKey>) -> usize {
+        self.remove_keys(keys)
+    }
+
     /// Delete all entries for a specific device
     pub fn delete_device(&self, dev_eui: &DevEui) -> usize {
         let dev_eui_norm = dev_eui.normalized();
-        let mut deleted_count = 0;
-        let mut deleted_bytes = 0;
 
         // Find and remove all entries for this device
         let start_key = MemtableKey {
@@ -179,12 +241,39 @@ impl Memtable {
             .map(|entry| entry.key().clone())
             .collect();
 
-        // Delete the entries
-        for key in keys_to_delete {
+        self.remove_keys(keys_to_delete)
+    }
+
+    /// Delete all entries for an application (matched on the normalized ID)
+    pub fn delete_application(&self, app_id: &str) -> usize {
+        let app_id = ApplicationId::normalize(app_id);
+
+        // The memtable is keyed by device, so every entry has to be checked
+        let keys_to_delete: Vec<MemtableKey> = self
+            .data
+            .iter()
+            .filter(|entry| {
+                entry
+                    .value()
+                    .application_id()
+                    .is_some_and(|id| id.normalized() == app_id)
+            })
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        self.remove_keys(keys_to_delete)
+    }
+
+    /// Remove entries and their share of the size estimate, returning how many were removed
+    fn remove_keys(&self, keys: Vec<MemtableKey>) -> usize {
+        let mut deleted_count = 0;
+        let mut deleted_bytes = 0;
+
+        for key in keys {
             if let Some(entry) = self.data.remove(&key) {
                 deleted_count += 1;
-                // Approximate size calculation
-                let frame_size = std::mem::size_of_val(&entry.value()) + std::mem::size_of_val(&key);
+                // Same approximation as insert()
+                let frame_size = std::mem::size_of_val(entry.value()) + std::mem::size_of_val(&key);
                 deleted_bytes += frame_size;
             }
         }
@@ -225,6 +314,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("test-device".to_string()),
             received_at: timestamp,
+            stored_at: None,
             f_port: 1,
             f_cnt: 42,
             confirmed: false,
@@ -317,4 +407,39 @@ mod tests {
         // Just check that the method works
         let _ = should_flush;
     }
+
+    #[test]
+    fn test_memtable_delete_application() {
+        let memtable = Memtable::new();
+        let now = Utc::now();
+        memtable.insert(create_test_frame("0000000000000001", now)).unwrap();
+        memtable.insert(create_test_frame("0000000000000002", now)).unwrap();
+
+        let mut other = create_test_frame("0000000000000003", now);
+        if let Frame::Uplink(uplink) = &mut other {
+            uplink.application_id = ApplicationId::new("other-app".to_string());
+        }
+        memtable.insert(other).unwrap();
+        let size_before = memtable.size_bytes();
+
+        assert_eq!(memtable.delete_application("Test-App"), 2);
+        assert_eq!(memtable.len(), 1);
+        assert_eq!(memtable.size_bytes(), size_before / 3);
+        assert_eq!(memtable.delete_application("test-app"), 0);
+    }
+
+    #[test]
+    fn test_memtable_remove_flushed() {
+        let memtable = Memtable::new();
+        let now = Utc::now();
+        memtable.insert(create_test_frame("0000000000000001", now)).unwrap();
+        let flushed: Vec<MemtableKey> = memtable.iter().map(|(key, _)| key).collect();
+
+        // Written after the flush took its snapshot
+        memtable.insert(create_test_frame("0000000000000002", now)).unwrap();
+
+        assert_eq!(memtable.remove_flushed(flushed), 1);
+        assert_eq!(memtable.len(), 1);
+        assert_eq!(memtable.iter().next().unwrap().0.dev_eui, "0000000000000002");
+    }
 }
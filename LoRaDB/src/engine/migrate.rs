@@ -0,0 +1,230 @@
+use crate::engine::compaction::find_sstables;
+use crate::engine::memtable::MemtableKey;
+use crate::engine::sstable::{SSTableReader, SSTableWriter, SSTABLE_VERSION};
+use crate::error::LoraDbError;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Outcome of rewriting a data directory's SSTables in the current format
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// SSTables rewritten in the current format
+    pub migrated_tables: usize,
+    /// SSTables already in the current format, left alone
+    pub current_tables: usize,
+    /// Entries copied into rewritten tables
+    pub migrated_entries: u64,
+    /// Entries that couldn't be read or were out of key order, kept only in the backup
+    pub skipped_entries: u64,
+    /// Tables that couldn't be migrated and the reason, each left as it was
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Rewrite every older-version SSTable in `data_dir` (and its per-application
+/// subdirectories) in the current format
+///
+/// Must not run while a server uses the data directory. Each old table is renamed to
+/// `sstable-NNNNNNNN.sst.vN` before its entries are copied into a new table under the
+/// original name. The backup is kept, entries that couldn't be read are only in there.
+/// A table that fails to migrate is restored from its backup.
+pub fn migrate_sstables(data_dir: &Path) -> Result<MigrationReport> {
+    let mut report = MigrationReport::default();
+
+    for path in find_sstables(data_dir)? {
+        let version = match SSTableReader::version(&path) {
+            Ok(version) => version,
+            Err(e) => {
+                report.failed.push((path, e.to_string()));
+                continue;
+            }
+        };
+        if version == SSTABLE_VERSION {
+            report.current_tables += 1;
+            continue;
+        }
+        if version > SSTABLE_VERSION {
+            let reason = format!(
+                "version {} is newer than this build (current: {})",
+                version, SSTABLE_VERSION
+            );
+            report.failed.push((path, reason));
+            continue;
+        }
+
+        match migrate_sstable(&path, version) {
+            Ok((migrated, skipped)) => {
+                report.migrated_tables += 1;
+                report.migrated_entries += migrated;
+                report.skipped_entries += skipped;
+            }
+            Err(e) => {
+                warn!("Failed to migrate SSTable {:?}: {:#}", path, e);
+                report.failed.push((path, format!("{:#}", e)));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Rewrite one SSTable, returning the number of entries copied and skipped
+fn migrate_sstable(path: &Path, version: u16) -> Result<(u64, u64)> {
+    let backup = path.with_extension(format!("sst.v{}", version));
+    if backup.exists() {
+        return Err(LoraDbError::StorageError(format!(
+            "backup {:?} already exists, remove it or move it away first",
+            backup
+        ))
+        .into());
+    }
+
+    fs::rename(path, &backup).with_context(|| format!("Failed to back up {:?}", path))?;
+    match copy_entries(path, &backup) {
+        Ok(counts) => {
+            info!(
+                "Migrated SSTable {:?} from version {} ({} entries, {} skipped), backup at {:?}",
+                path, version, counts.0, counts.1, backup
+            );
+            Ok(counts)
+        }
+        Err(e) => {
+            // The writer removes its temporary file, put the original back in place
+            if let Err(restore_error) = fs::rename(&backup, path) {
+                warn!("Failed to restore {:?} from {:?}: {}", path, backup, restore_error);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Copy the readable entries of the old table at `source` into a new table at `path`
+fn copy_entries(path: &Path, source: &Path) -> Result<(u64, u64)> {
+    let reader = SSTableReader::open(source.to_path_buf())?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut writer = SSTableWriter::new(reader.id(), dir);
+
+    // The writer names its file after the table ID, which must be the original name
+    let output = dir.join(format!("sstable-{:08}.sst", reader.id()));
+    if output != path {
+        return Err(LoraDbError::StorageError(format!(
+            "SSTable ID {} doesn't match its file name",
+            reader.id()
+        ))
+        .into());
+    }
+
+    let mut migrated = 0u64;
+    let mut skipped = 0u64;
+    let mut last_key: Option<MemtableKey> = None;
+    for entry in reader.iter_entries() {
+        let (key, frame, _size) = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Skipping unreadable entry in {:?}: {:#}", source, e);
+                skipped += 1;
+                continue;
+            }
+        };
+        if last_key.as_ref().is_some_and(|last| key <= *last) {
+            skipped += 1;
+            continue;
+        }
+
+        writer.add(key.clone(), frame)?;
+        last_key = Some(key);
+        migrated += 1;
+    }
+
+    if migrated == 0 {
+        return Err(LoraDbError::StorageError(format!(
+            "none of the {} entries could be read",
+            skipped
+        ))
+        .into());
+    }
+    writer.finish()?;
+
+    Ok((migrated, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::frames::{Frame, UplinkFrame};
+    use crate::model::lorawan::*;
+    use chrono::Utc;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::TempDir;
+
+    fn write_sstable(dir: &Path, id: u64, version: u16) -> PathBuf {
+        let mut writer = SSTableWriter::new(id, dir);
+        for f_cnt in 0..3u32 {
+            let timestamp = Utc::now() + chrono::Duration::seconds(f_cnt as i64);
+            let frame = Frame::Uplink(UplinkFrame {
+                dev_eui: DevEui::new("0123456789abcdef".to_string()).unwrap(),
+                application_id: ApplicationId::new("test-app".to_string()),
+                device_name: None,
+                received_at: timestamp,
+                stored_at: None,
+                f_port: 1,
+                f_cnt,
+                confirmed: false,
+                adr: true,
+                dr: DataRate::new_lora(125000, 7),
+                frequency: 868100000,
+                rx_info: vec![],
+                decoded_payload: None,
+                raw_payload: None,
+            });
+            let key = MemtableKey::new(frame.dev_eui(), timestamp, f_cnt as u64);
+            writer.add(key, frame).unwrap();
+        }
+        writer.finish().unwrap();
+
+        // Stamp an older version into the header, the layout is otherwise the same
+        let path = dir.join(format!("sstable-{:08}.sst", id));
+        let mut file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(4)).unwrap();
+        file.write_all(&version.to_le_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_migrate_rewrites_old_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        let old = write_sstable(temp_dir.path(), 1, 1);
+        let current = write_sstable(temp_dir.path(), 2, SSTABLE_VERSION);
+        // Readable before migrating, its frames are upgraded as they are read
+        assert_eq!(SSTableReader::open(old.clone()).unwrap().iter_all().unwrap().len(), 3);
+
+        let report = migrate_sstables(temp_dir.path()).unwrap();
+        assert_eq!(report.migrated_tables, 1);
+        assert_eq!(report.current_tables, 1);
+        assert_eq!(report.migrated_entries, 3);
+        assert_eq!(report.skipped_entries, 0);
+        assert!(report.failed.is_empty());
+
+        let reader = SSTableReader::open(old.clone()).unwrap();
+        assert_eq!(reader.iter_all().unwrap().len(), 3);
+        assert!(temp_dir.path().join("sstable-00000001.sst.v1").exists());
+        assert!(SSTableReader::open(current).is_ok());
+
+        // A second run finds nothing left to do and keeps the backup
+        let report = migrate_sstables(temp_dir.path()).unwrap();
+        assert_eq!(report.migrated_tables, 0);
+        assert_eq!(report.current_tables, 2);
+    }
+
+    #[test]
+    fn test_migrate_refuses_newer_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        let newer = write_sstable(temp_dir.path(), 1, SSTABLE_VERSION + 1);
+
+        let report = migrate_sstables(temp_dir.path()).unwrap();
+        assert_eq!(report.migrated_tables, 0);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(SSTableReader::version(&newer).unwrap(), SSTABLE_VERSION + 1);
+    }
+}
@@ -4,3 +4,4 @@ pub mod sstable;
 pub mod compaction;
 pub mod index;
 pub mod iterator;
+pub mod migrate;
@@ -1,20 +1,27 @@
 use crate::engine::memtable::MemtableKey;
 use crate::error::LoraDbError;
-use crate::model::frames::Frame;
-use crate::model::lorawan::DevEui;
+use crate::model::frames::{Frame, FRAME_VERSION, MIN_FRAME_VERSION};
+use crate::model::lorawan::{ApplicationId, DevEui};
 use crate::util::bloom::BloomFilter;
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use crc32fast::Hasher;
 use lz4::{Decoder, EncoderBuilder};
-use std::collections::HashSet;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use tracing::{debug, info, warn};
 
 const SSTABLE_MAGIC: u32 = 0x5353544C; // "SSTL"
-const SSTABLE_VERSION: u16 = 2; // v2: Fixed bincode compatibility for Frame
+pub const SSTABLE_VERSION: u16 = FRAME_VERSION; // v2: Fixed bincode compatibility for Frame
+/// Oldest version `SSTableReader::open` reads, all share the v2 file layout
+/// Versions so far only differ in the frame encoding, the table version is the frame version
+const MIN_SSTABLE_VERSION: u16 = MIN_FRAME_VERSION;
+const HEADER_LEN: u64 = 4 + 2 + 8 + 8 + 4; // magic | version | id | num_entries | bloom_size
+const FOOTER_LEN: u64 = 8 + 8; // created_at | index_offset
 
 /// SSTable metadata
 #[derive(Debug, Clone)]
@@ -27,6 +34,8 @@ pub struct SSTableMetadata {
     pub bloom_filter: BloomFilter,
     pub data_size_bytes: u64,
     pub compressed_size_bytes: u64,
+    /// Size of the whole file on disk, including header, bloom filter and footer
+    pub file_size_bytes: u64,
     pub application_ids: HashSet<String>,
 }
 
@@ -47,11 +56,24 @@ struct IndexEntry {
 pub struct SSTableWriter {
     id: u64,
     output_path: PathBuf,
-    entries: Vec<(MemtableKey, Frame)>,
+    /// Entries are streamed to a temporary file, renamed into place by `finish`
+    file: Option<PendingFile>,
+    index_entries: Vec<IndexEntry>,
     bloom_filter: BloomFilter,
     application_ids: HashSet<String>,
 }
 
+/// Temporary file an unfinished SSTable is being written to
+struct PendingFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// Bytes written so far
+    position: u64,
+    data_start_offset: u64,
+    /// Serialized bloom filter size reserved in the header
+    bloom_size: u32,
+}
+
 impl SSTableWriter {
     pub fn new(id: u64, output_dir: &Path) -> Self {
         let output_path = output_dir.join(format!("sstable-{:08}.sst", id));
@@ -62,17 +84,19 @@ impl SSTableWriter {
         Self {
             id,
             output_path,
-            entries: Vec::new(),
+            file: None,
+            index_entries: Vec::new(),
             bloom_filter,
             application_ids: HashSet::new(),
         }
     }
 
     /// Add an entry to the SSTable (must be added in sorted order)
+    /// The frame is written out right away, only its index entry is kept in memory
     pub fn add(&mut self, key: MemtableKey, frame: Frame) -> Result<()> {
         // Verify sorted order
-        if let Some((last_key, _)) = self.entries.last() {
-            if &key <= last_key {
+        if let Some(last) = self.index_entries.last() {
+            if key <= last.key {
                 return Err(LoraDbError::StorageError(
                     "SSTable entries must be added in sorted order".into(),
                 )
@@ -85,24 +109,61 @@ impl SSTableWriter {
 
         // Track application ID for retention policy
         if let Some(app_id) = frame.application_id() {
-            self.application_ids.insert(app_id.as_str().to_string());
+            self.application_ids.insert(app_id.normalized());
         }
 
-        self.entries.push((key, frame));
-        Ok(())
-    }
+        if self.file.is_none() {
+            self.file = Some(self.create_file()?);
+        }
+        let file = self.file.as_mut().expect("file was just created");
 
-    /// Finalize and write SSTable to disk
-    pub fn finish(self) -> Result<SSTableMetadata> {
-        if self.entries.is_empty() {
-            return Err(LoraDbError::StorageError("Cannot write empty SSTable".into()).into());
+        // Serialize frame
+        let frame_data = frame.encode()?;
+
+        // Compress with LZ4
+        let mut compressed = Vec::new();
+        {
+            let mut encoder = EncoderBuilder::new()
+                .level(4)
+                .build(&mut compressed)?;
+            encoder.write_all(&frame_data)?;
+            let (_, result) = encoder.finish();
+            result?;
         }
 
+        let compressed_size = compressed.len() as u32;
+
+        // Calculate checksum
+        let mut hasher = Hasher::new();
+        hasher.update(&compressed);
+        let checksum = hasher.finalize();
+
+        // Write: [compressed_size(4) | compressed_data(N) | checksum(4)]
+        file.writer.write_all(&compressed_size.to_le_bytes())?;
+        file.writer.write_all(&compressed)?;
+        file.writer.write_all(&checksum.to_le_bytes())?;
+
+        let entry_size = 4 + compressed_size + 4;
+
+        self.index_entries.push(IndexEntry {
+            key,
+            offset: file.position,
+            size: entry_size,
+        });
+        file.position += entry_size as u64;
+
+        Ok(())
+    }
+
+    /// Create the temporary file and write a header with space reserved for the
+    /// entry count and bloom filter, both filled in by `finish`
+    fn create_file(&self) -> Result<PendingFile> {
+        let path = self.output_path.with_extension("sst.tmp");
         let file = OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(&self.output_path)?;
+            .open(&path)?;
 
         // Set strict permissions (0600)
         #[cfg(unix)]
@@ -113,74 +174,103 @@ impl SSTableWriter {
 
         let mut writer = BufWriter::new(file);
 
-        // Prepare metadata
-        let min_key = self.entries.first().unwrap().0.clone();
-        let max_key = self.entries.last().unwrap().0.clone();
-        let num_entries = self.entries.len() as u64;
-
-        // Write header
+        // Header, the entry count is written by finish
         writer.write_all(&SSTABLE_MAGIC.to_le_bytes())?;
         writer.write_all(&SSTABLE_VERSION.to_le_bytes())?;
         writer.write_all(&self.id.to_le_bytes())?;
-        writer.write_all(&num_entries.to_le_bytes())?;
+        writer.write_all(&0u64.to_le_bytes())?;
 
-        // Serialize and write bloom filter
+        // The filter's serialized size only depends on its parameters, so the
+        // empty filter reserves exactly the space the final one needs
         let bloom_data = bincode::serialize(&self.bloom_filter)?;
         let bloom_size = bloom_data.len() as u32;
         writer.write_all(&bloom_size.to_le_bytes())?;
         writer.write_all(&bloom_data)?;
 
-        // Write data blocks and build index
-        let data_start_offset = writer.stream_position()?;
-        let mut index_entries = Vec::new();
-
-        for (key, frame) in &self.entries {
-            let entry_offset = writer.stream_position()?;
-
-            // Serialize frame
-            let frame_data = bincode::serialize(frame)?;
-
-            // Compress with LZ4
-            let mut compressed = Vec::new();
-            {
-                let mut encoder = EncoderBuilder::new()
-                    .level(4)
-                    .build(&mut compressed)?;
-                encoder.write_all(&frame_data)?;
-                let (_, result) = encoder.finish();
-                result?;
-            }
+        let data_start_offset = HEADER_LEN + bloom_size as u64;
+        Ok(PendingFile {
+            path,
+            writer,
+            position: data_start_offset,
+            data_start_offset,
+            bloom_size,
+        })
+    }
 
-            let compressed_size = compressed.len() as u32;
+    /// Bytes of entry data written so far, excluding the header, bloom filter and index
+    pub fn data_size_bytes(&self) -> u64 {
+        self.file
+            .as_ref()
+            .map_or(0, |file| file.position - file.data_start_offset)
+    }
 
-            // Calculate checksum
-            let mut hasher = Hasher::new();
-            hasher.update(&compressed);
-            let checksum = hasher.finalize();
+    /// Finalize and write SSTable to disk
+    pub fn finish(mut self) -> Result<SSTableMetadata> {
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => {
+                return Err(LoraDbError::StorageError("Cannot write empty SSTable".into()).into());
+            }
+        };
+        let created_at = match self.write_trailer(&mut file) {
+            Ok(created_at) => created_at,
+            Err(e) => {
+                let _ = std::fs::remove_file(&file.path);
+                return Err(e);
+            }
+        };
+        // Only a complete SSTable ever appears under its final name
+        if let Err(e) = std::fs::rename(&file.path, &self.output_path) {
+            let _ = std::fs::remove_file(&file.path);
+            return Err(e.into());
+        }
 
-            // Write: [compressed_size(4) | compressed_data(N) | checksum(4)]
-            writer.write_all(&compressed_size.to_le_bytes())?;
-            writer.write_all(&compressed)?;
-            writer.write_all(&checksum.to_le_bytes())?;
+        // Prepare metadata
+        let file_size_bytes = std::fs::metadata(&self.output_path)?.len();
+        let min_key = self.index_entries.first().unwrap().key.clone();
+        let max_key = self.index_entries.last().unwrap().key.clone();
+        let num_entries = self.index_entries.len() as u64;
+        let data_size_bytes = self.data_end_offset() - file.data_start_offset;
+        let compressed_size_bytes = file.position - file.data_start_offset;
 
-            let entry_size = 4 + compressed_size + 4;
+        info!(
+            "Wrote SSTable {} with {} entries, {} bytes (compressed: {})",
+            self.id, num_entries, data_size_bytes, compressed_size_bytes
+        );
 
-            index_entries.push(IndexEntry {
-                key: key.clone(),
-                offset: entry_offset,
-                size: entry_size,
-            });
-        }
+        Ok(SSTableMetadata {
+            id: self.id,
+            created_at,
+            num_entries,
+            min_key,
+            max_key,
+            bloom_filter: self.bloom_filter.clone(),
+            data_size_bytes,
+            compressed_size_bytes,
+            file_size_bytes,
+            application_ids: std::mem::take(&mut self.application_ids),
+        })
+    }
 
-        let data_end_offset = writer.stream_position()?;
-        let data_size_bytes = data_end_offset - data_start_offset;
+    /// Offset just past the last data block
+    fn data_end_offset(&self) -> u64 {
+        self.index_entries
+            .last()
+            .map_or(0, |entry| entry.offset + entry.size as u64)
+    }
+
+    /// Write index and footer after the data blocks, fill in the header and sync
+    /// Returns the creation time stored in the footer; `file.position` is left at the end of the index
+    fn write_trailer(&self, file: &mut PendingFile) -> Result<DateTime<Utc>> {
+        let writer = &mut file.writer;
 
         // Write index
-        let index_offset = writer.stream_position()?;
-        let index_count = index_entries.len() as u32;
+        let index_offset = file.position;
+        let index_count = self.index_entries.len() as u32;
         writer.write_all(&index_count.to_le_bytes())?;
+        file.position += 4;
 
-        for entry in &index_entries {
+        for entry in &self.index_entries {
             // Serialize key
             let key_data = bincode::serialize(&entry.key)?;
             let key_size = key_data.len() as u32;
@@ -188,17 +278,16 @@ impl SSTableWriter {
             writer.write_all(&key_data)?;
             writer.write_all(&entry.offset.to_le_bytes())?;
             writer.write_all(&entry.size.to_le_bytes())?;
+            file.position += 4 + key_data.len() as u64 + 8 + 4;
         }
 
-        let index_end_offset = writer.stream_position()?;
-
         // Write footer with metadata
         // Layout: min_key (size+data) | max_key (size+data) | created_at (8) | index_offset (8)
         // This puts fixed-size data at the end for easy seeking
 
         // Serialize min/max keys
-        let min_key_data = bincode::serialize(&min_key)?;
-        let max_key_data = bincode::serialize(&max_key)?;
+        let min_key_data = bincode::serialize(&self.index_entries.first().unwrap().key)?;
+        let max_key_data = bincode::serialize(&self.index_entries.last().unwrap().key)?;
         let min_key_size = min_key_data.len() as u32;
         let max_key_size = max_key_data.len() as u32;
 
@@ -208,44 +297,233 @@ impl SSTableWriter {
         writer.write_all(&max_key_data)?;
 
         // Write fixed-size footer at end
-        let created_at_micros = Utc::now().timestamp_micros();
-        writer.write_all(&created_at_micros.to_le_bytes())?;
+        let created_at = Utc::now();
+        writer.write_all(&created_at.timestamp_micros().to_le_bytes())?;
         writer.write_all(&index_offset.to_le_bytes())?;
 
+        // Fill in the entry count and bloom filter reserved in the header
+        let bloom_data = bincode::serialize(&self.bloom_filter)?;
+        if bloom_data.len() as u32 != file.bloom_size {
+            return Err(LoraDbError::StorageError(format!(
+                "Bloom filter grew from {} to {} bytes",
+                file.bloom_size,
+                bloom_data.len()
+            ))
+            .into());
+        }
+        writer.seek(SeekFrom::Start(4 + 2 + 8))?;
+        writer.write_all(&(self.index_entries.len() as u64).to_le_bytes())?;
+        writer.write_all(&file.bloom_size.to_le_bytes())?;
+        writer.write_all(&bloom_data)?;
+
         writer.flush()?;
+        writer.get_ref().sync_all()?;
 
-        let compressed_size_bytes = index_end_offset - data_start_offset;
+        Ok(created_at)
+    }
+}
 
-        info!(
-            "Wrote SSTable {} with {} entries, {} bytes (compressed: {})",
-            self.id, num_entries, data_size_bytes, compressed_size_bytes
-        );
+impl Drop for SSTableWriter {
+    fn drop(&mut self) {
+        // Abandoned before finish, e.g. a failed compaction
+        if let Some(file) = self.file.take() {
+            drop(file.writer);
+            let _ = std::fs::remove_file(&file.path);
+        }
+    }
+}
 
-        Ok(SSTableMetadata {
-            id: self.id,
-            created_at: DateTime::from_timestamp_micros(created_at_micros).unwrap(),
-            num_entries,
-            min_key,
-            max_key,
-            bloom_filter: self.bloom_filter,
-            data_size_bytes,
-            compressed_size_bytes,
-            application_ids: self.application_ids,
+/// Cache of open SSTables (index and file handle), shared by readers created with
+/// `SSTableReader::open_cached`, evicting the least recently used table over capacity
+pub struct SSTableCache {
+    capacity: usize,
+    inner: Mutex<CacheState>,
+}
+
+struct CacheState {
+    tables: HashMap<PathBuf, CachedTable>,
+    /// Incremented on every access, used as the recency stamp
+    clock: u64,
+}
+
+struct CachedTable {
+    last_used: u64,
+    table: Arc<OpenTable>,
+}
+
+/// An SSTable's index and an open handle to its file
+struct OpenTable {
+    index: Vec<IndexEntry>,
+    file: Mutex<File>,
+}
+
+impl SSTableCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            inner: Mutex::new(CacheState {
+                tables: HashMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    /// Number of tables currently open
+    pub fn len(&self) -> usize {
+        self.inner.lock().tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the table at `path` is currently open
+    pub fn contains(&self, path: &Path) -> bool {
+        self.inner.lock().tables.contains_key(path)
+    }
+
+    fn get(&self, path: &Path) -> Option<Arc<OpenTable>> {
+        let mut state = self.inner.lock();
+        state.clock += 1;
+        let clock = state.clock;
+        state.tables.get_mut(path).map(|cached| {
+            cached.last_used = clock;
+            cached.table.clone()
         })
     }
+
+    fn insert(&self, path: PathBuf, table: Arc<OpenTable>) {
+        let mut state = self.inner.lock();
+        state.clock += 1;
+        let last_used = state.clock;
+        state.tables.insert(path, CachedTable { last_used, table });
+
+        while state.tables.len() > self.capacity {
+            let oldest = state
+                .tables
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(path, _)| path.clone());
+            match oldest {
+                Some(path) => {
+                    debug!("Closing SSTable {:?} (open table cache full)", path);
+                    state.tables.remove(&path);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&self, path: &Path) {
+        self.inner.lock().tables.remove(path);
+    }
 }
 
 /// SSTable reader for querying data
+/// Bloom filter, key range and max timestamp stay in memory so tables can be pruned
+/// without touching disk. The index is either resident or loaded through an `SSTableCache`
 pub struct SSTableReader {
     id: u64,
+    /// Format version from the header, older tables have their frames upgraded on read
+    version: u16,
     path: PathBuf,
     metadata: SSTableMetadata,
-    index: Vec<IndexEntry>,
+    /// Offset of the index block, for reloading it after the table was closed
+    index_offset: u64,
+    /// Oldest and newest entry timestamps across all devices (keys are sorted by device first)
+    min_timestamp_micros: i64,
+    max_timestamp_micros: i64,
+    /// Application IDs found by scanning a table whose metadata didn't have them
+    scanned_application_ids: OnceLock<HashSet<String>>,
+    storage: ReaderStorage,
+}
+
+enum ReaderStorage {
+    /// Index kept for the reader's lifetime, the file is opened per read
+    Resident(Vec<IndexEntry>),
+    /// Index and file handle opened on demand and held in the shared cache
+    Cached(Arc<SSTableCache>),
+}
+
+/// A reader's index and, when cached, its open file, for the duration of one operation
+enum TableView<'a> {
+    Resident(&'a [IndexEntry]),
+    Cached(Arc<OpenTable>),
+}
+
+impl TableView<'_> {
+    fn index(&self) -> &[IndexEntry] {
+        match self {
+            TableView::Resident(index) => index,
+            TableView::Cached(table) => &table.index,
+        }
+    }
 }
 
 impl SSTableReader {
-    /// Open an existing SSTable
+    /// Whether a file looks like a fully written SSTable: a valid header and a footer
+    /// pointing at an index inside the file
+    /// A write interrupted by a crash leaves a file that fails this check
+    pub fn is_complete(path: &Path) -> Result<bool> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < HEADER_LEN + FOOTER_LEN {
+            return Ok(false);
+        }
+
+        let mut magic_buf = [0u8; 4];
+        file.read_exact(&mut magic_buf)?;
+        if u32::from_le_bytes(magic_buf) != SSTABLE_MAGIC {
+            return Ok(false);
+        }
+
+        file.seek(SeekFrom::End(-(FOOTER_LEN as i64)))?;
+        let mut created_at_buf = [0u8; 8];
+        file.read_exact(&mut created_at_buf)?;
+        let mut index_offset_buf = [0u8; 8];
+        file.read_exact(&mut index_offset_buf)?;
+
+        let created_at = DateTime::from_timestamp_micros(i64::from_le_bytes(created_at_buf));
+        let index_offset = u64::from_le_bytes(index_offset_buf);
+        Ok(created_at.is_some() && index_offset >= HEADER_LEN && index_offset + 4 <= len - FOOTER_LEN)
+    }
+
+    /// Format version stored in an SSTable's header
+    pub fn version(path: &Path) -> Result<u16> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; 6];
+        file.read_exact(&mut header)?;
+
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        if magic != SSTABLE_MAGIC {
+            return Err(LoraDbError::StorageError(format!(
+                "Invalid SSTable magic: expected 0x{:08X}, got 0x{:08X}",
+                SSTABLE_MAGIC, magic
+            ))
+            .into());
+        }
+        Ok(u16::from_le_bytes([header[4], header[5]]))
+    }
+
+    /// Open an existing SSTable, keeping its index in memory
+    /// Tables written by an older version are read as well, their frames are upgraded as
+    /// they are read. Entries that can't be upgraded return an error from `iter_entries`
     pub fn open(path: PathBuf) -> Result<Self> {
+        let (mut reader, index) = Self::open_metadata(path)?;
+        reader.storage = ReaderStorage::Resident(index);
+        Ok(reader)
+    }
+
+    /// Open an existing SSTable, keeping only its metadata in memory
+    /// The index and file handle are reopened through `cache` when the table is read
+    pub fn open_cached(path: PathBuf, cache: &Arc<SSTableCache>) -> Result<Self> {
+        let (mut reader, _index) = Self::open_metadata(path)?;
+        reader.storage = ReaderStorage::Cached(cache.clone());
+        Ok(reader)
+    }
+
+    /// Read header, bloom filter and footer, returning the reader without storage and the index
+    fn open_metadata(path: PathBuf) -> Result<(Self, Vec<IndexEntry>)> {
         let mut file = File::open(&path)?;
         let mut reader = BufReader::new(&mut file);
 
@@ -264,10 +542,10 @@ impl SSTableReader {
         let mut version_buf = [0u8; 2];
         reader.read_exact(&mut version_buf)?;
         let version = u16::from_le_bytes(version_buf);
-        if version != SSTABLE_VERSION {
+        if !(MIN_SSTABLE_VERSION..=SSTABLE_VERSION).contains(&version) {
             warn!(
-                "Skipping SSTable {:?} with incompatible version {} (current: {})",
-                path, version, SSTABLE_VERSION
+                "Skipping SSTable {:?} with incompatible version {} (supported: {} to {})",
+                path, version, MIN_SSTABLE_VERSION, SSTABLE_VERSION
             );
             return Err(LoraDbError::IncompatibleSStableVersion(version).into());
         }
@@ -288,6 +566,7 @@ impl SSTableReader {
         let mut bloom_data = vec![0u8; bloom_size as usize];
         reader.read_exact(&mut bloom_data)?;
         let bloom_filter: BloomFilter = bincode::deserialize(&bloom_data)?;
+        let data_start_offset = HEADER_LEN + bloom_size as u64;
 
         // Seek to footer to read metadata
         drop(reader); // Close BufReader before opening new file handle
@@ -295,12 +574,14 @@ impl SSTableReader {
         // Footer layout: min_key (size+data) | max_key (size+data) | created_at (8) | index_offset (8)
         // Read fixed-size footer from end first
         let mut footer_reader = File::open(&path)?;
-        footer_reader.seek(SeekFrom::End(-16))?;
+        let file_size_bytes = footer_reader.seek(SeekFrom::End(-(FOOTER_LEN as i64)))? + FOOTER_LEN;
 
         let mut created_at_buf = [0u8; 8];
         footer_reader.read_exact(&mut created_at_buf)?;
         let created_at_micros = i64::from_le_bytes(created_at_buf);
-        let created_at = DateTime::from_timestamp_micros(created_at_micros).unwrap();
+        let created_at = DateTime::from_timestamp_micros(created_at_micros).ok_or_else(|| {
+            LoraDbError::StorageError(format!("Invalid SSTable footer in {:?}", path))
+        })?;
 
         let mut index_offset_buf = [0u8; 8];
         footer_reader.read_exact(&mut index_offset_buf)?;
@@ -308,32 +589,8 @@ impl SSTableReader {
 
         // Now read index to find where footer starts
         let mut index_reader = File::open(&path)?;
-        index_reader.seek(SeekFrom::Start(index_offset))?;
-
-        let mut index_count_buf = [0u8; 4];
-        index_reader.read_exact(&mut index_count_buf)?;
-        let index_count = u32::from_le_bytes(index_count_buf);
-
-        let mut index = Vec::with_capacity(index_count as usize);
-        for _ in 0..index_count {
-            let mut key_size_buf = [0u8; 4];
-            index_reader.read_exact(&mut key_size_buf)?;
-            let key_size = u32::from_le_bytes(key_size_buf);
-
-            let mut key_data = vec![0u8; key_size as usize];
-            index_reader.read_exact(&mut key_data)?;
-            let key: MemtableKey = bincode::deserialize(&key_data)?;
-
-            let mut offset_buf = [0u8; 8];
-            index_reader.read_exact(&mut offset_buf)?;
-            let offset = u64::from_le_bytes(offset_buf);
-
-            let mut size_buf = [0u8; 4];
-            index_reader.read_exact(&mut size_buf)?;
-            let size = u32::from_le_bytes(size_buf);
-
-            index.push(IndexEntry { key, offset, size });
-        }
+        let index = read_index(&mut index_reader, index_offset)?;
+        let index_end = index_reader.stream_position()?;
 
         // Now read min/max keys from after the index
         let mut min_key_size_buf = [0u8; 4];
@@ -359,19 +616,70 @@ impl SSTableReader {
             min_key,
             max_key,
             bloom_filter,
-            data_size_bytes: 0, // Not stored in file
-            compressed_size_bytes: 0,
+            // Data blocks run from the end of the bloom filter to the index
+            data_size_bytes: index_offset.saturating_sub(data_start_offset),
+            compressed_size_bytes: index_end.saturating_sub(data_start_offset),
+            file_size_bytes,
             application_ids: HashSet::new(), // Will be populated lazily if needed for retention
         };
 
+        let min_timestamp_micros = index
+            .iter()
+            .map(|entry| entry.key.timestamp)
+            .min()
+            .unwrap_or(metadata.min_key.timestamp);
+        let max_timestamp_micros = index
+            .iter()
+            .map(|entry| entry.key.timestamp)
+            .max()
+            .unwrap_or(metadata.max_key.timestamp);
+
         debug!("Opened SSTable {} with {} entries", id, num_entries);
 
-        Ok(Self {
+        let reader = Self {
             id,
+            version,
             path,
             metadata,
-            index,
-        })
+            index_offset,
+            min_timestamp_micros,
+            max_timestamp_micros,
+            scanned_application_ids: OnceLock::new(),
+            storage: ReaderStorage::Resident(Vec::new()),
+        };
+        Ok((reader, index))
+    }
+
+    /// The index for one operation, reopening the table if the cache closed it
+    fn table(&self) -> Result<TableView<'_>> {
+        match &self.storage {
+            ReaderStorage::Resident(index) => Ok(TableView::Resident(index)),
+            ReaderStorage::Cached(cache) => {
+                if let Some(table) = cache.get(&self.path) {
+                    return Ok(TableView::Cached(table));
+                }
+
+                let mut file = File::open(&self.path)?;
+                let index = read_index(&mut file, self.index_offset)?;
+                let table = Arc::new(OpenTable {
+                    index,
+                    file: Mutex::new(file),
+                });
+                cache.insert(self.path.clone(), table.clone());
+                Ok(TableView::Cached(table))
+            }
+        }
+    }
+
+    /// Load the index ahead of the first query that needs it
+    /// With `read_data`, also read the whole file once so its data is in the OS page cache
+    pub fn warm(&self, read_data: bool) -> Result<()> {
+        self.table()?;
+        if read_data {
+            let mut file = File::open(&self.path)?;
+            std::io::copy(&mut file, &mut std::io::sink())?;
+        }
+        Ok(())
     }
 
     /// Check if a device might exist in this SSTable (using bloom filter)
@@ -387,16 +695,32 @@ impl SSTableReader {
     /// Iterate over all frames in this SSTable
     /// Used for rebuilding device registry on startup
     pub fn iter_all(&self) -> Result<Vec<Frame>> {
+        let table = self.table()?;
         let mut results = Vec::new();
 
-        for entry in &self.index {
-            let frame = self.read_frame(entry)?;
+        for entry in table.index() {
+            let frame = self.read_frame(&table, entry)?;
             results.push(frame);
         }
 
         Ok(results)
     }
 
+    /// Iterate all entries in key order, one at a time, with each entry's on-disk size in bytes
+    pub fn iter_entries(&self) -> impl Iterator<Item = Result<(MemtableKey, Frame, u64)>> + '_ {
+        let (table, open_error) = match self.table() {
+            Ok(table) => (Some(table), None),
+            Err(e) => (None, Some(Err(e))),
+        };
+        let len = table.as_ref().map_or(0, |table| table.index().len());
+
+        open_error.into_iter().chain((0..len).map(move |i| {
+            let table = table.as_ref().expect("table is open when len > 0");
+            let entry = &table.index()[i];
+            Ok((entry.key.clone(), self.read_frame(table, entry)?, entry.size as u64))
+        }))
+    }
+
     /// Scan for entries matching a device and time range
     pub fn scan(
         &self,
@@ -412,76 +736,168 @@ impl SSTableReader {
         let start_key = MemtableKey::range_start(dev_eui, start_time);
         let end_key = MemtableKey::range_end(dev_eui, end_time);
 
+        let table = self.table()?;
+        let index = table.index();
         let mut results = Vec::new();
 
-        // Binary search to find starting point
-        let start_idx = self
-            .index
-            .binary_search_by(|entry| entry.key.cmp(&start_key))
-            .unwrap_or_else(|idx| idx);
+        // First entry at or after start_key, even if the index held equal keys
+        let start_idx = index.partition_point(|entry| entry.key < start_key);
 
         // Scan from start_idx until we exceed end_key
-        for entry in &self.index[start_idx..] {
+        for entry in &index[start_idx..] {
             if entry.key > end_key {
                 break;
             }
 
-            if &entry.key >= &start_key && &entry.key <= &end_key {
-                // Read and decompress frame
-                let frame = self.read_frame(entry)?;
-                results.push(frame);
-            }
+            // Read and decompress frame
+            let frame = self.read_frame(&table, entry)?;
+            results.push(frame);
         }
 
         Ok(results)
     }
 
-    /// Read a single frame at a given index entry
-    fn read_frame(&self, entry: &IndexEntry) -> Result<Frame> {
-        let mut file = File::open(&self.path)?;
-        file.seek(SeekFrom::Start(entry.offset))?;
+    /// Iterate a device's entries in a time range, reading one frame per step
+    /// The range's index entries and a file handle are taken up front, so the iterator
+    /// keeps working after the table is closed, compacted away or deleted
+    pub fn range_iter(
+        &self,
+        dev_eui: &DevEui,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+    ) -> Result<SSTableRangeIter> {
+        let start_key = MemtableKey::range_start(dev_eui, start_time);
+        let end_key = MemtableKey::range_end(dev_eui, end_time);
+
+        let entries: Vec<IndexEntry> = if self.might_contain(dev_eui) {
+            let table = self.table()?;
+            let index = table.index();
+            let start_idx = index.partition_point(|entry| entry.key < start_key);
+            index[start_idx..]
+                .iter()
+                .take_while(|entry| entry.key <= end_key)
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
 
-        let mut reader = BufReader::new(file);
+        // No need to hold a file open for a range with nothing in it
+        let file = if entries.is_empty() {
+            None
+        } else {
+            Some(File::open(&self.path)?)
+        };
 
-        // Read compressed size
-        let mut size_buf = [0u8; 4];
-        reader.read_exact(&mut size_buf)?;
-        let compressed_size = u32::from_le_bytes(size_buf);
+        Ok(SSTableRangeIter {
+            id: self.id,
+            version: self.version,
+            file,
+            entries: entries.into_iter(),
+        })
+    }
 
-        // Read compressed data
-        let mut compressed_data = vec![0u8; compressed_size as usize];
-        reader.read_exact(&mut compressed_data)?;
+    /// Get the frame stored under exactly `key` (DevEUI, timestamp and sequence)
+    /// with a single index lookup instead of a range scan
+    pub fn get(&self, key: &MemtableKey) -> Result<Option<Frame>> {
+        if !self.metadata.bloom_filter.contains(&key.dev_eui) {
+            return Ok(None);
+        }
 
-        // Read checksum
-        let mut checksum_buf = [0u8; 4];
-        reader.read_exact(&mut checksum_buf)?;
-        let stored_checksum = u32::from_le_bytes(checksum_buf);
+        let table = self.table()?;
+        let index = table.index();
+        match index.binary_search_by(|entry| entry.key.cmp(key)) {
+            Ok(idx) => Ok(Some(self.read_frame(&table, &index[idx])?)),
+            Err(_) => Ok(None),
+        }
+    }
 
-        // Verify checksum
-        let mut hasher = Hasher::new();
-        hasher.update(&compressed_data);
-        let computed_checksum = hasher.finalize();
+    /// Number of a device's entries and their total on-disk (compressed) size in bytes,
+    /// from the index alone without reading any frames
+    pub fn device_usage(&self, dev_eui: &DevEui) -> Result<(u64, u64)> {
+        if !self.might_contain(dev_eui) {
+            return Ok((0, 0));
+        }
 
-        if stored_checksum != computed_checksum {
-            return Err(LoraDbError::StorageError(format!(
-                "Checksum mismatch in SSTable {}",
-                self.id
-            ))
-            .into());
+        let start_key = MemtableKey::range_start(dev_eui, None);
+        let end_key = MemtableKey::range_end(dev_eui, None);
+        let table = self.table()?;
+        let index = table.index();
+        let start_idx = index.partition_point(|entry| entry.key < start_key);
+
+        Ok(index[start_idx..]
+            .iter()
+            .take_while(|entry| entry.key <= end_key)
+            .fold((0, 0), |(frames, bytes), entry| (frames + 1, bytes + entry.size as u64)))
+    }
+
+    /// Get the newest frame for a device without scanning its whole range
+    pub fn get_latest(&self, dev_eui: &DevEui) -> Result<Option<Frame>> {
+        if !self.might_contain(dev_eui) {
+            return Ok(None);
         }
 
-        // Decompress
-        let mut decompressed = Vec::new();
-        {
-            let mut decoder = Decoder::new(&compressed_data[..])?;
-            decoder.read_to_end(&mut decompressed)?;
+        // The entry just before the end of the device's key range is its newest
+        let end_key = MemtableKey::range_end(dev_eui, None);
+        let table = self.table()?;
+        let index = table.index();
+        let end_idx = index.partition_point(|entry| entry.key <= end_key);
+
+        match end_idx.checked_sub(1).map(|idx| &index[idx]) {
+            Some(entry) if entry.key.dev_eui == end_key.dev_eui => {
+                Ok(Some(self.read_frame(&table, entry)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Scan a device's frames after `after_micros`, returning at least `limit` frames
+    /// (finishing the last timestamp) and whether the device has no more frames
+    pub fn scan_after(
+        &self,
+        dev_eui: &DevEui,
+        after_micros: Option<i64>,
+        limit: usize,
+    ) -> Result<(Vec<Frame>, bool)> {
+        if !self.might_contain(dev_eui) {
+            return Ok((Vec::new(), true));
+        }
+
+        let start_key = MemtableKey::resume_after(dev_eui, after_micros);
+        let end_key = MemtableKey::range_end(dev_eui, None);
+
+        let table = self.table()?;
+        let index = table.index();
+        let start_idx = index.partition_point(|entry| entry.key < start_key);
+
+        let mut results = Vec::new();
+        let mut last_timestamp = None;
+        for entry in &index[start_idx..] {
+            if entry.key > end_key {
+                break;
+            }
+            if results.len() >= limit && last_timestamp != Some(entry.key.timestamp) {
+                return Ok((results, false));
+            }
+            last_timestamp = Some(entry.key.timestamp);
+            results.push(self.read_frame(&table, entry)?);
         }
 
-        // Deserialize frame
-        let frame: Frame = bincode::deserialize(&decompressed)
-            .context("Failed to deserialize frame from SSTable")?;
+        Ok((results, true))
+    }
 
-        Ok(frame)
+    /// Read a single frame at a given index entry
+    fn read_frame(&self, table: &TableView<'_>, entry: &IndexEntry) -> Result<Frame> {
+        match table {
+            TableView::Resident(_) => {
+                let mut file = File::open(&self.path)?;
+                read_frame_from(self.id, self.version, &mut file, entry)
+            }
+            TableView::Cached(open) => {
+                let mut file = open.file.lock();
+                read_frame_from(self.id, self.version, &mut file, entry)
+            }
+        }
     }
 
     /// Get the SSTable ID
@@ -494,32 +910,158 @@ impl SSTableReader {
         &self.path
     }
 
+    /// Get the minimum timestamp in this SSTable (for the retention size budget)
+    pub fn min_timestamp(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp_micros(self.min_timestamp_micros)
+    }
+
     /// Get the maximum timestamp in this SSTable (for retention policy)
     pub fn max_timestamp(&self) -> Option<DateTime<Utc>> {
         // Convert microseconds timestamp to DateTime
-        DateTime::from_timestamp_micros(self.metadata.max_key.timestamp)
+        DateTime::from_timestamp_micros(self.max_timestamp_micros)
     }
 
-    /// Get all application IDs in this SSTable (for retention policy)
-    /// Scans the SSTable if not already populated in metadata
+    /// Get all normalized application IDs in this SSTable (for retention policy)
+    /// Scans the SSTable the first time if not already populated in metadata
     pub fn application_ids(&self) -> Result<HashSet<String>> {
         // If already populated (from new SSTables), return it
+        // SSTables written before normalization may still hold the original casing
         if !self.metadata.application_ids.is_empty() {
-            return Ok(self.metadata.application_ids.clone());
+            return Ok(self
+                .metadata
+                .application_ids
+                .iter()
+                .map(|id| ApplicationId::normalize(id))
+                .collect());
         }
 
-        // Otherwise, scan the SSTable to build the set
+        // Otherwise, scan the SSTable to build the set, once since the table never changes
+        if let Some(app_ids) = self.scanned_application_ids.get() {
+            return Ok(app_ids.clone());
+        }
         let mut app_ids = HashSet::new();
         for frame in self.iter_all()? {
             if let Some(app_id) = frame.application_id() {
-                app_ids.insert(app_id.as_str().to_string());
+                app_ids.insert(app_id.normalized());
             }
         }
 
-        Ok(app_ids)
+        Ok(self.scanned_application_ids.get_or_init(|| app_ids).clone())
     }
 }
 
+/// One device's entries in a key range of an SSTable, see `SSTableReader::range_iter`
+pub struct SSTableRangeIter {
+    id: u64,
+    version: u16,
+    file: Option<File>,
+    entries: std::vec::IntoIter<IndexEntry>,
+}
+
+impl Iterator for SSTableRangeIter {
+    type Item = Result<(MemtableKey, Frame)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next()?;
+        let file = self.file.as_mut().expect("file is open while entries remain");
+        Some(read_frame_from(self.id, self.version, file, &entry).map(|frame| (entry.key, frame)))
+    }
+}
+
+impl Drop for SSTableReader {
+    fn drop(&mut self) {
+        // Close the table now rather than when it ages out, it may be about to be deleted
+        if let ReaderStorage::Cached(cache) = &self.storage {
+            cache.remove(&self.path);
+        }
+    }
+}
+
+/// Read and verify the frame stored at one index entry of SSTable `id`
+fn read_frame_from(id: u64, version: u16, file: &mut File, entry: &IndexEntry) -> Result<Frame> {
+    file.seek(SeekFrom::Start(entry.offset))?;
+
+    let mut reader = BufReader::new(file);
+
+    // Read compressed size
+    let mut size_buf = [0u8; 4];
+    reader.read_exact(&mut size_buf)?;
+    let compressed_size = u32::from_le_bytes(size_buf);
+
+    // Read compressed data
+    let mut compressed_data = vec![0u8; compressed_size as usize];
+    reader.read_exact(&mut compressed_data)?;
+
+    // Read checksum
+    let mut checksum_buf = [0u8; 4];
+    reader.read_exact(&mut checksum_buf)?;
+    let stored_checksum = u32::from_le_bytes(checksum_buf);
+
+    // Verify checksum
+    let mut hasher = Hasher::new();
+    hasher.update(&compressed_data);
+    let computed_checksum = hasher.finalize();
+
+    if stored_checksum != computed_checksum {
+        return Err(LoraDbError::StorageError(format!(
+            "Checksum mismatch in SSTable {}",
+            id
+        ))
+        .into());
+    }
+
+    // Decompress
+    let mut decompressed = Vec::new();
+    {
+        let mut decoder = Decoder::new(&compressed_data[..])?;
+        decoder.read_to_end(&mut decompressed)?;
+    }
+
+    // Deserialize frame
+    let frame = Frame::decode_version(&decompressed, version)
+        .context("Failed to deserialize frame from SSTable")?;
+
+    Ok(frame)
+}
+
+/// Read the index block at `index_offset`, leaving `file` positioned just after it
+fn read_index(file: &mut File, index_offset: u64) -> Result<Vec<IndexEntry>> {
+    file.seek(SeekFrom::Start(index_offset))?;
+    let mut reader = BufReader::new(&mut *file);
+
+    let mut index_count_buf = [0u8; 4];
+    reader.read_exact(&mut index_count_buf)?;
+    let index_count = u32::from_le_bytes(index_count_buf);
+
+    let mut index = Vec::with_capacity(index_count as usize);
+    for _ in 0..index_count {
+        let mut key_size_buf = [0u8; 4];
+        reader.read_exact(&mut key_size_buf)?;
+        let key_size = u32::from_le_bytes(key_size_buf);
+
+        let mut key_data = vec![0u8; key_size as usize];
+        reader.read_exact(&mut key_data)?;
+        let key: MemtableKey = bincode::deserialize(&key_data)?;
+
+        let mut offset_buf = [0u8; 8];
+        reader.read_exact(&mut offset_buf)?;
+        let offset = u64::from_le_bytes(offset_buf);
+
+        let mut size_buf = [0u8; 4];
+        reader.read_exact(&mut size_buf)?;
+        let size = u32::from_le_bytes(size_buf);
+
+        index.push(IndexEntry { key, offset, size });
+    }
+
+    // BufReader reads ahead, rewind the file to the end of the index
+    let position = reader.stream_position()?;
+    drop(reader);
+    file.seek(SeekFrom::Start(position))?;
+
+    Ok(index)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -534,6 +1076,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("test-device".to_string()),
             received_at: timestamp,
+            stored_at: None,
             f_port: 1,
             f_cnt: 42,
             confirmed: false,
@@ -591,6 +1134,89 @@ mod tests {
         assert_eq!(recent_frames.len(), 2);
     }
 
+    #[test]
+    fn test_sstable_scan_identical_timestamps_at_boundary() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let other = DevEui::new("FEDCBA9876543210".to_string()).unwrap();
+        let at = Utc::now() - chrono::Duration::minutes(5);
+        let before = at - chrono::Duration::seconds(1);
+        let after = at + chrono::Duration::seconds(1);
+
+        // Three frames share `at`, the first with the sequence a range start uses
+        let mut writer = SSTableWriter::new(1, temp_dir.path());
+        let entries = [
+            (&dev_eui, before, 0),
+            (&dev_eui, at, 0),
+            (&dev_eui, at, 1),
+            (&dev_eui, at, 2),
+            (&dev_eui, after, 3),
+            (&other, at, 0),
+        ];
+        for (dev, timestamp, sequence) in entries {
+            let key = MemtableKey::new(dev, timestamp, sequence);
+            writer.add(key, create_test_frame(dev.as_str(), timestamp)).unwrap();
+        }
+        writer.finish().unwrap();
+        let reader = SSTableReader::open(temp_dir.path().join("sstable-00000001.sst")).unwrap();
+
+        let frames = reader.scan(&dev_eui, Some(at), Some(at)).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| f.timestamp() == at));
+        assert_eq!(reader.scan(&dev_eui, Some(at), None).unwrap().len(), 4);
+        assert_eq!(reader.scan(&dev_eui, None, Some(at)).unwrap().len(), 4);
+
+        let iter = reader.range_iter(&dev_eui, Some(at), Some(at)).unwrap();
+        assert_eq!(iter.count(), 3);
+        assert_eq!(reader.scan(&other, Some(at), Some(at)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_sstable_get_latest() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui1 = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let dev_eui2 = DevEui::new("FEDCBA9876543210".to_string()).unwrap();
+
+        let now = Utc::now();
+        let one_hour_ago = now - chrono::Duration::hours(1);
+
+        let mut writer = SSTableWriter::new(1, temp_dir.path());
+        writer
+            .add(
+                MemtableKey::new(&dev_eui1, one_hour_ago, 0),
+                create_test_frame("0123456789ABCDEF", one_hour_ago),
+            )
+            .unwrap();
+        writer
+            .add(
+                MemtableKey::new(&dev_eui1, now, 1),
+                create_test_frame("0123456789ABCDEF", now),
+            )
+            .unwrap();
+        writer
+            .add(
+                MemtableKey::new(&dev_eui2, one_hour_ago, 2),
+                create_test_frame("FEDCBA9876543210", one_hour_ago),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+
+        let reader = SSTableReader::open(temp_dir.path().join("sstable-00000001.sst")).unwrap();
+
+        let latest = reader.get_latest(&dev_eui1).unwrap().unwrap();
+        assert_eq!(latest.timestamp(), now);
+
+        let latest = reader.get_latest(&dev_eui2).unwrap().unwrap();
+        assert_eq!(latest.timestamp(), one_hour_ago);
+
+        // Min and max timestamps span all devices, not just the first and last key
+        assert_eq!(reader.max_timestamp().unwrap().timestamp_micros(), now.timestamp_micros());
+        assert_eq!(
+            reader.min_timestamp().unwrap().timestamp_micros(),
+            one_hour_ago.timestamp_micros()
+        );
+    }
+
     #[test]
     fn test_sstable_bloom_filter() {
         let temp_dir = TempDir::new().unwrap();
@@ -616,6 +1242,126 @@ mod tests {
         // We can't assert !might_contain because of false positives
     }
 
+    #[test]
+    fn test_sstable_get_exact_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        let mut writer = SSTableWriter::new(1, temp_dir.path());
+        for i in 0..5 {
+            let timestamp = now + chrono::Duration::seconds(i);
+            writer
+                .add(
+                    MemtableKey::new(&dev_eui, timestamp, i as u64),
+                    create_test_frame("0123456789ABCDEF", timestamp),
+                )
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let reader = SSTableReader::open(temp_dir.path().join("sstable-00000001.sst")).unwrap();
+        let timestamp = now + chrono::Duration::seconds(3);
+
+        let frame = reader.get(&MemtableKey::new(&dev_eui, timestamp, 3)).unwrap().unwrap();
+        assert_eq!(frame.timestamp().timestamp_micros(), timestamp.timestamp_micros());
+
+        // Every part of the key has to match
+        assert!(reader.get(&MemtableKey::new(&dev_eui, timestamp, 4)).unwrap().is_none());
+        let later = now + chrono::Duration::seconds(10);
+        assert!(reader.get(&MemtableKey::new(&dev_eui, later, 3)).unwrap().is_none());
+        let other = DevEui::new("FEDCBA9876543210".to_string()).unwrap();
+        assert!(reader.get(&MemtableKey::new(&other, timestamp, 3)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_sstable_device_usage() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_a = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let dev_b = DevEui::new("FEDCBA9876543210".to_string()).unwrap();
+        let now = Utc::now();
+
+        let mut writer = SSTableWriter::new(1, temp_dir.path());
+        for i in 0..3 {
+            let timestamp = now + chrono::Duration::seconds(i);
+            writer
+                .add(
+                    MemtableKey::new(&dev_a, timestamp, i as u64),
+                    create_test_frame("0123456789ABCDEF", timestamp),
+                )
+                .unwrap();
+        }
+        writer
+            .add(
+                MemtableKey::new(&dev_b, now, 3),
+                create_test_frame("FEDCBA9876543210", now),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+
+        let reader = SSTableReader::open(temp_dir.path().join("sstable-00000001.sst")).unwrap();
+        let (frames_a, bytes_a) = reader.device_usage(&dev_a).unwrap();
+        let (frames_b, bytes_b) = reader.device_usage(&dev_b).unwrap();
+        assert_eq!(frames_a, 3);
+        assert_eq!(frames_b, 1);
+        assert!(bytes_a > bytes_b && bytes_b > 0);
+        // Entry sizes, with their length prefix and checksum, make up the whole data section
+        assert_eq!(bytes_a + bytes_b, reader.metadata().data_size_bytes);
+
+        let other = DevEui::new("1111111111111111".to_string()).unwrap();
+        assert_eq!(reader.device_usage(&other).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn test_sstable_cache_closes_least_recently_used() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        for id in 1..=3 {
+            let mut writer = SSTableWriter::new(id, temp_dir.path());
+            let timestamp = now - chrono::Duration::hours(id as i64);
+            writer
+                .add(
+                    MemtableKey::new(&dev_eui, timestamp, 0),
+                    create_test_frame("0123456789ABCDEF", timestamp),
+                )
+                .unwrap();
+            writer.finish().unwrap();
+        }
+
+        let cache = Arc::new(SSTableCache::new(2));
+        let readers: Vec<_> = (1..=3)
+            .map(|id| {
+                let path = temp_dir.path().join(format!("sstable-{:08}.sst", id));
+                SSTableReader::open_cached(path, &cache).unwrap()
+            })
+            .collect();
+
+        // Opening only reads metadata
+        assert!(cache.is_empty());
+        assert!(readers[0].might_contain(&dev_eui));
+
+        assert_eq!(readers[0].scan(&dev_eui, None, None).unwrap().len(), 1);
+        assert_eq!(readers[1].scan(&dev_eui, None, None).unwrap().len(), 1);
+        assert_eq!(cache.len(), 2);
+
+        // Touch table 1 so table 2 is the one closed
+        readers[0].get_latest(&dev_eui).unwrap().unwrap();
+        assert_eq!(readers[2].scan(&dev_eui, None, None).unwrap().len(), 1);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(readers[0].path()).is_some());
+        assert!(cache.get(readers[1].path()).is_none());
+
+        // A closed table is reopened transparently
+        assert_eq!(readers[1].iter_all().unwrap().len(), 1);
+        assert_eq!(readers[1].iter_entries().count(), 1);
+
+        // Dropping a reader closes its table
+        drop(readers);
+        assert!(cache.is_empty());
+    }
+
     #[test]
     fn test_sstable_sorted_order_enforcement() {
         let temp_dir = TempDir::new().unwrap();
@@ -637,4 +1383,31 @@ mod tests {
         let result = writer.add(key2, create_test_frame("0123456789ABCDEF", one_hour_ago));
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_sstable_written_to_temp_file_until_finished() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+        let final_path = temp_dir.path().join("sstable-00000001.sst");
+        let temp_path = temp_dir.path().join("sstable-00000001.sst.tmp");
+
+        // An abandoned writer cleans up after itself
+        let mut writer = SSTableWriter::new(1, temp_dir.path());
+        writer
+            .add(MemtableKey::new(&dev_eui, now, 0), create_test_frame("0123456789ABCDEF", now))
+            .unwrap();
+        assert!(temp_path.exists() && !final_path.exists());
+        drop(writer);
+        assert!(!temp_path.exists() && !final_path.exists());
+
+        let mut writer = SSTableWriter::new(1, temp_dir.path());
+        writer
+            .add(MemtableKey::new(&dev_eui, now, 0), create_test_frame("0123456789ABCDEF", now))
+            .unwrap();
+        writer.finish().unwrap();
+        assert!(!temp_path.exists());
+        assert!(SSTableReader::is_complete(&final_path).unwrap());
+        assert_eq!(SSTableReader::open(final_path).unwrap().iter_all().unwrap().len(), 1);
+    }
 }
@@ -1,10 +1,13 @@
+use crate::config::WalDurability;
 use crate::error::LoraDbError;
-use crate::model::frames::Frame;
+use crate::model::frames::{Frame, FRAME_VERSION, MIN_FRAME_VERSION};
 use anyhow::{Context, Result};
 use crc32fast::Hasher;
 use std::fs::{create_dir_all, File, OpenOptions};
 use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use parking_lot::Mutex;
 use tracing::{error, info, warn};
@@ -12,15 +15,29 @@ use tracing::{error, info, warn};
 #[allow(dead_code)]
 const WAL_SEGMENT_SIZE: u64 = 64 * 1024 * 1024; // 64MB per segment
 const WAL_MAGIC: u32 = 0x4C4F5241; // "LORA"
-const WAL_VERSION: u16 = 2; // v2: Fixed bincode compatibility for serde_json::Value
+const WAL_VERSION: u16 = FRAME_VERSION; // v2: Fixed bincode compatibility for serde_json::Value
+/// Oldest entry version replayed, versions so far only differ in the frame encoding
+const MIN_WAL_VERSION: u16 = MIN_FRAME_VERSION;
+
+/// Outcome of replaying the WAL on startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ReplayReport {
+    /// Frames successfully recovered
+    pub recovered: usize,
+    /// Entries skipped due to checksum, version or deserialization failures
+    pub skipped: usize,
+    /// Segments that could not be read at all
+    pub failed_segments: usize,
+}
 
 /// Write-Ahead Log for durability
 pub struct WriteAheadLog {
     data_dir: PathBuf,
     current_segment: Arc<Mutex<WalSegment>>,
     segment_number: u64,
-    #[allow(dead_code)]
-    sync_interval_ms: u64,
+    durability: WalDurability,
+    /// Set by appends since the last fsync, so idle interval syncs are free
+    dirty: AtomicBool,
 }
 
 struct WalSegment {
@@ -42,7 +59,7 @@ struct WalEntry {
 }
 
 impl WriteAheadLog {
-    pub fn open(data_dir: &Path, sync_interval_ms: u64) -> Result<Self> {
+    pub fn open(data_dir: &Path, durability: WalDurability) -> Result<Self> {
         let wal_dir = data_dir.join("wal");
         create_dir_all(&wal_dir).context("Failed to create WAL directory")?;
 
@@ -60,25 +77,30 @@ impl WriteAheadLog {
         let segment = WalSegment::open(&segment_path)?;
 
         info!(
-            "Opened WAL at {:?}, segment {}",
-            wal_dir, segment_number
+            "Opened WAL at {:?}, segment {}, durability {:?}",
+            wal_dir, segment_number, durability
         );
 
         Ok(Self {
             data_dir: wal_dir,
             current_segment: Arc::new(Mutex::new(segment)),
             segment_number,
-            sync_interval_ms,
+            durability,
+            dirty: AtomicBool::new(false),
         })
     }
 
+    /// Get the configured fsync policy
+    pub fn durability(&self) -> WalDurability {
+        self.durability
+    }
+
     /// Append a frame to the WAL
     pub fn append(&self, frame: &Frame) -> Result<()> {
         let mut segment = self.current_segment.lock();
 
         // Serialize frame
-        let payload =
-            bincode::serialize(frame).context("Failed to serialize frame")?;
+        let payload = frame.encode().context("Failed to serialize frame")?;
 
         if payload.len() > u32::MAX as usize {
             return Err(
@@ -103,9 +125,15 @@ impl WriteAheadLog {
 
         segment.size += 4 + 2 + 4 + payload.len() as u64 + 4;
 
-        // Flush to ensure durability
+        // Hand the entry to the OS so it survives a process crash
         segment.file.flush()?;
 
+        if self.durability == WalDurability::Always {
+            segment.file.get_ref().sync_data()?;
+        } else {
+            self.dirty.store(true, Ordering::Release);
+        }
+
         Ok(())
     }
 
@@ -113,41 +141,54 @@ impl WriteAheadLog {
     pub fn sync(&self) -> Result<()> {
         let mut segment = self.current_segment.lock();
 
+        self.dirty.store(false, Ordering::Release);
         segment.file.flush()?;
         segment.file.get_mut().sync_all()?;
         Ok(())
     }
 
+    /// Sync only if anything was appended since the last sync
+    /// Returns whether an fsync was performed
+    pub fn sync_if_dirty(&self) -> Result<bool> {
+        if !self.dirty.load(Ordering::Acquire) {
+            return Ok(false);
+        }
+        self.sync()?;
+        Ok(true)
+    }
+
     /// Replay all WAL segments and return frames
-    pub fn replay(&self) -> Result<Vec<Frame>> {
+    pub fn replay(&self) -> Result<(Vec<Frame>, ReplayReport)> {
         let mut frames = Vec::new();
+        let mut report = ReplayReport::default();
 
-        for segment_num in 0..=self.segment_number {
+        // Segments flushed to SSTables are deleted, so the numbers left can have gaps
+        let segments = Self::list_segments(&self.data_dir)?;
+        for segment_num in segments.into_iter().filter(|n| *n <= self.segment_number) {
             let path = Self::segment_path(&self.data_dir, segment_num);
-            if !path.exists() {
-                continue;
-            }
-
             match Self::replay_segment(&path) {
-                Ok(segment_frames) => {
+                Ok((segment_frames, skipped)) => {
                     info!(
                         "Replayed {} frames from segment {}",
                         segment_frames.len(),
                         segment_num
                     );
+                    report.recovered += segment_frames.len();
+                    report.skipped += skipped;
                     frames.extend(segment_frames);
                 }
                 Err(e) => {
                     error!("Failed to replay segment {}: {}", segment_num, e);
+                    report.failed_segments += 1;
                     // Continue with next segment instead of failing
                 }
             }
         }
 
-        Ok(frames)
+        Ok((frames, report))
     }
 
-    fn replay_segment(path: &Path) -> Result<Vec<Frame>> {
+    fn replay_segment(path: &Path) -> Result<(Vec<Frame>, usize)> {
         let file = File::open(path)?;
         let mut reader = BufReader::new(file);
         let mut frames = Vec::new();
@@ -201,15 +242,18 @@ impl WriteAheadLog {
                         continue;
                     }
 
-                    // Check version compatibility
-                    if version != WAL_VERSION {
-                        warn!("Incompatible WAL version {} (current: {}), skipping entry", version, WAL_VERSION);
+                    // Check version compatibility, older entries are upgraded as they are decoded
+                    if !(MIN_WAL_VERSION..=WAL_VERSION).contains(&version) {
+                        warn!(
+                            "Incompatible WAL version {} (supported: {} to {}), skipping entry",
+                            version, MIN_WAL_VERSION, WAL_VERSION
+                        );
                         skipped_entries += 1;
                         continue;
                     }
 
                     // Deserialize frame
-                    match bincode::deserialize::<Frame>(&payload) {
+                    match Frame::decode_version(&payload, version) {
                         Ok(frame) => frames.push(frame),
                         Err(e) => {
                             warn!("Failed to deserialize frame: {}, skipping", e);
@@ -232,33 +276,46 @@ impl WriteAheadLog {
             warn!("Skipped {} incompatible WAL entries during replay", skipped_entries);
         }
 
-        Ok(frames)
+        Ok((frames, skipped_entries))
     }
 
-    /// Delete all WAL segments (after successful compaction)
-    pub fn truncate(&self) -> Result<()> {
-        for segment_num in 0..=self.segment_number {
-            let path = Self::segment_path(&self.data_dir, segment_num);
-            if path.exists() {
-                std::fs::remove_file(&path)?;
-            }
-        }
+    /// Seal the current segment and continue in a new one
+    /// Returns the sealed segment's number: it and every older segment hold exactly the
+    /// frames appended before the rotation. Needs `&mut self`, so callers hold the WAL's
+    /// write lock and no append can land in either segment halfway
+    pub fn rotate(&mut self) -> Result<u64> {
+        let mut segment = self.current_segment.lock();
+        segment.file.flush()?;
+        segment.file.get_ref().sync_data()?;
 
-        // Create new segment 0
-        let new_segment_path = Self::segment_path(&self.data_dir, 0);
-        let new_segment = WalSegment::open(&new_segment_path)?;
+        let sealed = self.segment_number;
+        let next = sealed + 1;
+        *segment = WalSegment::open(&Self::segment_path(&self.data_dir, next))?;
+        self.segment_number = next;
 
-        let mut current = self.current_segment.lock();
-        *current = new_segment;
+        Ok(sealed)
+    }
 
+    /// Delete segments up to and including `segment_number`, once their frames are in SSTables
+    pub fn remove_segments_through(&self, segment_number: u64) -> Result<()> {
+        for segment_num in Self::list_segments(&self.data_dir)? {
+            if segment_num <= segment_number && segment_num != self.segment_number {
+                std::fs::remove_file(Self::segment_path(&self.data_dir, segment_num))?;
+            }
+        }
         Ok(())
     }
 
     fn find_latest_segment(dir: &Path) -> Result<u64> {
-        let mut max_num = 0u64;
+        Ok(Self::list_segments(dir)?.last().copied().unwrap_or(0))
+    }
+
+    /// Numbers of the segments in `dir`, in ascending order
+    fn list_segments(dir: &Path) -> Result<Vec<u64>> {
+        let mut segments = Vec::new();
 
         if !dir.exists() {
-            return Ok(0);
+            return Ok(segments);
         }
 
         for entry in std::fs::read_dir(dir)? {
@@ -266,19 +323,18 @@ impl WriteAheadLog {
             let name = entry.file_name();
             let name_str = name.to_string_lossy();
 
-            if name_str.starts_with("wal-") && name_str.ends_with(".log") {
-                if let Some(num_str) = name_str
-                    .strip_prefix("wal-")
-                    .and_then(|s| s.strip_suffix(".log"))
-                {
-                    if let Ok(num) = num_str.parse::<u64>() {
-                        max_num = max_num.max(num);
-                    }
+            if let Some(num_str) = name_str
+                .strip_prefix("wal-")
+                .and_then(|s| s.strip_suffix(".log"))
+            {
+                if let Ok(num) = num_str.parse::<u64>() {
+                    segments.push(num);
                 }
             }
         }
 
-        Ok(max_num)
+        segments.sort_unstable();
+        Ok(segments)
     }
 
     fn segment_path(dir: &Path, segment_num: u64) -> PathBuf {
@@ -322,6 +378,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("test-device".to_string()),
             received_at: Utc::now(),
+            stored_at: None,
             f_port: 1,
             f_cnt: 42,
             confirmed: false,
@@ -370,22 +427,78 @@ mod tests {
         assert_eq!(deserialized.dev_eui().as_str(), "0123456789ABCDEF");
     }
 
+    #[test]
+    fn test_frame_encoding_carries_stored_at() {
+        let mut frame = create_test_frame();
+
+        // Frames written before stored_at was recorded decode without one
+        let legacy = bincode::serialize(&frame).unwrap();
+        assert!(Frame::decode(&legacy).unwrap().stored_at().is_none());
+
+        let stored_at = Utc::now();
+        frame.set_stored_at(stored_at);
+        let encoded = frame.encode().unwrap();
+        assert_eq!(Frame::decode(&encoded).unwrap().stored_at(), Some(stored_at));
+
+        // Older readers ignore the trailing timestamp
+        let old_reader: Frame = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(old_reader.dev_eui().as_str(), "0123456789ABCDEF");
+    }
+
+    #[test]
+    fn test_decode_version_1_frames() {
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let application_id = ApplicationId::new("test-app".to_string());
+        let received_at = Utc::now();
+        let dr = DataRate::new_lora(125000, 7);
+        let rx_info: Vec<crate::model::gateway::GatewayRxInfo> = vec![];
+
+        // Version 1 skipped None fields: no device_name or decoded_payload here
+        let v1 = bincode::serialize(&(
+            0u32, &dev_eui, &application_id, received_at, 1u8, 42u32, false, true, &dr,
+            868100000u64, &rx_info, Some("aGVsbG8="),
+        ))
+        .unwrap();
+        let frame = Frame::decode_version(&v1, 1).unwrap();
+        match frame {
+            Frame::Uplink(uplink) => {
+                assert!(uplink.device_name.is_none());
+                assert_eq!(uplink.received_at, received_at);
+                assert_eq!(uplink.f_cnt, 42);
+                assert_eq!(uplink.raw_payload.as_deref(), Some("aGVsbG8="));
+            }
+            _ => panic!("expected an uplink"),
+        }
+
+        // Decoded payloads were written without type information
+        let v1 = bincode::serialize(&(
+            0u32, &dev_eui, &application_id, Some("sensor"), received_at, 1u8, 42u32, false,
+            true, &dr, 868100000u64, &rx_info, Some(serde_json::json!({"temperature": 21.5})),
+        ))
+        .unwrap();
+        assert!(Frame::decode_version(&v1, 1).is_err());
+
+        assert!(Frame::decode_version(&create_test_frame().encode().unwrap(), 3).is_err());
+    }
+
     #[test]
     fn test_wal_append_and_replay() {
         let temp_dir = TempDir::new().unwrap();
 
         // Write frames
         {
-            let wal = WriteAheadLog::open(temp_dir.path(), 1000).unwrap();
+            let wal = WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
             let frame = create_test_frame();
             wal.append(&frame).unwrap();
             wal.sync().unwrap();
         }
 
         // Reopen and replay
-        let wal = WriteAheadLog::open(temp_dir.path(), 1000).unwrap();
-        let replayed = wal.replay().unwrap();
+        let wal = WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
+        let (replayed, report) = wal.replay().unwrap();
         assert_eq!(replayed.len(), 1);
+        assert_eq!(report.recovered, 1);
+        assert_eq!(report.skipped, 0);
     }
 
     #[test]
@@ -394,7 +507,7 @@ mod tests {
 
         // Write frames
         {
-            let wal = WriteAheadLog::open(temp_dir.path(), 1000).unwrap();
+            let wal = WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
             for _ in 0..10 {
                 let frame = create_test_frame();
                 wal.append(&frame).unwrap();
@@ -403,8 +516,83 @@ mod tests {
         }
 
         // Reopen and replay
-        let wal = WriteAheadLog::open(temp_dir.path(), 1000).unwrap();
-        let replayed = wal.replay().unwrap();
+        let wal = WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
+        let (replayed, _report) = wal.replay().unwrap();
         assert_eq!(replayed.len(), 10);
     }
+
+    #[test]
+    fn test_wal_sync_if_dirty() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let wal = WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
+        assert!(!wal.sync_if_dirty().unwrap());
+        wal.append(&create_test_frame()).unwrap();
+        assert!(wal.sync_if_dirty().unwrap());
+        assert!(!wal.sync_if_dirty().unwrap());
+
+        // Always mode fsyncs on append, leaving nothing for the background task
+        let always_dir = TempDir::new().unwrap();
+        let wal = WriteAheadLog::open(always_dir.path(), WalDurability::Always).unwrap();
+        wal.append(&create_test_frame()).unwrap();
+        assert!(!wal.sync_if_dirty().unwrap());
+    }
+
+    #[test]
+    fn test_wal_rotate_and_remove_segments() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let mut wal =
+                WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
+            wal.append(&create_test_frame()).unwrap();
+            wal.append(&create_test_frame()).unwrap();
+            assert_eq!(wal.rotate().unwrap(), 0);
+            wal.append(&create_test_frame()).unwrap();
+            assert_eq!(wal.rotate().unwrap(), 1);
+            wal.append(&create_test_frame()).unwrap();
+
+            // Segments 0 and 1 are flushed, the current segment is never removed
+            wal.remove_segments_through(1).unwrap();
+            wal.remove_segments_through(5).unwrap();
+            wal.sync().unwrap();
+        }
+
+        let wal = WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
+        assert_eq!(wal.segment_number, 2);
+        let (replayed, report) = wal.replay().unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(report.recovered, 1);
+    }
+
+    #[test]
+    fn test_wal_replay_reports_skipped_entries() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let wal = WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
+            wal.append(&create_test_frame()).unwrap();
+            wal.append(&create_test_frame()).unwrap();
+            wal.sync().unwrap();
+        }
+
+        // Corrupt the checksum of the last entry
+        let path = WriteAheadLog::segment_path(&temp_dir.path().join("wal"), 0);
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        std::fs::write(&path, bytes).unwrap();
+
+        let wal = WriteAheadLog::open(temp_dir.path(), WalDurability::Interval(1000)).unwrap();
+        let (replayed, report) = wal.replay().unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(
+            report,
+            ReplayReport {
+                recovered: 1,
+                skipped: 1,
+                failed_segments: 0,
+            }
+        );
+    }
 }
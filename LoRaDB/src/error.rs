@@ -1,5 +1,14 @@
+use serde::Serialize;
 use thiserror::Error;
 
+/// One invalid field of a request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// Name of the path parameter, query parameter or JSON field
+    pub field: String,
+    pub issue: String,
+}
+
 #[derive(Error, Debug)]
 pub enum LoraDbError {
     #[error("MQTT connection error: {0}")]
@@ -26,18 +35,43 @@ pub enum LoraDbError {
     #[error("Invalid DevEUI: {0}")]
     InvalidDevEui(String),
 
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Device not found: {0}")]
+    DeviceNotFound(String),
+
     #[error("Incompatible SSTable version: {0}")]
     IncompatibleSStableVersion(u16),
 
     #[error("Query parse error: {0}")]
     QueryParseError(String),
 
+    /// Invalid request input, with the offending fields for clients to highlight
+    #[error("Validation error: {message}")]
+    ValidationError {
+        message: String,
+        details: Vec<FieldError>,
+    },
+
     #[error("Query execution error: {0}")]
     QueryExecutionError(String),
 
     #[error("Authentication error: {0}")]
     AuthError(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Read-only: {0}")]
+    ReadOnly(String),
+
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+
     #[error("Configuration error: {0}")]
     ConfigError(String),
 
@@ -1,18 +1,53 @@
 use super::common::{validate_payload_size, MessageParser, MAX_MQTT_PAYLOAD_SIZE};
+use crate::config::ApplicationKey;
 use crate::error::LoraDbError;
 use crate::model::decoded::DecodedPayload;
 use crate::model::frames::{Frame, JoinRequest, StatusFrame, UplinkFrame};
 use crate::model::gateway::{GatewayLocation, GatewayRxInfo};
 use crate::model::lorawan::*;
+use crate::storage::payload_profile_manager::PayloadProfileManager;
 use anyhow::Result;
 use chrono::Utc;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
-pub struct ChirpStackParser;
+pub struct ChirpStackParser {
+    application_key: ApplicationKey,
+    /// Device profile field mappings applied to decoded payloads, None stores them as decoded
+    payload_profiles: Option<Arc<PayloadProfileManager>>,
+}
 
 impl ChirpStackParser {
     pub fn new() -> Self {
-        Self
+        Self::with_application_key(ApplicationKey::default())
+    }
+
+    /// Parser that stores frames under the application ID or name, see `ApplicationKey`
+    pub fn with_application_key(application_key: ApplicationKey) -> Self {
+        Self {
+            application_key,
+            payload_profiles: None,
+        }
+    }
+
+    /// Add canonical field names to decoded payloads, see `PayloadProfileManager`
+    pub fn with_payload_profiles(mut self, payload_profiles: Arc<PayloadProfileManager>) -> Self {
+        self.payload_profiles = Some(payload_profiles);
+        self
+    }
+
+    /// Decoded payload with the device profile's field mapping applied, if any
+    fn decode_payload(
+        &self,
+        object: Option<serde_json::Value>,
+        profile_id: Option<&str>,
+        profile_name: Option<&str>,
+    ) -> Option<DecodedPayload> {
+        let mut decoded = object.map(DecodedPayload::from_json)?;
+        if let Some(payload_profiles) = &self.payload_profiles {
+            payload_profiles.normalize(profile_id, profile_name, &mut decoded);
+        }
+        Some(decoded)
     }
 }
 
@@ -22,6 +57,69 @@ impl Default for ChirpStackParser {
     }
 }
 
+/// Guess the event type of a ChirpStack webhook payload from the fields it carries
+/// Returns `None` when the payload isn't a JSON object or matches no known event
+pub fn detect_event(payload: &[u8]) -> Option<&'static str> {
+    let value: serde_json::Value = serde_json::from_slice(payload).ok()?;
+    let fields = value.as_object()?;
+
+    // Uplinks carry a payload or frame counters, and also a devAddr, so check them first
+    if ["object", "data", "fPort", "fCnt"].iter().any(|f| fields.contains_key(*f)) {
+        Some("up")
+    } else if fields.contains_key("margin") {
+        Some("status")
+    } else if fields.contains_key("devAddr") {
+        Some("join")
+    } else {
+        None
+    }
+}
+
+/// ChirpStack v4 downlink enqueue command, published to `downlink_topic`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChirpStackDownlink {
+    /// Lowercase hex DevEUI, as ChirpStack uses it in topics and payloads
+    pub dev_eui: String,
+    pub confirmed: bool,
+    pub f_port: u8,
+    /// Base64 encoded FRMPayload
+    pub data: String,
+}
+
+impl ChirpStackDownlink {
+    pub fn new(dev_eui: &DevEui, f_port: u8, data: String, confirmed: bool) -> Self {
+        Self {
+            dev_eui: dev_eui.as_str().to_lowercase(),
+            confirmed,
+            f_port,
+            data,
+        }
+    }
+
+    /// Topic the ChirpStack MQTT integration reads enqueue commands from
+    /// `application_id` must be the ChirpStack application ID, not its name
+    pub fn topic(&self, application_id: &str) -> String {
+        format!("application/{}/device/{}/command/down", application_id, self.dev_eui)
+    }
+}
+
+/// The application ID or name, whichever `key` selects, falling back to the other when it's blank
+/// Empty when ChirpStack sent neither, the storage engine decides what to do with those frames
+fn resolve_application(key: ApplicationKey, name: Option<String>, id: String) -> String {
+    let name = name.unwrap_or_default();
+    let (preferred, other) = match key {
+        ApplicationKey::Id => (id, name),
+        ApplicationKey::Name => (name, id),
+    };
+    let preferred = preferred.trim();
+    if preferred.is_empty() {
+        other.trim().to_string()
+    } else {
+        preferred.to_string()
+    }
+}
+
 /// ChirpStack v4 join event format
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -103,10 +201,8 @@ struct ChirpStackDeviceInfo {
     #[serde(default)]
     application_name: Option<String>,
     #[serde(default)]
-    #[allow(dead_code)]
     device_profile_id: Option<String>,
     #[serde(default)]
-    #[allow(dead_code)]
     device_profile_name: Option<String>,
     #[serde(default)]
     #[allow(dead_code)]
@@ -147,9 +243,57 @@ struct ChirpStackLocation {
 struct ChirpStackTxInfo {
     #[serde(default)]
     frequency: Option<u64>,
+    /// An object keyed by modulation type on ChirpStack v4, only the type name on v3
     #[serde(default)]
-    #[allow(dead_code)]
-    modulation: Option<serde_json::Value>, // Can be string or object
+    modulation: Option<serde_json::Value>,
+}
+
+/// ChirpStack v4 `txInfo.modulation`, holding the parameters of the modulation used
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ChirpStackModulation {
+    #[serde(rename_all = "camelCase")]
+    Lora {
+        #[serde(default)]
+        bandwidth: u32,
+        #[serde(default)]
+        spreading_factor: u8,
+    },
+    Fsk {
+        #[serde(default)]
+        datarate: u32,
+    },
+    #[serde(rename_all = "camelCase")]
+    LrFhss {
+        #[serde(default)]
+        operating_channel_width: u32,
+    },
+}
+
+/// Data rate from the uplink's modulation parameters
+/// Without them (ChirpStack v3, or an unknown modulation) the `dr` index is taken as
+/// the LoRa spreading factor at 125 kHz
+fn parse_data_rate(tx_info: Option<&ChirpStackTxInfo>, dr: Option<u8>) -> DataRate {
+    let modulation = tx_info
+        .and_then(|tx| tx.modulation.clone())
+        .filter(serde_json::Value::is_object)
+        .map(serde_json::from_value::<ChirpStackModulation>);
+
+    match modulation {
+        Some(Ok(ChirpStackModulation::Lora {
+            bandwidth,
+            spreading_factor,
+        })) => DataRate::new_lora(bandwidth, spreading_factor),
+        Some(Ok(ChirpStackModulation::Fsk { datarate })) => DataRate::new_fsk(datarate),
+        Some(Ok(ChirpStackModulation::LrFhss {
+            operating_channel_width,
+        })) => DataRate::new_lr_fhss(operating_channel_width),
+        Some(Err(e)) => {
+            tracing::debug!("Unrecognized ChirpStack modulation, using dr: {}", e);
+            DataRate::new_lora(125000, dr.unwrap_or(0))
+        }
+        None => DataRate::new_lora(125000, dr.unwrap_or(0)),
+    }
 }
 
 impl MessageParser for ChirpStackParser {
@@ -173,9 +317,11 @@ impl MessageParser for ChirpStackParser {
             .map_err(|e| LoraDbError::MqttParseError(e.to_string()))?;
 
         // Use application ID from deviceInfo
-        let application_id = msg.device_info.application_name
-            .or(Some(msg.device_info.application_id))
-            .unwrap_or_else(|| "unknown".to_string());
+        let application_id = resolve_application(
+            self.application_key,
+            msg.device_info.application_name,
+            msg.device_info.application_id,
+        );
 
         // Parse timestamp if available
         let received_at = msg.time
@@ -193,16 +339,23 @@ impl MessageParser for ChirpStackParser {
             );
         }
 
+        let decoded_payload = self.decode_payload(
+            msg.object,
+            msg.device_info.device_profile_id.as_deref(),
+            msg.device_info.device_profile_name.as_deref(),
+        );
+
         let uplink = UplinkFrame {
             dev_eui,
             application_id: ApplicationId::new(application_id),
             device_name: msg.device_info.device_name,
             received_at,
+            stored_at: None,
             f_port,
             f_cnt: msg.f_cnt.unwrap_or(0),
             confirmed: msg.confirmed,
             adr: msg.adr,
-            dr: DataRate::new_lora(125000, msg.dr.unwrap_or(0)), // Default to 125kHz bandwidth
+            dr: parse_data_rate(msg.tx_info.as_ref(), msg.dr),
             frequency: msg.tx_info.as_ref().and_then(|tx| tx.frequency).unwrap_or(0),
             rx_info: msg
                 .rx_info
@@ -226,7 +379,7 @@ impl MessageParser for ChirpStackParser {
                     }),
                 })
                 .collect(),
-            decoded_payload: msg.object.map(DecodedPayload::from_json),
+            decoded_payload,
             raw_payload: msg.data,
         };
 
@@ -253,9 +406,11 @@ impl ChirpStackParser {
         let dev_eui = DevEui::new(msg.device_info.dev_eui)
             .map_err(|e| LoraDbError::MqttParseError(e.to_string()))?;
 
-        let application_id = msg.device_info.application_name
-            .or(Some(msg.device_info.application_id))
-            .unwrap_or_else(|| "unknown".to_string());
+        let application_id = resolve_application(
+            self.application_key,
+            msg.device_info.application_name,
+            msg.device_info.application_id,
+        );
 
         let received_at = msg.time
             .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok())
@@ -271,16 +426,23 @@ impl ChirpStackParser {
             );
         }
 
+        let decoded_payload = self.decode_payload(
+            msg.object,
+            msg.device_info.device_profile_id.as_deref(),
+            msg.device_info.device_profile_name.as_deref(),
+        );
+
         let uplink = UplinkFrame {
             dev_eui,
             application_id: ApplicationId::new(application_id),
             device_name: msg.device_info.device_name,
             received_at,
+            stored_at: None,
             f_port,
             f_cnt: msg.f_cnt.unwrap_or(0),
             confirmed: msg.confirmed,
             adr: msg.adr,
-            dr: DataRate::new_lora(125000, msg.dr.unwrap_or(0)),
+            dr: parse_data_rate(msg.tx_info.as_ref(), msg.dr),
             frequency: msg.tx_info.as_ref().and_then(|tx| tx.frequency).unwrap_or(0),
             rx_info: msg
                 .rx_info
@@ -303,7 +465,7 @@ impl ChirpStackParser {
                     }),
                 })
                 .collect(),
-            decoded_payload: msg.object.map(DecodedPayload::from_json),
+            decoded_payload,
             raw_payload: msg.data,
         };
 
@@ -323,9 +485,11 @@ impl ChirpStackParser {
         let dev_eui = DevEui::new(msg.device_info.dev_eui)
             .map_err(|e| LoraDbError::MqttParseError(e.to_string()))?;
 
-        let application_id = msg.device_info.application_name
-            .or(Some(msg.device_info.application_id.clone()))
-            .unwrap_or_else(|| "unknown".to_string());
+        let application_id = resolve_application(
+            self.application_key,
+            msg.device_info.application_name,
+            msg.device_info.application_id,
+        );
 
         let received_at = msg.time
             .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok())
@@ -358,6 +522,7 @@ impl ChirpStackParser {
             dev_eui,
             join_eui: application_id, // Use application ID as join_eui
             received_at,
+            stored_at: None,
             rx_info,
         }))
     }
@@ -375,9 +540,11 @@ impl ChirpStackParser {
         let dev_eui = DevEui::new(msg.device_info.dev_eui)
             .map_err(|e| LoraDbError::MqttParseError(e.to_string()))?;
 
-        let application_id = msg.device_info.application_name
-            .or(Some(msg.device_info.application_id))
-            .unwrap_or_else(|| "unknown".to_string());
+        let application_id = resolve_application(
+            self.application_key,
+            msg.device_info.application_name,
+            msg.device_info.application_id,
+        );
 
         let received_at = msg.time
             .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok())
@@ -395,6 +562,7 @@ impl ChirpStackParser {
             application_id: ApplicationId::new(application_id),
             device_name: msg.device_info.device_name,
             received_at,
+            stored_at: None,
             margin: msg.margin,
             battery_level,
         }))
@@ -407,7 +575,7 @@ mod tests {
 
     #[test]
     fn test_chirpstack_parser() {
-        let parser = ChirpStackParser;
+        let parser = ChirpStackParser::new();
 
         let payload = r#"{
             "time": "2025-11-26T06:14:58.501022+00:00",
@@ -457,9 +625,87 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_detect_event() {
+        assert_eq!(detect_event(br#"{"devAddr": "01", "fCnt": 3, "data": "AQ=="}"#), Some("up"));
+        assert_eq!(detect_event(br#"{"devAddr": "01", "fPort": 1}"#), Some("up"));
+        assert_eq!(detect_event(br#"{"margin": 10, "batteryLevel": 90}"#), Some("status"));
+        assert_eq!(detect_event(br#"{"devAddr": "01", "deviceInfo": {}}"#), Some("join"));
+        assert_eq!(detect_event(br#"{"acknowledged": true}"#), None);
+        assert_eq!(detect_event(b"not json"), None);
+    }
+
+    #[test]
+    fn test_downlink_message() {
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let downlink = ChirpStackDownlink::new(&dev_eui, 10, "AQI=".to_string(), true);
+
+        assert_eq!(
+            downlink.topic("3f8a2c1e-0000-4000-8000-000000000001"),
+            "application/3f8a2c1e-0000-4000-8000-000000000001/device/0123456789abcdef/command/down"
+        );
+        assert_eq!(
+            serde_json::to_value(&downlink).unwrap(),
+            serde_json::json!({
+                "devEui": "0123456789abcdef",
+                "confirmed": true,
+                "fPort": 10,
+                "data": "AQI="
+            })
+        );
+    }
+
+    #[test]
+    fn test_resolve_application() {
+        let name = || Some("app".to_string());
+        assert_eq!(resolve_application(ApplicationKey::Id, name(), "id".to_string()), "id");
+        assert_eq!(resolve_application(ApplicationKey::Name, name(), "id".to_string()), "app");
+        assert_eq!(
+            resolve_application(ApplicationKey::Name, Some(" ".to_string()), "id".to_string()),
+            "id"
+        );
+        assert_eq!(resolve_application(ApplicationKey::Id, name(), " ".to_string()), "app");
+        assert_eq!(resolve_application(ApplicationKey::Id, None, String::new()), "");
+    }
+
+    #[test]
+    fn test_application_key_with_renamed_application() {
+        let uplink = |name: &str| {
+            serde_json::json!({
+                "deviceInfo": {
+                    "devEui": "0123456789abcdef",
+                    "applicationId": "3f8a2c1e-0000-4000-8000-000000000001",
+                    "applicationName": name
+                },
+                "fPort": 1,
+                "data": "AQID"
+            })
+            .to_string()
+        };
+        let app_of = |parser: &ChirpStackParser, name: &str| {
+            parser
+                .parse_uplink(uplink(name).as_bytes())
+                .unwrap()
+                .application_id()
+                .unwrap()
+                .as_str()
+                .to_string()
+        };
+
+        // Keyed by ID, frames from before and after the rename stay together
+        let by_id = ChirpStackParser::new();
+        assert_eq!(app_of(&by_id, "greenhouse"), "3f8a2c1e-0000-4000-8000-000000000001");
+        assert_eq!(app_of(&by_id, "greenhouse-north"), "3f8a2c1e-0000-4000-8000-000000000001");
+
+        // Keyed by name, the rename starts a new application
+        let by_name = ChirpStackParser::with_application_key(ApplicationKey::Name);
+        assert_eq!(app_of(&by_name, "greenhouse"), "greenhouse");
+        assert_eq!(app_of(&by_name, "greenhouse-north"), "greenhouse-north");
+    }
+
     #[test]
     fn test_chirpstack_parser_missing_rx_metadata() {
-        let parser = ChirpStackParser;
+        let parser = ChirpStackParser::new();
 
         // Test with missing snr, rssi, and gatewayId fields
         let payload = r#"{
@@ -533,7 +779,7 @@ mod tests {
         match frame {
             Frame::JoinRequest(join) => {
                 assert_eq!(join.dev_eui.as_str(), "0123456789abcdef");
-                assert_eq!(join.join_eui, "Test App");
+                assert_eq!(join.join_eui, "test-app");
                 assert_eq!(join.rx_info.len(), 1);
             }
             _ => panic!("Expected JoinRequest frame"),
@@ -617,4 +863,88 @@ mod tests {
             _ => panic!("Expected Uplink frame"),
         }
     }
+
+    #[test]
+    fn test_parse_modulation() {
+        let parser = ChirpStackParser::new();
+        let data_rate = |tx_info: &str| {
+            let payload = format!(
+                r#"{{
+                    "deviceInfo": {{"devEui": "0123456789abcdef", "applicationId": "test-app"}},
+                    "fPort": 1,
+                    "dr": 3,
+                    "txInfo": {}
+                }}"#,
+                tx_info
+            );
+            match parser.parse_uplink(payload.as_bytes()).unwrap() {
+                Frame::Uplink(uplink) => uplink.dr,
+                _ => panic!("Expected Uplink frame"),
+            }
+        };
+
+        let lora = data_rate(
+            r#"{"frequency": 868100000, "modulation": {"lora": {
+                "bandwidth": 250000, "spreadingFactor": 9, "codeRate": "CR_4_5"}}}"#,
+        );
+        assert_eq!(lora.modulation, "LORA");
+        assert_eq!((lora.bandwidth, lora.spreading_factor), (250000, 9));
+        assert_eq!(lora.label(), "SF9BW250");
+
+        let fsk = data_rate(r#"{"modulation": {"fsk": {"datarate": 50000}}}"#);
+        assert_eq!(fsk.modulation, "FSK");
+        assert_eq!(fsk.bitrate, Some(50000));
+        assert_eq!(fsk.label(), "FSK50000");
+        assert!(fsk.airtime_ms(13).is_some());
+
+        let lr_fhss = data_rate(
+            r#"{"modulation": {"lrFhss": {"operatingChannelWidth": 137000, "gridSteps": 52}}}"#,
+        );
+        assert_eq!((lr_fhss.modulation.as_str(), lr_fhss.bandwidth), ("LR_FHSS", 137000));
+
+        // ChirpStack v3 only names the modulation, so dr is used at 125 kHz
+        let v3 = data_rate(r#"{"frequency": 868100000, "modulation": "LORA"}"#);
+        assert_eq!((v3.bandwidth, v3.spreading_factor), (125000, 3));
+    }
+
+    #[tokio::test]
+    async fn test_payload_profile_adds_canonical_fields() {
+        let payload_profiles = Arc::new(PayloadProfileManager::in_memory());
+        payload_profiles
+            .set(
+                "Dragino LHT65",
+                [("TempC_SHT".to_string(), "temperature".to_string())].into_iter().collect(),
+            )
+            .await
+            .unwrap();
+
+        let payload = r#"{
+            "deviceInfo": {
+                "devEui": "0123456789abcdef",
+                "applicationId": "test-app",
+                "deviceProfileId": "b3a1c4d2-0000-4000-8000-000000000001",
+                "deviceProfileName": "Dragino LHT65"
+            },
+            "fPort": 2,
+            "rxInfo": [],
+            "object": {"TempC_SHT": 21.5}
+        }"#;
+
+        let decoded_of = |parser: &ChirpStackParser| {
+            match parser.parse_uplink(payload.as_bytes()).unwrap() {
+                Frame::Uplink(uplink) => uplink.decoded_payload.unwrap().object,
+                _ => panic!("Expected Uplink frame"),
+            }
+        };
+
+        // Both the vendor and the canonical field are stored
+        let parser = ChirpStackParser::new().with_payload_profiles(payload_profiles);
+        let object = decoded_of(&parser);
+        assert_eq!(object["TempC_SHT"], 21.5);
+        assert_eq!(object["temperature"], 21.5);
+
+        // Without profiles the payload is passed through as decoded
+        let object = decoded_of(&ChirpStackParser::new());
+        assert!(object.get("temperature").is_none());
+    }
 }
@@ -1,86 +1,288 @@
-use crate::config::MqttConfig;
+use crate::config::{ApplicationKey, BrokerConfig, BrokerParser, MqttConfig, MqttQos};
 use crate::error::LoraDbError;
-use crate::ingest::chirpstack::ChirpStackParser;
+use crate::ingest::chirpstack::{ChirpStackDownlink, ChirpStackParser};
 use crate::ingest::common::MessageParser;
 use crate::ingest::ttn::TtnParser;
 use crate::model::frames::Frame;
+use crate::storage::payload_profile_manager::PayloadProfileManager;
 use anyhow::{Context, Result};
-use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS, Transport};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS, TlsConfiguration, Transport};
+use serde::Serialize;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 use tracing::{debug, error, info, warn};
 
 const MAX_MQTT_PACKET_SIZE: usize = 256 * 1024; // 256KB
 pub const MQTT_KEEP_ALIVE: u64 = 30; // 30 seconds
-const RECONNECT_DELAY: Duration = Duration::from_secs(5);
 
-/// MQTT broker configuration
+/// Delay before each reconnect attempt, doubling from the configured interval up to
+/// the configured maximum and starting over once a connection succeeds
+struct ReconnectBackoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl ReconnectBackoff {
+    fn new(mqtt_config: &MqttConfig) -> Self {
+        // A zero interval would retry a down broker in a tight loop
+        let initial = Duration::from_secs(mqtt_config.reconnect_interval_secs.max(1));
+        let max = Duration::from_secs(mqtt_config.max_reconnect_interval_secs).max(initial);
+        Self {
+            initial,
+            max,
+            next: initial,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = self.next.saturating_mul(2).min(self.max);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
+
+impl From<MqttQos> for QoS {
+    fn from(qos: MqttQos) -> Self {
+        match qos {
+            MqttQos::AtMostOnce => QoS::AtMostOnce,
+            MqttQos::AtLeastOnce => QoS::AtLeastOnce,
+            MqttQos::ExactlyOnce => QoS::ExactlyOnce,
+        }
+    }
+}
+
+/// Publishes downlinks through the first ChirpStack broker's connection
+/// Cloning shares the connection; publishing fails while the client is disconnected
 #[derive(Clone)]
-pub struct BrokerConfig {
-    pub broker_url: String,
-    pub topic_prefix: String,
+pub struct DownlinkPublisher {
+    /// Client of the current connection, replaced on every reconnect
+    client: Arc<RwLock<Option<AsyncClient>>>,
+    qos: QoS,
+}
+
+impl DownlinkPublisher {
+    fn new(qos: QoS) -> Self {
+        Self {
+            client: Arc::new(RwLock::new(None)),
+            qos,
+        }
+    }
+
+    fn connected(&self, client: AsyncClient) {
+        *self.client.write() = Some(client);
+    }
+
+    fn disconnected(&self) {
+        *self.client.write() = None;
+    }
+
+    /// Whether a broker connection is currently available
+    pub fn is_connected(&self) -> bool {
+        self.client.read().is_some()
+    }
+
+    /// Enqueue a downlink by publishing it to the device's ChirpStack command topic
+    /// Returns the topic it was published to
+    pub async fn publish(
+        &self,
+        application_id: &str,
+        downlink: &ChirpStackDownlink,
+    ) -> std::result::Result<String, LoraDbError> {
+        // Clone the client out so the lock isn't held across the publish
+        let client = self.client.read().clone().ok_or_else(|| {
+            LoraDbError::MqttError("Not connected to the ChirpStack MQTT broker".to_string())
+        })?;
+
+        let topic = downlink.topic(application_id);
+        let payload = serde_json::to_vec(downlink)
+            .map_err(|e| LoraDbError::SerializationError(e.to_string()))?;
+        client
+            .publish(topic.as_str(), self.qos, false, payload)
+            .await
+            .map_err(|e| LoraDbError::MqttError(format!("Failed to publish downlink: {}", e)))?;
+
+        Ok(topic)
+    }
 }
 
-/// MQTT ingestion client that connects to ChirpStack and/or TTN
+/// Connection state of one broker, as reported by `GET /stats`
+#[derive(Debug, Clone, Serialize)]
+pub struct BrokerStatus {
+    pub name: String,
+    pub connected: bool,
+    /// When the broker last acknowledged a connection
+    pub last_connected_at: Option<DateTime<Utc>>,
+    /// When the last message arrived from the broker, parsed or not
+    pub last_message_at: Option<DateTime<Utc>>,
+    /// Seconds since the last message
+    pub last_message_age_secs: Option<i64>,
+    /// Why the last connection ended, cleared on reconnect
+    pub last_error: Option<String>,
+}
+
+/// Connection state of every configured broker, updated by the client tasks
+/// Cloning shares the state
+#[derive(Clone)]
+pub struct MqttStatus {
+    brokers: Arc<RwLock<Vec<BrokerStatus>>>,
+}
+
+impl MqttStatus {
+    pub(crate) fn new(mqtt_config: &MqttConfig) -> Self {
+        let brokers = mqtt_config
+            .brokers
+            .iter()
+            .map(|broker| BrokerStatus {
+                name: broker.name.clone(),
+                connected: false,
+                last_connected_at: None,
+                last_message_at: None,
+                last_message_age_secs: None,
+                last_error: None,
+            })
+            .collect();
+        Self {
+            brokers: Arc::new(RwLock::new(brokers)),
+        }
+    }
+
+    fn update(&self, name: &str, update: impl FnOnce(&mut BrokerStatus)) {
+        if let Some(broker) = self.brokers.write().iter_mut().find(|b| b.name == name) {
+            update(broker);
+        }
+    }
+
+    fn connected(&self, name: &str) {
+        self.update(name, |broker| {
+            broker.connected = true;
+            broker.last_connected_at = Some(Utc::now());
+            broker.last_error = None;
+        });
+    }
+
+    fn disconnected(&self, name: &str, error: String) {
+        self.update(name, |broker| {
+            broker.connected = false;
+            broker.last_error = Some(error);
+        });
+    }
+
+    fn message_received(&self, name: &str) {
+        self.update(name, |broker| broker.last_message_at = Some(Utc::now()));
+    }
+
+    /// Every broker's state, in configuration order
+    pub fn brokers(&self) -> Vec<BrokerStatus> {
+        let now = Utc::now();
+        let mut brokers = self.brokers.read().clone();
+        for broker in &mut brokers {
+            broker.last_message_age_secs = broker.last_message_at.map(|at| (now - at).num_seconds());
+        }
+        brokers
+    }
+
+    /// Names of the brokers without a connection
+    pub fn disconnected_brokers(&self) -> Vec<String> {
+        self.brokers
+            .read()
+            .iter()
+            .filter(|broker| !broker.connected)
+            .map(|broker| broker.name.clone())
+            .collect()
+    }
+}
+
+/// MQTT ingestion client that connects to any number of ChirpStack and TTN brokers
 pub struct MqttIngestor {
     mqtt_config: MqttConfig,
-    chirpstack_broker: Option<BrokerConfig>,
-    ttn_broker: Option<BrokerConfig>,
     frame_tx: mpsc::Sender<Frame>,
+    downlinks: DownlinkPublisher,
+    status: MqttStatus,
+    /// Which ChirpStack field frames are stored under as their application ID
+    application_key: ApplicationKey,
+    /// Device profile field mappings for ChirpStack decoded payloads
+    payload_profiles: Option<Arc<PayloadProfileManager>>,
 }
 
 impl MqttIngestor {
-    pub fn new(
-        mqtt_config: MqttConfig,
-        chirpstack_broker: Option<BrokerConfig>,
-        ttn_broker: Option<BrokerConfig>,
-        frame_tx: mpsc::Sender<Frame>,
-    ) -> Self {
+    pub fn new(mqtt_config: MqttConfig, frame_tx: mpsc::Sender<Frame>) -> Self {
+        let downlinks = DownlinkPublisher::new(QoS::from(mqtt_config.qos));
+        let status = MqttStatus::new(&mqtt_config);
         Self {
             mqtt_config,
-            chirpstack_broker,
-            ttn_broker,
             frame_tx,
+            downlinks,
+            status,
+            application_key: ApplicationKey::default(),
+            payload_profiles: None,
         }
     }
 
+    /// Store ChirpStack frames under the application ID or name, see `ApplicationKey`
+    pub fn with_application_key(mut self, application_key: ApplicationKey) -> Self {
+        self.application_key = application_key;
+        self
+    }
+
+    /// Add canonical field names to ChirpStack decoded payloads, see `PayloadProfileManager`
+    pub fn with_payload_profiles(mut self, payload_profiles: Arc<PayloadProfileManager>) -> Self {
+        self.payload_profiles = Some(payload_profiles);
+        self
+    }
+
+    /// Publisher for downlinks over the first ChirpStack broker's connection,
+    /// None without a ChirpStack broker
+    pub fn downlink_publisher(&self) -> Option<DownlinkPublisher> {
+        self.downlink_broker().map(|_| self.downlinks.clone())
+    }
+
+    /// Connection state of the brokers, for `GET /stats` and `/health/ready`
+    pub fn status(&self) -> MqttStatus {
+        self.status.clone()
+    }
+
+    /// Index of the broker downlinks are published through
+    fn downlink_broker(&self) -> Option<usize> {
+        self.mqtt_config
+            .brokers
+            .iter()
+            .position(|broker| broker.parser == BrokerParser::ChirpStack)
+    }
+
     /// Start MQTT ingestion (spawns background tasks)
+    /// Client tasks are aborted when this future is dropped, releasing their
+    /// frame senders so the frame processor can drain and exit
     pub async fn start(self) -> Result<()> {
-        let mut tasks = Vec::new();
-
-        // Start ChirpStack client if configured
-        if let Some(broker_cfg) = self.chirpstack_broker {
-            let mqtt_cfg = self.mqtt_config.clone();
-            let tx = self.frame_tx.clone();
-            let handle = tokio::spawn(async move {
-                Self::run_client(
-                    mqtt_cfg,
-                    broker_cfg,
-                    "chirpstack",
-                    Arc::new(ChirpStackParser::new()),
-                    tx,
-                )
-                .await
-            });
-            tasks.push(handle);
-        }
+        let mut tasks = JoinSet::new();
 
-        // Start TTN client if configured
-        if let Some(broker_cfg) = self.ttn_broker {
+        let downlink_broker = self.downlink_broker();
+        for (index, broker_cfg) in self.mqtt_config.brokers.iter().cloned().enumerate() {
             let mqtt_cfg = self.mqtt_config.clone();
             let tx = self.frame_tx.clone();
-            let handle = tokio::spawn(async move {
-                Self::run_client(
-                    mqtt_cfg,
-                    broker_cfg,
-                    "ttn",
-                    Arc::new(TtnParser::new()),
-                    tx,
-                )
-                .await
+            let parser: Arc<dyn MessageParser + Send + Sync> = match broker_cfg.parser {
+                BrokerParser::ChirpStack => {
+                    let mut parser = ChirpStackParser::with_application_key(self.application_key);
+                    if let Some(payload_profiles) = self.payload_profiles.clone() {
+                        parser = parser.with_payload_profiles(payload_profiles);
+                    }
+                    Arc::new(parser)
+                }
+                BrokerParser::Ttn => Arc::new(TtnParser::new()),
+            };
+            let downlinks = (downlink_broker == Some(index)).then(|| self.downlinks.clone());
+            let status = self.status.clone();
+            tasks.spawn(async move {
+                Self::run_client(mqtt_cfg, broker_cfg, parser, tx, downlinks, status).await
             });
-            tasks.push(handle);
         }
 
         if tasks.is_empty() {
@@ -92,9 +294,12 @@ impl MqttIngestor {
 
         info!("Started {} MQTT client(s)", tasks.len());
 
+        // Drop our own sender so only the client tasks keep the channel open
+        drop(self.frame_tx);
+
         // Wait for all tasks (they run forever unless error)
-        for task in tasks {
-            if let Err(e) = task.await {
+        while let Some(result) = tasks.join_next().await {
+            if let Err(e) = result {
                 error!("MQTT task failed: {}", e);
             }
         }
@@ -106,44 +311,105 @@ impl MqttIngestor {
     async fn run_client(
         mqtt_config: MqttConfig,
         broker_config: BrokerConfig,
-        name: &str,
         parser: Arc<dyn MessageParser + Send + Sync>,
         frame_tx: mpsc::Sender<Frame>,
+        downlinks: Option<DownlinkPublisher>,
+        status: MqttStatus,
     ) -> Result<()> {
+        let name = broker_config.name.as_str();
+        let mut backoff = ReconnectBackoff::new(&mqtt_config);
         loop {
-            match Self::connect_and_run(
+            let result = Self::connect_and_run(
                 &mqtt_config,
                 &broker_config,
-                name,
                 parser.clone(),
                 frame_tx.clone(),
+                downlinks.as_ref(),
+                &status,
+                &mut backoff,
             )
-            .await
-            {
+            .await;
+            if let Some(downlinks) = &downlinks {
+                downlinks.disconnected();
+            }
+
+            match result {
                 Ok(_) => {
                     info!("{} MQTT client disconnected gracefully", name);
+                    status.disconnected(name, "Disconnected".to_string());
                 }
                 Err(e) => {
                     error!("{} MQTT client error: {}", name, e);
+                    status.disconnected(name, format!("{:#}", e));
                 }
             }
 
+            let delay = backoff.next_delay();
             warn!(
                 "{} MQTT client disconnected, reconnecting in {:?}",
-                name, RECONNECT_DELAY
+                name, delay
             );
-            tokio::time::sleep(RECONNECT_DELAY).await;
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Client ID for a broker connection: stable when configured, random otherwise
+    fn client_id(mqtt_config: &MqttConfig, name: &str) -> String {
+        match &mqtt_config.client_id {
+            Some(prefix) => format!("{}-{}", prefix, name),
+            None => format!("loradb-{}-{}", name, uuid::Uuid::new_v4()),
+        }
+    }
+
+    /// TLS transport trusting the system's root certificates, plus the configured CA if any
+    fn tls_transport(mqtt_config: &MqttConfig) -> Result<Transport> {
+        let ca_cert = match &mqtt_config.tls_ca_cert {
+            Some(ca_cert) => ca_cert,
+            None => return Ok(Transport::tls_with_default_config()),
+        };
+
+        // rumqttc's own rustls, which may differ from the version the API server uses
+        use rumqttc::tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+        let mut roots = RootCertStore::empty();
+        match rustls_native_certs::load_native_certs() {
+            Ok(certs) => {
+                roots.add_parsable_certificates(certs);
+            }
+            Err(e) => warn!("Failed to load system root certificates for MQTT: {}", e),
+        }
+
+        let pem = std::fs::read(ca_cert)
+            .with_context(|| format!("Failed to read MQTT CA certificate {:?}", ca_cert))?;
+        let certs = rustls_pemfile::certs(&mut pem.as_slice())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid MQTT CA certificate {:?}", ca_cert))?;
+        let (added, _) = roots.add_parsable_certificates(certs);
+        if added == 0 {
+            return Err(LoraDbError::TlsError(format!(
+                "No certificates found in MQTT CA certificate {:?}",
+                ca_cert
+            ))
+            .into());
         }
+
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Transport::tls_with_config(TlsConfiguration::Rustls(Arc::new(config))))
     }
 
     /// Connect to MQTT broker and process messages
     async fn connect_and_run(
         mqtt_config: &MqttConfig,
         broker_config: &BrokerConfig,
-        name: &str,
         parser: Arc<dyn MessageParser + Send + Sync>,
         frame_tx: mpsc::Sender<Frame>,
+        downlinks: Option<&DownlinkPublisher>,
+        status: &MqttStatus,
+        backoff: &mut ReconnectBackoff,
     ) -> Result<()> {
+        let name = broker_config.name.as_str();
         // Parse broker URL
         let broker_url = &broker_config.broker_url;
         let use_tls = broker_url.starts_with("mqtts://") || broker_url.starts_with("ssl://");
@@ -166,23 +432,33 @@ impl MqttIngestor {
         );
 
         // Create MQTT options
-        let client_id = format!("loradb-{}-{}", name, uuid::Uuid::new_v4());
+        let client_id = Self::client_id(mqtt_config, name);
         let mut mqttoptions = MqttOptions::new(&client_id, host.clone(), port);
+        mqttoptions.set_clean_session(mqtt_config.clean_session);
+        if !mqtt_config.clean_session {
+            info!("{} MQTT: Resuming persistent session as {}", name, client_id);
+        }
 
         mqttoptions.set_keep_alive(Duration::from_secs(MQTT_KEEP_ALIVE));
         mqttoptions.set_max_packet_size(MAX_MQTT_PACKET_SIZE, MAX_MQTT_PACKET_SIZE);
 
         // Set credentials if provided
-        if let (Some(username), Some(password)) = (&mqtt_config.username, &mqtt_config.password) {
+        if let (Some(username), Some(password)) = (&broker_config.username, &broker_config.password) {
             mqttoptions.set_credentials(username, password);
         }
 
         // Configure TLS if needed
         if use_tls {
-            // Use rustls transport (will load system certificates automatically)
-            mqttoptions.set_transport(Transport::tls_with_default_config());
-
-            info!("{} MQTT: TLS configured with system certificates", name);
+            mqttoptions.set_transport(Self::tls_transport(mqtt_config)?);
+
+            match &mqtt_config.tls_ca_cert {
+                Some(ca_cert) => info!(
+                    "{} MQTT: TLS configured with system certificates and CA {}",
+                    name,
+                    ca_cert.display()
+                ),
+                None => info!("{} MQTT: TLS configured with system certificates", name),
+            }
         }
 
         // Create client and event loop
@@ -191,23 +467,32 @@ impl MqttIngestor {
         // Subscribe to topics
         let topic = format!("{}/#", broker_config.topic_prefix);
         client
-            .subscribe(&topic, QoS::AtLeastOnce)
+            .subscribe(&topic, QoS::from(mqtt_config.qos))
             .await
             .context("Failed to subscribe to topic")?;
 
-        info!("{} MQTT: Subscribed to topic: {}", name, topic);
+        info!("{} MQTT: Subscribed to topic: {} ({:?})", name, topic, mqtt_config.qos);
+
+        if let Some(downlinks) = downlinks {
+            downlinks.connected(client.clone());
+        }
 
         // Process events
         loop {
             match eventloop.poll().await {
                 Ok(Event::Incoming(Incoming::Publish(publish))) => {
+                    status.message_received(name);
                     debug!(
                         "{} MQTT: Received message on topic: {}",
                         name, publish.topic
                     );
 
                     // Parse message
-                    match parser.parse_message(&publish.topic, &publish.payload) {
+                    let parsed = {
+                        let _span = tracing::info_span!("ingest.parse", topic = %publish.topic).entered();
+                        parser.parse_message(&publish.topic, &publish.payload)
+                    };
+                    match parsed {
                         Ok(Some(frame)) => {
                             // Log successful parse
                             info!(
@@ -241,6 +526,8 @@ impl MqttIngestor {
                 }
                 Ok(Event::Incoming(Incoming::ConnAck(_))) => {
                     info!("{} MQTT: Connected successfully", name);
+                    status.connected(name);
+                    backoff.reset();
                 }
                 Ok(Event::Incoming(Incoming::SubAck(_))) => {
                     info!("{} MQTT: Subscription acknowledged", name);
@@ -278,6 +565,136 @@ mod tests {
         assert!(!use_tls);
     }
 
+    #[test]
+    fn test_client_id() {
+        let mut config = MqttConfig {
+            brokers: Vec::new(),
+            client_id: None,
+            clean_session: true,
+            tls_ca_cert: None,
+            tls_client_cert: None,
+            tls_client_key: None,
+            reconnect_interval_secs: 5,
+            max_reconnect_interval_secs: 300,
+            qos: MqttQos::AtLeastOnce,
+        };
+
+        // Random per connection by default
+        let first = MqttIngestor::client_id(&config, "chirpstack");
+        assert!(first.starts_with("loradb-chirpstack-"));
+        assert_ne!(first, MqttIngestor::client_id(&config, "chirpstack"));
+
+        // Stable across reconnects once configured, and distinct per broker
+        config.client_id = Some("loradb-site1".to_string());
+        assert_eq!(MqttIngestor::client_id(&config, "chirpstack"), "loradb-site1-chirpstack");
+        assert_eq!(MqttIngestor::client_id(&config, "ttn"), "loradb-site1-ttn");
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let mut config = MqttConfig {
+            brokers: Vec::new(),
+            client_id: None,
+            clean_session: true,
+            tls_ca_cert: None,
+            tls_client_cert: None,
+            tls_client_key: None,
+            reconnect_interval_secs: 5,
+            max_reconnect_interval_secs: 30,
+            qos: MqttQos::AtLeastOnce,
+        };
+
+        let mut backoff = ReconnectBackoff::new(&config);
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![5, 10, 20, 30, 30]);
+
+        // Starts over after a successful connection
+        backoff.reset();
+        assert_eq!(backoff.next_delay().as_secs(), 5);
+
+        // Never retries without a pause, and the maximum is at least the interval
+        config.reconnect_interval_secs = 0;
+        config.max_reconnect_interval_secs = 0;
+        let mut backoff = ReconnectBackoff::new(&config);
+        assert_eq!(backoff.next_delay().as_secs(), 1);
+        assert_eq!(backoff.next_delay().as_secs(), 1);
+    }
+
+    #[test]
+    fn test_mqtt_status() {
+        let config = MqttConfig {
+            brokers: ["chirpstack", "ttn"]
+                .into_iter()
+                .map(|name| BrokerConfig {
+                    name: name.to_string(),
+                    broker_url: "mqtt://localhost:1883".to_string(),
+                    topic_prefix: "application".to_string(),
+                    parser: BrokerParser::ChirpStack,
+                    username: None,
+                    password: None,
+                })
+                .collect(),
+            client_id: None,
+            clean_session: true,
+            tls_ca_cert: None,
+            tls_client_cert: None,
+            tls_client_key: None,
+            reconnect_interval_secs: 5,
+            max_reconnect_interval_secs: 300,
+            qos: MqttQos::AtLeastOnce,
+        };
+
+        let status = MqttStatus::new(&config);
+        assert_eq!(status.disconnected_brokers(), vec!["chirpstack", "ttn"]);
+
+        status.connected("chirpstack");
+        status.message_received("chirpstack");
+        status.disconnected("ttn", "Connection refused".to_string());
+        assert_eq!(status.disconnected_brokers(), vec!["ttn"]);
+
+        let brokers = status.brokers();
+        assert!(brokers[0].connected && brokers[0].last_connected_at.is_some());
+        assert_eq!(brokers[0].last_message_age_secs, Some(0));
+        assert_eq!(brokers[1].last_error.as_deref(), Some("Connection refused"));
+        assert!(brokers[1].last_message_at.is_none());
+
+        // A reconnect clears the error
+        status.connected("ttn");
+        assert!(status.brokers()[1].last_error.is_none());
+    }
+
+    #[test]
+    fn test_tls_transport_rejects_ca_without_certificates() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let ca_cert = temp_dir.path().join("ca.pem");
+        std::fs::write(&ca_cert, "not a certificate\n").unwrap();
+
+        let mut config = MqttConfig {
+            brokers: Vec::new(),
+            client_id: None,
+            clean_session: true,
+            tls_ca_cert: Some(ca_cert),
+            tls_client_cert: None,
+            tls_client_key: None,
+            reconnect_interval_secs: 5,
+            max_reconnect_interval_secs: 300,
+            qos: MqttQos::AtLeastOnce,
+        };
+        assert!(MqttIngestor::tls_transport(&config).is_err());
+
+        config.tls_ca_cert = Some(temp_dir.path().join("missing.pem"));
+        assert!(MqttIngestor::tls_transport(&config).is_err());
+    }
+
+    #[test]
+    fn test_mqtt_qos_parsing() {
+        assert_eq!("0".parse::<MqttQos>().unwrap(), MqttQos::AtMostOnce);
+        assert_eq!("at_least_once".parse::<MqttQos>().unwrap(), MqttQos::AtLeastOnce);
+        assert_eq!("2".parse::<MqttQos>().unwrap(), MqttQos::ExactlyOnce);
+        assert!("3".parse::<MqttQos>().is_err());
+        assert_eq!(QoS::from(MqttQos::default()), QoS::AtLeastOnce);
+    }
+
     #[tokio::test]
     async fn test_frame_channel() {
         let (tx, mut rx) = mpsc::channel(10);
@@ -290,6 +707,7 @@ mod tests {
                 application_id: crate::model::lorawan::ApplicationId::new("test-app".to_string()),
                 device_name: Some("test-device".to_string()),
                 received_at: chrono::Utc::now(),
+                stored_at: None,
                 f_port: 1,
                 f_cnt: 42,
                 confirmed: false,
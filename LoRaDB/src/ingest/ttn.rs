@@ -156,6 +156,7 @@ impl MessageParser for TtnParser {
             ),
             device_name: Some(msg.end_device_ids.device_id),
             received_at,
+            stored_at: None,
             f_port,
             f_cnt: msg.uplink_message.f_cnt,
             confirmed: msg.uplink_message.confirmed,
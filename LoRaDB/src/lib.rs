@@ -1,3 +1,4 @@
+pub mod alert;
 pub mod api;
 pub mod config;
 pub mod engine;
@@ -10,3 +11,17 @@ pub mod storage;
 pub mod util;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Git commit this binary was built from ("unknown" when it couldn't be determined)
+pub const GIT_SHA: &str = env!("LORADB_GIT_SHA");
+
+/// Unix time (seconds) the binary was built
+pub const BUILD_TIMESTAMP: &str = env!("LORADB_BUILD_TIMESTAMP");
+
+/// Cargo features compiled into this binary
+pub fn features() -> Vec<&'static str> {
+    env!("LORADB_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect()
+}
@@ -1,24 +1,39 @@
+use loradb::alert::{AlertWebhook, OfflineWatchdog};
 use loradb::api::http::HttpServer;
 use loradb::config::Config;
-use loradb::ingest::mqtt::{BrokerConfig, MqttIngestor};
+use loradb::engine::migrate;
+use loradb::ingest::mqtt::MqttIngestor;
 use loradb::security::api_token::ApiTokenStore;
 use loradb::security::jwt::JwtService;
 use loradb::storage::StorageEngine;
 use anyhow::Result;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("loradb=info".parse()?)
-        )
-        .json()
-        .init();
+    // Read before the config is loaded so config errors are logged the same way
+    // Dropped last, which flushes spans still waiting for OTLP export
+    let _telemetry = init_tracing()?;
+
+    // Maintenance commands run against the data directory and exit
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(command) = args.get(1) {
+        return match command.as_str() {
+            "migrate-sstables" => migrate_sstables(args.get(2).map(PathBuf::from)),
+            _ => Err(anyhow::anyhow!(
+                "Unknown command '{}', usage: {} [migrate-sstables [DATA_DIR]]",
+                command,
+                args[0]
+            )),
+        };
+    }
 
     info!("Starting LoRaDB v{}", loradb::VERSION);
 
@@ -28,40 +43,107 @@ async fn main() -> Result<()> {
 
     info!("Configuration loaded successfully");
 
+    // Built here rather than with #[tokio::main] so the worker count comes from the config
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if config.api.worker_threads > 0 {
+        runtime.worker_threads(config.api.worker_threads);
+    }
+    runtime.enable_all().build()?.block_on(run(config))
+}
+
+/// Start storage, ingestion and the API server, and run until shutdown
+async fn run(config: Config) -> Result<()> {
+
     // Initialize storage engine
-    info!("Initializing storage engine at {}", config.storage.data_dir.display());
+    if config.storage.in_memory {
+        info!("Initializing in-memory storage engine");
+    } else {
+        info!("Initializing storage engine at {}", config.storage.data_dir.display());
+    }
     let storage = Arc::new(StorageEngine::new(config.storage.clone()).await?);
     info!("Storage engine initialized");
 
     // Initialize JWT service
     info!("Initializing JWT authentication");
-    let jwt_service = Arc::new(JwtService::new(&config.api.jwt_secret)?);
+    let jwt_service = Arc::new(
+        JwtService::new(&config.api.jwt_secret)?
+            .with_verification_secrets(&config.api.jwt_verification_secrets)?,
+    );
 
     // Initialize API token store
     info!("Initializing API token store");
     let token_store_path = config.storage.data_dir.join("api_tokens.json");
-    let api_token_store = Arc::new(ApiTokenStore::new(&token_store_path)?);
-    info!("API token store initialized at {}", token_store_path.display());
+    let api_token_store = Arc::new(if config.storage.in_memory {
+        ApiTokenStore::in_memory()
+    } else if config.storage.read_only {
+        ApiTokenStore::open_read_only(&token_store_path)?
+    } else {
+        ApiTokenStore::new(&token_store_path)?
+    });
+    if config.storage.in_memory {
+        info!("API token store initialized in memory");
+    } else {
+        info!("API token store initialized at {}", token_store_path.display());
+    }
 
-    // Initialize HTTP server
-    info!("Initializing API server on {}", config.api.bind_addr);
-    let http_server = HttpServer::new(
-        storage.clone(),
-        jwt_service,
-        api_token_store,
-        config.api.clone(),
-    );
+    // A read-only replica leaves flushing, compaction and retention to the primary
+    let mut background_handles = Vec::new();
+    if config.storage.read_only {
+        info!("Read-only mode: writes are rejected, SSTables are picked up from the primary");
+        background_handles.extend(storage.clone().start_replica_refresh());
+    } else if config.storage.in_memory {
+        // Nothing is flushed, compacted or aged out, frames stay in the memtable until exit
+        info!("In-memory mode: flush, compaction and retention enforcement are disabled");
+    } else {
+        // Start periodic memtable flush (every 5 minutes)
+        info!("Starting periodic memtable flush task");
+        background_handles.push(storage.clone().start_periodic_flush());
+
+        // Start background compaction (triggered by memtable flushes)
+        info!("Starting background compaction task");
+        background_handles.push(storage.clone().start_compaction_task());
 
-    // Start periodic memtable flush (every 5 minutes)
-    info!("Starting periodic memtable flush task");
-    let flush_handle = storage.clone().start_periodic_flush();
+        // Start background WAL fsync (interval durability mode only)
+        background_handles.extend(storage.clone().start_wal_sync_task());
 
-    // Start periodic retention enforcement
-    info!("Starting retention policy enforcement task");
-    let retention_handle = storage.clone().start_retention_enforcement();
+        // Start periodic retention enforcement
+        info!("Starting retention policy enforcement task");
+        background_handles.push(storage.clone().start_retention_enforcement());
+
+        // Remove long expired or revoked API tokens, once at startup and then periodically
+        if let Some(days) = config.api.token_retention_days {
+            info!("Starting API token cleanup task");
+            background_handles.push(api_token_store.clone().start_cleanup_task(
+                std::time::Duration::from_secs(config.api.token_cleanup_interval_hours * 3600),
+                chrono::Duration::days(days as i64),
+            ));
+        }
+    }
+
+    // Alert on devices that stop sending, replicas leave this to the primary
+    if let Some(threshold) = config.alerts.offline_threshold {
+        if config.storage.read_only {
+            info!("Read-only mode: offline device watchdog disabled");
+        } else {
+            let mut watchdog = OfflineWatchdog::new(storage.device_registry().clone(), threshold);
+            if let Some(url) = &config.alerts.webhook_url {
+                watchdog = watchdog.with_webhook(AlertWebhook::new(url)?);
+            }
+            background_handles.push(watchdog.start(std::time::Duration::from_secs(
+                config.alerts.offline_check_interval_secs,
+            )));
+        }
+    }
+
+    let mqtt_configured = !config.mqtt.brokers.is_empty();
+    if mqtt_configured && config.storage.read_only {
+        warn!("MQTT brokers are configured but ignored in read-only mode");
+    }
 
     // Initialize MQTT ingestion (optional)
-    let (mqtt_handle, processor_handle) = if config.mqtt.chirpstack_broker.is_some() || config.mqtt.ttn_broker.is_some() {
+    let (mqtt_handle, processor_handle, downlink_publisher, mqtt_status) = if mqtt_configured
+        && !config.storage.read_only
+    {
         info!("Initializing MQTT ingestion");
 
         // Create channel for MQTT -> Storage communication
@@ -73,22 +155,11 @@ async fn main() -> Result<()> {
             storage_clone.start_frame_processor(frame_rx).await;
         });
 
-        let chirpstack_broker = config.mqtt.chirpstack_broker.clone().map(|url| BrokerConfig {
-            broker_url: url,
-            topic_prefix: "application/+/device/+/event".to_string(),
-        });
-
-        let ttn_broker = config.mqtt.ttn_broker.clone().map(|url| BrokerConfig {
-            broker_url: url,
-            topic_prefix: "v3/+/devices/+".to_string(),
-        });
-
-        let mqtt_ingestor = MqttIngestor::new(
-            config.mqtt.clone(),
-            chirpstack_broker,
-            ttn_broker,
-            frame_tx,
-        );
+        let mqtt_ingestor = MqttIngestor::new(config.mqtt.clone(), frame_tx)
+            .with_application_key(config.storage.application_key)
+            .with_payload_profiles(storage.payload_profiles().clone());
+        let downlink_publisher = mqtt_ingestor.downlink_publisher();
+        let mqtt_status = mqtt_ingestor.status();
 
         let mqtt_handle = tokio::spawn(async move {
             if let Err(e) = mqtt_ingestor.start().await {
@@ -96,12 +167,28 @@ async fn main() -> Result<()> {
             }
         });
 
-        (Some(mqtt_handle), Some(processor_handle))
+        (Some(mqtt_handle), Some(processor_handle), downlink_publisher, Some(mqtt_status))
     } else {
         info!("MQTT ingestion disabled - using HTTP ingestion only");
-        (None, None)
+        (None, None, None, None)
     };
 
+    // Initialize HTTP server
+    info!("Initializing API server on {}", config.api.bind_addr);
+    let mut http_server = HttpServer::new(
+        storage.clone(),
+        jwt_service,
+        api_token_store.clone(),
+        config.api.clone(),
+    );
+    if let Some(publisher) = downlink_publisher {
+        info!("Downlinks enabled through the ChirpStack MQTT connection");
+        http_server = http_server.with_downlink_publisher(publisher);
+    }
+    if let Some(status) = mqtt_status {
+        http_server = http_server.with_mqtt_status(status);
+    }
+
     info!("LoRaDB started successfully");
 
     // Start HTTP server in background
@@ -133,34 +220,172 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Stop MQTT ingestion first
+    // Stop MQTT ingestion first; this drops the frame senders and closes the channel
     if let Some(handle) = mqtt_handle {
         handle.abort();
+        let _ = handle.await;
     }
 
     // Stop HTTP server
     server_handle.abort();
 
-    // Stop periodic flush task
-    flush_handle.abort();
-
-    // Stop retention enforcement task
-    retention_handle.abort();
+    // Stop background tasks: flush, compaction (SSTables left over threshold are
+    // compacted on next start), WAL sync (storage shutdown does a final sync),
+    // retention, token cleanup, replica refresh and the offline watchdog
+    for handle in background_handles {
+        handle.abort();
+    }
 
     // Give a moment for in-flight requests to complete
     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
+    // Let the frame processor write everything still buffered in the channel
+    if let Some(handle) = processor_handle {
+        info!("Draining buffered MQTT frames...");
+        if let Err(e) = handle.await {
+            error!("Frame processor failed during shutdown: {}", e);
+        }
+    }
+
     // Flush storage engine (ensures memtable is written to SSTable)
     if let Err(e) = storage.shutdown().await {
         error!("Error during storage shutdown: {}", e);
     }
 
-    // Stop frame processor last (if MQTT was enabled)
-    if let Some(handle) = processor_handle {
-        handle.abort();
-    }
-
     info!("LoRaDB shutdown complete");
 
     Ok(())
 }
+
+/// Rewrite SSTables left by older versions in the current format
+/// The data directory defaults to LORADB_STORAGE_DATA_DIR; the server must be stopped
+fn migrate_sstables(data_dir: Option<PathBuf>) -> Result<()> {
+    let data_dir = data_dir
+        .or_else(|| std::env::var_os("LORADB_STORAGE_DATA_DIR").map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("/var/lib/loradb"));
+    info!("Migrating SSTables in {}", data_dir.display());
+
+    let report = migrate::migrate_sstables(&data_dir)?;
+    info!(
+        migrated_tables = report.migrated_tables,
+        current_tables = report.current_tables,
+        migrated_entries = report.migrated_entries,
+        skipped_entries = report.skipped_entries,
+        failed_tables = report.failed.len(),
+        "SSTable migration finished"
+    );
+
+    for (path, reason) in &report.failed {
+        error!("Could not migrate {}: {}", path.display(), reason);
+    }
+    if !report.failed.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} SSTable(s) could not be migrated",
+            report.failed.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Set up the log subscriber from LORADB_LOG_FORMAT, LORADB_LOG_LEVEL and LORADB_LOG_SPAN_TIMINGS,
+/// plus OTLP span export with the `otel` feature
+fn init_tracing() -> Result<Telemetry> {
+    let span_events = if std::env::var("LORADB_LOG_SPAN_TIMINGS")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+    {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+
+    // LORADB_LOG_LEVEL takes EnvFilter directives ("debug", "info,loradb=debug")
+    // and replaces RUST_LOG, which otherwise applies with loradb at info
+    let filter = match std::env::var("LORADB_LOG_LEVEL") {
+        Ok(level) if !level.trim().is_empty() => EnvFilter::try_new(level.trim())
+            .map_err(|e| anyhow::anyhow!("Invalid LORADB_LOG_LEVEL '{}': {}", level, e))?,
+        _ => EnvFilter::from_default_env().add_directive("loradb=info".parse()?),
+    };
+
+    let layer = tracing_subscriber::fmt::layer().with_span_events(span_events);
+
+    let format = std::env::var("LORADB_LOG_FORMAT").unwrap_or_default();
+    let fmt_layer = match format.trim().to_ascii_lowercase().as_str() {
+        "" | "json" => layer.json().boxed(),
+        "pretty" => layer.pretty().boxed(),
+        "compact" => layer.compact().boxed(),
+        other => {
+            return Err(anyhow::anyhow!(
+                "Invalid LORADB_LOG_FORMAT '{}' (expected json, pretty or compact)",
+                other
+            ))
+        }
+    };
+
+    let registry = tracing_subscriber::registry().with(filter).with(fmt_layer);
+
+    #[cfg(feature = "otel")]
+    {
+        let provider = init_otel()?;
+        let otel_layer = provider.as_ref().map(|provider| {
+            use opentelemetry::trace::TracerProvider;
+            tracing_opentelemetry::layer().with_tracer(provider.tracer("loradb"))
+        });
+        registry.with(otel_layer).init();
+        Ok(Telemetry { provider })
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        Ok(Telemetry {})
+    }
+}
+
+/// Tracing state that has to outlive `main`
+struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush OpenTelemetry spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Export spans over OTLP/HTTP when OTEL_EXPORTER_OTLP_ENDPOINT is set
+/// The exporter reads the endpoint and the other OTEL_EXPORTER_OTLP_* variables itself
+#[cfg(feature = "otel")]
+fn init_otel() -> Result<Option<opentelemetry_sdk::trace::SdkTracerProvider>> {
+    use opentelemetry_sdk::Resource;
+
+    match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(endpoint) if !endpoint.trim().is_empty() => {}
+        _ => return Ok(None),
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| anyhow::anyhow!("Invalid OTLP exporter configuration: {}", e))?;
+
+    // OTEL_SERVICE_NAME, when set, is picked up by the resource builder instead
+    let mut resource = Resource::builder();
+    if std::env::var("OTEL_SERVICE_NAME").map_or(true, |name| name.trim().is_empty()) {
+        resource = resource.with_service_name("loradb");
+    }
+
+    // The batch processor exports from its own thread, the tokio runtime isn't built yet
+    Ok(Some(
+        opentelemetry_sdk::trace::SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource.build())
+            .build(),
+    ))
+}
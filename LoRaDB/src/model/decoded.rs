@@ -54,6 +54,36 @@ impl DecodedPayload {
         Self { object }
     }
 
+    /// Nesting depth of the object: 0 for a plain value, 1 for a flat object or array
+    pub fn depth(&self) -> usize {
+        fn depth(value: &Value) -> usize {
+            match value {
+                Value::Object(map) => 1 + map.values().map(depth).max().unwrap_or(0),
+                Value::Array(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+                _ => 0,
+            }
+        }
+        depth(&self.object)
+    }
+
+    /// Size of the object as stored, in bytes of JSON
+    pub fn encoded_len(&self) -> usize {
+        serde_json::to_vec(&self.object).map(|json| json.len()).unwrap_or(0)
+    }
+
+    /// Replace objects and arrays nested deeper than `max_depth` with null
+    pub fn truncate_depth(&mut self, max_depth: usize) {
+        fn truncate(value: &mut Value, remaining: usize) {
+            match value {
+                Value::Object(_) | Value::Array(_) if remaining == 0 => *value = Value::Null,
+                Value::Object(map) => map.values_mut().for_each(|v| truncate(v, remaining - 1)),
+                Value::Array(items) => items.iter_mut().for_each(|v| truncate(v, remaining - 1)),
+                _ => {}
+            }
+        }
+        truncate(&mut self.object, max_depth);
+    }
+
     /// Get a field by path (e.g., "temperature" or "sensor.temp")
     pub fn get_field(&self, path: &str) -> Option<&Value> {
         let mut current = &self.object;
@@ -107,6 +137,21 @@ mod tests {
         assert_eq!(payload.object, json!("this is just a string, not JSON"));
     }
 
+    #[test]
+    fn test_depth_and_truncate() {
+        let mut payload = DecodedPayload::from_json(json!({
+            "temperature": 22.5,
+            "sensor": {"readings": [{"value": 1}]}
+        }));
+        assert_eq!(payload.depth(), 4);
+        assert_eq!(DecodedPayload::from_json(json!(1)).depth(), 0);
+
+        payload.truncate_depth(2);
+        assert_eq!(payload.depth(), 2);
+        assert_eq!(payload.object, json!({"temperature": 22.5, "sensor": {"readings": null}}));
+        assert_eq!(payload.encoded_len(), payload.object.to_string().len());
+    }
+
     #[test]
     fn test_normal_object() {
         // Normal objects should work as before
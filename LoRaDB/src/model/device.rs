@@ -1,12 +1,19 @@
-use super::lorawan::DevEui;
+use super::lorawan::{ApplicationId, DevEui};
 use chrono::{DateTime, Utc};
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 /// Thread-safe device registry
 #[derive(Clone)]
 pub struct DeviceRegistry {
     devices: Arc<DashMap<String, DeviceInfo>>, // Key: normalized DevEUI
+    /// Soft-deleted devices, hidden from lookups and listings until restored or removed
+    deleted: Arc<DashSet<String>>,
+    /// User-assigned names, shown instead of the network server's `device_name`
+    name_overrides: Arc<DashMap<String, String>>,
+    /// Normalized IDs of every application a frame has been registered for
+    applications: Arc<DashSet<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -19,21 +26,38 @@ pub struct DeviceInfo {
     pub frame_count: u64,
 }
 
+/// Devices and frames registered for one application
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplicationSummary {
+    /// Normalized application ID
+    pub application_id: String,
+    pub device_count: usize,
+    pub frame_count: u64,
+    /// Most recent frame of any of its devices
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
 impl DeviceRegistry {
     pub fn new() -> Self {
         Self {
             devices: Arc::new(DashMap::new()),
+            deleted: Arc::new(DashSet::new()),
+            name_overrides: Arc::new(DashMap::new()),
+            applications: Arc::new(DashSet::new()),
         }
     }
 
     /// Register a device or update last seen time
+    /// Returns true if this is the first frame seen for the application
     pub fn register_or_update(
         &self,
         dev_eui: DevEui,
         name: Option<String>,
         app_id: String,
-    ) {
+    ) -> bool {
         let key = dev_eui.normalized();
+        let new_application =
+            !app_id.is_empty() && self.applications.insert(ApplicationId::normalize(&app_id));
 
         self.devices
             .entry(key.clone())
@@ -52,15 +76,66 @@ impl DeviceRegistry {
                 last_seen: Some(Utc::now()),
                 frame_count: 1,
             });
+
+        new_application
     }
 
     pub fn get(&self, dev_eui: &DevEui) -> Option<DeviceInfo> {
         let key = dev_eui.normalized();
-        self.devices.get(&key).map(|r| r.value().clone())
+        if self.deleted.contains(&key) {
+            return None;
+        }
+        self.devices.get(&key).map(|r| self.with_name_override(r.value().clone()))
+    }
+
+    /// Application a device's frames are stored under, soft-deleted devices included
+    pub fn application_of(&self, dev_eui: &DevEui) -> Option<String> {
+        self.devices
+            .get(&dev_eui.normalized())
+            .map(|r| r.value().application_id.clone())
     }
 
     pub fn list_all(&self) -> Vec<DeviceInfo> {
-        self.devices.iter().map(|r| r.value().clone()).collect()
+        self.devices
+            .iter()
+            .filter(|r| !self.deleted.contains(r.key()))
+            .map(|r| self.with_name_override(r.value().clone()))
+            .collect()
+    }
+
+    /// Devices not seen since `cutoff`, longest silent first
+    pub fn list_offline(&self, cutoff: DateTime<Utc>) -> Vec<DeviceInfo> {
+        let mut devices: Vec<DeviceInfo> = self
+            .list_all()
+            .into_iter()
+            .filter(|device| device.last_seen.unwrap_or(device.first_seen) < cutoff)
+            .collect();
+        devices.sort_by_key(|device| device.last_seen.unwrap_or(device.first_seen));
+        devices
+    }
+
+    /// Per-application device and frame counts, soft-deleted devices excluded,
+    /// ordered by application ID
+    pub fn application_summaries(&self) -> Vec<ApplicationSummary> {
+        let mut summaries: BTreeMap<String, ApplicationSummary> = BTreeMap::new();
+        for device in self.list_all() {
+            if device.application_id.is_empty() {
+                continue;
+            }
+            let app_id = ApplicationId::normalize(&device.application_id);
+            let summary = summaries
+                .entry(app_id.clone())
+                .or_insert_with(|| ApplicationSummary {
+                    application_id: app_id,
+                    device_count: 0,
+                    frame_count: 0,
+                    last_seen: None,
+                });
+            summary.device_count += 1;
+            summary.frame_count += device.frame_count;
+            summary.last_seen = summary.last_seen.max(device.last_seen);
+        }
+        summaries.into_values().collect()
     }
 
     /// Alias for list_all for API compatibility
@@ -70,17 +145,86 @@ impl DeviceRegistry {
 
     /// Get device by DevEUI string
     pub fn get_device(&self, dev_eui_str: &str) -> Option<DeviceInfo> {
-        self.devices.get(dev_eui_str).map(|r| r.value().clone())
+        if self.deleted.contains(dev_eui_str) {
+            return None;
+        }
+        self.devices
+            .get(dev_eui_str)
+            .map(|r| self.with_name_override(r.value().clone()))
     }
 
     pub fn device_count(&self) -> usize {
-        self.devices.len()
+        self.devices
+            .iter()
+            .filter(|r| !self.deleted.contains(r.key()))
+            .count()
     }
 
     /// Remove a device from the registry
     pub fn remove_device(&self, dev_eui_str: &str) -> bool {
+        self.deleted.remove(dev_eui_str);
+        self.name_overrides.remove(dev_eui_str);
         self.devices.remove(dev_eui_str).is_some()
     }
+
+    /// Remove every device of an application, soft-deleted ones included,
+    /// and forget the application. Returns the removed devices
+    pub fn remove_application(&self, app_id: &str) -> Vec<DevEui> {
+        let app_id = ApplicationId::normalize(app_id);
+        let devices: Vec<DevEui> = self
+            .devices
+            .iter()
+            .filter(|r| ApplicationId::normalize(&r.value().application_id) == app_id)
+            .map(|r| r.value().dev_eui.clone())
+            .collect();
+
+        for dev_eui in &devices {
+            self.remove_device(&dev_eui.normalized());
+        }
+        self.applications.remove(&app_id);
+        devices
+    }
+
+    /// Hide a device from lookups and listings, it keeps being updated by new frames
+    pub fn mark_deleted(&self, dev_eui: &DevEui) {
+        self.deleted.insert(dev_eui.normalized());
+    }
+
+    /// Make a soft-deleted device visible again, returns false if it wasn't deleted
+    pub fn restore(&self, dev_eui: &DevEui) -> bool {
+        self.deleted.remove(&dev_eui.normalized()).is_some()
+    }
+
+    /// Show `name` instead of the network server's name (None = use the network server's)
+    pub fn set_name_override(&self, dev_eui: &DevEui, name: Option<String>) {
+        let key = dev_eui.normalized();
+        match name {
+            Some(name) => {
+                self.name_overrides.insert(key, name);
+            }
+            None => {
+                self.name_overrides.remove(&key);
+            }
+        }
+    }
+
+    /// User-assigned name for a device, if it has one
+    pub fn name_override(&self, dev_eui: &DevEui) -> Option<String> {
+        self.name_overrides
+            .get(&dev_eui.normalized())
+            .map(|r| r.value().clone())
+    }
+
+    fn with_name_override(&self, mut info: DeviceInfo) -> DeviceInfo {
+        if let Some(name) = self.name_overrides.get(&info.dev_eui.normalized()) {
+            info.device_name = Some(name.value().clone());
+        }
+        info
+    }
+
+    pub fn is_deleted(&self, dev_eui: &DevEui) -> bool {
+        self.deleted.contains(&dev_eui.normalized())
+    }
 }
 
 impl Default for DeviceRegistry {
@@ -125,4 +269,105 @@ mod tests {
         assert_eq!(device.device_name, Some("updated-device".to_string()));
         assert_eq!(device.frame_count, 2);
     }
+
+    #[test]
+    fn test_soft_deleted_device_is_hidden() {
+        let registry = DeviceRegistry::new();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        registry.register_or_update(dev_eui.clone(), None, "test-app".to_string());
+
+        registry.mark_deleted(&dev_eui);
+        assert!(registry.get(&dev_eui).is_none());
+        assert!(registry.get_device(&dev_eui.normalized()).is_none());
+        assert!(registry.list_all().is_empty());
+        assert_eq!(registry.device_count(), 0);
+
+        // Frames that arrive meanwhile are still counted
+        registry.register_or_update(dev_eui.clone(), None, "test-app".to_string());
+
+        assert!(registry.restore(&dev_eui));
+        assert!(!registry.restore(&dev_eui));
+        assert_eq!(registry.get(&dev_eui).unwrap().frame_count, 2);
+    }
+
+    #[test]
+    fn test_application_summaries() {
+        let registry = DeviceRegistry::new();
+        let devices = [
+            ("0000000000000001", "App-A"),
+            ("0000000000000002", "app-a"),
+            ("0000000000000003", "app-b"),
+            ("0000000000000004", "app-b"),
+        ];
+        for (dev_eui, app_id) in devices {
+            let dev_eui = DevEui::new(dev_eui.to_string()).unwrap();
+            registry.register_or_update(dev_eui, None, app_id.to_string());
+        }
+        let first = DevEui::new("0000000000000001".to_string()).unwrap();
+        registry.register_or_update(first, None, "App-A".to_string());
+        registry.mark_deleted(&DevEui::new("0000000000000004".to_string()).unwrap());
+
+        let summaries = registry.application_summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].application_id, "app-a");
+        assert_eq!((summaries[0].device_count, summaries[0].frame_count), (2, 3));
+        assert_eq!((summaries[1].device_count, summaries[1].frame_count), (1, 1));
+        assert!(summaries[1].last_seen.is_some());
+    }
+
+    #[test]
+    fn test_list_offline() {
+        let registry = DeviceRegistry::new();
+        let first = DevEui::new("0000000000000001".to_string()).unwrap();
+        let second = DevEui::new("0000000000000002".to_string()).unwrap();
+        registry.register_or_update(first.clone(), None, "test-app".to_string());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let between = Utc::now();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        registry.register_or_update(second.clone(), None, "test-app".to_string());
+
+        let offline = registry.list_offline(between);
+        assert_eq!(offline.len(), 1);
+        assert_eq!(offline[0].dev_eui, first);
+
+        // Longest silent first
+        let offline = registry.list_offline(Utc::now() + chrono::Duration::seconds(1));
+        let order: Vec<_> = offline.iter().map(|d| d.dev_eui.clone()).collect();
+        assert_eq!(order, [first.clone(), second]);
+
+        registry.mark_deleted(&first);
+        assert!(registry.list_offline(between).is_empty());
+    }
+
+    #[test]
+    fn test_name_override() {
+        let registry = DeviceRegistry::new();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        registry.register_or_update(dev_eui.clone(), Some("lns-name".to_string()), "test-app".to_string());
+
+        registry.set_name_override(&dev_eui, Some("Boiler room".to_string()));
+        // New frames keep updating the network name underneath
+        registry.register_or_update(dev_eui.clone(), Some("lns-renamed".to_string()), "test-app".to_string());
+        assert_eq!(registry.get(&dev_eui).unwrap().device_name.as_deref(), Some("Boiler room"));
+        assert_eq!(registry.list_all()[0].device_name.as_deref(), Some("Boiler room"));
+
+        registry.set_name_override(&dev_eui, None);
+        assert_eq!(registry.get(&dev_eui).unwrap().device_name.as_deref(), Some("lns-renamed"));
+    }
+
+    #[test]
+    fn test_register_reports_new_applications() {
+        let registry = DeviceRegistry::new();
+        let first = DevEui::new("0000000000000001".to_string()).unwrap();
+        let second = DevEui::new("0000000000000002".to_string()).unwrap();
+
+        assert!(registry.register_or_update(first.clone(), None, "Env-Sensors".to_string()));
+        assert!(!registry.register_or_update(first, None, "Env-Sensors".to_string()));
+        assert!(!registry.register_or_update(second.clone(), None, "env-sensors".to_string()));
+        assert!(registry.register_or_update(second, None, "other-app".to_string()));
+
+        // Frames without an application never count as a new one
+        let third = DevEui::new("0000000000000003".to_string()).unwrap();
+        assert!(!registry.register_or_update(third, None, String::new()));
+    }
 }
@@ -2,8 +2,15 @@ use super::decoded::DecodedPayload;
 use super::gateway::GatewayRxInfo;
 use super::lorawan::{ApplicationId, DataRate, DevEui, FCnt, Frequency};
 use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+/// Frame encoding written by this build, recorded in SSTable headers and WAL entries
+/// v2: optional fields always written, decoded payloads stored as JSON strings
+pub const FRAME_VERSION: u16 = 2;
+/// Oldest frame encoding `Frame::decode_version` reads
+pub const MIN_FRAME_VERSION: u16 = 1;
+
 /// Uplink frame (data from device to network)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UplinkFrame {
@@ -13,6 +20,10 @@ pub struct UplinkFrame {
 
     // Timing
     pub received_at: DateTime<Utc>,
+    /// When the frame was written to storage, set by `StorageEngine::write`
+    /// Kept out of the serde encoding, `Frame::encode` appends it instead
+    #[serde(skip)]
+    pub stored_at: Option<DateTime<Utc>>,
 
     // Frame info
     pub f_port: u8,
@@ -32,6 +43,24 @@ pub struct UplinkFrame {
     pub raw_payload: Option<String>, // Base64-encoded; removed skip_serializing_if for bincode
 }
 
+/// MHDR, FHDR (without FOpts), FPort and MIC around the FRMPayload
+const PHY_PAYLOAD_OVERHEAD: usize = 13;
+
+impl UplinkFrame {
+    /// Estimated time on air in milliseconds, from the data rate and payload length
+    /// The PHY payload is taken as the raw FRMPayload plus 13 bytes of MAC overhead;
+    /// MAC commands in FOpts are not recorded, so those add airtime not counted here.
+    /// None if the data rate is incomplete or the raw payload isn't valid base64
+    pub fn airtime_ms(&self) -> Option<f64> {
+        use base64::Engine;
+        let frm_payload_len = match &self.raw_payload {
+            Some(raw) => base64::engine::general_purpose::STANDARD.decode(raw).ok()?.len(),
+            None => 0,
+        };
+        self.dr.airtime_ms(frm_payload_len + PHY_PAYLOAD_OVERHEAD)
+    }
+}
+
 /// Downlink frame (data from network to device)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownlinkFrame {
@@ -39,6 +68,10 @@ pub struct DownlinkFrame {
     pub application_id: ApplicationId,
 
     pub queued_at: DateTime<Utc>,
+    /// When the frame was written to storage, set by `StorageEngine::write`
+    /// Kept out of the serde encoding, `Frame::encode` appends it instead
+    #[serde(skip)]
+    pub stored_at: Option<DateTime<Utc>>,
     pub f_port: u8,
     pub f_cnt: FCnt,
     pub confirmed: bool,
@@ -52,6 +85,10 @@ pub struct JoinRequest {
     pub dev_eui: DevEui,
     pub join_eui: String,
     pub received_at: DateTime<Utc>,
+    /// When the frame was written to storage, set by `StorageEngine::write`
+    /// Kept out of the serde encoding, `Frame::encode` appends it instead
+    #[serde(skip)]
+    pub stored_at: Option<DateTime<Utc>>,
     pub rx_info: Vec<GatewayRxInfo>,
 }
 
@@ -60,6 +97,10 @@ pub struct JoinRequest {
 pub struct JoinAccept {
     pub dev_eui: DevEui,
     pub accepted_at: DateTime<Utc>,
+    /// When the frame was written to storage, set by `StorageEngine::write`
+    /// Kept out of the serde encoding, `Frame::encode` appends it instead
+    #[serde(skip)]
+    pub stored_at: Option<DateTime<Utc>>,
     pub dev_addr: String,
 }
 
@@ -70,6 +111,10 @@ pub struct StatusFrame {
     pub application_id: ApplicationId,
     pub device_name: Option<String>,
     pub received_at: DateTime<Utc>,
+    /// When the frame was written to storage, set by `StorageEngine::write`
+    /// Kept out of the serde encoding, `Frame::encode` appends it instead
+    #[serde(skip)]
+    pub stored_at: Option<DateTime<Utc>>,
     pub margin: i16,           // Link margin in dB
     pub battery_level: u8,     // Battery percentage (0-100, 255=unavailable)
 }
@@ -106,6 +151,80 @@ impl Frame {
         }
     }
 
+    /// Gateways that received the frame, empty for frames sent by the network server
+    pub fn rx_info(&self) -> &[GatewayRxInfo] {
+        match self {
+            Frame::Uplink(f) => &f.rx_info,
+            Frame::JoinRequest(f) => &f.rx_info,
+            Frame::Downlink(_) | Frame::JoinAccept(_) | Frame::Status(_) => &[],
+        }
+    }
+
+    /// Overwrite the frame's primary timestamp (the one `timestamp()` returns)
+    pub fn set_timestamp(&mut self, timestamp: DateTime<Utc>) {
+        match self {
+            Frame::Uplink(f) => f.received_at = timestamp,
+            Frame::Downlink(f) => f.queued_at = timestamp,
+            Frame::JoinRequest(f) => f.received_at = timestamp,
+            Frame::JoinAccept(f) => f.accepted_at = timestamp,
+            Frame::Status(f) => f.received_at = timestamp,
+        }
+    }
+
+    /// When the frame was written to storage, None for frames stored before this was recorded
+    pub fn stored_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Frame::Uplink(f) => f.stored_at,
+            Frame::Downlink(f) => f.stored_at,
+            Frame::JoinRequest(f) => f.stored_at,
+            Frame::JoinAccept(f) => f.stored_at,
+            Frame::Status(f) => f.stored_at,
+        }
+    }
+
+    pub fn set_stored_at(&mut self, stored_at: DateTime<Utc>) {
+        let stored_at = Some(stored_at);
+        match self {
+            Frame::Uplink(f) => f.stored_at = stored_at,
+            Frame::Downlink(f) => f.stored_at = stored_at,
+            Frame::JoinRequest(f) => f.stored_at = stored_at,
+            Frame::JoinAccept(f) => f.stored_at = stored_at,
+            Frame::Status(f) => f.stored_at = stored_at,
+        }
+    }
+
+    /// Binary encoding used by the WAL and SSTables: the bincode frame, followed by
+    /// `stored_at` if set. Older readers ignore the trailing timestamp, and frames
+    /// they wrote decode without one
+    pub fn encode(&self) -> bincode::Result<Vec<u8>> {
+        let mut bytes = bincode::serialize(self)?;
+        if let Some(stored_at) = self.stored_at() {
+            bincode::serialize_into(&mut bytes, &stored_at)?;
+        }
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> bincode::Result<Self> {
+        let mut rest = bytes;
+        let mut frame: Frame = bincode::deserialize_from(&mut rest)?;
+        if !rest.is_empty() {
+            frame.set_stored_at(bincode::deserialize(rest)?);
+        }
+        Ok(frame)
+    }
+
+    /// Decode a frame written in encoding `version`, upgrading older layouts in memory
+    pub fn decode_version(bytes: &[u8], version: u16) -> bincode::Result<Self> {
+        match version {
+            FRAME_VERSION => Self::decode(bytes),
+            1 => decode_v1(bytes),
+            _ => Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "unsupported frame version {} (supported: {} to {})",
+                version, MIN_FRAME_VERSION, FRAME_VERSION
+            )))),
+        }
+    }
+
     pub fn application_id(&self) -> Option<&ApplicationId> {
         match self {
             Frame::Uplink(f) => Some(&f.application_id),
@@ -114,4 +233,117 @@ impl Frame {
             _ => None,
         }
     }
+
+    /// Replace the application ID of frames that carry one
+    pub fn set_application_id(&mut self, application_id: ApplicationId) {
+        match self {
+            Frame::Uplink(f) => f.application_id = application_id,
+            Frame::Downlink(f) => f.application_id = application_id,
+            Frame::Status(f) => f.application_id = application_id,
+            _ => {}
+        }
+    }
+
+    /// Data rate the frame was received at, uplinks only
+    pub fn data_rate(&self) -> Option<&DataRate> {
+        match self {
+            Frame::Uplink(f) => Some(&f.dr),
+            _ => None,
+        }
+    }
+
+    /// Estimated time on air in milliseconds, uplinks only
+    pub fn airtime_ms(&self) -> Option<f64> {
+        match self {
+            Frame::Uplink(f) => f.airtime_ms(),
+            _ => None,
+        }
+    }
+
+    /// FPort of data frames, None for join and status frames
+    pub fn f_port(&self) -> Option<u8> {
+        match self {
+            Frame::Uplink(f) => Some(f.f_port),
+            Frame::Downlink(f) => Some(f.f_port),
+            _ => None,
+        }
+    }
+}
+
+/// Decode a version 1 frame
+///
+/// Version 1 skipped an uplink's `device_name`, `decoded_payload` and `raw_payload` when
+/// they were None, and wrote decoded payloads as bare JSON values, which bincode can't
+/// read back without type information. Uplinks with a decoded payload are therefore
+/// unrecoverable. The other frame types are laid out as in version 2
+fn decode_v1(bytes: &[u8]) -> bincode::Result<Frame> {
+    let mut rest = bytes;
+    let variant: u32 = bincode::deserialize_from(&mut rest)?;
+    if variant != 0 {
+        return Frame::decode(bytes);
+    }
+
+    let dev_eui = bincode::deserialize_from(&mut rest)?;
+    let application_id = bincode::deserialize_from(&mut rest)?;
+    let device_name = v1_optional(&mut rest)?;
+    let received_at = bincode::deserialize_from(&mut rest)?;
+    let f_port = bincode::deserialize_from(&mut rest)?;
+    let f_cnt = bincode::deserialize_from(&mut rest)?;
+    let confirmed = bincode::deserialize_from(&mut rest)?;
+    let adr = bincode::deserialize_from(&mut rest)?;
+    let dr = bincode::deserialize_from(&mut rest)?;
+    let frequency = bincode::deserialize_from(&mut rest)?;
+    let rx_info = bincode::deserialize_from(&mut rest)?;
+
+    // What's left is the raw payload alone, or a None decoded payload and the raw payload.
+    // Anything else starts with a decoded payload
+    let raw_payload = match rest {
+        [] => None,
+        _ => match exactly::<Option<String>>(rest) {
+            Some(raw_payload) => raw_payload,
+            None => match rest {
+                [0, tail @ ..] => exactly::<Option<String>>(tail).ok_or_else(|| {
+                    bincode::ErrorKind::Custom("invalid version 1 raw payload".to_string())
+                })?,
+                _ => {
+                    return Err(Box::new(bincode::ErrorKind::Custom(
+                        "version 1 decoded payloads can't be read".to_string(),
+                    )))
+                }
+            },
+        },
+    };
+
+    Ok(Frame::Uplink(UplinkFrame {
+        dev_eui,
+        application_id,
+        device_name,
+        received_at,
+        stored_at: None,
+        f_port,
+        f_cnt,
+        confirmed,
+        adr,
+        dr,
+        frequency,
+        rx_info,
+        decoded_payload: None,
+        raw_payload,
+    }))
+}
+
+/// A version 1 optional field: an Option if it was written, None if it was skipped
+/// Skipped fields are told apart by the next field, which never starts with a 0 or 1 byte
+fn v1_optional<T: DeserializeOwned>(rest: &mut &[u8]) -> bincode::Result<Option<T>> {
+    match rest.first() {
+        Some(0) | Some(1) => bincode::deserialize_from(rest),
+        _ => Ok(None),
+    }
+}
+
+/// `bytes` decoded as a `T` that takes up all of them
+fn exactly<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    let mut rest = bytes;
+    let value = bincode::deserialize_from(&mut rest).ok()?;
+    rest.is_empty().then_some(value)
 }
@@ -1,5 +1,9 @@
-use super::lorawan::{GatewayEui, Rssi, Snr};
+use super::lorawan::{DevEui, GatewayEui, Rssi, Snr};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GatewayRxInfo {
@@ -18,3 +22,142 @@ pub struct GatewayLocation {
     pub longitude: f64,
     pub altitude: Option<f64>,  // Removed skip_serializing_if for bincode compatibility
 }
+
+/// Gateway ID ChirpStack frames are given when the network server sent none
+const UNKNOWN_GATEWAY: &str = "unknown";
+
+/// Thread-safe registry of gateways seen in frames' reception info
+#[derive(Clone, Default)]
+pub struct GatewayRegistry {
+    gateways: Arc<DashMap<String, GatewayEntry>>, // Key: gateway ID
+}
+
+struct GatewayEntry {
+    location: Option<GatewayLocation>,
+    /// Timestamp of the frame `location` was reported with
+    located_at: Option<DateTime<Utc>>,
+    last_seen: DateTime<Utc>,
+    /// Normalized DevEUIs of the devices the gateway has heard
+    devices: HashSet<String>,
+}
+
+/// A gateway's last-known location and coverage, for `GET /gateways/map`
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayInfo {
+    pub gateway_id: String,
+    pub location: Option<GatewayLocation>,
+    pub last_seen: DateTime<Utc>,
+    /// Distinct devices the gateway has heard
+    pub device_count: usize,
+}
+
+impl GatewayRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the gateways that received a frame from `dev_eui` at `heard_at`
+    /// Frames may be recorded out of order (e.g. when rebuilding from SSTables),
+    /// so a location only replaces one reported with an older frame
+    pub fn record(&self, dev_eui: &DevEui, rx_info: &[GatewayRxInfo], heard_at: DateTime<Utc>) {
+        for rx in rx_info {
+            if rx.gateway_id.as_str() == UNKNOWN_GATEWAY {
+                continue;
+            }
+
+            let mut entry = self
+                .gateways
+                .entry(rx.gateway_id.as_str().to_string())
+                .or_insert_with(|| GatewayEntry {
+                    location: None,
+                    located_at: None,
+                    last_seen: heard_at,
+                    devices: HashSet::new(),
+                });
+            entry.last_seen = entry.last_seen.max(heard_at);
+            entry.devices.insert(dev_eui.normalized());
+            if let Some(location) = &rx.location {
+                if entry.located_at.map_or(true, |at| at <= heard_at) {
+                    entry.location = Some(location.clone());
+                    entry.located_at = Some(heard_at);
+                }
+            }
+        }
+    }
+
+    /// Forget a device, e.g. after its data was deleted
+    pub fn remove_device(&self, dev_eui: &DevEui) {
+        let key = dev_eui.normalized();
+        for mut entry in self.gateways.iter_mut() {
+            entry.devices.remove(&key);
+        }
+    }
+
+    /// Every known gateway, ordered by ID
+    pub fn list_all(&self) -> Vec<GatewayInfo> {
+        let mut gateways: Vec<GatewayInfo> = self
+            .gateways
+            .iter()
+            .map(|r| GatewayInfo {
+                gateway_id: r.key().clone(),
+                location: r.location.clone(),
+                last_seen: r.last_seen,
+                device_count: r.devices.len(),
+            })
+            .collect();
+        gateways.sort_by(|a, b| a.gateway_id.cmp(&b.gateway_id));
+        gateways
+    }
+
+    pub fn gateway_count(&self) -> usize {
+        self.gateways.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rx(gateway_id: &str, latitude: Option<f64>) -> GatewayRxInfo {
+        GatewayRxInfo {
+            gateway_id: GatewayEui::new(gateway_id.to_string()),
+            rssi: -80,
+            snr: 7.5,
+            channel: 0,
+            rf_chain: 0,
+            location: latitude.map(|latitude| GatewayLocation {
+                latitude,
+                longitude: 4.9,
+                altitude: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_gateway_registry() {
+        let registry = GatewayRegistry::new();
+        let device_a = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let device_b = DevEui::new("FEDCBA9876543210".to_string()).unwrap();
+        let now = Utc::now();
+        let earlier = now - chrono::Duration::hours(1);
+
+        registry.record(&device_a, &[rx("gw-1", Some(52.0)), rx("unknown", None)], now);
+        registry.record(&device_b, &[rx("gw-1", None), rx("gw-2", None)], earlier);
+        registry.record(&device_a, &[rx("gw-1", None)], now);
+        // An older frame doesn't move the gateway back
+        registry.record(&device_b, &[rx("gw-1", Some(51.0))], earlier);
+
+        let gateways = registry.list_all();
+        assert_eq!(gateways.len(), 2);
+        assert_eq!(gateways[0].gateway_id, "gw-1");
+        assert_eq!(gateways[0].location.as_ref().unwrap().latitude, 52.0);
+        assert_eq!(gateways[0].last_seen, now);
+        assert_eq!(gateways[0].device_count, 2);
+        assert_eq!(gateways[1].gateway_id, "gw-2");
+        assert!(gateways[1].location.is_none());
+
+        registry.remove_device(&device_b);
+        let gateways = registry.list_all();
+        assert_eq!((gateways[0].device_count, gateways[1].device_count), (1, 0));
+    }
+}
@@ -68,6 +68,17 @@ impl ApplicationId {
         Self(id)
     }
 
+    /// Normalize a raw application ID (trimmed, lowercase) so casing from the
+    /// network server or the API doesn't affect matching
+    pub fn normalize(id: &str) -> String {
+        id.trim().to_lowercase()
+    }
+
+    /// Returns the application ID as a normalized lowercase string
+    pub fn normalized(&self) -> String {
+        Self::normalize(&self.0)
+    }
+
     pub fn as_str(&self) -> &str {
         &self.0
     }
@@ -94,6 +105,68 @@ impl DataRate {
             bitrate: None,
         }
     }
+
+    pub fn new_fsk(bitrate: u32) -> Self {
+        Self {
+            modulation: "FSK".to_string(),
+            bandwidth: 0,
+            spreading_factor: 0,
+            bitrate: Some(bitrate),
+        }
+    }
+
+    /// LR-FHSS, whose bandwidth is the operating channel width
+    pub fn new_lr_fhss(operating_channel_width: u32) -> Self {
+        Self {
+            modulation: "LR_FHSS".to_string(),
+            bandwidth: operating_channel_width,
+            spreading_factor: 0,
+            bitrate: None,
+        }
+    }
+
+    /// Human-readable form, e.g. "SF7BW125" for LoRa or "FSK50000" for FSK
+    pub fn label(&self) -> String {
+        if self.modulation.eq_ignore_ascii_case("LORA") {
+            let bandwidth_khz = if self.bandwidth % 1000 == 0 {
+                (self.bandwidth / 1000).to_string()
+            } else {
+                (self.bandwidth as f64 / 1000.0).to_string()
+            };
+            format!("SF{}BW{}", self.spreading_factor, bandwidth_khz)
+        } else {
+            format!(
+                "{}{}",
+                self.modulation.to_ascii_uppercase(),
+                self.bitrate.map(|b| b.to_string()).unwrap_or_default()
+            )
+        }
+    }
+
+    /// Time on air in milliseconds of a PHY payload of `payload_len` bytes
+    /// Assumes the LoRaWAN uplink defaults: 8 symbol preamble, explicit header, CRC on,
+    /// coding rate 4/5, and low data rate optimization when a symbol lasts 16 ms or more.
+    /// None if the data rate is incomplete (no bandwidth, spreading factor or bitrate)
+    pub fn airtime_ms(&self, payload_len: usize) -> Option<f64> {
+        if self.modulation.eq_ignore_ascii_case("LORA") {
+            if self.bandwidth == 0 || !(5..=12).contains(&self.spreading_factor) {
+                return None;
+            }
+            let sf = self.spreading_factor as f64;
+            let symbol_ms = 2f64.powf(sf) / self.bandwidth as f64 * 1000.0;
+            let low_dr_optimize = if symbol_ms >= 16.0 { 1.0 } else { 0.0 };
+
+            let preamble_ms = (8.0 + 4.25) * symbol_ms;
+            let payload_bits = 8.0 * payload_len as f64 - 4.0 * sf + 28.0 + 16.0;
+            let payload_symbols = 8.0
+                + ((payload_bits / (4.0 * (sf - 2.0 * low_dr_optimize))).ceil() * 5.0).max(0.0);
+            Some(preamble_ms + payload_symbols * symbol_ms)
+        } else {
+            // FSK: 5 byte preamble, 3 byte sync word, length byte and 2 byte CRC around the payload
+            let bitrate = self.bitrate.filter(|b| *b > 0)?;
+            Some((5 + 3 + 1 + payload_len + 2) as f64 * 8.0 / bitrate as f64 * 1000.0)
+        }
+    }
 }
 
 /// Frequency in Hz
@@ -124,6 +197,42 @@ mod tests {
         assert!(DevEui::new("0123456789ABCD-F".to_string()).is_err());
     }
 
+    #[test]
+    fn test_data_rate_label() {
+        assert_eq!(DataRate::new_lora(125000, 7).label(), "SF7BW125");
+        assert_eq!(DataRate::new_lora(500000, 12).label(), "SF12BW500");
+        assert_eq!(DataRate::new_lora(62500, 9).label(), "SF9BW62.5");
+
+        let fsk = DataRate {
+            modulation: "FSK".to_string(),
+            bandwidth: 0,
+            spreading_factor: 0,
+            bitrate: Some(50000),
+        };
+        assert_eq!(fsk.label(), "FSK50000");
+    }
+
+    #[test]
+    fn test_data_rate_airtime() {
+        // 13 byte PHY payload (empty FRMPayload)
+        let sf7 = DataRate::new_lora(125000, 7).airtime_ms(13).unwrap();
+        assert!((sf7 - 46.336).abs() < 0.001);
+
+        // Low data rate optimization kicks in at SF11/SF12 on 125 kHz
+        let sf12 = DataRate::new_lora(125000, 12).airtime_ms(13).unwrap();
+        assert!((sf12 - 1155.072).abs() < 0.001);
+
+        let fsk = DataRate {
+            modulation: "FSK".to_string(),
+            bandwidth: 0,
+            spreading_factor: 0,
+            bitrate: Some(50000),
+        };
+        assert!((fsk.airtime_ms(13).unwrap() - 3.84).abs() < 0.001);
+
+        assert!(DataRate::new_lora(0, 7).airtime_ms(13).is_none());
+    }
+
     #[test]
     fn test_deveui_normalized() {
         let deveui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
@@ -0,0 +1,194 @@
+use crate::query::dsl::{FilterClause, Query, QueryResult};
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Short-lived cache of query results, for dashboards polling the same queries
+///
+/// Entries are keyed by the parsed query, so queries that differ only in spacing
+/// or keyword case share an entry, and expire `ttl` after they were stored. New
+/// frames don't invalidate entries, so a cached result can be up to `ttl` stale.
+pub struct QueryCache {
+    ttl: Duration,
+    max_entries: usize,
+    /// Only cache BETWEEN queries that end in the past, whose results can't
+    /// change as new frames arrive
+    strict_freshness: bool,
+    entries: Mutex<HashMap<String, CachedResult>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+struct CachedResult {
+    result: QueryResult,
+    stored_at: Instant,
+}
+
+/// Query cache counters since startup, reported by `GET /stats`
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct QueryCacheStats {
+    pub enabled: bool,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to make room before they expired
+    pub evictions: u64,
+}
+
+impl QueryCache {
+    pub fn new(ttl: Duration, max_entries: usize, strict_freshness: bool) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            strict_freshness,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Cache key of a query run with an optional field allowlist
+    pub fn key(query: &Query, allowed_fields: Option<&[String]>) -> String {
+        format!("{:?}|{:?}", query, allowed_fields)
+    }
+
+    /// Whether the query's result may be cached at all
+    pub fn is_cacheable(&self, query: &Query) -> bool {
+        if !self.strict_freshness {
+            return true;
+        }
+        matches!(query.filter, Some(FilterClause::Between { end, .. }) if end < Utc::now())
+    }
+
+    /// Unexpired result stored under `key`, counting a hit or a miss
+    pub fn get(&self, key: &str) -> Option<QueryResult> {
+        let mut entries = self.entries.lock();
+        let result = match entries.get(key) {
+            Some(cached) if cached.stored_at.elapsed() < self.ttl => Some(cached.result.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+
+        match &result {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+
+    /// Store a result, making room by dropping expired entries, then the oldest
+    pub fn insert(&self, key: String, result: QueryResult) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, cached| cached.stored_at.elapsed() < self.ttl);
+        }
+        while entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.stored_at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => {
+                    entries.remove(&oldest);
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+                None => break,
+            }
+        }
+
+        entries.insert(
+            key,
+            CachedResult {
+                result,
+                stored_at: Instant::now(),
+            },
+        );
+    }
+
+    pub fn stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            enabled: true,
+            entries: self.entries.lock().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parser::QueryParser;
+
+    fn result(total_frames: usize) -> QueryResult {
+        QueryResult {
+            dev_eui: "0123456789ABCDEF".to_string(),
+            application_id: None,
+            total_frames,
+            estimated_bytes: 0,
+            frames: vec![],
+            distinct: None,
+            buckets: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_expires_and_evicts_oldest() {
+        let cache = QueryCache::new(Duration::from_millis(100), 2, false);
+        cache.insert("a".to_string(), result(1));
+        cache.insert("b".to_string(), result(2));
+        assert_eq!(cache.get("a").unwrap().total_frames, 1);
+
+        // Full, so the oldest entry makes room
+        cache.insert("c".to_string(), result(3));
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.get("c").unwrap().total_frames, 3);
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(cache.get("c").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 2, 1));
+    }
+
+    #[test]
+    fn test_strict_freshness_only_caches_past_windows() {
+        let parser = QueryParser::new();
+        let cache = QueryCache::new(Duration::from_secs(5), 10, true);
+
+        let last = parser
+            .parse("SELECT * FROM device '0123456789ABCDEF' WHERE LAST '1h'")
+            .unwrap();
+        assert!(!cache.is_cacheable(&last));
+
+        let past = parser
+            .parse(
+                "SELECT * FROM device '0123456789ABCDEF' \
+                 WHERE BETWEEN '2025-01-01T00:00:00Z' AND '2025-01-02T00:00:00Z'",
+            )
+            .unwrap();
+        assert!(cache.is_cacheable(&past));
+
+        // Same query, different spelling
+        let respelled = parser
+            .parse(
+                "select *  from device '0123456789ABCDEF' \
+                 where between '2025-01-01T00:00:00Z' and '2025-01-02T00:00:00Z'",
+            )
+            .unwrap();
+        assert_eq!(QueryCache::key(&past, None), QueryCache::key(&respelled, None));
+    }
+}
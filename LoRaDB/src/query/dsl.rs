@@ -1,3 +1,4 @@
+use crate::model::frames::Frame;
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -7,6 +8,9 @@ pub struct Query {
     pub select: SelectClause,
     pub from: FromClause,
     pub filter: Option<FilterClause>,
+    /// Conditions ANDed with the time filter, frames must match all of them
+    pub predicates: Vec<Predicate>,
+    pub group_by: Option<GroupByClause>,
     pub limit: Option<usize>,
 }
 
@@ -21,16 +25,90 @@ pub enum SelectClause {
     Downlink,
     /// SELECT join - only join request/accept frames
     Join,
+    /// SELECT joinrequest - only join request frames
+    JoinRequest,
+    /// SELECT joinaccept - only join accept frames
+    JoinAccept,
     /// SELECT status - only status frames (battery/margin)
     Status,
+    /// SELECT rx - one row per (frame, gateway) pair, flattening rx_info
+    Rx,
     /// SELECT field1, field2, ... - specific fields
     Fields(Vec<String>),
+    /// SELECT DISTINCT field - unique values of a single field
+    Distinct(String),
+    /// SELECT AVG(field), MIN(field), ... - aggregate of a numeric field per GROUP BY bucket
+    Aggregate {
+        function: AggregateFunction,
+        field: String,
+    },
+}
+
+/// Aggregate function applied to a field's values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+impl AggregateFunction {
+    /// Look up a function by name (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "AVG" => Some(Self::Avg),
+            "MIN" => Some(Self::Min),
+            "MAX" => Some(Self::Max),
+            "SUM" => Some(Self::Sum),
+            "COUNT" => Some(Self::Count),
+            _ => None,
+        }
+    }
 }
 
-/// FROM clause - which device to query
+/// GROUP BY clause - how to bucket frames for aggregation
 #[derive(Debug, Clone, PartialEq)]
-pub struct FromClause {
-    pub dev_eui: String,
+pub enum GroupByClause {
+    /// GROUP BY time(interval) - fixed-width buckets aligned to the Unix epoch
+    Time(Duration),
+}
+
+/// FROM clause - which device, or which application's devices, to query
+#[derive(Debug, Clone, PartialEq)]
+pub enum FromClause {
+    /// FROM device 'DevEUI'
+    Device(String),
+    /// FROM application 'app-id' - every registered device of the application
+    Application(String),
+}
+
+impl FromClause {
+    /// DevEUI of a single-device query
+    pub fn dev_eui(&self) -> Option<&str> {
+        match self {
+            FromClause::Device(dev_eui) => Some(dev_eui),
+            FromClause::Application(_) => None,
+        }
+    }
+
+    /// Application ID of an application query
+    pub fn application_id(&self) -> Option<&str> {
+        match self {
+            FromClause::Device(_) => None,
+            FromClause::Application(application_id) => Some(application_id),
+        }
+    }
+}
+
+impl std::fmt::Display for FromClause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FromClause::Device(dev_eui) => write!(f, "device '{}'", dev_eui),
+            FromClause::Application(application_id) => write!(f, "application '{}'", application_id),
+        }
+    }
 }
 
 /// WHERE clause - time range filtering
@@ -47,16 +125,55 @@ pub enum FilterClause {
     Last(Duration),
 }
 
+/// WHERE condition on a top-level frame field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    /// f_port = n (join and status frames have no FPort and never match)
+    FPort(u8),
+    /// dr = 'SF7BW125', compared case-insensitively with `DataRate::label` (uplinks only)
+    DataRate(String),
+}
+
+impl Predicate {
+    pub fn matches(&self, frame: &Frame) -> bool {
+        match self {
+            Predicate::FPort(port) => frame.f_port() == Some(*port),
+            Predicate::DataRate(label) => frame
+                .data_rate()
+                .is_some_and(|dr| dr.label().eq_ignore_ascii_case(label)),
+        }
+    }
+}
+
 impl Query {
     pub fn new(select: SelectClause, from: FromClause, filter: Option<FilterClause>, limit: Option<usize>) -> Self {
         Self {
             select,
             from,
             filter,
+            predicates: Vec::new(),
+            group_by: None,
             limit,
         }
     }
 
+    /// Set the WHERE predicates
+    pub fn with_predicates(mut self, predicates: Vec<Predicate>) -> Self {
+        self.predicates = predicates;
+        self
+    }
+
+    /// Whether a frame satisfies every WHERE predicate
+    pub fn matches_predicates(&self, frame: &Frame) -> bool {
+        self.predicates.iter().all(|predicate| predicate.matches(frame))
+    }
+
+    /// Set the GROUP BY clause
+    pub fn with_group_by(mut self, group_by: GroupByClause) -> Self {
+        self.group_by = Some(group_by);
+        self
+    }
+
     /// Get the time range from the filter clause
     pub fn time_range(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
         match &self.filter {
@@ -74,9 +191,53 @@ impl Query {
 /// Query result wrapping frames with metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResult {
+    /// Queried device, empty for application queries
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub dev_eui: String,
+    /// Queried application, only present for application queries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application_id: Option<String>,
     pub total_frames: usize,
+    /// Approximate size in bytes of the serialized frames (or DISTINCT values / buckets)
+    #[serde(default)]
+    pub estimated_bytes: usize,
     pub frames: Vec<serde_json::Value>,
+    /// Unique field values, only present for SELECT DISTINCT queries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distinct: Option<DistinctValues>,
+    /// Aggregated values per time bucket, only present for GROUP BY time queries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<Vec<TimeBucket>>,
+}
+
+/// Frame count and size of a query's result, without the frames themselves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryEstimate {
+    /// Queried device, empty for application queries
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub dev_eui: String,
+    /// Queried application, only present for application queries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application_id: Option<String>,
+    pub total_frames: usize,
+    /// Approximate size in bytes of the serialized frames (or DISTINCT values / buckets)
+    pub estimated_bytes: usize,
+}
+
+/// Aggregate value for one GROUP BY time bucket (empty buckets are omitted)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeBucket {
+    pub bucket_start: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// Unique values a field has taken, in order of first appearance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DistinctValues {
+    pub field: String,
+    pub values: Vec<serde_json::Value>,
+    /// True if collection stopped early because the value cap was reached
+    pub truncated: bool,
 }
 
 #[cfg(test)]
@@ -87,15 +248,13 @@ mod tests {
     fn test_query_creation() {
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: "0123456789ABCDEF".to_string(),
-            },
+            FromClause::Device("0123456789ABCDEF".to_string()),
             None,
             None,
         );
 
         assert_eq!(query.select, SelectClause::All);
-        assert_eq!(query.from.dev_eui, "0123456789ABCDEF");
+        assert_eq!(query.from.dev_eui(), Some("0123456789ABCDEF"));
         assert!(query.filter.is_none());
         assert!(query.limit.is_none());
     }
@@ -107,9 +266,7 @@ mod tests {
 
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: "0123456789ABCDEF".to_string(),
-            },
+            FromClause::Device("0123456789ABCDEF".to_string()),
             Some(FilterClause::Between { start, end }),
             None,
         );
@@ -125,9 +282,7 @@ mod tests {
 
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: "0123456789ABCDEF".to_string(),
-            },
+            FromClause::Device("0123456789ABCDEF".to_string()),
             Some(FilterClause::Since(start)),
             None,
         );
@@ -141,9 +296,7 @@ mod tests {
     fn test_time_range_last() {
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: "0123456789ABCDEF".to_string(),
-            },
+            FromClause::Device("0123456789ABCDEF".to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             None,
         );
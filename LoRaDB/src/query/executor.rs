@@ -1,51 +1,382 @@
 use crate::error::LoraDbError;
 use crate::model::frames::Frame;
-use crate::model::lorawan::DevEui;
-use crate::query::dsl::{Query, QueryResult, SelectClause};
-use crate::storage::StorageEngine;
+use crate::model::lorawan::{ApplicationId, DevEui};
+use crate::query::cache::{QueryCache, QueryCacheStats};
+use crate::query::dsl::{
+    AggregateFunction, DistinctValues, FromClause, GroupByClause, Query, QueryEstimate, QueryResult,
+    SelectClause, TimeBucket,
+};
+use crate::storage::{ScanStats, StorageEngine};
 use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
 use std::sync::Arc;
+use std::time::Instant;
 
 /// Maximum number of results returned by a single query
 const MAX_QUERY_RESULTS: usize = 10_000;
 
+/// Maximum number of unique values returned by SELECT DISTINCT
+const MAX_DISTINCT_VALUES: usize = 1_000;
+
+/// Maximum number of devices a FROM application query scans
+const MAX_APPLICATION_DEVICES: usize = 1_000;
+
+/// Frame fields copied onto every SELECT rx row alongside the gateway's reception info
+const RX_ROW_FRAME_FIELDS: [&str; 6] =
+    ["dev_eui", "received_at", "f_cnt", "frequency", "dr", "dr_label"];
+
+/// `io::Write` sink that only counts bytes, for sizing JSON without buffering it
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Number of bytes `value` takes when serialized as compact JSON
+fn serialized_size<T: Serialize + ?Sized>(value: &T) -> usize {
+    let mut counter = ByteCounter(0);
+    // Writing to a counter can't fail, and neither can serializing JSON values
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
+/// Render a window as whole days, hours or minutes for error messages
+fn format_window(window: Duration) -> String {
+    if window.num_days() > 0 {
+        format!("{}d", window.num_days())
+    } else if window.num_hours() > 0 {
+        format!("{}h", window.num_hours())
+    } else {
+        format!("{}m", window.num_minutes())
+    }
+}
+
+/// Time spent in each stage of one query, and what the scan read
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueryTimings {
+    /// Reading frames from the memtable and SSTables, including WHERE predicates
+    pub scan: std::time::Duration,
+    /// Limiting, aggregating and rendering the frames as JSON
+    pub serialize: std::time::Duration,
+    pub scan_stats: ScanStats,
+    /// Served from the query cache, without a scan
+    pub cached: bool,
+}
+
 /// Query executor that runs queries against the storage engine
 pub struct QueryExecutor {
     storage: Arc<StorageEngine>,
+    /// Window applied to queries with no LAST/SINCE/BETWEEN (None = reject them)
+    default_window: Option<Duration>,
+    /// Longest time range a query may scan (None = unlimited)
+    max_window: Option<Duration>,
+    /// Recent results of repeated queries (None = always scan)
+    cache: Option<QueryCache>,
 }
 
 impl QueryExecutor {
     pub fn new(storage: Arc<StorageEngine>) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            default_window: None,
+            max_window: None,
+            cache: None,
+        }
+    }
+
+    /// Run queries without a time filter over the last `window` instead of rejecting them
+    pub fn with_default_window(mut self, window: Option<Duration>) -> Self {
+        self.default_window = window;
+        self
+    }
+
+    /// Reject queries whose time range is longer than `window`
+    pub fn with_max_window(mut self, window: Option<Duration>) -> Self {
+        self.max_window = window;
+        self
+    }
+
+    /// Serve repeated queries from `cache` while their results are fresh enough
+    pub fn with_cache(mut self, cache: Option<QueryCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Query cache counters, all zero while caching is off
+    pub fn cache_stats(&self) -> QueryCacheStats {
+        self.cache.as_ref().map(QueryCache::stats).unwrap_or_default()
     }
 
     /// Execute a query and return results
     pub async fn execute(&self, query: &Query) -> Result<QueryResult> {
-        // SECURITY: Enforce mandatory time filter to prevent unbounded queries
-        if query.filter.is_none() {
-            return Err(LoraDbError::QueryExecutionError(
-                "Time filter is required for security. Use WHERE LAST, SINCE, or BETWEEN clause.".to_string()
-            ).into());
+        self.execute_restricted(query, None).await
+    }
+
+    /// Execute a query, stripping any frame fields not covered by `allowed_fields`
+    /// (dot-separated paths) from every returned frame, regardless of the SELECT
+    pub async fn execute_restricted(
+        &self,
+        query: &Query,
+        allowed_fields: Option<&[String]>,
+    ) -> Result<QueryResult> {
+        Ok(self.execute_timed(query, allowed_fields).await?.0)
+    }
+
+    /// Execute a restricted query, also reporting how long the scan and rendering took
+    #[tracing::instrument(name = "query.execute", skip_all, fields(from = %query.from))]
+    pub async fn execute_timed(
+        &self,
+        query: &Query,
+        allowed_fields: Option<&[String]>,
+    ) -> Result<(QueryResult, QueryTimings)> {
+        let cache_key = match &self.cache {
+            Some(cache) if cache.is_cacheable(query) => {
+                let key = QueryCache::key(query, allowed_fields);
+                if let Some(result) = cache.get(&key) {
+                    let timings = QueryTimings {
+                        cached: true,
+                        ..Default::default()
+                    };
+                    return Ok((result, timings));
+                }
+                Some(key)
+            }
+            _ => None,
+        };
+
+        let scan_start = Instant::now();
+        let (frames, scan_stats) = self.fetch_frames(query).await?;
+        let scan = scan_start.elapsed();
+
+        let serialize_start = Instant::now();
+        let result = self.render_result(query, frames, allowed_fields)?;
+        let timings = QueryTimings {
+            scan,
+            serialize: serialize_start.elapsed(),
+            scan_stats,
+            cached: false,
+        };
+
+        if let (Some(cache), Some(key)) = (&self.cache, cache_key) {
+            cache.insert(key, result.clone());
+        }
+
+        Ok((result, timings))
+    }
+
+    /// Turn the frames in range into the query's result
+    fn render_result(
+        &self,
+        query: &Query,
+        frames: Vec<Frame>,
+        allowed_fields: Option<&[String]>,
+    ) -> Result<QueryResult> {
+        // DISTINCT scans every frame in range but only returns the unique values
+        if let SelectClause::Distinct(field) = &query.select {
+            return Ok(self.collect_distinct(query, field, &frames, allowed_fields));
+        }
+
+        // Aggregates reduce every frame in range to one value per time bucket
+        if let SelectClause::Aggregate { function, field } = &query.select {
+            let interval = match &query.group_by {
+                Some(GroupByClause::Time(interval)) => *interval,
+                None => {
+                    return Err(LoraDbError::QueryExecutionError(
+                        "Aggregate functions require a GROUP BY time(...) clause".to_string(),
+                    )
+                    .into())
+                }
+            };
+            return Ok(self.aggregate_buckets(query, *function, field, interval, &frames, allowed_fields));
+        }
+
+        let frames = self.limit_and_filter(query, frames);
+
+        // Convert frames to JSON
+        let _span = tracing::info_span!("query.serialize", frames = frames.len()).entered();
+        let json_frames: Vec<serde_json::Value> = if query.select == SelectClause::Rx {
+            self.render_rx_rows(query, &frames, allowed_fields)
+        } else {
+            frames
+                .iter()
+                .map(|frame| self.render_frame(frame, &query.select, allowed_fields))
+                .collect()
+        };
+
+        Ok(QueryResult {
+            dev_eui: query.from.dev_eui().unwrap_or_default().to_string(),
+            application_id: query.from.application_id().map(str::to_string),
+            total_frames: json_frames.len(),
+            estimated_bytes: serialized_size(&json_frames),
+            frames: json_frames,
+            distinct: None,
+            buckets: None,
+        })
+    }
+
+    /// Estimate the frame count and payload size of a query without building the response
+    /// Each frame is still projected so the size reflects SELECT and the field allowlist
+    pub async fn estimate_restricted(
+        &self,
+        query: &Query,
+        allowed_fields: Option<&[String]>,
+    ) -> Result<QueryEstimate> {
+        // DISTINCT and aggregate results are already small, so just run them
+        if matches!(query.select, SelectClause::Distinct(_) | SelectClause::Aggregate { .. }) {
+            let result = self.execute_restricted(query, allowed_fields).await?;
+            return Ok(QueryEstimate {
+                dev_eui: result.dev_eui,
+                application_id: result.application_id,
+                total_frames: result.total_frames,
+                estimated_bytes: result.estimated_bytes,
+            });
+        }
+
+        let (frames, _) = self.fetch_frames(query).await?;
+        let frames = self.limit_and_filter(query, frames);
+
+        let (row_count, frame_bytes) = if query.select == SelectClause::Rx {
+            let rows = self.render_rx_rows(query, &frames, allowed_fields);
+            (rows.len(), rows.iter().map(serialized_size).sum())
+        } else {
+            let frame_bytes: usize = frames
+                .iter()
+                .map(|frame| serialized_size(&self.render_frame(frame, &query.select, allowed_fields)))
+                .sum();
+            (frames.len(), frame_bytes)
+        };
+
+        Ok(QueryEstimate {
+            dev_eui: query.from.dev_eui().unwrap_or_default().to_string(),
+            application_id: query.from.application_id().map(str::to_string),
+            total_frames: row_count,
+            // Brackets plus a comma between each frame, as serialized in QueryResult
+            estimated_bytes: 2 + frame_bytes + row_count.saturating_sub(1),
+        })
+    }
+
+    /// Validate the query's time filter and fetch every frame in range from storage
+    /// that matches the WHERE predicates
+    async fn fetch_frames(&self, query: &Query) -> Result<(Vec<Frame>, ScanStats)> {
+        // SECURITY: Enforce a time filter to prevent unbounded queries, falling back
+        // to the configured default window when the query has none
+        let (start_time, end_time) = match (&query.filter, self.default_window) {
+            (Some(_), _) => query.time_range(),
+            (None, Some(window)) => (Some(Utc::now() - window), None),
+            (None, None) => {
+                return Err(LoraDbError::QueryExecutionError(
+                    "Time filter is required for security. Use WHERE LAST, SINCE, or BETWEEN clause.".to_string()
+                ).into());
+            }
+        };
+
+        // SECURITY: Bound how much history a query can scan, not just how much it returns
+        if let (Some(max), Some(start)) = (self.max_window, start_time) {
+            let window = end_time.unwrap_or_else(Utc::now) - start;
+            if window > max {
+                return Err(LoraDbError::QueryParseError(format!(
+                    "Query time range of {} exceeds the maximum of {}, narrow it or use the export API",
+                    format_window(window),
+                    format_window(max)
+                ))
+                .into());
+            }
         }
 
-        // Parse DevEUI
-        let dev_eui = DevEui::new(query.from.dev_eui.clone())
-            .map_err(|e| LoraDbError::QueryExecutionError(e.to_string()))?;
+        let (mut frames, stats) = match &query.from {
+            FromClause::Device(dev_eui) => {
+                // Parse DevEUI
+                let dev_eui = DevEui::new(dev_eui.clone())
+                    .map_err(|e| LoraDbError::QueryExecutionError(e.to_string()))?;
+
+                // Query storage engine
+                self.storage
+                    .query_with_stats(&dev_eui, start_time, end_time)
+                    .await?
+            }
+            FromClause::Application(application_id) => {
+                self.fetch_application_frames(application_id, start_time, end_time)
+                    .await?
+            }
+        };
+
+        // Predicates are on top-level fields, so filter before LIMIT and projection
+        if !query.predicates.is_empty() {
+            frames.retain(|frame| query.matches_predicates(frame));
+        }
 
-        // Get time range
-        let (start_time, end_time) = query.time_range();
+        Ok((frames, stats))
+    }
 
-        // Query storage engine
-        let mut frames = self
+    /// Fetch every frame in range from all devices registered to an application,
+    /// merged into timestamp order
+    async fn fetch_application_frames(
+        &self,
+        application_id: &str,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+    ) -> Result<(Vec<Frame>, ScanStats)> {
+        let application_id = ApplicationId::normalize(application_id);
+        let devices: Vec<DevEui> = self
             .storage
-            .query(&dev_eui, start_time, end_time)
-            .await?;
+            .device_registry()
+            .list_all()
+            .into_iter()
+            .filter(|device| ApplicationId::normalize(&device.application_id) == application_id)
+            .map(|device| device.dev_eui)
+            .collect();
 
-        // SECURITY: Apply user limit or MAX_QUERY_RESULTS, whichever is smaller
-        let effective_limit = query
+        // SECURITY: Each device is a separate scan, so bound the fan-out
+        if devices.len() > MAX_APPLICATION_DEVICES {
+            return Err(LoraDbError::QueryParseError(format!(
+                "Application has {} devices, more than the {} a single query may scan; query devices individually",
+                devices.len(),
+                MAX_APPLICATION_DEVICES
+            ))
+            .into());
+        }
+
+        let mut frames = Vec::new();
+        let mut stats = ScanStats::default();
+        for dev_eui in &devices {
+            let (device_frames, device_stats) = self
+                .storage
+                .query_with_stats(dev_eui, start_time, end_time)
+                .await?;
+            frames.extend(device_frames);
+            stats.memtable_frames += device_stats.memtable_frames;
+            stats.sstables_scanned += device_stats.sstables_scanned;
+            stats.sstable_frames += device_stats.sstable_frames;
+        }
+
+        // Stable sort keeps each device's own frames in storage order
+        frames.sort_by_key(|frame| frame.timestamp());
+
+        Ok((frames, stats))
+    }
+
+    /// User LIMIT or MAX_QUERY_RESULTS, whichever is smaller
+    fn effective_limit(query: &Query) -> usize {
+        query
             .limit
             .unwrap_or(MAX_QUERY_RESULTS)
-            .min(MAX_QUERY_RESULTS);
+            .min(MAX_QUERY_RESULTS)
+    }
+
+    /// Apply LIMIT (capped at MAX_QUERY_RESULTS) and the SELECT clause's frame type filter
+    fn limit_and_filter(&self, query: &Query, mut frames: Vec<Frame>) -> Vec<Frame> {
+        // SECURITY: Apply user limit or MAX_QUERY_RESULTS, whichever is smaller
+        let effective_limit = Self::effective_limit(query);
 
         if frames.len() > effective_limit {
             if let Some(user_limit) = query.limit {
@@ -66,31 +397,236 @@ impl QueryExecutor {
         }
 
         // Apply SELECT clause filtering
-        frames = self.filter_frames(frames, &query.select);
+        self.filter_frames(frames, &query.select)
+    }
 
-        // Convert frames to JSON
-        let json_frames: Vec<serde_json::Value> = frames
+    /// Convert a frame to JSON, applying the field allowlist and then SELECT projection
+    fn render_frame(
+        &self,
+        frame: &Frame,
+        select: &SelectClause,
+        allowed_fields: Option<&[String]>,
+    ) -> serde_json::Value {
+        // Enforce the caller's field allowlist before projecting so SELECT can't bypass it,
+        // including through array indices and wildcards
+        let restricted = self.restrict_fields(self.frame_to_json(frame), allowed_fields);
+        self.project_fields(restricted, select)
+    }
+
+    /// Explode each frame's rx_info into one row per gateway for SELECT rx
+    /// Gateway fields keep their frame path ("rx_info.rssi") so the allowlist applies
+    /// as it would to the frame; rows are capped at LIMIT like frames are
+    fn render_rx_rows(
+        &self,
+        query: &Query,
+        frames: &[Frame],
+        allowed_fields: Option<&[String]>,
+    ) -> Vec<serde_json::Value> {
+        frames
+            .iter()
+            .flat_map(|frame| self.rx_rows(frame))
+            .take(Self::effective_limit(query))
+            .map(|row| self.restrict_fields(row, allowed_fields))
+            .collect()
+    }
+
+    /// One row per gateway that heard the frame, empty if it has no rx_info
+    fn rx_rows(&self, frame: &Frame) -> Vec<serde_json::Value> {
+        let mut json = match self.frame_to_json(frame) {
+            serde_json::Value::Object(map) => map,
+            _ => return Vec::new(),
+        };
+        let rx_info = match json.remove("rx_info") {
+            Some(serde_json::Value::Array(items)) => items,
+            _ => return Vec::new(),
+        };
+
+        let frame_fields: serde_json::Map<String, serde_json::Value> = RX_ROW_FRAME_FIELDS
             .iter()
-            .map(|frame| {
-                // Serialize frame to JSON
-                let json = serde_json::to_value(frame).unwrap_or(serde_json::json!({}));
+            .filter_map(|&field| json.remove(field).map(|value| (field.to_string(), value)))
+            .collect();
+
+        rx_info
+            .into_iter()
+            .map(|rx| {
+                let mut row = frame_fields.clone();
+                if let serde_json::Value::Object(rx) = rx {
+                    for (key, value) in rx {
+                        row.insert(format!("rx_info.{}", key), value);
+                    }
+                }
+                serde_json::Value::Object(row)
+            })
+            .collect()
+    }
+
+    /// Collect the unique values of `field` across frames, capped at LIMIT or
+    /// MAX_DISTINCT_VALUES, whichever is smaller
+    fn collect_distinct(
+        &self,
+        query: &Query,
+        field: &str,
+        frames: &[Frame],
+        allowed_fields: Option<&[String]>,
+    ) -> QueryResult {
+        let cap = query
+            .limit
+            .unwrap_or(MAX_DISTINCT_VALUES)
+            .min(MAX_DISTINCT_VALUES);
+
+        let mut seen = HashSet::new();
+        let mut values = Vec::new();
+        let mut truncated = false;
+
+        for frame in frames {
+            if let Some(value) = self.extract_field(frame, field, allowed_fields) {
+                // serde_json::Value isn't hashable, so dedupe on its canonical string form
+                if seen.insert(value.to_string()) {
+                    if values.len() == cap {
+                        truncated = true;
+                        break;
+                    }
+                    values.push(value);
+                }
+            }
+        }
+
+        if truncated {
+            tracing::warn!(
+                "SELECT DISTINCT {} reached the cap of {} values, truncating",
+                field,
+                cap
+            );
+        }
 
-                // Unwrap enum variant for easier querying (e.g., {"Uplink": {...}} -> {...})
-                let unwrapped_json = self.unwrap_frame_variant(json);
+        QueryResult {
+            dev_eui: query.from.dev_eui().unwrap_or_default().to_string(),
+            application_id: query.from.application_id().map(str::to_string),
+            total_frames: frames.len(),
+            estimated_bytes: serialized_size(&values),
+            frames: Vec::new(),
+            distinct: Some(DistinctValues {
+                field: field.to_string(),
+                values,
+                truncated,
+            }),
+            buckets: None,
+        }
+    }
 
-                // Unwrap stringified decoded_payload.object (handles old data and bincode format)
-                let decoded_json = self.unwrap_decoded_payload(unwrapped_json);
+    /// Bucket frames into epoch-aligned intervals and aggregate a numeric field per bucket
+    /// Non-numeric values are ignored (COUNT counts any present value); empty buckets are omitted
+    fn aggregate_buckets(
+        &self,
+        query: &Query,
+        function: AggregateFunction,
+        field: &str,
+        interval: chrono::Duration,
+        frames: &[Frame],
+        allowed_fields: Option<&[String]>,
+    ) -> QueryResult {
+        let interval_micros = interval.num_microseconds().unwrap_or(i64::MAX).max(1);
+
+        // bucket start (micros) -> (count, sum, min, max)
+        let mut buckets: BTreeMap<i64, (u64, f64, f64, f64)> = BTreeMap::new();
+
+        for frame in frames {
+            let value = match self.extract_field(frame, field, allowed_fields) {
+                Some(value) => value,
+                None => continue,
+            };
+            let number = match (function, value.as_f64()) {
+                (AggregateFunction::Count, n) => n.unwrap_or(0.0),
+                (_, Some(n)) => n,
+                (_, None) => continue,
+            };
+
+            let bucket = frame.timestamp().timestamp_micros().div_euclid(interval_micros) * interval_micros;
+            let entry = buckets
+                .entry(bucket)
+                .or_insert((0, 0.0, f64::INFINITY, f64::NEG_INFINITY));
+            entry.0 += 1;
+            entry.1 += number;
+            entry.2 = entry.2.min(number);
+            entry.3 = entry.3.max(number);
+        }
 
-                // Apply field projection if needed
-                self.project_fields(decoded_json, &query.select)
+        // LIMIT applies to the number of buckets returned
+        let buckets: Vec<TimeBucket> = buckets
+            .into_iter()
+            .take(query.limit.unwrap_or(usize::MAX))
+            .filter_map(|(start, (count, sum, min, max))| {
+                let value = match function {
+                    AggregateFunction::Avg => sum / count as f64,
+                    AggregateFunction::Min => min,
+                    AggregateFunction::Max => max,
+                    AggregateFunction::Sum => sum,
+                    AggregateFunction::Count => count as f64,
+                };
+                DateTime::from_timestamp_micros(start).map(|bucket_start| TimeBucket {
+                    bucket_start,
+                    value,
+                })
             })
             .collect();
 
-        Ok(QueryResult {
-            dev_eui: query.from.dev_eui.clone(),
-            total_frames: json_frames.len(),
-            frames: json_frames,
-        })
+        QueryResult {
+            dev_eui: query.from.dev_eui().unwrap_or_default().to_string(),
+            application_id: query.from.application_id().map(str::to_string),
+            total_frames: frames.len(),
+            estimated_bytes: serialized_size(&buckets),
+            frames: Vec::new(),
+            distinct: None,
+            buckets: Some(buckets),
+        }
+    }
+
+    /// Extract a single field from a frame, as a SELECT of that field would,
+    /// honouring the caller's field allowlist
+    fn extract_field(
+        &self,
+        frame: &Frame,
+        field: &str,
+        allowed_fields: Option<&[String]>,
+    ) -> Option<serde_json::Value> {
+        match self.render_frame(frame, &SelectClause::Fields(vec![field.to_string()]), allowed_fields) {
+            serde_json::Value::Object(mut map) => map.remove(field),
+            _ => None,
+        }
+    }
+
+    /// Convert a frame to the flattened JSON shape returned by queries
+    pub fn frame_to_json(&self, frame: &Frame) -> serde_json::Value {
+        // Serialize frame to JSON
+        let json = serde_json::to_value(frame).unwrap_or(serde_json::json!({}));
+
+        // Unwrap enum variant for easier querying (e.g., {"Uplink": {...}} -> {...})
+        let unwrapped_json = self.unwrap_frame_variant(json);
+
+        // Unwrap stringified decoded_payload.object (handles old data and bincode format)
+        let mut json = self.unwrap_decoded_payload(unwrapped_json);
+
+        // User-assigned device name over the network server's, then the derived,
+        // human-readable data rate next to the raw one, the estimated airtime and
+        // when the frame was stored
+        if let serde_json::Value::Object(map) = &mut json {
+            if let Some(name) = map.get_mut("device_name") {
+                if let Some(custom) = self.storage.device_registry().name_override(frame.dev_eui()) {
+                    *name = serde_json::Value::String(custom);
+                }
+            }
+            if let Some(dr) = frame.data_rate() {
+                map.insert("dr_label".to_string(), serde_json::Value::String(dr.label()));
+            }
+            if let Some(airtime) = frame.airtime_ms() {
+                map.insert("airtime_ms".to_string(), serde_json::json!(airtime));
+            }
+            if let Some(stored_at) = frame.stored_at() {
+                map.insert("stored_at".to_string(), serde_json::json!(stored_at));
+            }
+        }
+
+        json
     }
 
     /// Filter frames based on SELECT clause
@@ -109,11 +645,25 @@ impl QueryExecutor {
                 .into_iter()
                 .filter(|f| matches!(f, Frame::JoinRequest(_) | Frame::JoinAccept(_)))
                 .collect(),
+            SelectClause::JoinRequest => frames
+                .into_iter()
+                .filter(|f| matches!(f, Frame::JoinRequest(_)))
+                .collect(),
+            SelectClause::JoinAccept => frames
+                .into_iter()
+                .filter(|f| matches!(f, Frame::JoinAccept(_)))
+                .collect(),
             SelectClause::Status => frames
                 .into_iter()
                 .filter(|f| matches!(f, Frame::Status(_)))
                 .collect(),
+            SelectClause::Rx => frames
+                .into_iter()
+                .filter(|f| matches!(f, Frame::Uplink(_) | Frame::JoinRequest(_)))
+                .collect(),
             SelectClause::Fields(_) => frames, // Field projection happens later
+            SelectClause::Distinct(_) => frames, // Handled separately by collect_distinct
+            SelectClause::Aggregate { .. } => frames, // Handled separately by aggregate_buckets
         }
     }
 
@@ -128,7 +678,7 @@ impl QueryExecutor {
                     // Extract nested value
                     if let Some(value) = self.get_nested_field(&json, field) {
                         // For nested paths, use the full path as the key
-                        result.insert(field.clone(), value.clone());
+                        result.insert(field.clone(), value);
                     }
                 } else {
                     // Top-level field access
@@ -146,13 +696,94 @@ impl QueryExecutor {
         }
     }
 
+    /// Drop every field not covered by the allowlist (None = keep everything)
+    /// A path allows its whole subtree; top-level keys may themselves be dotted
+    /// paths (from SELECT projection) and are matched as such
+    pub fn restrict_fields(
+        &self,
+        json: serde_json::Value,
+        allowed_fields: Option<&[String]>,
+    ) -> serde_json::Value {
+        let allowed: Vec<Vec<&str>> = match allowed_fields {
+            Some(fields) => fields.iter().map(|f| f.split('.').collect()).collect(),
+            None => return json,
+        };
+
+        match json {
+            serde_json::Value::Object(map) => serde_json::Value::Object(
+                map.into_iter()
+                    .filter_map(|(key, value)| {
+                        let path: Vec<&str> = key.split('.').collect();
+                        Self::filter_path(value, &path, &allowed).map(|v| (key, v))
+                    })
+                    .collect(),
+            ),
+            _ => serde_json::Value::Object(serde_json::Map::new()),
+        }
+    }
+
+    /// Keep `value` (located at `path`) only as far as the allowlist permits
+    fn filter_path(
+        value: serde_json::Value,
+        path: &[&str],
+        allowed: &[Vec<&str>],
+    ) -> Option<serde_json::Value> {
+        // An allowed path at or above this one grants the whole subtree
+        if allowed.iter().any(|a| path.starts_with(a)) {
+            return Some(value);
+        }
+
+        // Otherwise only descend if some allowed path lies below this one
+        if !allowed.iter().any(|a| a.starts_with(path)) {
+            return None;
+        }
+
+        match value {
+            serde_json::Value::Object(map) => {
+                let filtered: serde_json::Map<_, _> = map
+                    .into_iter()
+                    .filter_map(|(key, child)| {
+                        let mut child_path = path.to_vec();
+                        child_path.push(&key);
+                        Self::filter_path(child, &child_path, allowed).map(|v| (key.clone(), v))
+                    })
+                    .collect();
+                (!filtered.is_empty()).then_some(serde_json::Value::Object(filtered))
+            }
+            // Array elements share their parent's path (e.g. "rx_info.rssi")
+            serde_json::Value::Array(items) => {
+                let filtered: Vec<_> = items
+                    .into_iter()
+                    .filter_map(|item| Self::filter_path(item, path, allowed))
+                    .collect();
+                (!filtered.is_empty()).then_some(serde_json::Value::Array(filtered))
+            }
+            _ => None,
+        }
+    }
+
     /// Get a nested field using dot notation (e.g., "decoded_payload.object.co2")
-    fn get_nested_field<'a>(&self, json: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
-        let mut current = json;
-        for segment in path.split('.') {
-            current = current.get(segment)?;
+    /// Array elements are reached by index ("rx_info.0.rssi"), or all at once with `*`
+    /// ("rx_info.*.gateway_id"), which yields an array of the elements that have the field
+    fn get_nested_field(&self, json: &serde_json::Value, path: &str) -> Option<serde_json::Value> {
+        let segments: Vec<&str> = path.split('.').collect();
+        Self::resolve_path(json, &segments)
+    }
+
+    fn resolve_path(json: &serde_json::Value, segments: &[&str]) -> Option<serde_json::Value> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return Some(json.clone());
+        };
+
+        match (json, *segment) {
+            (serde_json::Value::Array(items), "*") => Some(serde_json::Value::Array(
+                items.iter().filter_map(|item| Self::resolve_path(item, rest)).collect(),
+            )),
+            (serde_json::Value::Array(items), index) => {
+                Self::resolve_path(items.get(index.parse::<usize>().ok()?)?, rest)
+            }
+            (value, key) => Self::resolve_path(value.get(key)?, rest),
         }
-        Some(current)
     }
 
     /// Unwrap Frame enum variant to make querying easier
@@ -201,25 +832,54 @@ impl QueryExecutor {
 mod tests {
     use std::collections::HashMap;
     use super::*;
-    use crate::config::StorageConfig;
+    use crate::config::{
+        ApplicationKey, ClockSkewAction, OversizedPayloadAction, StorageConfig, StorageLayout,
+        TimestampSource, UnknownApplicationAction, WalDurability,
+    };
     use crate::model::frames::UplinkFrame;
     use crate::model::lorawan::*;
-    use crate::query::dsl::{FilterClause, FromClause};
+    use crate::query::dsl::{FilterClause, FromClause, Predicate};
     use chrono::{Duration, Utc};
     use tempfile::TempDir;
 
     fn create_test_config(data_dir: &std::path::Path) -> StorageConfig {
         StorageConfig {
             data_dir: data_dir.to_path_buf(),
-            wal_sync_interval_ms: 1000,
+            wal_durability: WalDurability::Interval(1000),
             memtable_size_mb: 1,
+            memtable_hard_limit_factor: 0,
             memtable_flush_interval_secs: 300,
             compaction_threshold: 3,
+            max_open_sstables: 128,
+            warmup_sstables: 0,
+            warmup_read_data: false,
+            compaction_max_bytes_per_sec: 0,
+            compaction_fragmentation_threshold: 0.75,
+            compaction_target_sstable_entries: 10_000,
+            max_sstable_bytes: 0,
+            layout: StorageLayout::Flat,
+            max_clock_skew_secs: 300,
+            clock_skew_action: ClockSkewAction::Reject,
+            timestamp_source: TimestampSource::Network,
+            application_key: ApplicationKey::Id,
+            unknown_application_action: UnknownApplicationAction::Fallback,
+            fallback_application_id: "unknown".to_string(),
+            max_payload_depth: 16,
+            max_payload_bytes: 64 * 1024,
+            oversized_payload_action: OversizedPayloadAction::Reject,
+            selftest_dev_eui: "fffffffffffffffe".to_string(),
+            maintenance_window: None,
+            read_only: false,
+            replica_refresh_secs: 30,
+            in_memory: false,
             enable_encryption: false,
             encryption_key: None,
             retention_days: None,
             retention_apps: HashMap::new(),
             retention_check_interval_hours: 24,
+            retention_max_total_bytes: None,
+            retention_default_app_policy: None,
+            device_delete_grace_hours: 0,
         }
     }
 
@@ -229,6 +889,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("test-device".to_string()),
             received_at: timestamp,
+            stored_at: None,
             f_port: 1,
             f_cnt: 42,
             confirmed: false,
@@ -259,9 +920,7 @@ mod tests {
         // Execute query with time filter (now required)
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: dev_eui_str.to_string(),
-            },
+            FromClause::Device(dev_eui_str.to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             None,
         );
@@ -290,9 +949,7 @@ mod tests {
         // Query for last 1 hour (should get 2-3 frames)
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: dev_eui_str.to_string(),
-            },
+            FromClause::Device(dev_eui_str.to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             None,
         );
@@ -301,6 +958,148 @@ mod tests {
         assert!(result.total_frames >= 2);
     }
 
+    #[tokio::test]
+    async fn test_execute_query_default_window() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+
+        let dev_eui_str = "0123456789ABCDEF";
+        let now = Utc::now();
+        storage.write(create_test_uplink(dev_eui_str, now - Duration::hours(3))).await.unwrap();
+        storage.write(create_test_uplink(dev_eui_str, now - Duration::minutes(10))).await.unwrap();
+
+        let query = Query::new(
+            SelectClause::All,
+            FromClause::Device(dev_eui_str.to_string()),
+            None,
+            None,
+        );
+
+        // Without a default window, queries missing a time filter are rejected
+        let strict = QueryExecutor::new(storage.clone());
+        assert!(strict.execute(&query).await.is_err());
+
+        // With one, they only see frames inside the window
+        let windowed = QueryExecutor::new(storage).with_default_window(Some(Duration::hours(1)));
+        let result = windowed.execute(&query).await.unwrap();
+        assert_eq!(result.total_frames, 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_max_window() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor =
+            QueryExecutor::new(storage).with_max_window(Some(Duration::days(90)));
+
+        let query = |filter| {
+            Query::new(
+                SelectClause::All,
+                FromClause::Device("0123456789ABCDEF".to_string()),
+                Some(filter),
+                None,
+            )
+        };
+
+        let err = executor
+            .execute(&query(FilterClause::Between {
+                start: "2000-01-01T00:00:00Z".parse().unwrap(),
+                end: "2100-01-01T00:00:00Z".parse().unwrap(),
+            }))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LoraDbError>(),
+            Some(LoraDbError::QueryParseError(msg)) if msg.contains("exceeds the maximum of 90d")
+        ));
+
+        // SINCE and LAST are measured up to now
+        assert!(executor
+            .execute(&query(FilterClause::Since(Utc::now() - Duration::days(91))))
+            .await
+            .is_err());
+        assert!(executor
+            .execute(&query(FilterClause::Last(Duration::days(30))))
+            .await
+            .is_ok());
+        assert!(executor
+            .execute(&query(FilterClause::Between {
+                start: Utc::now() - Duration::days(200),
+                end: Utc::now() - Duration::days(150),
+            }))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_from_application() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        let now = Utc::now();
+        storage.write(create_test_uplink("0000000000000001", now - Duration::minutes(3))).await.unwrap();
+        storage.write(create_test_uplink("0000000000000002", now - Duration::minutes(2))).await.unwrap();
+        storage.write(create_test_uplink("0000000000000001", now - Duration::minutes(1))).await.unwrap();
+
+        let mut other = create_test_uplink("0000000000000003", now);
+        if let Frame::Uplink(uplink) = &mut other {
+            uplink.application_id = ApplicationId::new("other-app".to_string());
+        }
+        storage.write(other).await.unwrap();
+
+        let query = Query::new(
+            SelectClause::Fields(vec!["dev_eui".to_string()]),
+            FromClause::Application("Test-App".to_string()),
+            Some(FilterClause::Last(Duration::hours(1))),
+            None,
+        );
+        let result = executor.execute(&query).await.unwrap();
+
+        // Frames from both devices, merged oldest first, none from the other application
+        let dev_euis: Vec<_> = result.frames.iter().map(|f| f["dev_eui"].as_str().unwrap()).collect();
+        assert_eq!(dev_euis, ["0000000000000001", "0000000000000002", "0000000000000001"]);
+        assert_eq!(result.application_id.as_deref(), Some("Test-App"));
+        assert!(result.dev_eui.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_execute_timed_reports_scan_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        // One frame flushed to an SSTable, one still in the memtable
+        let dev_eui_str = "0123456789ABCDEF";
+        let now = Utc::now();
+        storage
+            .write(create_test_uplink(dev_eui_str, now - Duration::minutes(5)))
+            .await
+            .unwrap();
+        storage.shutdown().await.unwrap();
+        storage
+            .write(create_test_uplink(dev_eui_str, now - Duration::minutes(1)))
+            .await
+            .unwrap();
+
+        let query = Query::new(
+            SelectClause::All,
+            FromClause::Device(dev_eui_str.to_string()),
+            Some(FilterClause::Last(Duration::hours(1))),
+            None,
+        );
+        let (result, timings) = executor.execute_timed(&query, None).await.unwrap();
+
+        assert_eq!(result.total_frames, 2);
+        assert_eq!(timings.scan_stats.memtable_frames, 1);
+        assert_eq!(timings.scan_stats.sstables_scanned, 1);
+        assert_eq!(timings.scan_stats.sstable_frames, 1);
+    }
+
     #[tokio::test]
     async fn test_execute_query_uplink_only() {
         let temp_dir = TempDir::new().unwrap();
@@ -319,9 +1118,7 @@ mod tests {
         // Execute query for uplink only with time filter
         let query = Query::new(
             SelectClause::Uplink,
-            FromClause {
-                dev_eui: dev_eui_str.to_string(),
-            },
+            FromClause::Device(dev_eui_str.to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             None,
         );
@@ -340,9 +1137,7 @@ mod tests {
         // Query for device that doesn't exist (with time filter)
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: "FEDCBA9876543210".to_string(),
-            },
+            FromClause::Device("FEDCBA9876543210".to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             None,
         );
@@ -378,6 +1173,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("test-device".to_string()),
             received_at: Utc::now(),
+            stored_at: None,
             f_port: 1,
             f_cnt: 42,
             confirmed: false,
@@ -398,9 +1194,7 @@ mod tests {
                 "decoded_payload.object.TempC_SHT".to_string(),
                 "decoded_payload.object.sensor.voltage".to_string(),
             ]),
-            FromClause {
-                dev_eui: dev_eui_str.to_string(),
-            },
+            FromClause::Device(dev_eui_str.to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             None,
         );
@@ -419,6 +1213,38 @@ mod tests {
         assert!(frame_json.get("decoded_payload.object.humidity").is_none());
     }
 
+    #[tokio::test]
+    async fn test_execute_query_with_quoted_field_names() {
+        use crate::model::decoded::DecodedPayload;
+        use crate::query::parser::QueryParser;
+        use serde_json::json;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        let dev_eui_str = "0123456789ABCDEF";
+        let mut frame = create_test_uplink(dev_eui_str, Utc::now());
+        if let Frame::Uplink(ref mut uplink) = frame {
+            uplink.decoded_payload = Some(DecodedPayload::from_json(json!({
+                "Temp (C)": 21.5,
+                "sensor-1": "ok",
+            })));
+        }
+        storage.write(frame).await.unwrap();
+
+        let query = QueryParser::new()
+            .parse("SELECT `decoded_payload.object.Temp (C)`, \"decoded_payload.object.sensor-1\" FROM device '0123456789ABCDEF' WHERE LAST '1h'")
+            .unwrap();
+        let result = executor.execute(&query).await.unwrap();
+
+        assert_eq!(result.total_frames, 1);
+        let frame_json = &result.frames[0];
+        assert_eq!(frame_json["decoded_payload.object.Temp (C)"], json!(21.5));
+        assert_eq!(frame_json["decoded_payload.object.sensor-1"], json!("ok"));
+    }
+
     #[tokio::test]
     async fn test_execute_query_mixed_top_level_and_nested_fields() {
         use crate::model::decoded::DecodedPayload;
@@ -439,6 +1265,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("sensor-1".to_string()),
             received_at: Utc::now(),
+            stored_at: None,
             f_port: 2,
             f_cnt: 100,
             confirmed: false,
@@ -459,9 +1286,7 @@ mod tests {
                 "f_cnt".to_string(),
                 "decoded_payload.object.temperature".to_string(),
             ]),
-            FromClause {
-                dev_eui: dev_eui_str.to_string(),
-            },
+            FromClause::Device(dev_eui_str.to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             None,
         );
@@ -506,19 +1331,19 @@ mod tests {
         // Test deeply nested path
         assert_eq!(
             executor.get_nested_field(&json, "level1.level2.level3"),
-            Some(&json!("deep_value"))
+            Some(json!("deep_value"))
         );
 
         // Test two-level path
         assert_eq!(
             executor.get_nested_field(&json, "level1.value"),
-            Some(&json!(42))
+            Some(json!(42))
         );
 
         // Test top-level path
         assert_eq!(
             executor.get_nested_field(&json, "top"),
-            Some(&json!("top_value"))
+            Some(json!("top_value"))
         );
 
         // Test non-existent path
@@ -526,6 +1351,164 @@ mod tests {
             executor.get_nested_field(&json, "level1.nonexistent"),
             None
         );
+
+        // Array indices and wildcards
+        let json = json!({
+            "rx_info": [
+                { "gateway_id": "gw-1", "rssi": -80 },
+                { "gateway_id": "gw-2" }
+            ]
+        });
+        assert_eq!(executor.get_nested_field(&json, "rx_info.0.rssi"), Some(json!(-80)));
+        assert_eq!(executor.get_nested_field(&json, "rx_info.1.rssi"), None);
+        assert_eq!(executor.get_nested_field(&json, "rx_info.2.rssi"), None);
+        assert_eq!(
+            executor.get_nested_field(&json, "rx_info.*.gateway_id"),
+            Some(json!(["gw-1", "gw-2"]))
+        );
+        assert_eq!(executor.get_nested_field(&json, "rx_info.*.rssi"), Some(json!([-80])));
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_distinct() {
+        use crate::model::decoded::DecodedPayload;
+        use serde_json::json;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        let dev_eui_str = "0123456789ABCDEF";
+        let now = Utc::now();
+        for (i, mode) in ["eco", "boost", "eco", "off"].iter().enumerate() {
+            let mut frame = create_test_uplink(dev_eui_str, now - Duration::minutes(10 - i as i64));
+            if let Frame::Uplink(ref mut uplink) = frame {
+                uplink.decoded_payload = Some(DecodedPayload::from_json(json!({ "mode": mode })));
+            }
+            storage.write(frame).await.unwrap();
+        }
+
+        let mut query = Query::new(
+            SelectClause::Distinct("decoded_payload.object.mode".to_string()),
+            FromClause::Device(dev_eui_str.to_string()),
+            Some(FilterClause::Last(Duration::hours(1))),
+            None,
+        );
+
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.total_frames, 4);
+        assert!(result.frames.is_empty());
+        let distinct = result.distinct.unwrap();
+        assert_eq!(distinct.values, vec![json!("eco"), json!("boost"), json!("off")]);
+        assert!(!distinct.truncated);
+
+        // LIMIT caps the number of distinct values
+        query.limit = Some(2);
+        let distinct = executor.execute(&query).await.unwrap().distinct.unwrap();
+        assert_eq!(distinct.values, vec![json!("eco"), json!("boost")]);
+        assert!(distinct.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_group_by_time() {
+        use crate::model::decoded::DecodedPayload;
+        use crate::query::dsl::{AggregateFunction, GroupByClause, TimeBucket};
+        use serde_json::json;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        // Start from a 5-minute boundary half an hour ago
+        let bucket_micros = Duration::minutes(5).num_microseconds().unwrap();
+        let base_micros = (Utc::now() - Duration::minutes(30)).timestamp_micros();
+        let base = DateTime::from_timestamp_micros(base_micros - base_micros % bucket_micros).unwrap();
+
+        let dev_eui_str = "0123456789ABCDEF";
+        for (offset_mins, temperature) in [(1, json!(10.0)), (2, json!(20.0)), (6, json!(30.0)), (7, json!("n/a"))] {
+            let mut frame = create_test_uplink(dev_eui_str, base + Duration::minutes(offset_mins));
+            if let Frame::Uplink(ref mut uplink) = frame {
+                uplink.decoded_payload =
+                    Some(DecodedPayload::from_json(json!({ "temperature": temperature })));
+            }
+            storage.write(frame).await.unwrap();
+        }
+
+        let query = Query::new(
+            SelectClause::Aggregate {
+                function: AggregateFunction::Avg,
+                field: "decoded_payload.object.temperature".to_string(),
+            },
+            FromClause::Device(dev_eui_str.to_string()),
+            Some(FilterClause::Last(Duration::hours(1))),
+            None,
+        )
+        .with_group_by(GroupByClause::Time(Duration::minutes(5)));
+
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.total_frames, 4);
+        assert_eq!(
+            result.buckets.unwrap(),
+            vec![
+                TimeBucket { bucket_start: base, value: 15.0 },
+                TimeBucket { bucket_start: base + Duration::minutes(5), value: 30.0 },
+            ]
+        );
+
+        // Aggregates without GROUP BY are rejected
+        let mut ungrouped = query.clone();
+        ungrouped.group_by = None;
+        assert!(executor.execute(&ungrouped).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_restrict_fields() {
+        use serde_json::json;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage);
+
+        let frame = json!({
+            "dev_eui": "0123456789ABCDEF",
+            "decoded_payload": {
+                "object": { "temperature": 21.5, "latitude": 52.1 }
+            },
+            "rx_info": [
+                { "gateway_id": "gw1", "rssi": -80, "location": { "latitude": 52.0 } }
+            ]
+        });
+
+        let allowed = vec![
+            "dev_eui".to_string(),
+            "decoded_payload.object.temperature".to_string(),
+            "rx_info.rssi".to_string(),
+        ];
+
+        assert_eq!(
+            executor.restrict_fields(frame.clone(), Some(&allowed)),
+            json!({
+                "dev_eui": "0123456789ABCDEF",
+                "decoded_payload": { "object": { "temperature": 21.5 } },
+                "rx_info": [ { "rssi": -80 } ]
+            })
+        );
+
+        // Dotted keys produced by SELECT projection are matched as paths
+        let projected = json!({
+            "decoded_payload.object.temperature": 21.5,
+            "decoded_payload.object.latitude": 52.1
+        });
+        assert_eq!(
+            executor.restrict_fields(projected, Some(&allowed)),
+            json!({ "decoded_payload.object.temperature": 21.5 })
+        );
+
+        // No allowlist leaves the frame untouched
+        assert_eq!(executor.restrict_fields(frame.clone(), None), frame);
     }
 
     #[tokio::test]
@@ -548,6 +1531,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("test-device".to_string()),
             received_at: Utc::now(),
+            stored_at: None,
             f_port: 2,
             f_cnt: 4186,
             confirmed: false,
@@ -568,9 +1552,7 @@ mod tests {
                 "decoded_payload.object.Bat_status".to_string(),
                 "decoded_payload.object.TempC_SHT".to_string(),
             ]),
-            FromClause {
-                dev_eui: dev_eui_str.to_string(),
-            },
+            FromClause::Device(dev_eui_str.to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             None,
         );
@@ -603,9 +1585,7 @@ mod tests {
         // Query with LIMIT 10
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: dev_eui_str.to_string(),
-            },
+            FromClause::Device(dev_eui_str.to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             Some(10),
         );
@@ -614,6 +1594,182 @@ mod tests {
         assert_eq!(result.total_frames, 10);
     }
 
+    #[tokio::test]
+    async fn test_execute_query_f_port_predicate() {
+        use serde_json::json;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        // Alternate frames between port 1 and port 2
+        let dev_eui_str = "0123456789ABCDEF";
+        let now = Utc::now();
+        for i in 0..10 {
+            let mut frame = create_test_uplink(dev_eui_str, now + Duration::seconds(i));
+            if let Frame::Uplink(uplink) = &mut frame {
+                uplink.f_port = if i % 2 == 0 { 1 } else { 2 };
+            }
+            storage.write(frame).await.unwrap();
+        }
+
+        let from = FromClause::Device(dev_eui_str.to_string());
+        let last_hour = Some(FilterClause::Last(Duration::hours(1)));
+
+        let query = Query::new(SelectClause::All, from.clone(), last_hour.clone(), None)
+            .with_predicates(vec![Predicate::FPort(2)]);
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.total_frames, 5);
+        assert!(result.frames.iter().all(|frame| frame["f_port"] == json!(2)));
+
+        // LIMIT counts matching frames only
+        let query = Query::new(SelectClause::All, from.clone(), last_hour.clone(), Some(3))
+            .with_predicates(vec![Predicate::FPort(1)]);
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.total_frames, 3);
+        assert!(result.frames.iter().all(|frame| frame["f_port"] == json!(1)));
+
+        // DISTINCT sees the filtered frames too
+        let query = Query::new(SelectClause::Distinct("f_port".to_string()), from.clone(), last_hour.clone(), None)
+            .with_predicates(vec![Predicate::FPort(1)]);
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.distinct.unwrap().values, vec![json!(1)]);
+
+        let query = Query::new(SelectClause::All, from, last_hour, None)
+            .with_predicates(vec![Predicate::FPort(3)]);
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.total_frames, 0);
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_data_rate_label() {
+        use serde_json::json;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        let dev_eui_str = "0123456789ABCDEF";
+        let now = Utc::now();
+        for i in 0..4 {
+            let mut frame = create_test_uplink(dev_eui_str, now + Duration::seconds(i));
+            if let Frame::Uplink(uplink) = &mut frame {
+                if i % 2 == 1 {
+                    uplink.dr = DataRate::new_lora(125000, 12);
+                }
+            }
+            storage.write(frame).await.unwrap();
+        }
+
+        let from = FromClause::Device(dev_eui_str.to_string());
+        let last_hour = Some(FilterClause::Last(Duration::hours(1)));
+
+        let query = Query::new(
+            SelectClause::Fields(vec!["dr_label".to_string()]),
+            from.clone(),
+            last_hour.clone(),
+            None,
+        );
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.frames[0], json!({"dr_label": "SF7BW125"}));
+        assert_eq!(result.frames[1], json!({"dr_label": "SF12BW125"}));
+
+        // Labels match regardless of case
+        let query = Query::new(SelectClause::All, from.clone(), last_hour.clone(), None)
+            .with_predicates(vec![Predicate::DataRate("sf12bw125".to_string())]);
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.total_frames, 2);
+        assert!(result.frames.iter().all(|frame| frame["dr"]["spreading_factor"] == json!(12)));
+
+        let query = Query::new(SelectClause::Distinct("dr_label".to_string()), from, last_hour, None);
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(
+            result.distinct.unwrap().values,
+            vec![json!("SF7BW125"), json!("SF12BW125")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_airtime() {
+        use crate::query::dsl::{AggregateFunction, GroupByClause};
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        // Both frames in one 5-minute bucket
+        let bucket_micros = Duration::minutes(5).num_microseconds().unwrap();
+        let base_micros = (Utc::now() - Duration::minutes(30)).timestamp_micros();
+        let base = DateTime::from_timestamp_micros(base_micros - base_micros % bucket_micros).unwrap();
+
+        // 5 byte FRMPayload, 18 byte PHY payload: 51.456 ms at SF7, 1318.912 ms at SF12
+        let dev_eui_str = "0123456789ABCDEF";
+        for (offset_mins, spreading_factor) in [(1, 7), (2, 12)] {
+            let mut frame = create_test_uplink(dev_eui_str, base + Duration::minutes(offset_mins));
+            if let Frame::Uplink(uplink) = &mut frame {
+                uplink.dr = DataRate::new_lora(125000, spreading_factor);
+            }
+            storage.write(frame).await.unwrap();
+        }
+
+        let from = FromClause::Device(dev_eui_str.to_string());
+        let last_hour = Some(FilterClause::Last(Duration::hours(1)));
+
+        let query = Query::new(
+            SelectClause::Fields(vec!["airtime_ms".to_string()]),
+            from.clone(),
+            last_hour.clone(),
+            None,
+        );
+        let result = executor.execute(&query).await.unwrap();
+        let airtime = result.frames[0]["airtime_ms"].as_f64().unwrap();
+        assert!((airtime - 51.456).abs() < 0.001);
+
+        let query = Query::new(
+            SelectClause::Aggregate {
+                function: AggregateFunction::Sum,
+                field: "airtime_ms".to_string(),
+            },
+            from,
+            last_hour,
+            None,
+        )
+        .with_group_by(GroupByClause::Time(Duration::minutes(5)));
+        let buckets = executor.execute(&query).await.unwrap().buckets.unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert!((buckets[0].value - 1370.368).abs() < 0.001);
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_stored_at() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        // Arrives an hour after it was received
+        let dev_eui_str = "0123456789ABCDEF";
+        let received_at = Utc::now() - Duration::hours(1);
+        storage
+            .write(create_test_uplink(dev_eui_str, received_at))
+            .await
+            .unwrap();
+
+        let query = Query::new(
+            SelectClause::Fields(vec!["received_at".to_string(), "stored_at".to_string()]),
+            FromClause::Device(dev_eui_str.to_string()),
+            Some(FilterClause::Last(Duration::hours(2))),
+            None,
+        );
+        let result = executor.execute(&query).await.unwrap();
+        let stored_at: DateTime<Utc> =
+            serde_json::from_value(result.frames[0]["stored_at"].clone()).unwrap();
+        assert!(stored_at - received_at >= Duration::hours(1));
+    }
+
     #[tokio::test]
     async fn test_execute_query_limit_larger_than_results() {
         let temp_dir = TempDir::new().unwrap();
@@ -632,9 +1788,7 @@ mod tests {
         // Query with LIMIT 100 (larger than available frames)
         let query = Query::new(
             SelectClause::All,
-            FromClause {
-                dev_eui: dev_eui_str.to_string(),
-            },
+            FromClause::Device(dev_eui_str.to_string()),
             Some(FilterClause::Last(Duration::hours(1))),
             Some(100),
         );
@@ -642,4 +1796,110 @@ mod tests {
         let result = executor.execute(&query).await.unwrap();
         assert_eq!(result.total_frames, 5);
     }
+
+    #[tokio::test]
+    async fn test_execute_query_rx_rows() {
+        use crate::model::gateway::GatewayRxInfo;
+        use serde_json::json;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        let dev_eui_str = "0123456789ABCDEF";
+        let gateway = |id: &str, rssi: i16, snr: f32| GatewayRxInfo {
+            gateway_id: GatewayEui::new(id.to_string()),
+            rssi,
+            snr,
+            channel: 0,
+            rf_chain: 0,
+            location: None,
+        };
+
+        // Heard by two gateways, then by none
+        let mut frame = create_test_uplink(dev_eui_str, Utc::now());
+        if let Frame::Uplink(uplink) = &mut frame {
+            uplink.rx_info = vec![gateway("gw-a", -80, 7.5), gateway("gw-b", -112, -3.0)];
+        }
+        storage.write(frame).await.unwrap();
+        storage
+            .write(create_test_uplink(dev_eui_str, Utc::now() + Duration::seconds(1)))
+            .await
+            .unwrap();
+
+        let query = Query::new(
+            SelectClause::Rx,
+            FromClause::Device(dev_eui_str.to_string()),
+            Some(FilterClause::Last(Duration::hours(1))),
+            None,
+        );
+
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(result.total_frames, 2);
+        assert_eq!(result.frames[0]["f_cnt"], json!(42));
+        assert_eq!(result.frames[0]["rx_info.gateway_id"], json!("gw-a"));
+        assert_eq!(result.frames[1]["rx_info.gateway_id"], json!("gw-b"));
+        assert_eq!(result.frames[1]["rx_info.rssi"], json!(-112));
+        assert!(result.frames[0].get("raw_payload").is_none());
+
+        // The allowlist applies to rows as it does to frames
+        let allowed = vec!["received_at".to_string(), "rx_info.rssi".to_string()];
+        let result = executor.execute_restricted(&query, Some(&allowed)).await.unwrap();
+        let row = result.frames[0].as_object().unwrap();
+        assert_eq!(row.len(), 2);
+        assert!(row.contains_key("received_at"));
+        assert_eq!(row["rx_info.rssi"], json!(-80));
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_array_paths() {
+        use crate::model::gateway::GatewayRxInfo;
+        use serde_json::json;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let storage = Arc::new(StorageEngine::new(config).await.unwrap());
+        let executor = QueryExecutor::new(storage.clone());
+
+        let dev_eui_str = "0123456789ABCDEF";
+        let gateway = |id: &str, rssi: i16| GatewayRxInfo {
+            gateway_id: GatewayEui::new(id.to_string()),
+            rssi,
+            snr: 0.0,
+            channel: 0,
+            rf_chain: 0,
+            location: None,
+        };
+        let mut frame = create_test_uplink(dev_eui_str, Utc::now());
+        if let Frame::Uplink(uplink) = &mut frame {
+            uplink.rx_info = vec![gateway("gw-a", -80), gateway("gw-b", -112)];
+        }
+        storage.write(frame).await.unwrap();
+
+        let query = Query::new(
+            SelectClause::Fields(vec![
+                "rx_info.1.rssi".to_string(),
+                "rx_info.*.gateway_id".to_string(),
+                "rx_info.5.rssi".to_string(),
+            ]),
+            FromClause::Device(dev_eui_str.to_string()),
+            Some(FilterClause::Last(Duration::hours(1))),
+            None,
+        );
+
+        let result = executor.execute(&query).await.unwrap();
+        assert_eq!(
+            result.frames[0],
+            json!({ "rx_info.1.rssi": -112, "rx_info.*.gateway_id": ["gw-a", "gw-b"] })
+        );
+
+        // Indices and wildcards only reach what the allowlist permits
+        let allowed = vec!["rx_info.rssi".to_string()];
+        let result = executor.execute_restricted(&query, Some(&allowed)).await.unwrap();
+        assert_eq!(
+            result.frames[0],
+            json!({ "rx_info.1.rssi": -112, "rx_info.*.gateway_id": [] })
+        );
+    }
 }
@@ -1,3 +1,4 @@
+pub mod cache;
 pub mod dsl;
 pub mod parser;
 pub mod planner;
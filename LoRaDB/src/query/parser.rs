@@ -1,5 +1,7 @@
 use crate::error::LoraDbError;
-use crate::query::dsl::{FilterClause, FromClause, Query, SelectClause};
+use crate::query::dsl::{
+    AggregateFunction, FilterClause, FromClause, GroupByClause, Predicate, Query, SelectClause,
+};
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
 
@@ -10,12 +12,19 @@ const MAX_SELECT_FIELDS: usize = 100;
 ///
 /// Grammar:
 /// ```text
-/// Query     := SELECT SelectClause FROM FromClause [ WHERE FilterClause ] [ LIMIT integer ]
-/// SelectClause := * | uplink | downlink | join | Fields
-/// FromClause := device 'DevEUI'
+/// Query     := SELECT SelectClause FROM FromClause [ WHERE Condition { AND Condition } ]
+///              [ GROUP BY time(duration) ] [ LIMIT integer ]
+/// SelectClause := * | uplink | downlink | join | DISTINCT field
+///              | Aggregate(field) | Fields
+/// Aggregate := AVG | MIN | MAX | SUM | COUNT
+/// FromClause := device 'DevEUI' | application 'app-id'
+/// Condition := FilterClause | Predicate   (at most one FilterClause)
 /// FilterClause := BETWEEN 'timestamp' AND 'timestamp'
 ///              | SINCE 'timestamp'
 ///              | LAST 'duration'
+/// Field     := identifier | `quoted name` | "quoted name"
+/// Predicate := f_port = integer
+///              | dr = 'label'              (e.g. 'SF7BW125', also dr_label)
 /// ```
 pub struct QueryParser;
 
@@ -25,6 +34,22 @@ impl QueryParser {
     }
 
     pub fn parse(&self, input: &str) -> Result<Query> {
+        // An empty query would otherwise fail as a missing SELECT clause
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(LoraDbError::QueryParseError("Query is empty".to_string()).into());
+        }
+        if let Some(ch) = input
+            .chars()
+            .find(|ch| ch.is_control() && !matches!(ch, '\t' | '\n' | '\r'))
+        {
+            return Err(LoraDbError::QueryParseError(format!(
+                "Query contains a control character (U+{:04X})",
+                ch as u32
+            ))
+            .into());
+        }
+
         let mut tokens = Tokenizer::new(input).tokenize()?;
 
         // Parse SELECT clause
@@ -36,13 +61,39 @@ impl QueryParser {
         let from = self.parse_from(&mut tokens)?;
 
         // Parse optional WHERE clause
-        let filter = if self.peek_keyword(&tokens, "WHERE") {
+        let (filter, predicates) = if self.peek_keyword(&tokens, "WHERE") {
             self.expect_keyword(&mut tokens, "WHERE")?;
-            Some(self.parse_filter(&mut tokens)?)
+            self.parse_conditions(&mut tokens)?
+        } else {
+            (None, Vec::new())
+        };
+
+        // Parse optional GROUP BY clause
+        let group_by = if self.peek_keyword(&tokens, "GROUP") {
+            self.expect_keyword(&mut tokens, "GROUP")?;
+            self.expect_keyword(&mut tokens, "BY")?;
+            Some(self.parse_group_by(&mut tokens)?)
         } else {
             None
         };
 
+        // Aggregates need buckets to reduce into, and plain frame listings have nothing to group
+        match (&select, &group_by) {
+            (SelectClause::Aggregate { .. }, None) => {
+                return Err(LoraDbError::QueryParseError(
+                    "Aggregate functions require a GROUP BY time(...) clause".to_string(),
+                )
+                .into());
+            }
+            (SelectClause::Aggregate { .. }, Some(_)) | (_, None) => {}
+            (_, Some(_)) => {
+                return Err(LoraDbError::QueryParseError(
+                    "GROUP BY requires an aggregate function such as AVG(field)".to_string(),
+                )
+                .into());
+            }
+        }
+
         // Parse optional LIMIT clause
         let limit = if self.peek_keyword(&tokens, "LIMIT") {
             self.expect_keyword(&mut tokens, "LIMIT")?;
@@ -60,7 +111,11 @@ impl QueryParser {
             .into());
         }
 
-        Ok(Query::new(select, from, filter, limit))
+        let query = Query::new(select, from, filter, limit).with_predicates(predicates);
+        Ok(match group_by {
+            Some(group_by) => query.with_group_by(group_by),
+            None => query,
+        })
     }
 
     fn parse_select(&self, tokens: &mut Vec<Token>) -> Result<SelectClause> {
@@ -80,13 +135,57 @@ impl QueryParser {
                 Ok(SelectClause::Downlink)
             }
             Token::Identifier(ref s) if s.eq_ignore_ascii_case("join") => Ok(SelectClause::Join),
+            Token::Identifier(ref s) if s.eq_ignore_ascii_case("joinrequest") => {
+                Ok(SelectClause::JoinRequest)
+            }
+            Token::Identifier(ref s) if s.eq_ignore_ascii_case("joinaccept") => {
+                Ok(SelectClause::JoinAccept)
+            }
             Token::Identifier(ref s) if s.eq_ignore_ascii_case("status") => Ok(SelectClause::Status),
-            Token::Identifier(field) => {
+            Token::Identifier(ref s) if s.eq_ignore_ascii_case("rx") => Ok(SelectClause::Rx),
+            Token::Identifier(ref s)
+                if tokens.first() == Some(&Token::LParen)
+                    && AggregateFunction::from_name(s).is_some() =>
+            {
+                let function = AggregateFunction::from_name(s).unwrap();
+                tokens.remove(0); // consume (
+                let field = match tokens.first() {
+                    Some(Token::Identifier(field) | Token::QuotedIdentifier(field)) => field.clone(),
+                    _ => {
+                        return Err(LoraDbError::QueryParseError(format!(
+                            "Expected field name in {}()",
+                            s.to_ascii_uppercase()
+                        ))
+                        .into())
+                    }
+                };
+                tokens.remove(0);
+                self.expect_token(tokens, Token::RParen)?;
+                Ok(SelectClause::Aggregate { function, field })
+            }
+            Token::Identifier(ref s) if s.eq_ignore_ascii_case("DISTINCT") => {
+                if let Some(Token::Identifier(field) | Token::QuotedIdentifier(field)) =
+                    tokens.first()
+                {
+                    let field = field.clone();
+                    tokens.remove(0);
+                    Ok(SelectClause::Distinct(field))
+                } else {
+                    Err(LoraDbError::QueryParseError(
+                        "Expected field name after DISTINCT".to_string(),
+                    )
+                    .into())
+                }
+            }
+            // A quoted name is always a field, even if it spells a keyword
+            Token::Identifier(field) | Token::QuotedIdentifier(field) => {
                 // Parse comma-separated field list
                 let mut fields = vec![field];
                 while tokens.first() == Some(&Token::Comma) {
                     tokens.remove(0); // consume comma
-                    if let Some(Token::Identifier(field)) = tokens.first() {
+                    if let Some(Token::Identifier(field) | Token::QuotedIdentifier(field)) =
+                        tokens.first()
+                    {
                         fields.push(field.clone());
                         tokens.remove(0);
                     } else {
@@ -115,16 +214,121 @@ impl QueryParser {
     }
 
     fn parse_from(&self, tokens: &mut Vec<Token>) -> Result<FromClause> {
-        self.expect_keyword(tokens, "device")?;
-
-        if let Some(Token::String(dev_eui)) = tokens.first() {
-            let dev_eui = dev_eui.clone();
-            tokens.remove(0);
-            Ok(FromClause { dev_eui })
+        let application = if self.peek_keyword(tokens, "application") {
+            self.expect_keyword(tokens, "application")?;
+            true
         } else {
-            Err(LoraDbError::QueryParseError(
+            self.expect_keyword(tokens, "device")?;
+            false
+        };
+
+        match tokens.first() {
+            Some(Token::String(value) | Token::QuotedIdentifier(value))
+                if !(application && value.trim().is_empty()) =>
+            {
+                let value = value.clone();
+                tokens.remove(0);
+                Ok(if application {
+                    FromClause::Application(value)
+                } else {
+                    FromClause::Device(value)
+                })
+            }
+            _ if application => Err(LoraDbError::QueryParseError(
+                "Expected application ID string after 'application'".to_string(),
+            )
+            .into()),
+            _ => Err(LoraDbError::QueryParseError(
                 "Expected device EUI string after 'device'".to_string(),
             )
+            .into()),
+        }
+    }
+
+    /// Parse AND-separated WHERE conditions into the time filter and field predicates
+    fn parse_conditions(
+        &self,
+        tokens: &mut Vec<Token>,
+    ) -> Result<(Option<FilterClause>, Vec<Predicate>)> {
+        let mut filter = None;
+        let mut predicates = Vec::new();
+
+        loop {
+            let is_time_filter = ["BETWEEN", "SINCE", "LAST"]
+                .iter()
+                .any(|keyword| self.peek_keyword(tokens, keyword));
+
+            if is_time_filter {
+                if filter.is_some() {
+                    return Err(LoraDbError::QueryParseError(
+                        "Only one time filter (BETWEEN, SINCE or LAST) is allowed".to_string(),
+                    )
+                    .into());
+                }
+                filter = Some(self.parse_filter(tokens)?);
+            } else {
+                predicates.push(self.parse_predicate(tokens)?);
+            }
+
+            if !self.peek_keyword(tokens, "AND") {
+                break;
+            }
+            tokens.remove(0);
+        }
+
+        Ok((filter, predicates))
+    }
+
+    fn parse_predicate(&self, tokens: &mut Vec<Token>) -> Result<Predicate> {
+        let field = match tokens.first() {
+            Some(Token::Identifier(field)) => field.clone(),
+            other => {
+                return Err(LoraDbError::QueryParseError(format!(
+                    "Invalid filter clause: {:?}",
+                    other
+                ))
+                .into())
+            }
+        };
+        tokens.remove(0);
+        self.expect_token(tokens, Token::Equals)?;
+
+        if field.eq_ignore_ascii_case("f_port") {
+            match tokens.first() {
+                Some(Token::Integer(port)) => {
+                    let port = u8::try_from(*port).map_err(|_| {
+                        LoraDbError::QueryParseError(format!(
+                            "f_port must be between 0 and 255, got {}",
+                            port
+                        ))
+                    })?;
+                    tokens.remove(0);
+                    Ok(Predicate::FPort(port))
+                }
+                _ => Err(LoraDbError::QueryParseError(
+                    "Expected integer after 'f_port ='".to_string(),
+                )
+                .into()),
+            }
+        } else if field.eq_ignore_ascii_case("dr") || field.eq_ignore_ascii_case("dr_label") {
+            match tokens.first() {
+                Some(Token::String(label) | Token::QuotedIdentifier(label))
+                    if !label.trim().is_empty() =>
+                {
+                    let label = label.trim().to_string();
+                    tokens.remove(0);
+                    Ok(Predicate::DataRate(label))
+                }
+                _ => Err(LoraDbError::QueryParseError(
+                    "Expected data rate label after 'dr =', e.g. 'SF7BW125'".to_string(),
+                )
+                .into()),
+            }
+        } else {
+            Err(LoraDbError::QueryParseError(format!(
+                "Unsupported WHERE field '{}' (supported: f_port, dr)",
+                field
+            ))
             .into())
         }
     }
@@ -169,6 +373,48 @@ impl QueryParser {
         Ok(FilterClause::Last(duration))
     }
 
+    fn parse_group_by(&self, tokens: &mut Vec<Token>) -> Result<GroupByClause> {
+        self.expect_keyword(tokens, "time")?;
+        self.expect_token(tokens, Token::LParen)?;
+
+        // Accept both time('5m') and time(5m), which tokenizes as 5 + m
+        let interval = match tokens.first().cloned() {
+            Some(Token::String(_) | Token::QuotedIdentifier(_)) => self.expect_duration(tokens)?,
+            Some(Token::Integer(n)) => {
+                tokens.remove(0);
+                match tokens.first().cloned() {
+                    Some(Token::Identifier(unit)) => {
+                        tokens.remove(0);
+                        parse_duration(&format!("{}{}", n, unit))?
+                    }
+                    _ => {
+                        return Err(LoraDbError::QueryParseError(
+                            "Expected duration unit in time()".to_string(),
+                        )
+                        .into())
+                    }
+                }
+            }
+            _ => {
+                return Err(LoraDbError::QueryParseError(
+                    "Expected duration in time()".to_string(),
+                )
+                .into())
+            }
+        };
+
+        self.expect_token(tokens, Token::RParen)?;
+
+        if interval <= Duration::zero() {
+            return Err(LoraDbError::QueryParseError(
+                "GROUP BY time interval must be greater than 0".to_string(),
+            )
+            .into());
+        }
+
+        Ok(GroupByClause::Time(interval))
+    }
+
     fn parse_limit(&self, tokens: &mut Vec<Token>) -> Result<usize> {
         if let Some(Token::Integer(limit)) = tokens.first() {
             let limit = *limit;
@@ -202,23 +448,18 @@ impl QueryParser {
     }
 
     fn expect_timestamp(&self, tokens: &mut Vec<Token>) -> Result<DateTime<Utc>> {
-        if let Some(Token::String(ts_str)) = tokens.first() {
+        if let Some(Token::String(ts_str) | Token::QuotedIdentifier(ts_str)) = tokens.first() {
             let ts_str = ts_str.clone();
             tokens.remove(0);
 
-            DateTime::parse_from_rfc3339(&ts_str)
-                .map(|dt| dt.with_timezone(&Utc))
-                .map_err(|e| {
-                    LoraDbError::QueryParseError(format!("Invalid timestamp '{}': {}", ts_str, e))
-                        .into()
-                })
+            parse_timestamp(&ts_str)
         } else {
             Err(LoraDbError::QueryParseError("Expected timestamp string".to_string()).into())
         }
     }
 
     fn expect_duration(&self, tokens: &mut Vec<Token>) -> Result<Duration> {
-        if let Some(Token::String(dur_str)) = tokens.first() {
+        if let Some(Token::String(dur_str) | Token::QuotedIdentifier(dur_str)) = tokens.first() {
             let dur_str = dur_str.clone();
             tokens.remove(0);
 
@@ -239,6 +480,15 @@ impl QueryParser {
         Err(LoraDbError::QueryParseError(format!("Expected keyword '{}'", keyword)).into())
     }
 
+    fn expect_token(&self, tokens: &mut Vec<Token>, expected: Token) -> Result<()> {
+        if tokens.first() == Some(&expected) {
+            tokens.remove(0);
+            Ok(())
+        } else {
+            Err(LoraDbError::QueryParseError(format!("Expected {:?}", expected)).into())
+        }
+    }
+
     fn peek_keyword(&self, tokens: &[Token], keyword: &str) -> bool {
         if let Some(Token::Identifier(ref s)) = tokens.first() {
             s.eq_ignore_ascii_case(keyword)
@@ -254,8 +504,48 @@ impl Default for QueryParser {
     }
 }
 
+/// Epoch values with at least this many digits are taken as milliseconds, shorter ones
+/// as seconds (12 digits of seconds would be past the year 5000)
+const EPOCH_MILLIS_MIN_DIGITS: usize = 12;
+
+/// Parse a timestamp: RFC 3339, the same with a space before the time or an offset without
+/// a colon (`+0100`), or Unix epoch seconds or milliseconds such as "1700000000000"
+pub fn parse_timestamp(s: &str) -> Result<DateTime<Utc>> {
+    let s = s.trim();
+    let invalid = |reason: &str| -> anyhow::Error {
+        LoraDbError::QueryParseError(format!("Invalid timestamp '{}': {}", s, reason)).into()
+    };
+
+    if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        let value: i64 = s.parse().map_err(|_| invalid("epoch value out of range"))?;
+        let parsed = if s.len() >= EPOCH_MILLIS_MIN_DIGITS {
+            DateTime::from_timestamp_millis(value)
+        } else {
+            DateTime::from_timestamp(value, 0)
+        };
+        return parsed.ok_or_else(|| invalid("epoch value out of range"));
+    }
+
+    let rfc3339_error = match DateTime::parse_from_rfc3339(s) {
+        Ok(dt) => return Ok(dt.with_timezone(&Utc)),
+        Err(e) => e,
+    };
+
+    // Formats common tools produce that strict RFC 3339 rejects
+    for format in ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z"] {
+        if let Ok(dt) = DateTime::parse_from_str(s, format) {
+            return Ok(dt.with_timezone(&Utc));
+        }
+    }
+
+    Err(invalid(&format!(
+        "{}, expected RFC 3339 or Unix epoch seconds/milliseconds",
+        rfc3339_error
+    )))
+}
+
 /// Parse duration strings like "1h", "30m", "7d", "2w"
-fn parse_duration(s: &str) -> Result<Duration> {
+pub fn parse_duration(s: &str) -> Result<Duration> {
     let s = s.trim();
     if s.is_empty() {
         return Err(LoraDbError::QueryParseError("Empty duration string".to_string()).into());
@@ -286,10 +576,15 @@ fn parse_duration(s: &str) -> Result<Duration> {
 #[derive(Debug, Clone, PartialEq)]
 enum Token {
     Identifier(String),
+    /// Backtick- or double-quoted text, a field name in SELECT and a string elsewhere
+    QuotedIdentifier(String),
     String(String),
     Integer(usize),
     Asterisk,
     Comma,
+    Equals,
+    LParen,
+    RParen,
 }
 
 struct Tokenizer {
@@ -320,7 +615,19 @@ impl Tokenizer {
                     chars.next();
                     tokens.push(Token::Comma);
                 }
-                '\'' | '"' => {
+                '=' => {
+                    chars.next();
+                    tokens.push(Token::Equals);
+                }
+                '(' => {
+                    chars.next();
+                    tokens.push(Token::LParen);
+                }
+                ')' => {
+                    chars.next();
+                    tokens.push(Token::RParen);
+                }
+                '\'' | '"' | '`' => {
                     let quote = chars.next().unwrap();
                     let mut string = String::new();
                     while let Some(&ch) = chars.peek() {
@@ -330,7 +637,11 @@ impl Tokenizer {
                         }
                         string.push(chars.next().unwrap());
                     }
-                    tokens.push(Token::String(string));
+                    if quote == '\'' {
+                        tokens.push(Token::String(string));
+                    } else {
+                        tokens.push(Token::QuotedIdentifier(string));
+                    }
                 }
                 _ if ch.is_numeric() => {
                     // Parse pure numeric sequence as integer
@@ -350,9 +661,14 @@ impl Tokenizer {
                 }
                 _ if ch.is_alphanumeric() || ch == '_' => {
                     // Alphanumeric identifiers (preserves "1h", "field1", etc.)
+                    // A `*` segment is a wildcard over array elements ("rx_info.*.rssi")
                     let mut identifier = String::new();
                     while let Some(&ch) = chars.peek() {
-                        if ch.is_alphanumeric() || ch == '_' || ch == '.' {
+                        if ch.is_alphanumeric()
+                            || ch == '_'
+                            || ch == '.'
+                            || (ch == '*' && identifier.ends_with('.'))
+                        {
                             identifier.push(chars.next().unwrap());
                         } else {
                             break;
@@ -386,10 +702,49 @@ mod tests {
             .unwrap();
 
         assert_eq!(query.select, SelectClause::All);
-        assert_eq!(query.from.dev_eui, "0123456789ABCDEF");
+        assert_eq!(query.from, FromClause::Device("0123456789ABCDEF".to_string()));
         assert!(query.filter.is_none());
     }
 
+    #[test]
+    fn test_parse_empty_query() {
+        let parser = QueryParser::new();
+
+        for input in ["", "   ", "\n\t \r\n"] {
+            let err = parser.parse(input).unwrap_err().to_string();
+            assert!(err.contains("Query is empty"), "{}", err);
+        }
+
+        let err = parser
+            .parse("SELECT * FROM device '0123456789ABCDEF'\0")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("control character"), "{}", err);
+
+        // Surrounding whitespace is still fine
+        assert!(parser
+            .parse("\n  SELECT * FROM device '0123456789ABCDEF'  \n")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_parse_from_application() {
+        let parser = QueryParser::new();
+        let query = parser
+            .parse("SELECT uplink FROM application 'test-app' WHERE LAST '1h'")
+            .unwrap();
+        assert_eq!(query.from, FromClause::Application("test-app".to_string()));
+
+        let query = parser
+            .parse("SELECT * FROM APPLICATION \"Test App\" WHERE LAST '1h'")
+            .unwrap();
+        assert_eq!(query.from.application_id(), Some("Test App"));
+
+        assert!(parser.parse("SELECT * FROM application WHERE LAST '1h'").is_err());
+        assert!(parser.parse("SELECT * FROM application '' WHERE LAST '1h'").is_err());
+        assert!(parser.parse("SELECT * FROM gateway 'x' WHERE LAST '1h'").is_err());
+    }
+
     #[test]
     fn test_parse_select_uplink() {
         let parser = QueryParser::new();
@@ -400,6 +755,31 @@ mod tests {
         assert_eq!(query.select, SelectClause::Uplink);
     }
 
+    #[test]
+    fn test_parse_select_rx() {
+        let parser = QueryParser::new();
+        let query = parser
+            .parse("SELECT rx FROM device '0123456789ABCDEF' WHERE LAST '1h'")
+            .unwrap();
+
+        assert_eq!(query.select, SelectClause::Rx);
+    }
+
+    #[test]
+    fn test_parse_select_join_kinds() {
+        let parser = QueryParser::new();
+        for (select, expected) in [
+            ("join", SelectClause::Join),
+            ("joinrequest", SelectClause::JoinRequest),
+            ("JoinAccept", SelectClause::JoinAccept),
+        ] {
+            let query = parser
+                .parse(&format!("SELECT {} FROM device '0123456789ABCDEF'", select))
+                .unwrap();
+            assert_eq!(query.select, expected);
+        }
+    }
+
     #[test]
     fn test_parse_select_fields() {
         let parser = QueryParser::new();
@@ -415,6 +795,167 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_select_quoted_fields() {
+        let parser = QueryParser::new();
+        let query = parser
+            .parse("SELECT `decoded_payload.object.Temp (C)`, \"sensor-1\", f_cnt FROM device '0123456789ABCDEF'")
+            .unwrap();
+        assert_eq!(
+            query.select,
+            SelectClause::Fields(vec![
+                "decoded_payload.object.Temp (C)".to_string(),
+                "sensor-1".to_string(),
+                "f_cnt".to_string(),
+            ])
+        );
+
+        // Quoting a keyword selects a field of that name
+        let query = parser
+            .parse("SELECT `uplink` FROM device \"0123456789ABCDEF\"")
+            .unwrap();
+        assert_eq!(query.select, SelectClause::Fields(vec!["uplink".to_string()]));
+        assert_eq!(query.from, FromClause::Device("0123456789ABCDEF".to_string()));
+
+        let query = parser
+            .parse("SELECT AVG(`decoded_payload.object.Temp (C)`) FROM device '0123456789ABCDEF' WHERE LAST '1h' GROUP BY time(1h)")
+            .unwrap();
+        assert!(matches!(
+            query.select,
+            SelectClause::Aggregate { ref field, .. } if field == "decoded_payload.object.Temp (C)"
+        ));
+
+        // Single quotes remain string literals
+        assert!(parser
+            .parse("SELECT 'Temp (C)' FROM device '0123456789ABCDEF'")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_where_f_port() {
+        let parser = QueryParser::new();
+
+        let query = parser
+            .parse("SELECT * FROM device '0123456789ABCDEF' WHERE LAST '1h' AND f_port = 2")
+            .unwrap();
+        assert_eq!(query.filter, Some(FilterClause::Last(Duration::hours(1))));
+        assert_eq!(query.predicates, vec![Predicate::FPort(2)]);
+
+        // Predicates may come before the time filter, BETWEEN keeps its own AND
+        let query = parser
+            .parse("SELECT uplink FROM device '0123456789ABCDEF' WHERE F_PORT=10 AND BETWEEN '2025-01-01T00:00:00Z' AND '2025-01-02T00:00:00Z' LIMIT 5")
+            .unwrap();
+        assert_eq!(query.predicates, vec![Predicate::FPort(10)]);
+        assert!(matches!(query.filter, Some(FilterClause::Between { .. })));
+        assert_eq!(query.limit, Some(5));
+
+        assert!(parser
+            .parse("SELECT * FROM device '0123456789ABCDEF' WHERE LAST '1h' AND f_port = 256")
+            .is_err());
+        assert!(parser
+            .parse("SELECT * FROM device '0123456789ABCDEF' WHERE LAST '1h' AND f_cnt = 1")
+            .is_err());
+        assert!(parser
+            .parse("SELECT * FROM device '0123456789ABCDEF' WHERE LAST '1h' AND SINCE '2025-01-01T00:00:00Z'")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_where_data_rate() {
+        let parser = QueryParser::new();
+
+        let query = parser
+            .parse("SELECT dr_label FROM device '0123456789ABCDEF' WHERE LAST '1h' AND dr = 'SF7BW125' AND f_port = 1")
+            .unwrap();
+        assert_eq!(
+            query.predicates,
+            vec![Predicate::DataRate("SF7BW125".to_string()), Predicate::FPort(1)]
+        );
+
+        assert!(parser
+            .parse("SELECT * FROM device '0123456789ABCDEF' WHERE LAST '1h' AND dr = 7")
+            .is_err());
+        assert!(parser
+            .parse("SELECT * FROM device '0123456789ABCDEF' WHERE LAST '1h' AND dr = ''")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_select_distinct() {
+        let parser = QueryParser::new();
+        let query = parser
+            .parse("SELECT DISTINCT decoded_payload.object.mode FROM device '0123456789ABCDEF' WHERE LAST '7d'")
+            .unwrap();
+
+        assert_eq!(
+            query.select,
+            SelectClause::Distinct("decoded_payload.object.mode".to_string())
+        );
+
+        assert!(parser
+            .parse("SELECT DISTINCT FROM device '0123456789ABCDEF'")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_aggregate_group_by_time() {
+        use crate::query::dsl::{AggregateFunction, GroupByClause};
+
+        let parser = QueryParser::new();
+        let query = parser
+            .parse("SELECT AVG(decoded_payload.object.temperature) FROM device '0123456789ABCDEF' WHERE LAST '1d' GROUP BY time(5m)")
+            .unwrap();
+
+        assert_eq!(
+            query.select,
+            SelectClause::Aggregate {
+                function: AggregateFunction::Avg,
+                field: "decoded_payload.object.temperature".to_string(),
+            }
+        );
+        assert_eq!(query.group_by, Some(GroupByClause::Time(Duration::minutes(5))));
+
+        // Quoted interval and LIMIT after GROUP BY
+        let query = parser
+            .parse("SELECT max(f_cnt) FROM device '0123456789ABCDEF' WHERE LAST '1d' GROUP BY time('1h') LIMIT 10")
+            .unwrap();
+        assert_eq!(query.group_by, Some(GroupByClause::Time(Duration::hours(1))));
+        assert_eq!(query.limit, Some(10));
+
+        assert!(parser
+            .parse("SELECT AVG(f_cnt) FROM device '0123456789ABCDEF' GROUP BY time(0m)")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_aggregate_requires_group_by() {
+        let parser = QueryParser::new();
+        let err = parser
+            .parse("SELECT AVG(f_cnt) FROM device '0123456789ABCDEF' WHERE LAST '1d'")
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LoraDbError>(),
+            Some(LoraDbError::QueryParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_group_by_requires_aggregate() {
+        let parser = QueryParser::new();
+        for select in ["*", "f_cnt, dev_eui", "uplink"] {
+            let err = parser
+                .parse(&format!(
+                    "SELECT {} FROM device '0123456789ABCDEF' WHERE LAST '1d' GROUP BY time(1h)",
+                    select
+                ))
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<LoraDbError>(),
+                Some(LoraDbError::QueryParseError(_))
+            ));
+        }
+    }
+
     #[test]
     fn test_parse_where_last() {
         let parser = QueryParser::new();
@@ -443,6 +984,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_timestamp_formats() {
+        let expected = DateTime::parse_from_rfc3339("2023-11-14T22:13:20Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        // Epoch seconds and milliseconds
+        assert_eq!(parse_timestamp("1700000000").unwrap(), expected);
+        assert_eq!(parse_timestamp("1700000000000").unwrap(), expected);
+        assert_eq!(
+            parse_timestamp("1700000000123").unwrap(),
+            expected + Duration::milliseconds(123)
+        );
+
+        // RFC 3339 and the looser offset forms
+        assert_eq!(parse_timestamp("2023-11-14T22:13:20Z").unwrap(), expected);
+        assert_eq!(parse_timestamp("2023-11-14T23:13:20+01:00").unwrap(), expected);
+        assert_eq!(parse_timestamp("2023-11-14T23:13:20+0100").unwrap(), expected);
+        assert_eq!(parse_timestamp("2023-11-14 23:13:20.000+0100").unwrap(), expected);
+
+        assert!(parse_timestamp("").is_err());
+        assert!(parse_timestamp("-1700000000").is_err());
+        assert!(parse_timestamp("99999999999999999999").is_err());
+        assert!(parse_timestamp("2023-11-14").is_err());
+    }
+
+    #[test]
+    fn test_parse_where_since_epoch_millis() {
+        let parser = QueryParser::new();
+        let query = parser
+            .parse("SELECT * FROM device '0123456789ABCDEF' WHERE SINCE '1700000000000'")
+            .unwrap();
+
+        match query.filter {
+            Some(FilterClause::Since(start)) => assert_eq!(start.timestamp(), 1_700_000_000),
+            _ => panic!("Expected Since filter clause"),
+        }
+    }
+
     #[test]
     fn test_parse_where_between() {
         let parser = QueryParser::new();
@@ -515,6 +1095,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_array_field_paths() {
+        let parser = QueryParser::new();
+        let query = parser
+            .parse("SELECT rx_info.0.rssi, rx_info.*.gateway_id FROM device 'a84041c7a1881438'")
+            .unwrap();
+
+        match query.select {
+            SelectClause::Fields(fields) => {
+                assert_eq!(fields, vec!["rx_info.0.rssi", "rx_info.*.gateway_id"]);
+            }
+            _ => panic!("Expected Fields select clause"),
+        }
+
+        // A leading * is still SELECT *
+        assert!(matches!(
+            parser.parse("SELECT * FROM device 'a84041c7a1881438'").unwrap().select,
+            SelectClause::All
+        ));
+    }
+
     #[test]
     fn test_tokenize_integer() {
         let mut tokenizer = Tokenizer::new("LIMIT 100");
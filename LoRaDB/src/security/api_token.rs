@@ -8,7 +8,9 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use parking_lot::RwLock;
+use std::time::SystemTime;
+use parking_lot::{Mutex, RwLock};
+use tracing::{info, warn};
 
 /// API token prefix for easy identification
 const TOKEN_PREFIX: &str = "ldb_";
@@ -33,6 +35,15 @@ pub struct ApiToken {
     pub expires_at: Option<DateTime<Utc>>,
     /// Whether the token is active
     pub is_active: bool,
+    /// When the token was revoked (None for active tokens and ones revoked before this was recorded)
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Frame fields this token may see, as dot-separated paths (None = unrestricted)
+    #[serde(default)]
+    pub allowed_fields: Option<Vec<String>>,
+    /// Tenant whose applications this token is confined to (None = every tenant)
+    #[serde(default)]
+    pub tenant_id: Option<String>,
 }
 
 impl ApiToken {
@@ -47,6 +58,9 @@ impl ApiToken {
             last_used_at: None,
             expires_at: None,
             is_active: true,
+            revoked_at: None,
+            allowed_fields: None,
+            tenant_id: None,
         }
     }
 
@@ -83,8 +97,27 @@ impl ApiToken {
 
     /// Revoke the token
     pub fn revoke(&mut self) {
+        if self.is_active {
+            self.revoked_at = Some(Utc::now());
+        }
         self.is_active = false;
     }
+
+    /// When the token stopped being usable, None while it is still valid
+    /// Tokens revoked without a recorded time count from when they were last used or created
+    pub fn inactive_since(&self) -> Option<DateTime<Utc>> {
+        let revoked_at = if self.is_active {
+            None
+        } else {
+            Some(self.revoked_at.or(self.last_used_at).unwrap_or(self.created_at))
+        };
+        let expired_at = self.expires_at.filter(|_| self.is_expired());
+
+        match (revoked_at, expired_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
 }
 
 /// Generate a secure random API token
@@ -112,7 +145,12 @@ pub fn hash_token(token: &str) -> String {
 /// API token storage and management
 pub struct ApiTokenStore {
     tokens: Arc<RwLock<HashMap<String, ApiToken>>>,
-    storage_path: PathBuf,
+    /// None for an in-memory store, tokens are never saved
+    storage_path: Option<PathBuf>,
+    /// Never write the token file, reload it when another instance changes it
+    read_only: bool,
+    /// Modification time of the token file when it was last loaded
+    loaded_modified: Mutex<Option<SystemTime>>,
 }
 
 impl ApiTokenStore {
@@ -125,13 +163,44 @@ impl ApiTokenStore {
             fs::create_dir_all(parent)?;
         }
 
-        let mut store = Self {
+        // Load existing tokens if file exists
+        let load = storage_path.exists();
+        let store = Self {
             tokens: Arc::new(RwLock::new(HashMap::new())),
-            storage_path,
+            storage_path: Some(storage_path),
+            read_only: false,
+            loaded_modified: Mutex::new(None),
         };
+        if load {
+            store.load()?;
+        }
 
-        // Load existing tokens if file exists
-        if store.storage_path.exists() {
+        Ok(store)
+    }
+
+    /// Create an empty token store that is never written to disk
+    /// Tokens last until the process exits
+    pub fn in_memory() -> Self {
+        Self {
+            tokens: Arc::new(RwLock::new(HashMap::new())),
+            storage_path: None,
+            read_only: false,
+            loaded_modified: Mutex::new(None),
+        }
+    }
+
+    /// Open a token file owned by another instance (read-only replica mode)
+    /// Tokens can be validated but not created or revoked, and `last_used_at`
+    /// isn't recorded; the file is reloaded whenever it changes on disk
+    pub fn open_read_only<P: AsRef<Path>>(storage_path: P) -> Result<Self> {
+        let load = storage_path.as_ref().exists();
+        let store = Self {
+            tokens: Arc::new(RwLock::new(HashMap::new())),
+            storage_path: Some(storage_path.as_ref().to_path_buf()),
+            read_only: true,
+            loaded_modified: Mutex::new(None),
+        };
+        if load {
             store.load()?;
         }
 
@@ -139,22 +208,56 @@ impl ApiTokenStore {
     }
 
     /// Load tokens from disk
-    fn load(&mut self) -> Result<()> {
-        let data = fs::read_to_string(&self.storage_path)?;
+    fn load(&self) -> Result<()> {
+        let storage_path = match &self.storage_path {
+            Some(storage_path) => storage_path,
+            None => return Ok(()),
+        };
+        let modified = fs::metadata(storage_path).and_then(|m| m.modified()).ok();
+        let data = fs::read_to_string(storage_path)?;
         let tokens: HashMap<String, ApiToken> = serde_json::from_str(&data)?;
 
         let mut token_map = self.tokens.write();
         *token_map = tokens;
+        *self.loaded_modified.lock() = modified;
 
         Ok(())
     }
 
+    /// Reload the token file if it changed since it was last loaded
+    /// A file that can't be parsed (e.g. caught mid-write) keeps the previous tokens
+    fn reload_if_changed(&self) {
+        let modified = self
+            .storage_path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).and_then(|m| m.modified()).ok());
+        if modified.is_none() || modified == *self.loaded_modified.lock() {
+            return;
+        }
+
+        if let Err(e) = self.load() {
+            warn!("Failed to reload API tokens, keeping the previous set: {}", e);
+        }
+    }
+
     /// Save tokens to disk
     fn save(&self) -> Result<()> {
+        if self.read_only {
+            return Err(LoraDbError::ReadOnly(
+                "API tokens can only be changed on the primary".to_string(),
+            )
+            .into());
+        }
+
+        let storage_path = match &self.storage_path {
+            Some(storage_path) => storage_path,
+            None => return Ok(()),
+        };
+
         let token_map = self.tokens.read();
 
         let data = serde_json::to_string_pretty(&*token_map)?;
-        fs::write(&self.storage_path, data)?;
+        fs::write(storage_path, data)?;
 
         Ok(())
     }
@@ -191,6 +294,11 @@ impl ApiTokenStore {
 
     /// Validate a token and update last used time
     pub fn validate_token(&self, token: &str) -> Result<ApiToken> {
+        // Pick up tokens created or revoked on the primary
+        if self.read_only {
+            self.reload_if_changed();
+        }
+
         let token_hash = hash_token(token);
 
         let mut token_map = self.tokens.write();
@@ -208,6 +316,11 @@ impl ApiTokenStore {
             }
         }
 
+        // A read-only store can't persist last used time, so leave it untouched
+        if self.read_only {
+            return Ok(api_token.clone());
+        }
+
         // Update last used time
         api_token.update_last_used();
         let result = api_token.clone();
@@ -263,6 +376,64 @@ impl ApiTokenStore {
         Ok(())
     }
 
+    /// Restrict which frame fields a token can see (None removes the restriction)
+    pub fn set_allowed_fields(
+        &self,
+        token_id: &str,
+        user_id: &str,
+        allowed_fields: Option<Vec<String>>,
+    ) -> Result<ApiToken> {
+        let mut token_map = self.tokens.write();
+
+        let token = token_map
+            .values_mut()
+            .find(|t| t.id == token_id)
+            .ok_or_else(|| LoraDbError::AuthError("Token not found".to_string()))?;
+
+        // Check ownership
+        if token.created_by != user_id {
+            return Err(LoraDbError::AuthError("Unauthorized to modify this token".to_string()).into());
+        }
+
+        token.allowed_fields = allowed_fields;
+        let result = token.clone();
+        drop(token_map);
+
+        // Persist changes
+        self.save()?;
+
+        Ok(result)
+    }
+
+    /// Confine a token to one tenant's applications (None removes the restriction)
+    pub fn set_tenant(
+        &self,
+        token_id: &str,
+        user_id: &str,
+        tenant_id: Option<String>,
+    ) -> Result<ApiToken> {
+        let mut token_map = self.tokens.write();
+
+        let token = token_map
+            .values_mut()
+            .find(|t| t.id == token_id)
+            .ok_or_else(|| LoraDbError::AuthError("Token not found".to_string()))?;
+
+        // Check ownership
+        if token.created_by != user_id {
+            return Err(LoraDbError::AuthError("Unauthorized to modify this token".to_string()).into());
+        }
+
+        token.tenant_id = tenant_id;
+        let result = token.clone();
+        drop(token_map);
+
+        // Persist changes
+        self.save()?;
+
+        Ok(result)
+    }
+
     /// Delete a token by ID (admin only)
     pub fn delete_token(&self, token_id: &str) -> Result<()> {
         let mut token_map = self.tokens.write();
@@ -293,6 +464,46 @@ impl ApiTokenStore {
 
         Ok(removed_count)
     }
+
+    /// Remove tokens that have been expired or revoked for at least `older_than`
+    pub fn cleanup_inactive(&self, older_than: Duration) -> Result<usize> {
+        let cutoff = Utc::now() - older_than;
+        let mut token_map = self.tokens.write();
+
+        let initial_count = token_map.len();
+        token_map.retain(|_, t| t.inactive_since().map_or(true, |since| since > cutoff));
+        let removed_count = initial_count - token_map.len();
+
+        drop(token_map);
+
+        if removed_count > 0 {
+            self.save()?;
+        }
+
+        Ok(removed_count)
+    }
+
+    /// Periodically remove tokens expired or revoked for longer than `retention`,
+    /// so the token file doesn't grow without bound
+    pub fn start_cleanup_task(
+        self: Arc<Self>,
+        interval: std::time::Duration,
+        retention: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+                match self.cleanup_inactive(retention) {
+                    Ok(0) => {}
+                    Ok(removed) => info!("Removed {} expired or revoked API token(s)", removed),
+                    Err(e) => warn!("API token cleanup failed: {}", e),
+                }
+            }
+        })
+    }
 }
 
 #[cfg(test)]
@@ -380,6 +591,17 @@ mod tests {
         assert!(validated.last_used_at.is_some());
     }
 
+    #[test]
+    fn test_token_store_in_memory() {
+        let store = ApiTokenStore::in_memory();
+
+        let (token, api_token) = store
+            .create_token("Test Token".to_string(), "user123".to_string(), None)
+            .unwrap();
+        assert_eq!(store.validate_token(&token).unwrap().id, api_token.id);
+        assert_eq!(store.list_tokens("user123").unwrap().len(), 1);
+    }
+
     #[test]
     fn test_token_store_persistence() {
         let temp_dir = TempDir::new().unwrap();
@@ -452,6 +674,77 @@ mod tests {
         assert!(store.validate_token(&token).is_err());
     }
 
+    #[test]
+    fn test_token_store_read_only_follows_primary() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_path = temp_dir.path().join("tokens.json");
+        let primary = ApiTokenStore::new(&storage_path).unwrap();
+
+        let (token, api_token) = primary
+            .create_token("Test Token".to_string(), "user123".to_string(), None)
+            .unwrap();
+
+        let replica = ApiTokenStore::open_read_only(&storage_path).unwrap();
+        let validated = replica.validate_token(&token).unwrap();
+        assert!(validated.last_used_at.is_none());
+        assert!(replica
+            .create_token("Other".to_string(), "user123".to_string(), None)
+            .is_err());
+
+        // Revocation on the primary is picked up without a restart
+        primary.revoke_token(&api_token.id, "user123").unwrap();
+        assert!(replica.validate_token(&token).is_err());
+    }
+
+    #[test]
+    fn test_token_store_allowed_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_path = temp_dir.path().join("tokens.json");
+        let store = ApiTokenStore::new(&storage_path).unwrap();
+
+        let (token, api_token) = store
+            .create_token("Partner Token".to_string(), "user123".to_string(), None)
+            .unwrap();
+        assert!(api_token.allowed_fields.is_none());
+
+        let fields = vec!["dev_eui".to_string(), "decoded_payload.object.temperature".to_string()];
+
+        // Only the owner can restrict the token
+        assert!(store
+            .set_allowed_fields(&api_token.id, "other-user", Some(fields.clone()))
+            .is_err());
+        store
+            .set_allowed_fields(&api_token.id, "user123", Some(fields.clone()))
+            .unwrap();
+
+        let validated = store.validate_token(&token).unwrap();
+        assert_eq!(validated.allowed_fields, Some(fields));
+    }
+
+    #[test]
+    fn test_token_store_tenant() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_path = temp_dir.path().join("tokens.json");
+        let store = ApiTokenStore::new(&storage_path).unwrap();
+
+        let (token, api_token) = store
+            .create_token("Tenant Token".to_string(), "user123".to_string(), None)
+            .unwrap();
+        assert!(api_token.tenant_id.is_none());
+
+        assert!(store
+            .set_tenant(&api_token.id, "other-user", Some("acme".to_string()))
+            .is_err());
+        store
+            .set_tenant(&api_token.id, "user123", Some("acme".to_string()))
+            .unwrap();
+
+        // The tenant survives a reload
+        let reloaded = ApiTokenStore::new(&storage_path).unwrap();
+        let validated = reloaded.validate_token(&token).unwrap();
+        assert_eq!(validated.tenant_id.as_deref(), Some("acme"));
+    }
+
     #[test]
     fn test_token_store_cleanup_expired() {
         let temp_dir = TempDir::new().unwrap();
@@ -476,4 +769,32 @@ mod tests {
         assert_eq!(remaining.len(), 1);
         assert_eq!(remaining[0].name, "Valid Token");
     }
+
+    #[test]
+    fn test_token_store_cleanup_inactive() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_path = temp_dir.path().join("tokens.json");
+        let store = ApiTokenStore::new(&storage_path).unwrap();
+
+        let (_, expired) = store
+            .create_token("Expired Token".to_string(), "user1".to_string(), Some(-1))
+            .unwrap();
+        let (_, revoked) = store
+            .create_token("Revoked Token".to_string(), "user1".to_string(), None)
+            .unwrap();
+        store.revoke_token(&revoked.id, "user1").unwrap();
+        store
+            .create_token("Valid Token".to_string(), "user1".to_string(), Some(30))
+            .unwrap();
+
+        // Expired a day ago, revoked just now
+        assert!(expired.inactive_since().unwrap() <= Utc::now() - Duration::hours(23));
+        assert_eq!(store.cleanup_inactive(Duration::hours(12)).unwrap(), 1);
+        assert_eq!(store.list_all_tokens().unwrap().len(), 2);
+
+        assert_eq!(store.cleanup_inactive(Duration::zero()).unwrap(), 1);
+        let remaining = store.list_all_tokens().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].name, "Valid Token");
+    }
 }
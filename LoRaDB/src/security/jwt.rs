@@ -1,6 +1,7 @@
 use crate::error::LoraDbError;
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 
@@ -16,6 +17,9 @@ pub struct Claims {
     /// Optional custom claims
     #[serde(default)]
     pub role: Option<String>,
+    /// Tenant the holder is confined to (None = every tenant)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
 }
 
 impl Claims {
@@ -34,6 +38,7 @@ impl Claims {
             exp: exp.timestamp(),
             iat: now.timestamp(),
             role: None,
+            tenant: None,
         }
     }
 
@@ -44,6 +49,7 @@ impl Claims {
             exp: expiration.timestamp(),
             iat: Utc::now().timestamp(),
             role: None,
+            tenant: None,
         }
     }
 
@@ -75,6 +81,8 @@ impl Claims {
 pub struct JwtService {
     encoding_key: EncodingKey,
     decoding_key: DecodingKey,
+    /// Retired secrets still accepted for validation during key rotation
+    verification_keys: Vec<DecodingKey>,
     validation: Validation,
 }
 
@@ -100,10 +108,27 @@ impl JwtService {
         Ok(Self {
             encoding_key,
             decoding_key,
+            verification_keys: Vec::new(),
             validation,
         })
     }
 
+    /// Also accept tokens signed with these secrets, without signing new tokens with them
+    /// Lets a secret be rotated without invalidating tokens issued under the old one
+    pub fn with_verification_secrets(mut self, secrets: &[String]) -> Result<Self> {
+        for secret in secrets {
+            if secret.len() < 32 {
+                return Err(LoraDbError::AuthError(
+                    "JWT verification secrets must be at least 32 characters".to_string(),
+                )
+                .into());
+            }
+            self.verification_keys
+                .push(DecodingKey::from_secret(secret.as_bytes()));
+        }
+        Ok(self)
+    }
+
     /// Create JWT service from base64-encoded secret
     pub fn from_base64_secret(encoded_secret: &str) -> Result<Self> {
         use base64::Engine;
@@ -128,6 +153,7 @@ impl JwtService {
         Ok(Self {
             encoding_key,
             decoding_key,
+            verification_keys: Vec::new(),
             validation,
         })
     }
@@ -139,8 +165,22 @@ impl JwtService {
     }
 
     /// Validate and decode a JWT token
+    /// Tries the primary secret, then each verification secret in turn
     pub fn validate_token(&self, token: &str) -> Result<Claims> {
-        let token_data = decode::<Claims>(token, &self.decoding_key, &self.validation)
+        let mut result = decode::<Claims>(token, &self.decoding_key, &self.validation);
+
+        // Fall back to verification secrets only when the signature didn't match,
+        // an expired or malformed token is invalid whichever secret signed it
+        for key in &self.verification_keys {
+            match &result {
+                Err(e) if matches!(e.kind(), ErrorKind::InvalidSignature) => {
+                    result = decode::<Claims>(token, key, &self.validation);
+                }
+                _ => break,
+            }
+        }
+
+        let token_data = result
             .map_err(|e| LoraDbError::AuthError(format!("Token validation failed: {}", e)))?;
 
         Ok(token_data.claims)
@@ -230,6 +270,40 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_jwt_key_rotation() {
+        let old_secret = "secret-key-one-for-testing-purposes";
+        let new_secret = "secret-key-two-for-testing-purposes";
+
+        let old_service = JwtService::new(old_secret).unwrap();
+        let old_token = old_service.generate_token(Claims::new("user123".to_string())).unwrap();
+
+        // New primary secret with the old one kept for verification
+        let service = JwtService::new(new_secret)
+            .unwrap()
+            .with_verification_secrets(&[old_secret.to_string()])
+            .unwrap();
+        assert_eq!(service.validate_token(&old_token).unwrap().sub, "user123");
+
+        // New tokens are signed with the primary only
+        let new_token = service.generate_token(Claims::new("user456".to_string())).unwrap();
+        assert!(old_service.validate_token(&new_token).is_err());
+        assert_eq!(service.validate_token(&new_token).unwrap().sub, "user456");
+
+        // Expired tokens stay invalid even when signed with a verification secret
+        let past = Utc::now() - Duration::hours(1);
+        let expired = old_service
+            .generate_token(Claims::with_expiration("user123".to_string(), past))
+            .unwrap();
+        assert!(service.validate_token(&expired).is_err());
+
+        // Verification secrets have the same minimum length as the primary
+        assert!(JwtService::new(new_secret)
+            .unwrap()
+            .with_verification_secrets(&["short".to_string()])
+            .is_err());
+    }
+
     #[test]
     fn test_jwt_expired_token() {
         let service = JwtService::new("this-is-a-very-secure-secret-key-for-testing").unwrap();
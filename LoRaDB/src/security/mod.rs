@@ -2,3 +2,4 @@ pub mod tls;
 pub mod jwt;
 pub mod encryption;
 pub mod api_token;
+pub mod tenant;
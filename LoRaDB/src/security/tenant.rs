@@ -0,0 +1,67 @@
+use crate::model::lorawan::ApplicationId;
+use std::collections::HashMap;
+
+/// Which tenant each application belongs to, for sharing one LoRaDB between tenants
+///
+/// Principals scoped to a tenant (API tokens created for it, JWTs with a `tenant`
+/// claim) only see that tenant's applications; applications without a tenant are
+/// visible to unscoped principals only
+#[derive(Debug, Clone, Default)]
+pub struct TenantMap {
+    /// Key: normalized application ID
+    applications: HashMap<String, String>,
+}
+
+impl TenantMap {
+    /// Build the map from application ID to tenant ID pairs
+    pub fn new(applications: HashMap<String, String>) -> Self {
+        Self {
+            applications: applications
+                .into_iter()
+                .map(|(app_id, tenant)| (ApplicationId::normalize(&app_id), tenant))
+                .collect(),
+        }
+    }
+
+    /// Tenant an application belongs to, None if it isn't assigned to one
+    pub fn tenant_of(&self, app_id: &str) -> Option<&str> {
+        self.applications
+            .get(&ApplicationId::normalize(app_id))
+            .map(String::as_str)
+    }
+
+    /// Whether a principal scoped to `tenant` (None = unscoped) may access `app_id`
+    pub fn allows(&self, tenant: Option<&str>, app_id: &str) -> bool {
+        match tenant {
+            None => true,
+            Some(tenant) => self.tenant_of(app_id) == Some(tenant),
+        }
+    }
+
+    /// Whether any application is assigned to `tenant`
+    pub fn has_tenant(&self, tenant: &str) -> bool {
+        self.applications.values().any(|t| t == tenant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_map() {
+        let tenants = TenantMap::new(HashMap::from([
+            ("App-1".to_string(), "acme".to_string()),
+            ("app-2".to_string(), "globex".to_string()),
+        ]));
+
+        assert_eq!(tenants.tenant_of("app-1"), Some("acme"));
+        assert!(tenants.allows(Some("acme"), " APP-1 "));
+        assert!(!tenants.allows(Some("acme"), "app-2"));
+        // Unassigned applications are only visible to unscoped principals
+        assert!(!tenants.allows(Some("acme"), "app-3"));
+        assert!(tenants.allows(None, "app-3"));
+        assert!(tenants.has_tenant("globex"));
+        assert!(!tenants.has_tenant("initech"));
+    }
+}
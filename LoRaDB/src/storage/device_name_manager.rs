@@ -0,0 +1,116 @@
+use crate::model::lorawan::DevEui;
+use anyhow::Result;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// User-assigned device names with JSON persistence
+///
+/// The device registry is rebuilt from stored frames on startup, which only
+/// carry the network server's name, so the overrides are kept here and applied
+/// to the registry again after each rebuild.
+pub struct DeviceNameManager {
+    names: RwLock<HashMap<String, String>>, // Key: normalized DevEUI
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
+}
+
+impl DeviceNameManager {
+    /// Create a new device name manager, loading `device_names.json` if present
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let file_path = data_dir.join("device_names.json");
+
+        let names = if file_path.exists() {
+            match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => match serde_json::from_str::<HashMap<String, String>>(&content) {
+                    Ok(names) => {
+                        info!("Loaded {} device name override(s)", names.len());
+                        names
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse device names, using network server names: {}", e);
+                        HashMap::new()
+                    }
+                },
+                Err(e) => {
+                    warn!("Failed to read device names file, using network server names: {}", e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            names: RwLock::new(names),
+            file_path: Some(file_path),
+        })
+    }
+
+    /// Create an empty DeviceNameManager that keeps its state in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            names: RwLock::new(HashMap::new()),
+            file_path: None,
+        }
+    }
+
+    /// All name overrides, keyed by normalized DevEUI
+    pub fn list(&self) -> HashMap<String, String> {
+        self.names.read().clone()
+    }
+
+    /// Set the name shown for a device instead of the network server's
+    pub async fn set(&self, dev_eui: &DevEui, name: &str) -> Result<()> {
+        self.names
+            .write()
+            .insert(dev_eui.normalized(), name.to_string());
+        self.save().await?;
+        info!("Device {} renamed to '{}'", dev_eui.as_str(), name);
+        Ok(())
+    }
+
+    /// Go back to the network server's name, returns false if the device had no override
+    pub async fn remove(&self, dev_eui: &DevEui) -> Result<bool> {
+        let removed = self.names.write().remove(&dev_eui.normalized()).is_some();
+        if removed {
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    /// Save name overrides to disk
+    async fn save(&self) -> Result<()> {
+        // The read guard is dropped before awaiting
+        let save = super::persist_json(self.file_path.as_deref(), &*self.names.read());
+        save.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_names_persist() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+
+        {
+            let manager = DeviceNameManager::new(temp_dir.path()).await.unwrap();
+            manager.set(&dev_eui, "Boiler room").await.unwrap();
+        }
+
+        let manager = DeviceNameManager::new(temp_dir.path()).await.unwrap();
+        assert_eq!(
+            manager.list().get(&dev_eui.normalized()).map(String::as_str),
+            Some("Boiler room")
+        );
+
+        assert!(manager.remove(&dev_eui).await.unwrap());
+        assert!(!manager.remove(&dev_eui).await.unwrap());
+        assert!(manager.list().is_empty());
+    }
+}
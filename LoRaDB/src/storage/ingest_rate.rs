@@ -0,0 +1,152 @@
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Seconds of per-second counts kept, enough for the 15 minute rate
+const RATE_WINDOW_SECS: usize = 15 * 60;
+
+/// Tracks how many frames are stored per second over the last 15 minutes
+pub struct IngestRateTracker {
+    inner: Mutex<RateState>,
+}
+
+struct RateState {
+    /// Ring buffer of frame counts, indexed by unix second modulo its length
+    counts: Vec<u64>,
+    /// Unix second of the newest slot, older slots are cleared as time advances
+    head_secs: i64,
+    started_secs: i64,
+    total: u64,
+}
+
+/// Point-in-time view of ingest throughput, rates in frames per second
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestRateSnapshot {
+    /// Frames stored since startup
+    pub total: u64,
+    /// Frames stored in the last complete second
+    pub last_second: u64,
+    pub rate_1m: f64,
+    pub rate_5m: f64,
+    pub rate_15m: f64,
+}
+
+impl RateState {
+    /// Move the head to `now_secs`, zeroing the seconds skipped over
+    fn advance(&mut self, now_secs: i64) {
+        if now_secs <= self.head_secs {
+            return;
+        }
+        let skipped = (now_secs - self.head_secs).min(RATE_WINDOW_SECS as i64);
+        for secs in (now_secs - skipped + 1)..=now_secs {
+            self.counts[slot(secs)] = 0;
+        }
+        self.head_secs = now_secs;
+    }
+
+    /// Average per second over the `window_secs` complete seconds before the head,
+    /// or over the time since startup if that is shorter
+    fn rate(&self, window_secs: i64) -> f64 {
+        let window = window_secs.min(self.head_secs - self.started_secs).max(1);
+        let sum: u64 = (1..=window)
+            .map(|ago| self.counts[slot(self.head_secs - ago)])
+            .sum();
+        sum as f64 / window as f64
+    }
+}
+
+fn slot(secs: i64) -> usize {
+    secs.rem_euclid(RATE_WINDOW_SECS as i64) as usize
+}
+
+impl IngestRateTracker {
+    pub fn new() -> Self {
+        Self::starting_at(Utc::now().timestamp())
+    }
+
+    fn starting_at(now_secs: i64) -> Self {
+        Self {
+            inner: Mutex::new(RateState {
+                counts: vec![0; RATE_WINDOW_SECS],
+                head_secs: now_secs,
+                started_secs: now_secs,
+                total: 0,
+            }),
+        }
+    }
+
+    /// Record one stored frame
+    pub fn record(&self) {
+        self.record_at(Utc::now().timestamp());
+    }
+
+    fn record_at(&self, now_secs: i64) {
+        let mut state = self.inner.lock();
+        state.advance(now_secs);
+        // A clock step backwards counts towards the newest second
+        let head = slot(state.head_secs);
+        state.counts[head] += 1;
+        state.total += 1;
+    }
+
+    /// Current throughput
+    pub fn snapshot(&self) -> IngestRateSnapshot {
+        self.snapshot_at(Utc::now().timestamp())
+    }
+
+    fn snapshot_at(&self, now_secs: i64) -> IngestRateSnapshot {
+        let mut state = self.inner.lock();
+        state.advance(now_secs);
+
+        IngestRateSnapshot {
+            total: state.total,
+            last_second: state.counts[slot(state.head_secs - 1)],
+            rate_1m: state.rate(60),
+            rate_5m: state.rate(5 * 60),
+            rate_15m: state.rate(15 * 60),
+        }
+    }
+}
+
+impl Default for IngestRateTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ingest_rate_windows() {
+        let start = 1_700_000_000;
+        let tracker = IngestRateTracker::starting_at(start);
+
+        // 10 frames per second for the first two minutes
+        for secs in start..start + 120 {
+            for _ in 0..10 {
+                tracker.record_at(secs);
+            }
+        }
+
+        let snapshot = tracker.snapshot_at(start + 120);
+        assert_eq!(snapshot.total, 1_200);
+        assert_eq!(snapshot.last_second, 10);
+        assert_eq!(snapshot.rate_1m, 10.0);
+        // Windows longer than the uptime average over the uptime
+        assert_eq!(snapshot.rate_5m, 10.0);
+        assert_eq!(snapshot.rate_15m, 10.0);
+
+        // Three idle minutes later the short windows drop to zero
+        let snapshot = tracker.snapshot_at(start + 300);
+        assert_eq!(snapshot.last_second, 0);
+        assert_eq!(snapshot.rate_1m, 0.0);
+        assert_eq!(snapshot.rate_5m, 4.0);
+
+        // After the 15 minute window has passed everything has aged out
+        let snapshot = tracker.snapshot_at(start + 3_600);
+        assert_eq!(snapshot.total, 1_200);
+        assert_eq!(snapshot.rate_15m, 0.0);
+    }
+}
@@ -0,0 +1,147 @@
+use chrono::Duration;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Upper bounds (inclusive, milliseconds) of the ingestion lag histogram buckets
+/// A final unbounded bucket catches everything slower
+const LAG_BUCKET_BOUNDS_MS: [u64; 7] = [100, 1_000, 5_000, 30_000, 60_000, 600_000, 3_600_000];
+
+/// Number of recent samples kept for percentile calculation
+const LAG_WINDOW_SIZE: usize = 1024;
+
+/// Tracks the gap between a frame's network timestamp and when it was stored
+pub struct IngestionLagTracker {
+    inner: Mutex<LagState>,
+}
+
+struct LagState {
+    /// Counts per bucket since startup, one extra for the unbounded bucket
+    buckets: [u64; LAG_BUCKET_BOUNDS_MS.len() + 1],
+    samples: u64,
+    sum_ms: u64,
+    max_ms: u64,
+    /// Most recent samples, oldest first
+    window: VecDeque<u64>,
+}
+
+/// Point-in-time view of ingestion lag
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestionLagSnapshot {
+    /// Frames recorded since startup
+    pub samples: u64,
+    pub mean_ms: u64,
+    pub max_ms: u64,
+    /// Percentiles over the most recent frames
+    pub recent_p50_ms: u64,
+    pub recent_p95_ms: u64,
+    pub recent_p99_ms: u64,
+    pub buckets: Vec<LagBucket>,
+}
+
+/// Histogram bucket: frames whose lag was at most `le_ms` (`None` = unbounded)
+#[derive(Debug, Clone, Serialize)]
+pub struct LagBucket {
+    pub le_ms: Option<u64>,
+    pub count: u64,
+}
+
+impl IngestionLagTracker {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(LagState {
+                buckets: [0; LAG_BUCKET_BOUNDS_MS.len() + 1],
+                samples: 0,
+                sum_ms: 0,
+                max_ms: 0,
+                window: VecDeque::with_capacity(LAG_WINDOW_SIZE),
+            }),
+        }
+    }
+
+    /// Record one frame's lag, negative lag (timestamp in the future) counts as zero
+    pub fn record(&self, lag: Duration) {
+        let lag_ms = lag.num_milliseconds().max(0) as u64;
+        let bucket = LAG_BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| lag_ms <= bound)
+            .unwrap_or(LAG_BUCKET_BOUNDS_MS.len());
+
+        let mut state = self.inner.lock();
+        state.buckets[bucket] += 1;
+        state.samples += 1;
+        state.sum_ms = state.sum_ms.saturating_add(lag_ms);
+        state.max_ms = state.max_ms.max(lag_ms);
+
+        if state.window.len() == LAG_WINDOW_SIZE {
+            state.window.pop_front();
+        }
+        state.window.push_back(lag_ms);
+    }
+
+    /// Current histogram and percentiles
+    pub fn snapshot(&self) -> IngestionLagSnapshot {
+        let state = self.inner.lock();
+
+        let mut recent: Vec<u64> = state.window.iter().copied().collect();
+        recent.sort_unstable();
+        let percentile = |p: usize| -> u64 {
+            if recent.is_empty() {
+                0
+            } else {
+                recent[((recent.len() - 1) * p) / 100]
+            }
+        };
+
+        let buckets = state
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| LagBucket {
+                le_ms: LAG_BUCKET_BOUNDS_MS.get(i).copied(),
+                count,
+            })
+            .collect();
+
+        IngestionLagSnapshot {
+            samples: state.samples,
+            mean_ms: state.sum_ms.checked_div(state.samples).unwrap_or(0),
+            max_ms: state.max_ms,
+            recent_p50_ms: percentile(50),
+            recent_p95_ms: percentile(95),
+            recent_p99_ms: percentile(99),
+            buckets,
+        }
+    }
+}
+
+impl Default for IngestionLagTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lag_histogram() {
+        let tracker = IngestionLagTracker::new();
+
+        tracker.record(Duration::milliseconds(50));
+        tracker.record(Duration::milliseconds(2_000));
+        tracker.record(Duration::hours(2));
+        tracker.record(Duration::milliseconds(-500));
+
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.samples, 4);
+        assert_eq!(snapshot.max_ms, 7_200_000);
+        assert_eq!(snapshot.buckets[0].count, 2); // 50ms and the clamped future frame
+        assert_eq!(snapshot.buckets[2].count, 1);
+        assert_eq!(snapshot.buckets.last().unwrap().le_ms, None);
+        assert_eq!(snapshot.buckets.last().unwrap().count, 1);
+        assert_eq!(snapshot.recent_p50_ms, 50);
+        assert_eq!(snapshot.recent_p99_ms, 2_000);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::model::frames::Frame;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use parking_lot::RwLock;
+use tracing::{info, warn};
+
+/// Per-application ingestion kill-switch with JSON persistence
+pub struct IngestionManager {
+    disabled: Arc<RwLock<HashMap<String, DisabledApplication>>>,
+    dropped: Arc<DashMap<String, u64>>,
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
+}
+
+/// An application whose frames are currently being dropped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisabledApplication {
+    /// When ingestion was disabled
+    pub disabled_at: DateTime<Utc>,
+}
+
+impl IngestionManager {
+    /// Create a new ingestion manager, loading `ingestion.json` if present
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let file_path = data_dir.join("ingestion.json");
+
+        let disabled = if file_path.exists() {
+            match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => match serde_json::from_str::<HashMap<String, DisabledApplication>>(&content) {
+                    Ok(disabled) => {
+                        if !disabled.is_empty() {
+                            warn!("Ingestion is disabled for {} application(s)", disabled.len());
+                        }
+                        disabled
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse ingestion settings, all applications enabled: {}", e);
+                        HashMap::new()
+                    }
+                },
+                Err(e) => {
+                    warn!("Failed to read ingestion settings file, all applications enabled: {}", e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            disabled: Arc::new(RwLock::new(disabled)),
+            dropped: Arc::new(DashMap::new()),
+            file_path: Some(file_path),
+        })
+    }
+
+    /// Create an empty IngestionManager that keeps its state in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            disabled: Arc::new(RwLock::new(HashMap::new())),
+            dropped: Arc::new(DashMap::new()),
+            file_path: None,
+        }
+    }
+
+    /// Whether frames from an application are currently accepted
+    pub fn is_enabled(&self, app_id: &str) -> bool {
+        !self.disabled.read().contains_key(app_id)
+    }
+
+    /// When ingestion was disabled for an application, if it is
+    pub fn disabled_since(&self, app_id: &str) -> Option<DateTime<Utc>> {
+        self.disabled.read().get(app_id).map(|d| d.disabled_at)
+    }
+
+    /// Enable or disable ingestion for an application
+    pub async fn set_enabled(&self, app_id: &str, enabled: bool) -> Result<()> {
+        let changed = {
+            let mut disabled = self.disabled.write();
+            if enabled {
+                disabled.remove(app_id).is_some()
+            } else if disabled.contains_key(app_id) {
+                false
+            } else {
+                disabled.insert(
+                    app_id.to_string(),
+                    DisabledApplication {
+                        disabled_at: Utc::now(),
+                    },
+                );
+                true
+            }
+        };
+
+        if changed {
+            self.save().await?;
+            info!(
+                "Ingestion {} for application '{}'",
+                if enabled { "enabled" } else { "disabled" },
+                app_id
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Number of frames dropped for an application since startup
+    pub fn dropped_count(&self, app_id: &str) -> u64 {
+        self.dropped.get(app_id).map(|v| *v).unwrap_or(0)
+    }
+
+    /// Check whether a frame should be stored, counting it as dropped if not
+    /// Frames without an application ID are always accepted
+    pub fn admit(&self, frame: &Frame) -> bool {
+        let app_id = match frame.application_id() {
+            Some(id) => id.as_str(),
+            None => return true,
+        };
+
+        if self.is_enabled(app_id) {
+            return true;
+        }
+
+        *self.dropped.entry(app_id.to_string()).or_insert(0) += 1;
+        false
+    }
+
+    /// Save ingestion settings to disk
+    async fn save(&self) -> Result<()> {
+        // The read guard is dropped before awaiting
+        let save = super::persist_json(self.file_path.as_deref(), &*self.disabled.read());
+        save.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::frames::UplinkFrame;
+    use crate::model::lorawan::*;
+    use tempfile::TempDir;
+
+    fn create_test_uplink(app_id: &str) -> Frame {
+        Frame::Uplink(UplinkFrame {
+            dev_eui: DevEui::new("0123456789ABCDEF".to_string()).unwrap(),
+            application_id: ApplicationId::new(app_id.to_string()),
+            device_name: None,
+            received_at: Utc::now(),
+            stored_at: None,
+            f_port: 1,
+            f_cnt: 1,
+            confirmed: false,
+            adr: true,
+            dr: DataRate::new_lora(125000, 7),
+            frequency: 868100000,
+            rx_info: vec![],
+            decoded_payload: None,
+            raw_payload: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_disabled_application_is_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IngestionManager::new(temp_dir.path()).await.unwrap();
+
+        assert!(manager.admit(&create_test_uplink("noisy")));
+
+        manager.set_enabled("noisy", false).await.unwrap();
+        assert!(!manager.admit(&create_test_uplink("noisy")));
+        assert!(!manager.admit(&create_test_uplink("noisy")));
+        assert!(manager.admit(&create_test_uplink("quiet")));
+        assert_eq!(manager.dropped_count("noisy"), 2);
+
+        manager.set_enabled("noisy", true).await.unwrap();
+        assert!(manager.admit(&create_test_uplink("noisy")));
+    }
+
+    #[tokio::test]
+    async fn test_ingestion_persistence() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let manager = IngestionManager::new(temp_dir.path()).await.unwrap();
+            manager.set_enabled("noisy", false).await.unwrap();
+        }
+
+        let manager = IngestionManager::new(temp_dir.path()).await.unwrap();
+        assert!(!manager.is_enabled("noisy"));
+        assert!(manager.disabled_since("noisy").is_some());
+    }
+}
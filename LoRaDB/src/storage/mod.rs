@@ -1,33 +1,238 @@
-use crate::config::StorageConfig;
+use crate::config::{
+    ApplicationKey, ClockSkewAction, OversizedPayloadAction, StorageConfig, TimestampSource,
+    UnknownApplicationAction, WalDurability,
+};
 use crate::engine::compaction::CompactionManager;
-use crate::engine::memtable::Memtable;
-use crate::engine::sstable::{SSTableReader, SSTableWriter};
-use crate::engine::wal::WriteAheadLog;
+use crate::engine::memtable::{Memtable, MemtableKey};
+use crate::engine::sstable::{SSTableCache, SSTableReader, SSTableWriter};
+use crate::engine::wal::{ReplayReport, WriteAheadLog};
 use crate::error::LoraDbError;
+use crate::model::decoded::DecodedPayload;
 use crate::model::device::DeviceRegistry;
-use crate::model::frames::Frame;
-use crate::model::lorawan::DevEui;
+use crate::model::frames::{Frame, UplinkFrame};
+use crate::model::gateway::GatewayRegistry;
+use crate::model::lorawan::{ApplicationId, DataRate, DevEui};
+use crate::util::clock::{check_skew, TimestampSkew};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use std::path::PathBuf;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Notify};
 use parking_lot::RwLock;
-use tracing::{debug, info, warn};
-
+use tracing::{debug, error, info, warn};
+
+pub mod device_name_manager;
+pub mod ingest_rate;
+pub mod ingestion_lag;
+pub mod ingestion_manager;
+pub mod quota_manager;
+pub mod payload_profile_manager;
+pub mod sampling_manager;
 pub mod retention_manager;
-
+pub mod scan;
+pub mod schema_manager;
+pub mod tombstone_manager;
+pub mod transform_manager;
+
+use device_name_manager::DeviceNameManager;
+use ingest_rate::IngestRateTracker;
+use ingestion_lag::IngestionLagTracker;
+use ingestion_manager::IngestionManager;
+use quota_manager::QuotaManager;
+use payload_profile_manager::PayloadProfileManager;
+use sampling_manager::SamplingManager;
 use retention_manager::RetentionPolicyManager;
+use scan::FrameScan;
+use schema_manager::{SchemaCheck, SchemaManager};
+use tombstone_manager::{DeviceTombstone, TombstoneManager};
+use transform_manager::TransformManager;
+
+/// Write a manager's settings to `path` as pretty JSON with owner-only permissions,
+/// a no-op for in-memory managers (`path` None)
+/// `value` is serialized before the future is returned, so a lock guard borrowed for it
+/// must be dropped before awaiting: `let save = persist_json(..); save.await`
+pub(crate) fn persist_json<'a, T: Serialize + ?Sized>(
+    path: Option<&'a Path>,
+    value: &T,
+) -> impl std::future::Future<Output = Result<()>> + Send + 'a {
+    let json = serde_json::to_string_pretty(value);
+    async move {
+        let json = json?;
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        tokio::fs::write(path, json).await?;
+
+        // Set strict permissions on Unix
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// One page of a device's full history, in timestamp order
+#[derive(Debug)]
+pub struct HistoryPage {
+    pub frames: Vec<Frame>,
+    /// Timestamp (microseconds) to resume after, `None` once history is exhausted
+    pub resume_after: Option<i64>,
+}
+
+/// Frames caught by the clock skew check since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ClockSkewStats {
+    pub rejected: u64,
+    pub clamped: u64,
+}
+
+/// Frames that arrived without an application ID since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UnknownApplicationStats {
+    pub rejected: u64,
+    pub fallback: u64,
+}
+
+/// Frames whose decoded payload was too deep or too large since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct OversizedPayloadStats {
+    pub rejected: u64,
+    pub truncated: u64,
+}
+
+/// Frames read from each source by one device range query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    pub memtable_frames: usize,
+    /// SSTables the device's bloom filter check let through
+    pub sstables_scanned: usize,
+    pub sstable_frames: usize,
+}
+
+/// What `delete_application` removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ApplicationDeletion {
+    pub deleted_frames: usize,
+    pub deleted_devices: usize,
+}
+
+/// What a memtable flush wrote
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FlushSummary {
+    /// SSTables created, one or more per application directory
+    pub sstable_ids: Vec<u64>,
+    /// Frames moved from the memtable into them
+    pub entries: usize,
+}
+
+/// On-disk footprint of one device's flushed frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceStorageUsage {
+    /// Frames in SSTables, duplicates in not yet compacted SSTables are counted each time
+    pub frames: u64,
+    /// Compressed size of those frames in bytes, excluding index and bloom filter overhead
+    pub compressed_bytes: u64,
+    /// SSTables holding at least one of the device's frames
+    pub sstables: u64,
+}
+
+/// Size and bloom filter saturation of one SSTable
+#[derive(Debug, Clone, Serialize)]
+pub struct SSTableStats {
+    pub id: u64,
+    pub created_at: DateTime<Utc>,
+    pub num_entries: u64,
+    /// Key range: entries are ordered by DevEUI, then timestamp, so the timestamps
+    /// belong to the first and last device rather than bounding the whole table
+    pub min_dev_eui: String,
+    pub max_dev_eui: String,
+    pub min_timestamp: Option<DateTime<Utc>>,
+    pub max_timestamp: Option<DateTime<Utc>>,
+    /// Newest frame timestamp of any device, which retention compares against
+    pub newest_frame_at: Option<DateTime<Utc>>,
+    /// Normalized application IDs, read from the frames once for tables opened from disk
+    pub application_ids: Vec<String>,
+    pub data_size_bytes: u64,
+    pub compressed_size_bytes: u64,
+    pub file_size_bytes: u64,
+    pub bloom_num_bits: usize,
+    /// Fraction of bloom filter bits set
+    pub bloom_fill_ratio: f64,
+    /// Chance a lookup for an absent device still has to read the table
+    pub bloom_estimated_fp_rate: f64,
+}
+
+/// Application ID of self-test canary frames
+pub const SELFTEST_APPLICATION_ID: &str = "loradb-selftest";
+
+/// Outcome of one self-test: a canary frame written and read back
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub dev_eui: String,
+    /// The canary was found again with the timestamp it was written with
+    pub success: bool,
+    /// Time to append the canary to the WAL and memtable
+    pub write_ms: f64,
+    /// Time to query it back
+    pub read_ms: f64,
+    /// Canary frames deleted afterwards, `None` if cleanup wasn't requested
+    pub cleaned_up: Option<usize>,
+}
 
 /// Storage engine that manages WAL, memtable, SSTables, and compaction
 pub struct StorageEngine {
     data_dir: PathBuf,
-    wal: Arc<RwLock<WriteAheadLog>>,
+    /// None on a read-only instance, which never writes to the data directory
+    wal: Option<Arc<RwLock<WriteAheadLog>>>,
     memtable: Arc<RwLock<Memtable>>,
     sstables: Arc<RwLock<Vec<SSTableReader>>>,
+    /// Open index and file handles, bounded by `max_open_sstables`
+    sstable_cache: Arc<SSTableCache>,
     compaction_manager: Arc<RwLock<CompactionManager>>,
     device_registry: Arc<DeviceRegistry>,
+    /// Gateways seen in reception info, with the devices each has heard
+    gateway_registry: Arc<GatewayRegistry>,
     retention_manager: Arc<RetentionPolicyManager>,
+    schema_manager: Arc<SchemaManager>,
+    ingestion_manager: Arc<IngestionManager>,
+    quota_manager: Arc<QuotaManager>,
+    sampling_manager: Arc<SamplingManager>,
+    payload_profiles: Arc<PayloadProfileManager>,
+    transforms: Arc<TransformManager>,
+    tombstones: Arc<TombstoneManager>,
+    device_names: Arc<DeviceNameManager>,
+    ingestion_lag: Arc<IngestionLagTracker>,
+    ingest_rate: Arc<IngestRateTracker>,
+    skew_rejected: AtomicU64,
+    skew_clamped: AtomicU64,
+    unknown_app_rejected: AtomicU64,
+    unknown_app_fallback: AtomicU64,
+    oversized_payload_rejected: AtomicU64,
+    oversized_payload_truncated: AtomicU64,
+    /// Set when a flush or compaction ran out of disk space, cleared by the next one that succeeds
+    /// While set, writes no longer trigger flushes and `/health/ready` reports the engine degraded
+    disk_full: AtomicBool,
+    /// Last write found the memtable at its hard limit, for logging the change only
+    memtable_full: AtomicBool,
+    recovery_report: ReplayReport,
+    /// Signalled by flushes when the SSTable count crosses the compaction threshold
+    compaction_signal: Arc<Notify>,
+    /// Held for a whole flush, so two flushes never snapshot or write the same frames
+    flush_lock: tokio::sync::Mutex<()>,
+    /// Periodic flush interval, starts from `memtable_flush_interval_secs` and
+    /// can be changed at runtime
+    flush_interval_secs: Arc<RwLock<u64>>,
+    /// Signalled when the flush or retention interval changes so the waiting
+    /// background loop picks up the new value immediately
+    flush_interval_changed: Arc<Notify>,
+    retention_interval_changed: Arc<Notify>,
     config: StorageConfig,
 }
 
@@ -36,35 +241,76 @@ impl StorageEngine {
     pub async fn new(config: StorageConfig) -> Result<Self> {
         let data_dir = PathBuf::from(&config.data_dir);
 
-        // Create data directory if it doesn't exist
-        tokio::fs::create_dir_all(&data_dir).await?;
+        if config.in_memory {
+            // Nothing is read from or written to the data directory
+            warn!("Running in memory: frames and settings are lost on exit");
+        } else if config.read_only {
+            // A replica only reads what the primary writes, the directory must already exist
+            if !data_dir.is_dir() {
+                return Err(LoraDbError::ConfigError(format!(
+                    "Read-only data directory not found: {}",
+                    data_dir.display()
+                ))
+                .into());
+            }
+            info!("Opening {} read-only", data_dir.display());
+        } else {
+            // Create data directory if it doesn't exist
+            tokio::fs::create_dir_all(&data_dir).await?;
 
-        // Set strict permissions on data directory
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            tokio::fs::set_permissions(&data_dir, std::fs::Permissions::from_mode(0o700))
-                .await?;
+            // Set strict permissions on data directory
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                tokio::fs::set_permissions(&data_dir, std::fs::Permissions::from_mode(0o700))
+                    .await?;
+            }
         }
 
-        // Initialize WAL
-        let wal = WriteAheadLog::open(&data_dir, config.wal_sync_interval_ms)?;
+        let memtable = Memtable::new();
+
+        // A read-only instance has no WAL; the primary's unflushed frames aren't visible
+        let (wal, recovery_report) = if config.read_only || config.in_memory {
+            (None, ReplayReport::default())
+        } else {
+            // Initialize WAL
+            let wal = WriteAheadLog::open(&data_dir, config.wal_durability)?;
+
+            // Replay WAL to recover memtable
+            info!("Replaying WAL to recover state...");
+            let (recovered_frames, recovery_report) = wal.replay()?;
+            info!("Recovered {} frames from WAL", recovery_report.recovered);
+            if recovery_report.skipped > 0 || recovery_report.failed_segments > 0 {
+                warn!(
+                    "WAL recovery incomplete: {} entries skipped, {} segments unreadable",
+                    recovery_report.skipped, recovery_report.failed_segments
+                );
+            }
 
-        // Replay WAL to recover memtable
-        info!("Replaying WAL to recover state...");
-        let recovered_frames = wal.replay()?;
-        info!("Recovered {} frames from WAL", recovered_frames.len());
+            // Populate memtable with recovered frames
+            for frame in recovered_frames {
+                memtable.insert(frame).map_err(LoraDbError::StorageError)?;
+            }
 
-        // Initialize memtable and populate with recovered frames
-        let memtable = Memtable::new();
-        for frame in recovered_frames {
-            memtable.insert(frame).map_err(|e| LoraDbError::StorageError(e))?;
-        }
+            (Some(Arc::new(RwLock::new(wal))), recovery_report)
+        };
 
         // Initialize compaction manager and open existing SSTables
         let mut compaction_manager =
             CompactionManager::new(data_dir.clone(), config.compaction_threshold);
-        let sstables = compaction_manager.open_all_sstables()?;
+        compaction_manager.set_max_bytes_per_sec(config.compaction_max_bytes_per_sec);
+        compaction_manager.set_fragmentation_threshold(
+            config.compaction_fragmentation_threshold,
+            config.compaction_target_sstable_entries,
+        );
+        compaction_manager.set_layout(config.layout);
+        compaction_manager.set_read_only(config.read_only);
+        let sstable_cache = Arc::new(SSTableCache::new(config.max_open_sstables));
+        let sstables = if config.in_memory {
+            Vec::new()
+        } else {
+            compaction_manager.open_all_sstables(&sstable_cache)?
+        };
 
         info!(
             "Opened {} existing SSTables, next ID: {}",
@@ -72,8 +318,9 @@ impl StorageEngine {
             compaction_manager.next_sstable_id()
         );
 
-        // Initialize device registry
+        // Initialize device and gateway registries
         let device_registry = Arc::new(DeviceRegistry::new());
+        let gateway_registry = Arc::new(GatewayRegistry::new());
 
         // Rebuild device registry from existing data
         info!("Rebuilding device registry from stored data...");
@@ -81,27 +328,12 @@ impl StorageEngine {
 
         // Register devices from SSTables
         for sstable in &sstables {
-            if let Ok(frames) = sstable.iter_all() {
-                for frame in frames {
-                    device_registry.register_or_update(
-                        frame.dev_eui().clone(),
-                        match &frame {
-                            Frame::Uplink(f) => f.device_name.clone(),
-                            Frame::Downlink(_) => None,
-                            _ => None,
-                        },
-                        frame
-                            .application_id()
-                            .map(|id| id.as_str().to_string())
-                            .unwrap_or_default(),
-                    );
-                    device_count += 1;
-                }
-            }
+            device_count += register_sstable_frames(&device_registry, &gateway_registry, sstable);
         }
 
         // Register devices from memtable (already recovered from WAL)
         for (_key, frame) in memtable.iter() {
+            gateway_registry.record(frame.dev_eui(), frame.rx_info(), frame.timestamp());
             device_registry.register_or_update(
                 frame.dev_eui().clone(),
                 match &frame {
@@ -121,32 +353,293 @@ impl StorageEngine {
             device_registry.device_count(),
             device_count
         );
+        info!("Gateway registry rebuilt: {} gateways", gateway_registry.gateway_count());
+
+        // After the registry rebuild, which reads every table through the same cache
+        if config.warmup_sstables > 0 {
+            warm_sstables(
+                &sstables,
+                config.warmup_sstables.min(config.max_open_sstables),
+                config.warmup_read_data,
+            );
+        }
 
         // Initialize retention policy manager from environment variables
-        let retention_manager = RetentionPolicyManager::from_env(
-            &data_dir,
-            config.retention_days,
-            config.retention_apps.clone(),
-            config.retention_check_interval_hours,
-        )
-        .await?;
+        // (a read-only instance only loads the primary's policies, for listing)
+        let retention_manager = if config.in_memory {
+            RetentionPolicyManager::in_memory(
+                config.retention_days,
+                config.retention_apps.clone(),
+                config.retention_check_interval_hours,
+                config.retention_max_total_bytes,
+                config.retention_default_app_policy,
+            )
+        } else if config.read_only {
+            RetentionPolicyManager::load(&data_dir).await
+        } else {
+            RetentionPolicyManager::from_env(
+                &data_dir,
+                config.retention_days,
+                config.retention_apps.clone(),
+                config.retention_check_interval_hours,
+                config.retention_max_total_bytes,
+                config.retention_default_app_policy,
+            )
+            .await?
+        };
+
+        let (
+            schema_manager,
+            ingestion_manager,
+            quota_manager,
+            sampling_manager,
+            payload_profiles,
+            transforms,
+            tombstones,
+            device_names,
+        ) = if config.in_memory {
+            (
+                SchemaManager::in_memory(),
+                IngestionManager::in_memory(),
+                QuotaManager::in_memory(),
+                SamplingManager::in_memory(),
+                PayloadProfileManager::in_memory(),
+                TransformManager::in_memory(),
+                TombstoneManager::in_memory(),
+                DeviceNameManager::in_memory(),
+            )
+        } else {
+            (
+                // Per-application decoded payload schemas
+                SchemaManager::new(&data_dir).await?,
+                // Per-application ingestion kill-switches
+                IngestionManager::new(&data_dir).await?,
+                // Per-application daily ingest quotas and their usage
+                QuotaManager::new(&data_dir).await?,
+                SamplingManager::new(&data_dir).await?,
+                // Per-device-profile decoded field name mappings
+                PayloadProfileManager::new(&data_dir).await?,
+                // Per-application frame enrichment pipelines
+                TransformManager::new(&data_dir).await?,
+                // Soft-deleted devices, hidden until they are purged or undeleted
+                TombstoneManager::new(&data_dir).await?,
+                // User-assigned device names, shown instead of the network server's
+                DeviceNameManager::new(&data_dir).await?,
+            )
+        };
+
+        // Hide soft-deleted devices until they are purged or undeleted
+        for dev_eui in tombstones.list().into_keys() {
+            match DevEui::new(dev_eui.clone()) {
+                Ok(dev_eui) => device_registry.mark_deleted(&dev_eui),
+                Err(e) => warn!("Ignoring tombstone for invalid DevEUI {}: {}", dev_eui, e),
+            }
+        }
+
+        // Canaries left behind by self-tests without cleanup stay out of listings
+        device_registry.remove_device(&config.selftest_dev_eui.to_lowercase());
+
+        // The rebuilt registry only has the network server's names
+        for (dev_eui, name) in device_names.list() {
+            match DevEui::new(dev_eui.clone()) {
+                Ok(dev_eui) => device_registry.set_name_override(&dev_eui, Some(name)),
+                Err(e) => warn!("Ignoring name for invalid DevEUI {}: {}", dev_eui, e),
+            }
+        }
 
         Ok(Self {
             data_dir,
-            wal: Arc::new(RwLock::new(wal)),
+            wal,
             memtable: Arc::new(RwLock::new(memtable)),
             sstables: Arc::new(RwLock::new(sstables)),
+            sstable_cache,
             compaction_manager: Arc::new(RwLock::new(compaction_manager)),
             device_registry,
+            gateway_registry,
             retention_manager: Arc::new(retention_manager),
+            schema_manager: Arc::new(schema_manager),
+            ingestion_manager: Arc::new(ingestion_manager),
+            quota_manager: Arc::new(quota_manager),
+            sampling_manager: Arc::new(sampling_manager),
+            payload_profiles: Arc::new(payload_profiles),
+            transforms: Arc::new(transforms),
+            tombstones: Arc::new(tombstones),
+            device_names: Arc::new(device_names),
+            ingestion_lag: Arc::new(IngestionLagTracker::new()),
+            ingest_rate: Arc::new(IngestRateTracker::new()),
+            skew_rejected: AtomicU64::new(0),
+            skew_clamped: AtomicU64::new(0),
+            unknown_app_rejected: AtomicU64::new(0),
+            unknown_app_fallback: AtomicU64::new(0),
+            oversized_payload_rejected: AtomicU64::new(0),
+            oversized_payload_truncated: AtomicU64::new(0),
+            disk_full: AtomicBool::new(false),
+            memtable_full: AtomicBool::new(false),
+            recovery_report,
+            compaction_signal: Arc::new(Notify::new()),
+            flush_lock: tokio::sync::Mutex::new(()),
+            flush_interval_secs: Arc::new(RwLock::new(config.memtable_flush_interval_secs)),
+            flush_interval_changed: Arc::new(Notify::new()),
+            retention_interval_changed: Arc::new(Notify::new()),
             config,
         })
     }
 
+    /// Whether this instance keeps everything in memory (see `StorageConfig::in_memory`)
+    pub fn is_in_memory(&self) -> bool {
+        self.config.in_memory
+    }
+
+    /// Which ChirpStack field ingested frames are stored under (see `StorageConfig::application_key`)
+    pub fn application_key(&self) -> ApplicationKey {
+        self.config.application_key
+    }
+
+    /// Whether this instance serves reads only (see `StorageConfig::read_only`)
+    pub fn is_read_only(&self) -> bool {
+        self.config.read_only
+    }
+
+    /// Whether the last flush or compaction failed because the disk is full
+    pub fn is_disk_full(&self) -> bool {
+        self.disk_full.load(Ordering::Relaxed)
+    }
+
+    /// Memtable size at which writes are refused (None = no limit)
+    fn memtable_limit_bytes(&self) -> Option<usize> {
+        match self.config.memtable_hard_limit_factor {
+            0 => None,
+            _ if self.config.in_memory => None,
+            factor => Some(self.config.memtable_size_mb * factor * 1024 * 1024),
+        }
+    }
+
+    /// Whether the memtable has reached its hard limit, so writes are refused
+    pub fn is_memtable_full(&self) -> bool {
+        self.memtable_limit_bytes()
+            .is_some_and(|limit| self.memtable.read().size_bytes() >= limit)
+    }
+
+    /// Refuse writes while the memtable holds `memtable_hard_limit_factor` times its flush
+    /// size, so a memtable that can't be flushed sheds load instead of growing until OOM
+    fn check_memtable_limit(&self) -> Result<()> {
+        if !self.is_memtable_full() {
+            if self.memtable_full.swap(false, Ordering::Relaxed) {
+                info!("Memtable below its hard limit again, accepting writes");
+            }
+            return Ok(());
+        }
+
+        if !self.memtable_full.swap(true, Ordering::Relaxed) {
+            error!(
+                "Memtable reached its hard limit of {} MB without being flushed, refusing writes",
+                self.memtable_limit_bytes().unwrap_or_default() / (1024 * 1024)
+            );
+        }
+        Err(LoraDbError::Overloaded(
+            "Memtable is full and can't be flushed, try again later".to_string(),
+        )
+        .into())
+    }
+
+    /// Record the outcome of a flush or compaction, returning the error unchanged
+    /// Running out of space is latched until an operation succeeds again
+    fn track_disk_space<T>(&self, operation: &str, result: Result<T>) -> Result<T> {
+        match &result {
+            Ok(_) => {
+                if self.disk_full.swap(false, Ordering::Relaxed) {
+                    info!("Disk space available again after {}, flushes resumed", operation);
+                }
+            }
+            Err(e) if is_out_of_space(e) => {
+                if !self.disk_full.swap(true, Ordering::Relaxed) {
+                    error!(
+                        "Disk full during {}: memtable and WAL kept, only periodic flushes retry \
+                         until space is freed",
+                        operation
+                    );
+                }
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// Fail with `ReadOnly` if this instance must not write to the data directory
+    pub fn ensure_writable(&self) -> std::result::Result<(), LoraDbError> {
+        if self.config.read_only {
+            return Err(LoraDbError::ReadOnly(
+                "This instance is a read-only replica, send writes to the primary".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Write a frame to the storage engine
-    pub async fn write(&self, frame: Frame) -> Result<()> {
+    #[tracing::instrument(name = "storage.write", skip_all, fields(dev_eui = %frame.dev_eui()))]
+    pub async fn write(&self, mut frame: Frame) -> Result<()> {
+        self.ensure_writable()?;
+        self.check_memtable_limit()?;
+
+        // Canary frames only come from `self_test`, which keeps them out of the registry
+        if self.is_selftest_device(frame.dev_eui()) {
+            return Err(LoraDbError::InvalidFrame(format!(
+                "DevEUI {} is reserved for self-tests",
+                frame.dev_eui().as_str()
+            ))
+            .into());
+        }
+
+        // Frames without an application would escape per-application retention, schemas and quotas
+        self.resolve_application(&mut frame)?;
+
+        // Pathological decoder output would bloat storage and slow every query of the device
+        self.check_payload_limits(&mut frame)?;
+
+        // Enrich before the schema check, so schemas can require derived fields
+        self.transforms.apply(&mut frame);
+
+        // Enforce application schema before the frame touches WAL or registry
+        match self.schema_manager.check(&frame) {
+            SchemaCheck::Valid => {}
+            SchemaCheck::Flagged(problems) => {
+                warn!(
+                    "Frame for device {} does not match application schema: {}",
+                    frame.dev_eui().as_str(),
+                    problems.join("; ")
+                );
+            }
+            SchemaCheck::Rejected(problems) => {
+                return Err(LoraDbError::InvalidFrame(format!(
+                    "Frame does not match application schema: {}",
+                    problems.join("; ")
+                ))
+                .into());
+            }
+        }
+
+        // Network time is kept for the ingestion lag even when frames are keyed by ingest time
+        let network_time = frame.timestamp();
+        match self.config.timestamp_source {
+            TimestampSource::Network => {
+                // Keep timestamps from skewed upstream clocks out of the time index
+                self.check_clock_skew(&mut frame)?;
+            }
+            TimestampSource::Ingest => frame.set_timestamp(Utc::now()),
+        }
+
+        // Thin out chatty devices before the frame is billed or reaches the WAL
+        // Sampled-out frames are acknowledged like stored ones so senders don't retry them
+        if !self.sampling_manager.admit(&frame) {
+            debug!("Dropping frame for device {}: sampled out", frame.dev_eui().as_str());
+            return Ok(());
+        }
+
+        // Count against the application's daily quota last, so rejected frames aren't billed
+        self.quota_manager.consume(&frame)?;
+
         // Register device
-        self.device_registry.register_or_update(
+        let new_application = self.device_registry.register_or_update(
             frame.dev_eui().clone(),
             match &frame {
                 Frame::Uplink(f) => f.device_name.clone(),
@@ -160,130 +653,461 @@ impl StorageEngine {
                 .unwrap_or_default(),
         );
 
-        // Append to WAL first (for durability)
-        {
-            let wal = self.wal.read();
-            wal.append(&frame)?;
-        }
+        self.gateway_registry.record(frame.dev_eui(), frame.rx_info(), frame.timestamp());
 
-        // Insert into memtable
-        {
-            let memtable = self.memtable.read();
-            memtable.insert(frame).map_err(|e| LoraDbError::StorageError(e))?;
+        // New applications inherit the default application retention policy, if any
+        if let (true, Some(app_id)) = (new_application, frame.application_id()) {
+            if let Err(e) = self.retention_manager.apply_default_policy(app_id.as_str()).await {
+                warn!("Failed to apply default retention policy to '{}': {}", app_id.as_str(), e);
+            }
         }
 
-        // Check if memtable should be flushed
-        let should_flush = {
+        // Recorded next to the timestamp the frame is keyed by, so late arrivals show up
+        let stored_at = Utc::now();
+        frame.set_stored_at(stored_at);
+
+        // Append to WAL first (for durability), then insert into memtable
+        self.append_and_insert(frame)?;
+
+        // Gap between the network timestamp and storing the frame
+        self.ingestion_lag.record(stored_at - network_time);
+        self.ingest_rate.record();
+
+        // Check if memtable should be flushed (never in memory, it is the only copy)
+        // On a full disk the periodic flush retries instead of every write
+        let should_flush = !self.config.in_memory && !self.is_disk_full() && {
             let memtable = self.memtable.read();
             memtable.should_flush(self.config.memtable_size_mb)
         };
 
         if should_flush {
             debug!("Memtable flush triggered");
-            self.flush_memtable().await?;
+            match self.flush_memtable().await {
+                // The frame is already in the WAL and memtable, so it isn't lost
+                Err(e) if is_out_of_space(&e) => {}
+                result => {
+                    result?;
+                }
+            }
         }
 
         Ok(())
     }
 
-    /// Flush memtable to SSTable
-    async fn flush_memtable(&self) -> Result<()> {
-        info!("Flushing memtable to SSTable");
+    /// Reject or clamp a frame whose timestamp is too far in the future or implausibly old
+    fn check_clock_skew(&self, frame: &mut Frame) -> Result<()> {
+        let max_future = if self.config.max_clock_skew_secs == 0 {
+            chrono::Duration::MAX
+        } else {
+            chrono::Duration::seconds(self.config.max_clock_skew_secs as i64)
+        };
 
-        // Get next SSTable ID
-        let sstable_id = {
-            let mut compaction = self.compaction_manager.write();
-            compaction.allocate_sstable_id()
+        let now = Utc::now();
+        let problem = match check_skew(frame.timestamp(), now, max_future) {
+            TimestampSkew::InRange => return Ok(()),
+            TimestampSkew::Future(ahead) => {
+                format!("timestamp is {}s in the future", ahead.num_seconds())
+            }
+            TimestampSkew::TooOld => "timestamp is implausibly old".to_string(),
+        };
+
+        match self.config.clock_skew_action {
+            ClockSkewAction::Reject => {
+                self.skew_rejected.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Rejecting frame for device {}: {} ({})",
+                    frame.dev_eui().as_str(),
+                    problem,
+                    frame.timestamp()
+                );
+                Err(LoraDbError::InvalidFrame(format!(
+                    "Frame {}, check the gateway clock",
+                    problem
+                ))
+                .into())
+            }
+            ClockSkewAction::Clamp => {
+                self.skew_clamped.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Clamping frame timestamp for device {}: {} ({})",
+                    frame.dev_eui().as_str(),
+                    problem,
+                    frame.timestamp()
+                );
+                frame.set_timestamp(now);
+                Ok(())
+            }
+        }
+    }
+
+    /// Reject a frame without an application ID or move it to the fallback application
+    fn resolve_application(&self, frame: &mut Frame) -> Result<()> {
+        match frame.application_id() {
+            Some(id) if id.as_str().trim().is_empty() => {}
+            _ => return Ok(()),
+        }
+
+        match self.config.unknown_application_action {
+            UnknownApplicationAction::Reject => {
+                self.unknown_app_rejected.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Rejecting frame for device {}: no application ID",
+                    frame.dev_eui().as_str()
+                );
+                Err(LoraDbError::InvalidFrame(
+                    "Frame has no application ID, check the network server integration".to_string(),
+                )
+                .into())
+            }
+            UnknownApplicationAction::Fallback => {
+                self.unknown_app_fallback.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Storing frame for device {} under fallback application '{}': no application ID",
+                    frame.dev_eui().as_str(),
+                    self.config.fallback_application_id
+                );
+                frame.set_application_id(ApplicationId::new(
+                    self.config.fallback_application_id.clone(),
+                ));
+                Ok(())
+            }
+        }
+    }
+
+    /// Reject a frame whose decoded payload is nested too deeply or too large, or cut it down
+    fn check_payload_limits(&self, frame: &mut Frame) -> Result<()> {
+        let Frame::Uplink(uplink) = frame else {
+            return Ok(());
         };
+        let Some(decoded) = &mut uplink.decoded_payload else {
+            return Ok(());
+        };
+
+        let max_depth = self.config.max_payload_depth;
+        let max_bytes = self.config.max_payload_bytes;
+        let too_deep = max_depth > 0 && decoded.depth() > max_depth;
+        let too_large = |decoded: &DecodedPayload| max_bytes > 0 && decoded.encoded_len() > max_bytes;
+        if !too_deep && !too_large(decoded) {
+            return Ok(());
+        }
+
+        match self.config.oversized_payload_action {
+            OversizedPayloadAction::Reject => {
+                self.oversized_payload_rejected.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Rejecting frame for device {}: decoded payload exceeds the depth or size limit",
+                    uplink.dev_eui.as_str()
+                );
+                Err(LoraDbError::InvalidFrame(format!(
+                    "Decoded payload exceeds the limits of depth {} and {} bytes",
+                    max_depth, max_bytes
+                ))
+                .into())
+            }
+            OversizedPayloadAction::Truncate => {
+                self.oversized_payload_truncated.fetch_add(1, Ordering::Relaxed);
+                if too_deep {
+                    decoded.truncate_depth(max_depth);
+                }
+                if too_large(decoded) {
+                    uplink.decoded_payload = None;
+                }
+                warn!(
+                    "Truncated decoded payload of device {}: it exceeded the depth or size limit",
+                    uplink.dev_eui.as_str()
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Append a frame to the WAL and insert it into the memtable
+    /// The WAL lock is held until the frame is in the memtable, so a flush, which rotates
+    /// the WAL under its write lock, sees every frame of the segments it seals
+    fn append_and_insert(&self, frame: Frame) -> Result<()> {
+        let wal = self.wal.as_ref().map(|wal| wal.read());
+        if let Some(wal) = &wal {
+            wal.append(&frame)?;
+        }
+
+        let memtable = self.memtable.read();
+        memtable.insert(frame).map_err(LoraDbError::StorageError)?;
+        Ok(())
+    }
+
+    /// Flush the memtable to SSTables now, rather than on the size threshold or timer
+    /// Does nothing when the memtable is empty or the engine runs in memory
+    pub async fn flush(&self) -> Result<FlushSummary> {
+        self.ensure_writable()?;
+        if self.memtable.read().is_empty() {
+            return Ok(FlushSummary::default());
+        }
+        self.flush_memtable().await
+    }
+
+    /// Flush memtable to SSTable
+    /// With the per-application layout, one SSTable is written per application
+    #[tracing::instrument(name = "storage.flush", skip_all)]
+    async fn flush_memtable(&self) -> Result<FlushSummary> {
+        if self.config.in_memory {
+            debug!("Skipping memtable flush, running in memory");
+            return Ok(FlushSummary::default());
+        }
+
+        let _flushing = self.flush_lock.lock().await;
+        info!("Flushing memtable to SSTable");
 
-        // Create new SSTable writer
-        let mut writer = SSTableWriter::new(sstable_id, &self.data_dir);
+        // Copy all entries from memtable, partitioned by target directory
+        // The WAL moves to a new segment in the same step: writes hold the WAL lock until
+        // their frame is in the memtable, so the sealed segments hold exactly these frames
+        let (partitions, sealed_segment) = {
+            let mut wal = self.wal.as_ref().map(|wal| wal.write());
+            let sealed_segment = wal.as_mut().map(|wal| wal.rotate()).transpose()?;
 
-        // Copy all entries from memtable to SSTable
-        let entries: Vec<_> = {
             let memtable = self.memtable.read();
-            memtable.iter().collect()
+            let compaction = self.compaction_manager.read();
+            let mut partitions: BTreeMap<PathBuf, Vec<_>> = BTreeMap::new();
+            for (key, frame) in memtable.iter() {
+                let dir = compaction.sstable_dir(frame.application_id().map(|id| id.as_str()));
+                partitions.entry(dir).or_default().push((key, frame));
+            }
+            (partitions, sealed_segment)
+        };
+        let flushed_keys: Vec<MemtableKey> =
+            partitions.values().flatten().map(|(key, _)| key.clone()).collect();
+        let entries = flushed_keys.len();
+
+        // Written on the blocking pool like compaction, so a large flush doesn't hold up
+        // an async worker
+        // All or nothing: on failure the SSTables this flush already wrote are removed,
+        // the memtable and WAL are kept and the next flush writes everything again
+        let compaction_manager = self.compaction_manager.clone();
+        let max_bytes = self.config.max_sstable_bytes;
+        let result = match tokio::task::spawn_blocking(move || {
+            Self::write_flush_sstables(&compaction_manager, max_bytes, partitions)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(e) => Err(LoraDbError::StorageError(format!("Flush task failed: {}", e)).into()),
         };
+        let result = result.and_then(|written| self.install_flushed_sstables(written));
+        let installed = self.track_disk_space("flush", result)?;
+
+        // Only remove the flushed frames, frames written since the snapshot stay for the next flush
+        {
+            let memtable = self.memtable.read();
+            memtable.remove_flushed(flushed_keys);
+        }
+
+        // The sealed segments' frames are in SSTables now, later ones are in the new segment
+        if let (Some(wal), Some(sealed_segment)) = (&self.wal, sealed_segment) {
+            wal.read().remove_segments_through(sealed_segment)?;
+        }
+
+        // Hand compaction off to the background task, never run it on the write path
+        if self.needs_compaction() {
+            debug!("Compaction triggered, signalling background task");
+            self.compaction_signal.notify_one();
+        }
+
+        Ok(FlushSummary {
+            sstable_ids: installed,
+            entries,
+        })
+    }
+
+    /// Write the memtable partitions to SSTables, returning the ID and path of each one
+    /// Runs on the blocking pool, on failure the SSTables already finished are removed and
+    /// an unfinished SSTable's temporary file is removed when its writer is dropped
+    fn write_flush_sstables(
+        compaction_manager: &RwLock<CompactionManager>,
+        max_bytes: u64,
+        partitions: BTreeMap<PathBuf, Vec<(MemtableKey, Frame)>>,
+    ) -> Result<Vec<(u64, PathBuf)>> {
+        let mut written = Vec::new();
+        let result =
+            Self::write_flush_partitions(compaction_manager, max_bytes, partitions, &mut written);
+        if result.is_err() {
+            Self::discard_flushed_sstables(&written);
+        }
+        result.map(|()| written)
+    }
+
+    fn write_flush_partitions(
+        compaction_manager: &RwLock<CompactionManager>,
+        max_bytes: u64,
+        partitions: BTreeMap<PathBuf, Vec<(MemtableKey, Frame)>>,
+        written: &mut Vec<(u64, PathBuf)>,
+    ) -> Result<()> {
+        for (dir, entries) in partitions {
+            std::fs::create_dir_all(&dir)?;
+
+            let mut writer: Option<(u64, SSTableWriter)> = None;
+            for (key, frame) in entries {
+                let (_, current) = writer.get_or_insert_with(|| {
+                    // Get next SSTable ID
+                    let sstable_id = compaction_manager.write().allocate_sstable_id();
+                    (sstable_id, SSTableWriter::new(sstable_id, &dir))
+                });
+                current.add(key, frame)?;
+
+                // Roll over to a new SSTable once this one reaches the size limit
+                if max_bytes > 0 && current.data_size_bytes() >= max_bytes {
+                    let (sstable_id, full) = writer.take().expect("writer was just used");
+                    written.push(Self::finish_flushed_sstable(sstable_id, full, &dir)?);
+                }
+            }
 
-        for (key, frame) in entries {
-            writer.add(key, frame)?;
+            if let Some((sstable_id, last)) = writer {
+                written.push(Self::finish_flushed_sstable(sstable_id, last, &dir)?);
+            }
         }
 
+        Ok(())
+    }
+
+    /// Finish an SSTable written by a flush, returning its ID and path
+    fn finish_flushed_sstable(
+        sstable_id: u64,
+        writer: SSTableWriter,
+        dir: &Path,
+    ) -> Result<(u64, PathBuf)> {
         let metadata = writer.finish()?;
         info!(
             "Created SSTable {} with {} entries",
             sstable_id, metadata.num_entries
         );
 
-        // Open the new SSTable and add to list
-        let sstable_path = self.data_dir.join(format!("sstable-{:08}.sst", sstable_id));
-        let reader = SSTableReader::open(sstable_path)?;
+        Ok((sstable_id, dir.join(format!("sstable-{:08}.sst", sstable_id))))
+    }
 
-        {
-            let mut sstables = self.sstables.write();
-            sstables.push(reader);
+    /// Open the SSTables written by a flush and make them visible to reads all at once
+    /// Returns their IDs, if one can't be opened they are all removed
+    fn install_flushed_sstables(&self, written: Vec<(u64, PathBuf)>) -> Result<Vec<u64>> {
+        let readers = written
+            .iter()
+            .map(|(_, path)| SSTableReader::open_cached(path.clone(), &self.sstable_cache))
+            .collect::<Result<Vec<_>>>();
+        match readers {
+            Ok(readers) => {
+                self.sstables.write().extend(readers);
+                Ok(written.into_iter().map(|(sstable_id, _)| sstable_id).collect())
+            }
+            Err(e) => {
+                Self::discard_flushed_sstables(&written);
+                Err(e)
+            }
         }
+    }
 
-        // Clear memtable
-        {
-            let memtable = self.memtable.write();
-            memtable.clear();
+    /// Remove the SSTables written by a failed flush, their frames are still in the memtable
+    fn discard_flushed_sstables(written: &[(u64, PathBuf)]) {
+        if written.is_empty() {
+            return;
         }
 
-        // Truncate WAL (frames are now in SSTable)
-        {
-            let wal = self.wal.read();
-            wal.truncate()?;
+        for (_, path) in written {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove SSTable {} from failed flush: {}", path.display(), e);
+            }
         }
+        warn!("Discarded {} SSTables written by the failed flush", written.len());
+    }
 
-        // Check if compaction should be triggered
-        let should_compact = {
-            let sstables = self.sstables.read();
-            let compaction = self.compaction_manager.read();
-            compaction.should_compact(sstables.len())
-        };
+    /// Check if any directory has crossed the compaction threshold or is too fragmented
+    fn needs_compaction(&self) -> bool {
+        !self.compaction_groups().is_empty()
+    }
 
-        if should_compact {
-            info!("Compaction triggered");
-            self.compact().await?;
-        }
+    /// SSTable paths to merge, grouped by the directory they live in
+    fn compaction_groups(&self) -> Vec<(PathBuf, Vec<PathBuf>)> {
+        let sstables = self.sstable_entries();
+        self.compaction_manager.read().groups_to_compact(sstables)
+    }
 
-        Ok(())
+    /// Path and entry count of every SSTable
+    fn sstable_entries(&self) -> Vec<(PathBuf, u64)> {
+        let sstables = self.sstables.read();
+        sstables
+            .iter()
+            .map(|s| (s.path().to_path_buf(), s.metadata().num_entries))
+            .collect()
+    }
+
+    /// Highest SSTable fragmentation score of any directory, from 0 to 1
+    pub fn fragmentation_score(&self) -> f64 {
+        let sstables = self.sstable_entries();
+        self.compaction_manager.read().max_fragmentation_score(sstables)
+    }
+
+    /// Start background compaction task
+    /// Waits for a signal from `flush_memtable` and compacts until back under threshold
+    /// Returns a JoinHandle that can be aborted on shutdown
+    pub fn start_compaction_task(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        info!("Starting background compaction task");
+
+        tokio::spawn(async move {
+            loop {
+                // Also catches SSTables left over threshold from a previous run
+                while self.needs_compaction() {
+                    self.wait_for_maintenance_window("compaction").await;
+                    if let Err(e) = self.compact().await {
+                        warn!("Background compaction failed: {}", e);
+                        break;
+                    }
+                }
+
+                self.compaction_signal.notified().await;
+            }
+        })
     }
 
     /// Compact SSTables
+    /// The merge runs on the blocking pool, rate limited by the configured IO cap,
+    /// so it never ties up an async worker that foreground queries need
     async fn compact(&self) -> Result<()> {
         info!("Starting compaction");
 
-        // Collect SSTable paths (to reopen them in compaction)
-        let sstable_paths: Vec<_> = {
-            let sstables = self.sstables.read();
-            sstables.iter().map(|s| s.path().to_path_buf()).collect()
-        };
+        for (dir, sstable_paths) in self.compaction_groups() {
+            let result = self.compact_dir(dir, sstable_paths).await;
+            self.track_disk_space("compaction", result)?;
+        }
 
-        // Reopen SSTables for compaction
-        let old_sstables: Result<Vec<_>> = sstable_paths
-            .into_iter()
-            .map(SSTableReader::open)
-            .collect();
-        let old_sstables = old_sstables?;
+        info!("Compaction complete");
+
+        Ok(())
+    }
 
-        // Perform compaction
-        let (new_metadata, old_paths) = {
+    /// Merge the given SSTables into a single new one in the same directory
+    async fn compact_dir(&self, dir: PathBuf, sstable_paths: Vec<PathBuf>) -> Result<()> {
+        // Allocate the output ID up front so the manager lock isn't held while merging
+        let (new_id, max_bytes_per_sec) = {
             let mut compaction = self.compaction_manager.write();
-            compaction.compact(old_sstables)?
+            (compaction.allocate_sstable_id(), compaction.max_bytes_per_sec())
         };
 
+        // Reopen SSTables and perform compaction on a dedicated blocking task
+        let merge_dir = dir.clone();
+        let (new_metadata, old_paths) = tokio::task::spawn_blocking(move || {
+            let old_sstables = sstable_paths
+                .into_iter()
+                .map(SSTableReader::open)
+                .collect::<Result<Vec<_>>>()?;
+            CompactionManager::merge(&merge_dir, new_id, old_sstables, max_bytes_per_sec)
+        })
+        .await
+        .map_err(|e| LoraDbError::StorageError(format!("Compaction task failed: {}", e)))??;
+
         // Open new SSTable
-        let new_sstable_path = self
-            .data_dir
-            .join(format!("sstable-{:08}.sst", new_metadata.id));
-        let new_reader = SSTableReader::open(new_sstable_path)?;
+        let new_sstable_path = dir.join(format!("sstable-{:08}.sst", new_metadata.id));
+        let new_reader = SSTableReader::open_cached(new_sstable_path, &self.sstable_cache)?;
 
-        // Replace SSTables list with just the new one
+        // Swap the compacted SSTables for the new one, keeping any flushed meanwhile
         {
             let mut sstables = self.sstables.write();
-            *sstables = vec![new_reader];
+            sstables.retain(|s| !old_paths.iter().any(|p| p == s.path()));
+            sstables.insert(0, new_reader);
         }
 
         // Delete old SSTables
@@ -292,24 +1116,39 @@ impl StorageEngine {
             compaction.delete_old_sstables(old_paths)?;
         }
 
-        info!("Compaction complete");
-
         Ok(())
     }
 
     /// Query frames for a device in a time range
+    #[tracing::instrument(name = "storage.query", skip_all, fields(dev_eui = %dev_eui))]
     pub async fn query(
         &self,
         dev_eui: &DevEui,
         start_time: Option<DateTime<Utc>>,
         end_time: Option<DateTime<Utc>>,
     ) -> Result<Vec<Frame>> {
-        let mut results = Vec::new();
+        Ok(self.query_with_stats(dev_eui, start_time, end_time).await?.0)
+    }
 
-        // Query memtable
-        {
-            let memtable = self.memtable.read();
-            let memtable_results = memtable.scan_device_range(dev_eui, start_time, end_time);
+    /// Query frames for a device within a time range, with how many frames came from where
+    pub async fn query_with_stats(
+        &self,
+        dev_eui: &DevEui,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+    ) -> Result<(Vec<Frame>, ScanStats)> {
+        let mut stats = ScanStats::default();
+        if self.device_registry.is_deleted(dev_eui) {
+            return Ok((Vec::new(), stats));
+        }
+
+        let mut results = Vec::new();
+
+        // Query memtable
+        {
+            let memtable = self.memtable.read();
+            let memtable_results = memtable.scan_device_range(dev_eui, start_time, end_time);
+            stats.memtable_frames = memtable_results.len();
             results.extend(memtable_results);
         }
 
@@ -317,7 +1156,12 @@ impl StorageEngine {
         {
             let sstables = self.sstables.read();
             for sstable in sstables.iter() {
+                if !sstable.might_contain(dev_eui) {
+                    continue;
+                }
                 let sstable_results = sstable.scan(dev_eui, start_time, end_time)?;
+                stats.sstables_scanned += 1;
+                stats.sstable_frames += sstable_results.len();
                 results.extend(sstable_results);
             }
         }
@@ -331,7 +1175,156 @@ impl StorageEngine {
             dev_eui.as_str()
         );
 
-        Ok(results)
+        Ok((results, stats))
+    }
+
+    /// Stream a device's frames in a time range in key (timestamp) order, without
+    /// collecting them first like `query` does
+    /// The device's memtable frames are copied when the iterator is created, SSTable frames
+    /// are read one at a time as it advances. SSTables flushed afterwards aren't seen,
+    /// ones compacted or deleted meanwhile are still read to the end
+    pub fn scan_iter(
+        &self,
+        dev_eui: &DevEui,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+    ) -> impl Iterator<Item = Result<Frame>> + Send {
+        if self.device_registry.is_deleted(dev_eui) {
+            return FrameScan::empty();
+        }
+
+        let memtable_entries = self
+            .memtable
+            .read()
+            .scan_device_entries(dev_eui, start_time, end_time);
+        let sstables = self
+            .sstables
+            .read()
+            .iter()
+            .filter(|sstable| sstable.might_contain(dev_eui))
+            .map(|sstable| sstable.range_iter(dev_eui, start_time, end_time))
+            .collect();
+
+        FrameScan::new(memtable_entries, sstables)
+    }
+
+    /// Read one page of a device's full history, with no time filter or result cap
+    /// Each source is read only as far as the page needs, and pages never split a timestamp,
+    /// so resuming after the last timestamp is stable across flushes and compaction
+    pub async fn history_page(
+        &self,
+        dev_eui: &DevEui,
+        after_micros: Option<i64>,
+        limit: usize,
+    ) -> Result<HistoryPage> {
+        if self.device_registry.is_deleted(dev_eui) {
+            return Ok(HistoryPage {
+                frames: Vec::new(),
+                resume_after: None,
+            });
+        }
+
+        let limit = limit.max(1);
+
+        let (mut frames, mut exhausted) = {
+            let memtable = self.memtable.read();
+            memtable.scan_after(dev_eui, after_micros, limit)
+        };
+
+        {
+            let sstables = self.sstables.read();
+            for sstable in sstables.iter() {
+                let (sstable_frames, sstable_exhausted) =
+                    sstable.scan_after(dev_eui, after_micros, limit)?;
+                frames.extend(sstable_frames);
+                exhausted &= sstable_exhausted;
+            }
+        }
+
+        frames.sort_by_key(|f| f.timestamp());
+
+        // Every source returned all its frames up to the cut, so anything past it is left
+        // for the next page
+        let mut has_more = !exhausted;
+        if frames.len() > limit {
+            let cut = frames[limit - 1].timestamp();
+            let keep = frames.iter().take_while(|f| f.timestamp() <= cut).count();
+            has_more |= keep < frames.len();
+            frames.truncate(keep);
+        }
+
+        let resume_after = if has_more {
+            frames.last().map(|f| f.timestamp().timestamp_micros())
+        } else {
+            None
+        };
+
+        Ok(HistoryPage {
+            frames,
+            resume_after,
+        })
+    }
+
+    /// Get the most recent frame for a device
+    /// Checks the memtable first, then SSTables newest-first, stopping as soon as
+    /// no remaining SSTable can contain anything newer than the best match so far
+    pub async fn latest_frame(&self, dev_eui: &DevEui) -> Result<Option<Frame>> {
+        if self.device_registry.is_deleted(dev_eui) {
+            return Ok(None);
+        }
+
+        let mut latest = {
+            let memtable = self.memtable.read();
+            memtable.get_latest(dev_eui)
+        };
+
+        let sstables = self.sstables.read();
+        let mut ordered: Vec<&SSTableReader> = sstables.iter().collect();
+        ordered.sort_by_key(|s| std::cmp::Reverse(s.max_timestamp()));
+
+        for sstable in ordered {
+            if let (Some(best), Some(max_time)) = (&latest, sstable.max_timestamp()) {
+                if max_time <= best.timestamp() {
+                    break;
+                }
+            }
+
+            if let Some(frame) = sstable.get_latest(dev_eui)? {
+                if latest
+                    .as_ref()
+                    .map_or(true, |best| frame.timestamp() > best.timestamp())
+                {
+                    latest = Some(frame);
+                }
+            }
+        }
+
+        Ok(latest)
+    }
+
+    /// Count a device's frames and their compressed size across all SSTables
+    /// Uses only the SSTable indexes, frames still in the memtable are not included
+    pub fn device_storage_usage(&self, dev_eui: &DevEui) -> Result<DeviceStorageUsage> {
+        let mut usage = DeviceStorageUsage::default();
+        for sstable in self.sstables.read().iter() {
+            let (frames, bytes) = sstable.device_usage(dev_eui)?;
+            if frames > 0 {
+                usage.frames += frames;
+                usage.compressed_bytes += bytes;
+                usage.sstables += 1;
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Number of a device's frames still in the memtable, not yet flushed to SSTables
+    pub fn device_memtable_frames(&self, dev_eui: &DevEui) -> usize {
+        self.memtable.read().device_frame_count(dev_eui)
+    }
+
+    /// Soft-deleted devices pending their purge
+    pub fn tombstones(&self) -> &Arc<TombstoneManager> {
+        &self.tombstones
     }
 
     /// Get device registry
@@ -339,12 +1332,128 @@ impl StorageEngine {
         &self.device_registry
     }
 
+    /// Get gateway registry
+    pub fn gateway_registry(&self) -> &Arc<GatewayRegistry> {
+        &self.gateway_registry
+    }
+
     /// Get retention policy manager
     pub fn retention_manager(&self) -> &Arc<RetentionPolicyManager> {
         &self.retention_manager
     }
 
+    /// Get application schema manager
+    pub fn schema_manager(&self) -> &Arc<SchemaManager> {
+        &self.schema_manager
+    }
+
+    /// Get application ingestion manager
+    pub fn ingestion_manager(&self) -> &Arc<IngestionManager> {
+        &self.ingestion_manager
+    }
+
+    /// Get application ingest quota manager
+    pub fn quota_manager(&self) -> &Arc<QuotaManager> {
+        &self.quota_manager
+    }
+
+    /// Get uplink sampling rule manager
+    pub fn sampling_manager(&self) -> &Arc<SamplingManager> {
+        &self.sampling_manager
+    }
+
+    /// Get decoded payload profile manager
+    pub fn payload_profiles(&self) -> &Arc<PayloadProfileManager> {
+        &self.payload_profiles
+    }
+
+    /// Get frame transform pipeline manager
+    pub fn transforms(&self) -> &Arc<TransformManager> {
+        &self.transforms
+    }
+
+    /// Get the ingestion lag tracker
+    pub fn ingestion_lag(&self) -> &Arc<IngestionLagTracker> {
+        &self.ingestion_lag
+    }
+
+    /// Get the ingest throughput tracker
+    pub fn ingest_rate(&self) -> &Arc<IngestRateTracker> {
+        &self.ingest_rate
+    }
+
+    /// Get the number of frames rejected or clamped by the clock skew check
+    pub fn clock_skew_stats(&self) -> ClockSkewStats {
+        ClockSkewStats {
+            rejected: self.skew_rejected.load(Ordering::Relaxed),
+            clamped: self.skew_clamped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get the number of frames without an application ID that were rejected or stored
+    /// under the fallback application
+    pub fn unknown_application_stats(&self) -> UnknownApplicationStats {
+        UnknownApplicationStats {
+            rejected: self.unknown_app_rejected.load(Ordering::Relaxed),
+            fallback: self.unknown_app_fallback.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get the number of frames whose decoded payload was rejected or truncated for exceeding
+    /// the depth or size limit
+    pub fn oversized_payload_stats(&self) -> OversizedPayloadStats {
+        OversizedPayloadStats {
+            rejected: self.oversized_payload_rejected.load(Ordering::Relaxed),
+            truncated: self.oversized_payload_truncated.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get the result of the WAL replay performed at startup
+    pub fn recovery_report(&self) -> ReplayReport {
+        self.recovery_report
+    }
+
+    /// Get size and bloom filter statistics for every live SSTable, oldest first
+    pub fn sstable_stats(&self) -> Vec<SSTableStats> {
+        let sstables = self.sstables.read();
+        let mut stats: Vec<SSTableStats> = sstables
+            .iter()
+            .map(|sstable| {
+                let metadata = sstable.metadata();
+                let bloom = &metadata.bloom_filter;
+                let mut application_ids: Vec<String> = sstable
+                    .application_ids()
+                    .map(|ids| ids.into_iter().collect())
+                    .unwrap_or_else(|e| {
+                        warn!("Failed to read application IDs of SSTable {}: {}", metadata.id, e);
+                        Vec::new()
+                    });
+                application_ids.sort();
+                SSTableStats {
+                    id: metadata.id,
+                    created_at: metadata.created_at,
+                    num_entries: metadata.num_entries,
+                    min_dev_eui: metadata.min_key.dev_eui.clone(),
+                    max_dev_eui: metadata.max_key.dev_eui.clone(),
+                    min_timestamp: DateTime::from_timestamp_micros(metadata.min_key.timestamp),
+                    max_timestamp: DateTime::from_timestamp_micros(metadata.max_key.timestamp),
+                    newest_frame_at: sstable.max_timestamp(),
+                    application_ids,
+                    data_size_bytes: metadata.data_size_bytes,
+                    compressed_size_bytes: metadata.compressed_size_bytes,
+                    file_size_bytes: metadata.file_size_bytes,
+                    bloom_num_bits: bloom.num_bits(),
+                    bloom_fill_ratio: bloom.fill_ratio(),
+                    bloom_estimated_fp_rate: bloom.estimated_fp_rate(),
+                }
+            })
+            .collect();
+        stats.sort_by_key(|s| s.id);
+        stats
+    }
+
     /// Start background processing of frames from MQTT
+    /// Runs until every sender is dropped and all buffered frames are written
     pub async fn start_frame_processor(
         self: Arc<Self>,
         mut frame_rx: mpsc::Receiver<Frame>,
@@ -353,37 +1462,95 @@ impl StorageEngine {
 
         while let Some(frame) = frame_rx.recv().await {
             let dev_eui = frame.dev_eui().as_str().to_string();
+
+            if !self.ingestion_manager.admit(&frame) {
+                debug!("Dropping frame for device {}: application ingestion disabled", dev_eui);
+                continue;
+            }
+
             match self.write(frame).await {
                 Ok(_) => {
                     info!("Successfully stored frame for device {}", dev_eui);
                 }
+                Err(e) if matches!(e.downcast_ref(), Some(LoraDbError::QuotaExceeded(_))) => {
+                    debug!("Dropping frame for device {}: {}", dev_eui, e);
+                }
                 Err(e) => {
                     warn!("Failed to write frame for device {}: {}", dev_eui, e);
                 }
             }
         }
 
-        warn!("Frame processor stopped");
+        info!("Frame channel closed, frame processor drained and stopped");
+    }
+
+    /// Current periodic flush interval in seconds
+    pub fn flush_interval_secs(&self) -> u64 {
+        *self.flush_interval_secs.read()
+    }
+
+    /// Change the periodic flush interval, taking effect without a restart
+    /// The next flush is scheduled `secs` from now; the value is not persisted
+    /// and resets to `memtable_flush_interval_secs` on restart
+    pub fn set_flush_interval_secs(&self, secs: u64) -> std::result::Result<(), LoraDbError> {
+        self.ensure_writable()?;
+        if secs == 0 {
+            return Err(LoraDbError::QueryParseError(
+                "Flush interval must be greater than 0 seconds".to_string(),
+            ));
+        }
+
+        *self.flush_interval_secs.write() = secs;
+        self.flush_interval_changed.notify_one();
+        info!("Updated periodic memtable flush interval to {} seconds", secs);
+        Ok(())
+    }
+
+    /// Current retention check interval in hours
+    pub async fn retention_check_interval_hours(&self) -> u64 {
+        self.retention_manager.get_check_interval_hours().await
+    }
+
+    /// Change the retention check interval, taking effect without a restart
+    /// The next check is scheduled `hours` from now; the value is saved with the
+    /// retention policies
+    pub async fn set_retention_check_interval_hours(&self, hours: u64) -> Result<()> {
+        self.ensure_writable()?;
+        if hours == 0 {
+            return Err(LoraDbError::QueryParseError(
+                "Retention check interval must be greater than 0 hours".to_string(),
+            )
+            .into());
+        }
+
+        self.retention_manager.set_check_interval_hours(hours).await?;
+        self.retention_interval_changed.notify_one();
+        Ok(())
     }
 
     /// Start periodic memtable flush task
+    /// The interval is re-read before every wait, see `set_flush_interval_secs`
     /// Returns a JoinHandle that can be aborted on shutdown
     pub fn start_periodic_flush(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
-        let flush_interval_secs = self.config.memtable_flush_interval_secs;
-
         info!(
             "Starting periodic memtable flush (interval: {} seconds)",
-            flush_interval_secs
+            self.flush_interval_secs()
         );
 
         tokio::spawn(async move {
-            let mut interval = tokio::time::interval(
-                tokio::time::Duration::from_secs(flush_interval_secs)
-            );
-            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            // Like the retention task, the first pass runs at startup
+            let mut first_run = true;
 
             loop {
-                interval.tick().await;
+                if !first_run {
+                    let wait = tokio::time::Duration::from_secs(self.flush_interval_secs());
+                    tokio::select! {
+                        _ = tokio::time::sleep(wait) => {}
+                        // Interval changed, start waiting again with the new value
+                        _ = self.flush_interval_changed.notified() => continue,
+                    }
+                }
+                first_run = false;
 
                 // Check if memtable has data
                 let has_data = {
@@ -401,88 +1568,181 @@ impl StorageEngine {
                 } else {
                     debug!("Skipping periodic flush (memtable is empty)");
                 }
+
+                if let Err(e) = self.quota_manager.save_usage().await {
+                    warn!("Failed to save ingest quota usage: {}", e);
+                }
             }
         })
     }
 
+    /// Pick up SSTables the primary has written and drop ones it has compacted or deleted
+    /// Tables that fail to open (e.g. still being written) are retried on the next refresh
+    /// Returns the number of SSTables added and removed
+    pub fn refresh_sstables(&self) -> Result<(usize, usize)> {
+        let paths = self.compaction_manager.read().find_sstables()?;
+        let on_disk: HashSet<&PathBuf> = paths.iter().collect();
+
+        let loaded: HashSet<PathBuf> = {
+            let sstables = self.sstables.read();
+            sstables.iter().map(|s| s.path().to_path_buf()).collect()
+        };
+
+        let mut opened = Vec::new();
+        for path in paths.iter().filter(|p| !loaded.contains(*p)) {
+            match SSTableReader::open_cached(path.clone(), &self.sstable_cache) {
+                Ok(reader) => opened.push(reader),
+                Err(e) => debug!("Skipping SSTable {:?} until the next refresh: {}", path, e),
+            }
+        }
+
+        for sstable in &opened {
+            register_sstable_frames(&self.device_registry, &self.gateway_registry, sstable);
+        }
+
+        let added = opened.len();
+        let removed = {
+            let mut sstables = self.sstables.write();
+            let before = sstables.len();
+            sstables.retain(|s| on_disk.contains(&s.path().to_path_buf()));
+            let removed = before - sstables.len();
+            sstables.extend(opened);
+            removed
+        };
+
+        Ok((added, removed))
+    }
+
+    /// Start the periodic SSTable rescan for a read-only instance
+    /// Returns `None` on a writable instance, which tracks its own SSTables
+    pub fn start_replica_refresh(self: Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        if !self.config.read_only {
+            return None;
+        }
+
+        let refresh_secs = self.config.replica_refresh_secs.max(1);
+        info!("Starting read-only SSTable refresh (interval: {} seconds)", refresh_secs);
+
+        Some(tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_secs(refresh_secs));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+
+                match self.refresh_sstables() {
+                    Ok((0, 0)) => {}
+                    Ok((added, removed)) => {
+                        info!("SSTable refresh: {} added, {} removed", added, removed)
+                    }
+                    Err(e) => warn!("SSTable refresh failed: {}", e),
+                }
+            }
+        }))
+    }
+
     /// Enforce retention policy by deleting data older than configured retention period
     /// Supports both global and per-application retention policies
     pub async fn enforce_retention(&self) -> Result<()> {
+        self.ensure_writable()?;
+
         // Get current policies from manager
         let policies = self.retention_manager.get_policies().await;
 
         // Check if any retention policy is configured
-        if policies.global_days.is_none() && policies.applications.is_empty() {
+        let age_limited = policies.global_days.is_some() || !policies.applications.is_empty();
+        if !age_limited && policies.max_total_bytes.is_none() {
             debug!("No retention policy configured, skipping");
             return Ok(());
         }
 
-        info!("Enforcing retention policies (global + per-application)");
+        info!("Enforcing retention policies (global + per-application + size budget)");
 
         // Find SSTables that should be deleted based on retention policies
-        let sstables_to_delete: Vec<(u64, String)> = {
+        let sstables_to_delete: Vec<(u64, PathBuf, String)> = {
             let sstables = self.sstables.read();
             let mut to_delete = Vec::new();
 
-            for sstable in sstables.iter() {
-                // Get the SSTable's max timestamp
-                let max_time = match sstable.max_timestamp() {
-                    Some(time) => time,
-                    None => {
-                        warn!("SSTable {} has no max timestamp, skipping retention check", sstable.id());
-                        continue;
-                    }
-                };
+            if age_limited {
+                for sstable in sstables.iter() {
+                    // Get the SSTable's max timestamp
+                    let max_time = match sstable.max_timestamp() {
+                        Some(time) => time,
+                        None => {
+                            warn!("SSTable {} has no max timestamp, skipping retention check", sstable.id());
+                            continue;
+                        }
+                    };
 
-                // Get all application IDs in this SSTable
-                let app_ids = match sstable.application_ids() {
-                    Ok(ids) => ids,
-                    Err(e) => {
-                        warn!("Failed to get application IDs for SSTable {}: {}", sstable.id(), e);
-                        continue;
-                    }
-                };
+                    // Get all application IDs in this SSTable
+                    let app_ids = match sstable.application_ids() {
+                        Ok(ids) => ids,
+                        Err(e) => {
+                            warn!("Failed to get application IDs for SSTable {}: {}", sstable.id(), e);
+                            continue;
+                        }
+                    };
 
-                // Determine the SHORTEST retention period for this SSTable
-                // We can only delete if ALL applications in the SSTable are past retention
-                let mut shortest_retention: Option<u32> = None;
-                let mut policy_source = String::new();
-
-                for app_id in &app_ids {
-                    // Check for per-application policy first
-                    let retention_days = if let Some(policy) = policies.applications.get(app_id) {
-                        match policy.days {
-                            Some(days) => {
-                                policy_source = format!("app:{}", app_id);
-                                Some(days)
-                            },
-                            None => {
-                                // "never" - keep forever for this app
-                                shortest_retention = None;
-                                break;  // Can't delete if any app is "never"
+                    // Determine the SHORTEST retention period for this SSTable
+                    // We can only delete if ALL applications in the SSTable are past retention
+                    let mut shortest_retention: Option<u32> = None;
+                    let mut policy_source = String::new();
+
+                    for app_id in &app_ids {
+                        // Check for per-application policy first
+                        let retention_days = if let Some(policy) = policies.applications.get(app_id) {
+                            match policy.days {
+                                Some(days) => {
+                                    policy_source = format!("app:{}", app_id);
+                                    Some(days)
+                                },
+                                None => {
+                                    // "never" - keep forever for this app
+                                    shortest_retention = None;
+                                    break;  // Can't delete if any app is "never"
+                                }
                             }
+                        } else {
+                            // Fall back to global default
+                            policy_source = "global".to_string();
+                            policies.global_days
+                        };
+
+                        // Track the shortest retention period
+                        if let Some(days) = retention_days {
+                            shortest_retention = Some(match shortest_retention {
+                                Some(current) => current.max(days),  // Use longest to be safe
+                                None => days,
+                            });
                         }
-                    } else {
-                        // Fall back to global default
-                        policy_source = "global".to_string();
-                        policies.global_days
-                    };
+                    }
 
-                    // Track the shortest retention period
-                    if let Some(days) = retention_days {
-                        shortest_retention = Some(match shortest_retention {
-                            Some(current) => current.max(days),  // Use longest to be safe
-                            None => days,
-                        });
+                    // Check if SSTable should be deleted based on shortest retention
+                    if let Some(retention_days) = shortest_retention {
+                        let cutoff_time = Utc::now() - chrono::Duration::days(retention_days as i64);
+                        if max_time < cutoff_time {
+                            to_delete.push((sstable.id(), sstable.path().to_path_buf(), policy_source));
+                        }
                     }
                 }
+            }
+
+            // Size budget: delete the oldest remaining SSTables until the rest fit
+            if let Some(budget) = policies.max_total_bytes {
+                let mut remaining: Vec<&SSTableReader> = sstables
+                    .iter()
+                    .filter(|s| !to_delete.iter().any(|(_, path, _)| path == s.path()))
+                    .collect();
+                remaining.sort_by_key(|s| s.min_timestamp());
 
-                // Check if SSTable should be deleted based on shortest retention
-                if let Some(retention_days) = shortest_retention {
-                    let cutoff_time = Utc::now() - chrono::Duration::days(retention_days as i64);
-                    if max_time < cutoff_time {
-                        to_delete.push((sstable.id(), policy_source));
+                let mut total: u64 = remaining.iter().map(|s| s.metadata().file_size_bytes).sum();
+                for sstable in remaining {
+                    if total <= budget {
+                        break;
                     }
+                    total -= sstable.metadata().file_size_bytes;
+                    to_delete.push((sstable.id(), sstable.path().to_path_buf(), format!("size:{}", budget)));
                 }
             }
 
@@ -500,15 +1760,14 @@ impl StorageEngine {
         );
 
         // Delete the old SSTables
-        for (sstable_id, policy_source) in sstables_to_delete {
-            // Remove from in-memory list
+        for (sstable_id, sstable_path, policy_source) in sstables_to_delete {
+            // Remove from in-memory list, by path since IDs aren't guaranteed unique on disk
             {
                 let mut sstables = self.sstables.write();
-                sstables.retain(|s| s.id() != sstable_id);
+                sstables.retain(|s| s.path() != sstable_path);
             }
 
             // Delete the file
-            let sstable_path = self.data_dir.join(format!("sstable-{:08}.sst", sstable_id));
             match tokio::fs::remove_file(&sstable_path).await {
                 Ok(_) => info!("Deleted SSTable {} (retention policy: {})", sstable_id, policy_source),
                 Err(e) => warn!("Failed to delete SSTable {}: {}", sstable_id, e),
@@ -518,22 +1777,79 @@ impl StorageEngine {
         Ok(())
     }
 
+    /// Start the background WAL fsync task for `WalDurability::Interval`
+    /// Returns `None` for the other modes, which don't need one
+    pub fn start_wal_sync_task(self: Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let interval_ms = match self.config.wal_durability {
+            WalDurability::Interval(ms) => ms.max(1),
+            WalDurability::None | WalDurability::Always => return None,
+        };
+        let wal = self.wal.clone()?;
+
+        info!("Starting background WAL sync (interval: {} ms)", interval_ms);
+
+        Some(tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_millis(interval_ms));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+
+                let wal = wal.clone();
+                // fsync can block for a while, keep it off the async workers
+                let result = tokio::task::spawn_blocking(move || wal.read().sync_if_dirty()).await;
+                match result {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => warn!("WAL sync failed: {}", e),
+                    Err(e) => warn!("WAL sync task failed: {}", e),
+                }
+            }
+        }))
+    }
+
+    /// Sleep until the configured maintenance window is open, if one is configured
+    /// Heavy background IO (compaction, retention) calls this before each run
+    async fn wait_for_maintenance_window(&self, task: &str) {
+        let window = match self.config.maintenance_window {
+            Some(window) => window,
+            None => return,
+        };
+
+        let wait = window.until_open(Utc::now());
+        if wait > chrono::Duration::zero() {
+            info!(
+                "Deferring {} for {} minutes until the maintenance window opens",
+                task,
+                wait.num_minutes()
+            );
+            tokio::time::sleep(wait.to_std().unwrap_or_default()).await;
+        }
+    }
+
     /// Start periodic retention policy enforcement task
+    /// Runs once at startup, then waits the check interval from the retention
+    /// manager, re-read before every wait (see `set_retention_check_interval_hours`)
     /// Returns a JoinHandle that can be aborted on shutdown
     pub fn start_retention_enforcement(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
         info!("Starting retention enforcement background task");
 
         tokio::spawn(async move {
-            // Get initial check interval from retention manager
-            let check_interval_hours = self.retention_manager.get_check_interval_hours().await;
-
-            let mut interval = tokio::time::interval(
-                tokio::time::Duration::from_secs(check_interval_hours * 3600)
-            );
-            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            let mut first_run = true;
 
             loop {
-                interval.tick().await;
+                if !first_run {
+                    let check_interval_hours = self.retention_check_interval_hours().await;
+                    let wait = tokio::time::Duration::from_secs(check_interval_hours * 3600);
+                    tokio::select! {
+                        _ = tokio::time::sleep(wait) => {}
+                        // Interval changed, start waiting again with the new value
+                        _ = self.retention_interval_changed.notified() => continue,
+                    }
+                }
+                first_run = false;
+
+                self.wait_for_maintenance_window("retention enforcement").await;
 
                 info!("Running retention policy enforcement");
                 if let Err(e) = self.enforce_retention().await {
@@ -541,12 +1857,86 @@ impl StorageEngine {
                 } else {
                     info!("Retention enforcement completed");
                 }
+
+                match self.purge_deleted_devices().await {
+                    Ok(0) => {}
+                    Ok(purged) => info!("Purged {} soft-deleted device(s)", purged),
+                    Err(e) => warn!("Purging soft-deleted devices failed: {}", e),
+                }
             }
         })
     }
 
+    /// How long deleted devices stay recoverable, None when deletes are immediate
+    pub fn device_delete_grace(&self) -> Option<chrono::Duration> {
+        match self.config.device_delete_grace_hours {
+            0 => None,
+            hours => Some(chrono::Duration::hours(hours as i64)),
+        }
+    }
+
+    /// Hide a device from queries and listings, purging its data once the grace period ends
+    pub async fn soft_delete_device(
+        &self,
+        dev_eui: &DevEui,
+        grace: chrono::Duration,
+    ) -> Result<DeviceTombstone> {
+        self.ensure_writable()?;
+        let tombstone = self.tombstones.add(dev_eui, grace).await?;
+        self.device_registry.mark_deleted(dev_eui);
+        Ok(tombstone)
+    }
+
+    /// Show `name` for a device instead of the network server's name, or go back
+    /// to the network server's name with None
+    pub async fn set_device_name(&self, dev_eui: &DevEui, name: Option<&str>) -> Result<()> {
+        self.ensure_writable()?;
+        match name {
+            Some(name) => self.device_names.set(dev_eui, name).await?,
+            None => {
+                self.device_names.remove(dev_eui).await?;
+            }
+        }
+        self.device_registry
+            .set_name_override(dev_eui, name.map(str::to_string));
+        Ok(())
+    }
+
+    /// Restore a soft-deleted device, returns false if it isn't pending deletion
+    pub async fn undelete_device(&self, dev_eui: &DevEui) -> Result<bool> {
+        self.ensure_writable()?;
+        if !self.tombstones.remove(dev_eui).await? {
+            return Ok(false);
+        }
+        self.device_registry.restore(dev_eui);
+        info!("Device {} undeleted", dev_eui.as_str());
+        Ok(true)
+    }
+
+    /// Physically delete soft-deleted devices whose grace period has ended
+    /// Returns the number of devices purged
+    pub async fn purge_deleted_devices(&self) -> Result<usize> {
+        self.ensure_writable()?;
+
+        let mut purged = 0;
+        for dev_eui in self.tombstones.due(Utc::now()) {
+            let dev_eui = DevEui::new(dev_eui)
+                .map_err(|e| LoraDbError::InvalidDevEui(e.to_string()))?;
+            let deleted = self.delete_device(&dev_eui).await?;
+            info!(
+                "Purged soft-deleted device {} ({} frames)",
+                dev_eui.as_str(),
+                deleted
+            );
+            purged += 1;
+        }
+
+        Ok(purged)
+    }
+
     /// Delete all data for a specific device
     pub async fn delete_device(&self, dev_eui: &DevEui) -> Result<usize> {
+        self.ensure_writable()?;
         info!("Deleting all data for device {}", dev_eui.as_str());
 
         let mut total_deleted = 0;
@@ -560,113 +1950,250 @@ impl StorageEngine {
         }
 
         // 2. Rewrite SSTables without this device's data
+        total_deleted += self
+            .rewrite_sstables_without(|frame| frame.dev_eui() == dev_eui)
+            .await?;
+
+        // 3. Remove device from registry, along with any pending soft delete
+        self.device_registry.remove_device(&dev_eui.normalized());
+        self.gateway_registry.remove_device(dev_eui);
+        self.tombstones.remove(dev_eui).await?;
+        self.device_names.remove(dev_eui).await?;
+        info!("Removed device from registry");
+
+        info!(
+            "Deleted total of {} frames for device {}",
+            total_deleted,
+            dev_eui.as_str()
+        );
+
+        Ok(total_deleted)
+    }
+
+    /// Rewrite every SSTable holding frames matched by `remove` without them, in one pass
+    /// SSTables with no matching frames are kept as they are
+    /// Returns the number of frames removed
+    async fn rewrite_sstables_without(&self, remove: impl Fn(&Frame) -> bool) -> Result<usize> {
         let sstables_to_process = {
             let sstables = self.sstables.read();
             sstables.iter().map(|s| s.path().to_path_buf()).collect::<Vec<_>>()
         };
 
-        if !sstables_to_process.is_empty() {
-            info!("Rewriting {} SSTables to remove device data", sstables_to_process.len());
+        if sstables_to_process.is_empty() {
+            return Ok(0);
+        }
+
+        info!("Checking {} SSTables for frames to remove", sstables_to_process.len());
 
-            // Reopen SSTables for reading
-            let old_sstables: Result<Vec<_>> = sstables_to_process
-                .iter()
-                .map(|path| SSTableReader::open(path.clone()))
-                .collect();
-            let old_sstables = old_sstables?;
+        // Reopen SSTables for reading
+        let old_sstables: Result<Vec<_>> = sstables_to_process
+            .iter()
+            .map(|path| SSTableReader::open(path.clone()))
+            .collect();
+        let old_sstables = old_sstables?;
+
+        // Create new SSTables without the removed frames
+        let mut total_deleted = 0;
+        let mut new_sstables = Vec::new();
+        let mut old_paths = Vec::new();
 
-            // Create new SSTables without the deleted device
-            let mut new_sstables = Vec::new();
-            let mut old_paths = Vec::new();
+        for sstable in old_sstables {
+            let old_path = sstable.path().to_path_buf();
+
+            // Read all frames except the removed ones
+            let all_frames = sstable.iter_all()?;
+            let total = all_frames.len();
+            let frames: Vec<_> = all_frames.into_iter().filter(|frame| !remove(frame)).collect();
+
+            if frames.len() == total {
+                new_sstables.push(SSTableReader::open_cached(old_path, &self.sstable_cache)?);
+                continue;
+            }
+            total_deleted += total - frames.len();
+            old_paths.push(old_path);
+
+            // Only create new SSTable if there are remaining frames
+            if !frames.is_empty() {
+                let new_id = {
+                    let mut compaction = self.compaction_manager.write();
+                    compaction.allocate_sstable_id()
+                };
 
-            for sstable in old_sstables {
-                let old_path = sstable.path().to_path_buf();
-                old_paths.push(old_path);
+                // Keep the rewritten SSTable in the same directory as the original
+                let dir = sstable
+                    .path()
+                    .parent()
+                    .map(|p| p.to_path_buf())
+                    .unwrap_or_else(|| self.data_dir.clone());
+                let mut writer = SSTableWriter::new(new_id, &dir);
 
-                // Read all frames except those for the deleted device
-                let frames: Vec<_> = sstable
-                    .iter_all()?
+                // Sort frames by key and write to new SSTable
+                let mut keyed_frames: Vec<_> = frames
                     .into_iter()
-                    .filter(|frame| {
-                        let is_deleted_device = frame.dev_eui() == dev_eui;
-                        if is_deleted_device {
-                            total_deleted += 1;
-                        }
-                        !is_deleted_device
+                    .enumerate()
+                    .map(|(seq, frame)| {
+                        let key = crate::engine::memtable::MemtableKey::new(
+                            frame.dev_eui(),
+                            frame.timestamp(),
+                            seq as u64,
+                        );
+                        (key, frame)
                     })
                     .collect();
 
-                // Only create new SSTable if there are remaining frames
-                if !frames.is_empty() {
-                    let new_id = {
-                        let mut compaction = self.compaction_manager.write();
-                        compaction.allocate_sstable_id()
-                    };
+                keyed_frames.sort_by(|a, b| a.0.cmp(&b.0));
 
-                    let mut writer = SSTableWriter::new(new_id, &self.data_dir);
-
-                    // Sort frames by key and write to new SSTable
-                    let mut keyed_frames: Vec<_> = frames
-                        .into_iter()
-                        .enumerate()
-                        .map(|(seq, frame)| {
-                            let key = crate::engine::memtable::MemtableKey::new(
-                                frame.dev_eui(),
-                                frame.timestamp(),
-                                seq as u64,
-                            );
-                            (key, frame)
-                        })
-                        .collect();
-
-                    keyed_frames.sort_by(|a, b| a.0.cmp(&b.0));
-
-                    for (key, frame) in keyed_frames {
-                        writer.add(key, frame)?;
-                    }
+                for (key, frame) in keyed_frames {
+                    writer.add(key, frame)?;
+                }
 
-                    let metadata = writer.finish()?;
-                    info!("Created new SSTable {} with {} entries", metadata.id, metadata.num_entries);
+                let metadata = writer.finish()?;
+                info!("Created new SSTable {} with {} entries", metadata.id, metadata.num_entries);
 
-                    let new_path = self.data_dir.join(format!("sstable-{:08}.sst", metadata.id));
-                    new_sstables.push(SSTableReader::open(new_path)?);
-                } else {
-                    info!("SSTable had only deleted device's data, not creating new SSTable");
-                }
+                let new_path = dir.join(format!("sstable-{:08}.sst", metadata.id));
+                new_sstables.push(SSTableReader::open_cached(new_path, &self.sstable_cache)?);
+            } else {
+                info!("SSTable had only removed frames, not creating new SSTable");
             }
+        }
 
-            // Replace SSTables list with new ones
-            {
-                let mut sstables = self.sstables.write();
-                *sstables = new_sstables;
-            }
+        // Replace SSTables list with new ones
+        {
+            let mut sstables = self.sstables.write();
+            *sstables = new_sstables;
+        }
 
-            // Delete old SSTable files
-            for path in old_paths {
-                match tokio::fs::remove_file(&path).await {
-                    Ok(_) => debug!("Deleted old SSTable: {:?}", path),
-                    Err(e) => warn!("Failed to delete old SSTable {:?}: {}", path, e),
-                }
+        // Delete old SSTable files
+        for path in old_paths {
+            match tokio::fs::remove_file(&path).await {
+                Ok(_) => debug!("Deleted old SSTable: {:?}", path),
+                Err(e) => warn!("Failed to delete old SSTable {:?}: {}", path, e),
             }
         }
 
-        // 3. Remove device from registry
-        self.device_registry.remove_device(&dev_eui.as_str().to_string());
-        info!("Removed device from registry");
-
+        Ok(total_deleted)
+    }
+
+    /// Whether frames for this device are self-test canaries
+    pub fn is_selftest_device(&self, dev_eui: &DevEui) -> bool {
+        dev_eui.normalized() == self.config.selftest_dev_eui.to_lowercase()
+    }
+
+    /// Write a canary frame for the self-test DevEUI and query it back
+    /// The canary goes straight to the WAL and memtable, skipping schemas, sampling and
+    /// quotas so application settings can't fail the test, and is never registered
+    pub async fn self_test(&self, cleanup: bool) -> Result<SelfTestReport> {
+        self.ensure_writable()?;
+        self.check_memtable_limit()?;
+
+        let dev_eui = DevEui::new(self.config.selftest_dev_eui.clone())?;
+        let now = Utc::now();
+        let frame = Frame::Uplink(UplinkFrame {
+            dev_eui: dev_eui.clone(),
+            application_id: ApplicationId::new(SELFTEST_APPLICATION_ID.to_string()),
+            device_name: None,
+            received_at: now,
+            stored_at: Some(now),
+            f_port: 0,
+            f_cnt: 0,
+            confirmed: false,
+            adr: false,
+            dr: DataRate::new_lora(125000, 7),
+            frequency: 0,
+            rx_info: vec![],
+            decoded_payload: None,
+            raw_payload: None,
+        });
+
+        let started = std::time::Instant::now();
+        self.append_and_insert(frame)?;
+        let write_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let started = std::time::Instant::now();
+        let window = chrono::Duration::seconds(1);
+        let frames = self.query(&dev_eui, Some(now - window), Some(now + window)).await?;
+        let read_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let success = frames.iter().any(|frame| frame.timestamp() == now);
+        if !success {
+            error!("Self-test canary for {} was not read back", dev_eui.as_str());
+        }
+
+        let cleaned_up = if cleanup {
+            Some(self.delete_device(&dev_eui).await?)
+        } else {
+            None
+        };
+
+        Ok(SelfTestReport {
+            dev_eui: dev_eui.as_str().to_string(),
+            success,
+            write_ms,
+            read_ms,
+            cleaned_up,
+        })
+    }
+
+    /// Delete every device of an application and all their data, with one SSTable
+    /// rewrite for the whole application, and remove its retention policy
+    pub async fn delete_application(&self, app_id: &str) -> Result<ApplicationDeletion> {
+        self.ensure_writable()?;
+        let app_id = ApplicationId::normalize(app_id);
+        info!("Deleting all data for application {}", app_id);
+
+        let matches = |frame: &Frame| {
+            frame
+                .application_id()
+                .is_some_and(|id| id.normalized() == app_id)
+        };
+
+        // 1. Delete from memtable
+        let mut deleted_frames = {
+            let memtable = self.memtable.read();
+            memtable.delete_application(&app_id)
+        };
+
+        // 2. Rewrite SSTables without the application's data
+        deleted_frames += self.rewrite_sstables_without(matches).await?;
+
+        // 3. Remove its devices from the registry, with their soft deletes and names
+        let devices = self.device_registry.remove_application(&app_id);
+        for dev_eui in &devices {
+            self.gateway_registry.remove_device(dev_eui);
+            self.tombstones.remove(dev_eui).await?;
+            self.device_names.remove(dev_eui).await?;
+        }
+
+        // 4. Without data it would only fall back to the global policy anyway
+        self.retention_manager.remove_application(&app_id).await?;
+
         info!(
-            "Deleted total of {} frames for device {}",
-            total_deleted,
-            dev_eui.as_str()
+            "Deleted {} frames from {} devices for application {}",
+            deleted_frames,
+            devices.len(),
+            app_id
         );
 
-        Ok(total_deleted)
+        Ok(ApplicationDeletion {
+            deleted_frames,
+            deleted_devices: devices.len(),
+        })
     }
 
     /// Gracefully shut down storage engine by flushing memtable to SSTable
     pub async fn shutdown(&self) -> Result<()> {
         info!("Shutting down storage engine");
 
+        if self.config.read_only {
+            info!("Storage engine shutdown complete (read-only, nothing to flush)");
+            return Ok(());
+        }
+
+        if self.config.in_memory {
+            let frames = self.memtable.read().len();
+            info!("Storage engine shutdown complete (in memory, {} frames discarded)", frames);
+            return Ok(());
+        }
+
         // Check if memtable has any data to flush
         let has_data = {
             let memtable = self.memtable.read();
@@ -679,19 +2206,86 @@ impl StorageEngine {
         }
 
         // Sync WAL to ensure all data is written
-        {
-            let wal = self.wal.read();
-            wal.sync()?;
+        if let Some(wal) = &self.wal {
+            wal.read().sync()?;
         }
 
+        self.quota_manager.save_usage().await?;
+
         info!("Storage engine shutdown complete");
         Ok(())
     }
 }
 
+/// Whether an error was caused by the filesystem running out of space (ENOSPC) or quota (EDQUOT)
+fn is_out_of_space(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|io| {
+            matches!(
+                io.kind(),
+                std::io::ErrorKind::StorageFull | std::io::ErrorKind::QuotaExceeded
+            )
+        })
+}
+
+/// Load the indexes of the `count` newest SSTables (by ID) into the open-table cache
+fn warm_sstables(sstables: &[SSTableReader], count: usize, read_data: bool) {
+    let started = std::time::Instant::now();
+    let mut newest: Vec<&SSTableReader> = sstables.iter().collect();
+    newest.sort_by_key(|sstable| std::cmp::Reverse(sstable.id()));
+    newest.truncate(count);
+
+    // Oldest first, so the newest tables are the last to be evicted
+    let mut warmed = 0;
+    for sstable in newest.iter().rev() {
+        match sstable.warm(read_data) {
+            Ok(()) => warmed += 1,
+            Err(e) => warn!("Failed to warm up SSTable {:?}: {}", sstable.path(), e),
+        }
+    }
+
+    info!(
+        "Warmed up {} SSTable(s){} in {:?}",
+        warmed,
+        if read_data { " including data" } else { "" },
+        started.elapsed()
+    );
+}
+
+/// Register every device and gateway with frames in `sstable`,
+/// returning the number of frames seen
+fn register_sstable_frames(
+    registry: &DeviceRegistry,
+    gateways: &GatewayRegistry,
+    sstable: &SSTableReader,
+) -> usize {
+    let mut frame_count = 0;
+    if let Ok(frames) = sstable.iter_all() {
+        for frame in frames {
+            gateways.record(frame.dev_eui(), frame.rx_info(), frame.timestamp());
+            registry.register_or_update(
+                frame.dev_eui().clone(),
+                match &frame {
+                    Frame::Uplink(f) => f.device_name.clone(),
+                    Frame::Downlink(_) => None,
+                    _ => None,
+                },
+                frame
+                    .application_id()
+                    .map(|id| id.as_str().to_string())
+                    .unwrap_or_default(),
+            );
+            frame_count += 1;
+        }
+    }
+    frame_count
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{ClockSkewAction, StorageLayout, TimestampSource};
     use crate::model::frames::UplinkFrame;
     use crate::model::lorawan::*;
     use std::collections::HashMap;
@@ -700,15 +2294,41 @@ mod tests {
     fn create_test_config(data_dir: &std::path::Path) -> StorageConfig {
         StorageConfig {
             data_dir: data_dir.to_path_buf(),
-            wal_sync_interval_ms: 1000,
+            wal_durability: WalDurability::Interval(1000),
             memtable_size_mb: 1, // Small for testing
+            memtable_hard_limit_factor: 0,
             memtable_flush_interval_secs: 300,
             compaction_threshold: 3,
+            max_open_sstables: 128,
+            warmup_sstables: 0,
+            warmup_read_data: false,
+            compaction_max_bytes_per_sec: 0,
+            compaction_fragmentation_threshold: 0.75,
+            compaction_target_sstable_entries: 10_000,
+            max_sstable_bytes: 0,
+            layout: StorageLayout::Flat,
+            max_clock_skew_secs: 300,
+            clock_skew_action: ClockSkewAction::Reject,
+            timestamp_source: TimestampSource::Network,
+            application_key: ApplicationKey::Id,
+            unknown_application_action: UnknownApplicationAction::Fallback,
+            fallback_application_id: "unknown".to_string(),
+            max_payload_depth: 16,
+            max_payload_bytes: 64 * 1024,
+            oversized_payload_action: OversizedPayloadAction::Reject,
+            selftest_dev_eui: "fffffffffffffffe".to_string(),
+            maintenance_window: None,
+            read_only: false,
+            replica_refresh_secs: 30,
+            in_memory: false,
             enable_encryption: false,
             encryption_key: None,
             retention_days: None,
             retention_apps: HashMap::new(),
             retention_check_interval_hours: 24,
+            retention_max_total_bytes: None,
+            retention_default_app_policy: None,
+            device_delete_grace_hours: 0,
         }
     }
 
@@ -718,6 +2338,7 @@ mod tests {
             application_id: ApplicationId::new("test-app".to_string()),
             device_name: Some("test-device".to_string()),
             received_at: timestamp,
+            stored_at: None,
             f_port: 1,
             f_cnt: 42,
             confirmed: false,
@@ -749,6 +2370,936 @@ mod tests {
         assert_eq!(results[0].dev_eui(), &dev_eui);
     }
 
+    #[tokio::test]
+    async fn test_query_keeps_identical_timestamps_across_flushes() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let at = Utc::now() - chrono::Duration::minutes(5);
+
+        // The same timestamp in two SSTables and the memtable
+        for flush in [true, true, false] {
+            engine.write(create_test_frame("0123456789ABCDEF", at)).await.unwrap();
+            if flush {
+                engine.flush_memtable().await.unwrap();
+            }
+        }
+
+        let results = engine.query(&dev_eui, Some(at), Some(at)).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(engine.scan_iter(&dev_eui, Some(at), Some(at)).count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_scan_iter_merges_memtable_and_sstables() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let base = Utc::now() - chrono::Duration::minutes(10);
+        let at = |secs: i64| base + chrono::Duration::seconds(secs);
+
+        // Interleave timestamps across two SSTables and the memtable
+        for (batch, secs) in [[0, 3, 6], [1, 4, 7], [2, 5, 8]].iter().enumerate() {
+            for &s in secs {
+                engine.write(create_test_frame("0123456789ABCDEF", at(s))).await.unwrap();
+            }
+            engine.write(create_test_frame("FEDCBA9876543210", at(0))).await.unwrap();
+            if batch < 2 {
+                engine.flush_memtable().await.unwrap();
+            }
+        }
+
+        let mut scan = engine.scan_iter(&dev_eui, None, None);
+        let first = scan.next().unwrap().unwrap();
+        assert_eq!(first.timestamp(), at(0));
+
+        // Frames flushed after the scan started don't disturb it
+        engine.flush_memtable().await.unwrap();
+        let rest: Vec<_> = scan.map(|f| f.unwrap().timestamp()).collect();
+        assert_eq!(rest, (1..9).map(at).collect::<Vec<_>>());
+
+        let ranged: Vec<_> = engine
+            .scan_iter(&dev_eui, Some(at(2)), Some(at(5)))
+            .map(|f| f.unwrap().timestamp())
+            .collect();
+        assert_eq!(ranged, (2..=5).map(at).collect::<Vec<_>>());
+
+        let unknown = DevEui::new("1111111111111111".to_string()).unwrap();
+        assert_eq!(engine.scan_iter(&unknown, None, None).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_queries_reopen_sstables_beyond_open_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        config.max_open_sstables = 1;
+        let engine = StorageEngine::new(config.clone()).await.unwrap();
+
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        for i in 0..3 {
+            let timestamp = now - chrono::Duration::seconds(i);
+            engine.write(create_test_frame("0123456789ABCDEF", timestamp)).await.unwrap();
+            engine.flush_memtable().await.unwrap();
+        }
+        assert_eq!(engine.sstables.read().len(), 3);
+
+        // Every table is read, but only one stays open
+        let results = engine.query(&dev_eui, None, None).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(engine.sstable_cache.len(), 1);
+
+        let results = engine.query(&dev_eui, None, None).await.unwrap();
+        assert_eq!(results.len(), 3);
+
+        // Startup only loads metadata for pruning, the registry rebuild stays within the limit
+        drop(engine);
+        let engine = StorageEngine::new(config).await.unwrap();
+        assert!(engine.sstable_cache.len() <= 1);
+        let results = engine.query(&dev_eui, None, None).await.unwrap();
+        assert_eq!(results.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_opens_newest_sstables() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        let now = Utc::now();
+
+        {
+            let engine = StorageEngine::new(config.clone()).await.unwrap();
+            for i in 0..3 {
+                let timestamp = now - chrono::Duration::seconds(i);
+                engine.write(create_test_frame("0123456789ABCDEF", timestamp)).await.unwrap();
+                engine.flush_memtable().await.unwrap();
+            }
+        }
+
+        config.max_open_sstables = 2;
+        config.warmup_sstables = 5;
+        config.warmup_read_data = true;
+        let engine = StorageEngine::new(config).await.unwrap();
+
+        let mut ids: Vec<(u64, PathBuf)> = engine
+            .sstables
+            .read()
+            .iter()
+            .map(|s| (s.id(), s.path().to_path_buf()))
+            .collect();
+        ids.sort();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(engine.sstable_cache.len(), 2);
+        assert!(!engine.sstable_cache.contains(&ids[0].1));
+        assert!(engine.sstable_cache.contains(&ids[1].1));
+        assert!(engine.sstable_cache.contains(&ids[2].1));
+    }
+
+    #[tokio::test]
+    async fn test_soft_deleted_device_can_be_undeleted_until_purged() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789abcdef".to_string()).unwrap();
+        let now = Utc::now();
+
+        {
+            let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+            engine.write(create_test_frame("0123456789abcdef", now)).await.unwrap();
+            engine.flush_memtable().await.unwrap();
+
+            engine.soft_delete_device(&dev_eui, chrono::Duration::hours(1)).await.unwrap();
+            assert!(engine.query(&dev_eui, None, None).await.unwrap().is_empty());
+            assert!(engine.latest_frame(&dev_eui).await.unwrap().is_none());
+            assert_eq!(engine.device_registry().device_count(), 0);
+
+            // Still within the grace period, nothing to purge
+            assert_eq!(engine.purge_deleted_devices().await.unwrap(), 0);
+        }
+
+        // The tombstone survives a restart
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        assert!(engine.query(&dev_eui, None, None).await.unwrap().is_empty());
+
+        assert!(engine.undelete_device(&dev_eui).await.unwrap());
+        assert!(!engine.undelete_device(&dev_eui).await.unwrap());
+        assert_eq!(engine.query(&dev_eui, None, None).await.unwrap().len(), 1);
+        assert_eq!(engine.device_registry().device_count(), 1);
+
+        // Once the grace period is over the data is gone for good
+        engine.soft_delete_device(&dev_eui, chrono::Duration::zero()).await.unwrap();
+        assert_eq!(engine.purge_deleted_devices().await.unwrap(), 1);
+        assert!(!engine.undelete_device(&dev_eui).await.unwrap());
+        assert!(engine.query(&dev_eui, None, None).await.unwrap().is_empty());
+        assert!(engine.tombstones().list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_new_application_gets_default_retention_policy() {
+        use retention_manager::DefaultApplicationPolicy;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        config.retention_default_app_policy = Some(DefaultApplicationPolicy { days: Some(30) });
+        let now = Utc::now();
+
+        {
+            let engine = StorageEngine::new(config.clone()).await.unwrap();
+            let retention = engine.retention_manager();
+            engine.write(create_test_frame("0123456789abcdef", now)).await.unwrap();
+            assert_eq!(retention.get_application("test-app").await.unwrap().days, Some(30));
+
+            // Only the first frame materializes it, a removed policy stays removed
+            retention.remove_application("test-app").await.unwrap();
+            engine.write(create_test_frame("fedcba9876543210", now)).await.unwrap();
+            assert!(retention.get_application("test-app").await.is_none());
+            engine.shutdown().await.unwrap();
+        }
+
+        // Applications with stored data aren't new after a restart either
+        let engine = StorageEngine::new(config).await.unwrap();
+        engine.write(create_test_frame("0123456789abcdef", now)).await.unwrap();
+        assert!(engine.retention_manager().get_application("test-app").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_application_in_one_pass() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let now = Utc::now();
+        let other_app_frame = |dev_eui: &str| {
+            let mut frame = create_test_frame(dev_eui, now);
+            if let Frame::Uplink(uplink) = &mut frame {
+                uplink.application_id = ApplicationId::new("other-app".to_string());
+            }
+            frame
+        };
+
+        // A mixed SSTable, one with only the deleted app, and the memtable
+        engine.write(create_test_frame("0000000000000001", now)).await.unwrap();
+        engine.write(other_app_frame("0000000000000003")).await.unwrap();
+        engine.flush_memtable().await.unwrap();
+        engine.write(create_test_frame("0000000000000002", now)).await.unwrap();
+        engine.flush_memtable().await.unwrap();
+        engine.write(create_test_frame("0000000000000001", now)).await.unwrap();
+        engine.write(other_app_frame("0000000000000003")).await.unwrap();
+
+        let retention = engine.retention_manager();
+        retention.set_application("TEST-APP".to_string(), Some(7)).await.unwrap();
+
+        let deleted = engine.delete_application("Test-App").await.unwrap();
+        assert_eq!(deleted.deleted_frames, 3);
+        assert_eq!(deleted.deleted_devices, 2);
+
+        let gone = DevEui::new("0000000000000001".to_string()).unwrap();
+        let kept = DevEui::new("0000000000000003".to_string()).unwrap();
+        assert!(engine.query(&gone, None, None).await.unwrap().is_empty());
+        assert_eq!(engine.query(&kept, None, None).await.unwrap().len(), 2);
+        assert_eq!(engine.device_registry().device_count(), 1);
+        assert_eq!(engine.sstable_stats().len(), 1);
+        assert!(retention.get_application("test-app").await.is_none());
+
+        // The next frame makes it a new application again
+        assert!(engine
+            .device_registry()
+            .register_or_update(gone, None, "test-app".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_transforms_enrich_frames_before_storing() {
+        use transform_manager::Transform;
+
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let dev_eui = DevEui::new("0123456789abcdef".to_string()).unwrap();
+        engine
+            .transforms()
+            .set(
+                "TEST-APP",
+                vec![Transform::Constant {
+                    field: "site".to_string(),
+                    value: serde_json::json!("north"),
+                }],
+            )
+            .await
+            .unwrap();
+
+        engine.write(create_test_frame("0123456789abcdef", Utc::now())).await.unwrap();
+
+        let frames = engine.query(&dev_eui, None, None).await.unwrap();
+        match &frames[0] {
+            Frame::Uplink(f) => {
+                let payload = f.decoded_payload.as_ref().unwrap();
+                assert_eq!(payload.get_field("site"), Some(&serde_json::json!("north")));
+            }
+            other => panic!("expected an uplink, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_device_name_survives_registry_rebuild() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789abcdef".to_string()).unwrap();
+
+        {
+            let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+            engine.write(create_test_frame("0123456789abcdef", Utc::now())).await.unwrap();
+            engine.set_device_name(&dev_eui, Some("Boiler room")).await.unwrap();
+            engine.shutdown().await.unwrap();
+        }
+
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let device = engine.device_registry().get(&dev_eui).unwrap();
+        assert_eq!(device.device_name.as_deref(), Some("Boiler room"));
+
+        // Deleting the device drops its name along with its data
+        engine.delete_device(&dev_eui).await.unwrap();
+        assert!(engine.device_registry().name_override(&dev_eui).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_frame_processor_drains_channel_on_shutdown() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+        let frame_count = 50;
+
+        {
+            let engine = Arc::new(StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap());
+
+            // Fill the channel before the processor gets a chance to run
+            let (frame_tx, frame_rx) = mpsc::channel(frame_count);
+            for i in 0..frame_count {
+                let timestamp = now - chrono::Duration::seconds(i as i64);
+                frame_tx
+                    .send(create_test_frame("0123456789ABCDEF", timestamp))
+                    .await
+                    .unwrap();
+            }
+
+            let processor = tokio::spawn(engine.clone().start_frame_processor(frame_rx));
+
+            // Shutdown sequence: close the channel, wait for the drain, then flush
+            drop(frame_tx);
+            processor.await.unwrap();
+            engine.shutdown().await.unwrap();
+        }
+
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let results = engine.query(&dev_eui, None, None).await.unwrap();
+        assert_eq!(results.len(), frame_count);
+    }
+
+    #[tokio::test]
+    async fn test_write_does_not_block_on_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        // Slow enough that an inline compaction would take well over a second
+        config.compaction_max_bytes_per_sec = 1000;
+        let engine = Arc::new(StorageEngine::new(config).await.unwrap());
+        let now = Utc::now();
+
+        // Flush past the threshold (3) to leave a compaction pending
+        for i in 0..4 {
+            let timestamp = now - chrono::Duration::seconds(i);
+            engine.write(create_test_frame("0123456789ABCDEF", timestamp)).await.unwrap();
+            engine.flush_memtable().await.unwrap();
+        }
+        assert_eq!(engine.sstables.read().len(), 4);
+
+        let compaction_handle = engine.clone().start_compaction_task();
+
+        // A write + flush while compaction is running must not wait for it
+        let started = std::time::Instant::now();
+        engine.write(create_test_frame("0123456789ABCDEF", now + chrono::Duration::seconds(1))).await.unwrap();
+        engine.flush_memtable().await.unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_millis(500));
+
+        // The background task eventually brings the SSTable count back under threshold
+        tokio::time::timeout(std::time::Duration::from_secs(30), async {
+            while engine.needs_compaction() {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        compaction_handle.abort();
+
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        assert_eq!(engine.query(&dev_eui, None, None).await.unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_disk_full_is_latched_until_success() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        assert!(!engine.is_disk_full());
+
+        // Unrelated failures don't count
+        let other: Result<()> = Err(anyhow::anyhow!("corrupt block"));
+        assert!(engine.track_disk_space("flush", other).is_err());
+        assert!(!engine.is_disk_full());
+
+        // ENOSPC is recognized through added context
+        let full: Result<()> = Err(anyhow::Error::new(std::io::Error::from_raw_os_error(28))
+            .context("Failed to write SSTable"));
+        assert!(engine.track_disk_space("flush", full).is_err());
+        assert!(engine.is_disk_full());
+
+        assert!(engine.track_disk_space("compaction", Ok(())).is_ok());
+        assert!(!engine.is_disk_full());
+    }
+
+    #[tokio::test]
+    async fn test_memtable_hard_limit_refuses_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        config.memtable_hard_limit_factor = 1;
+        let engine = StorageEngine::new(config).await.unwrap();
+        let now = Utc::now();
+
+        // A full disk stops flushes, so the memtable keeps growing
+        let full: Result<()> = Err(anyhow::Error::new(std::io::Error::from_raw_os_error(28)));
+        assert!(engine.track_disk_space("flush", full).is_err());
+
+        let mut accepted = 0;
+        let err = loop {
+            let frame = create_test_frame("0123456789ABCDEF", now + chrono::Duration::milliseconds(accepted));
+            match engine.write(frame).await {
+                Ok(()) => accepted += 1,
+                Err(e) => break e,
+            }
+            assert!(accepted < 100_000, "memtable limit never reached");
+        };
+        assert!(matches!(err.downcast_ref::<LoraDbError>(), Some(LoraDbError::Overloaded(_))));
+        assert!(engine.is_memtable_full());
+        assert!(engine.memtable.read().size_bytes() >= 1024 * 1024);
+
+        // Once a flush gets through, writes are accepted again
+        engine.flush_memtable().await.unwrap();
+        assert!(!engine.is_memtable_full());
+        engine.write(create_test_frame("0123456789ABCDEF", now)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_engine_never_touches_disk() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join("unused");
+        let mut config = create_test_config(&data_dir);
+        config.in_memory = true;
+        let engine = StorageEngine::new(config).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        for i in 0..3 {
+            let timestamp = now - chrono::Duration::seconds(i);
+            engine.write(create_test_frame("0123456789ABCDEF", timestamp)).await.unwrap();
+        }
+        // Flushing keeps frames in the memtable, there is nowhere else to put them
+        engine.flush_memtable().await.unwrap();
+        assert_eq!(engine.query(&dev_eui, None, None).await.unwrap().len(), 3);
+        assert!(engine.device_registry().get(&dev_eui).is_some());
+
+        // Settings changes are kept in memory only
+        engine.quota_manager().set("app", Some(10), None).await.unwrap();
+        engine.retention_manager().set_global(Some(7)).await.unwrap();
+        assert_eq!(engine.retention_manager().get_global().await, Some(7));
+
+        engine.shutdown().await.unwrap();
+        assert!(!data_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_periodic_flush_picks_up_interval_change() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        config.memtable_flush_interval_secs = 3600;
+        let engine = Arc::new(StorageEngine::new(config).await.unwrap());
+
+        let flush_handle = engine.clone().start_periodic_flush();
+        // Let the startup pass run against the empty memtable
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        engine.write(create_test_frame("0123456789ABCDEF", Utc::now())).await.unwrap();
+        assert!(engine.set_flush_interval_secs(0).is_err());
+        engine.set_flush_interval_secs(1).unwrap();
+
+        // Flushed on the new interval rather than an hour from startup
+        tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            while engine.sstables.read().is_empty() {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        flush_handle.abort();
+
+        assert_eq!(engine.flush_interval_secs(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_per_application_layout_flush_and_recovery() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        config.layout = StorageLayout::PerApplication;
+
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let other_eui = DevEui::new("FEDCBA9876543210".to_string()).unwrap();
+        let now = Utc::now();
+
+        {
+            let engine = StorageEngine::new(config.clone()).await.unwrap();
+            engine.write(create_test_frame("0123456789ABCDEF", now)).await.unwrap();
+            let mut other = create_test_frame("FEDCBA9876543210", now);
+            if let Frame::Uplink(ref mut uplink) = other {
+                uplink.application_id = ApplicationId::new("other-app".to_string());
+            }
+            engine.write(other).await.unwrap();
+            engine.flush_memtable().await.unwrap();
+        }
+
+        // One SSTable per application, none at the top level
+        let compaction = CompactionManager::new(temp_dir.path().to_path_buf(), 3);
+        let paths = compaction.find_sstables().unwrap();
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().any(|p| p.parent() == Some(&temp_dir.path().join("test-app"))));
+        assert!(paths.iter().any(|p| p.parent() == Some(&temp_dir.path().join("other-app"))));
+
+        // Reopening with the flat layout still finds the partitioned SSTables
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        assert_eq!(engine.query(&dev_eui, None, None).await.unwrap().len(), 1);
+        assert_eq!(engine.query(&other_eui, None, None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retention_matches_application_id_case_insensitively() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+
+        // Policy set with one casing, data ingested with another
+        engine
+            .retention_manager()
+            .set_application("Env-Sensors".to_string(), Some(30))
+            .await
+            .unwrap();
+
+        let mut frame = create_test_frame("0123456789ABCDEF", Utc::now() - chrono::Duration::days(60));
+        if let Frame::Uplink(ref mut uplink) = frame {
+            uplink.application_id = ApplicationId::new("env-sensors".to_string());
+        }
+        engine.write(frame).await.unwrap();
+        engine.flush_memtable().await.unwrap();
+        assert_eq!(engine.query(&dev_eui, None, None).await.unwrap().len(), 1);
+
+        engine.enforce_retention().await.unwrap();
+        assert!(engine.query(&dev_eui, None, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retention_size_budget_deletes_oldest_sstables() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        for hours_ago in [3, 2, 1] {
+            let frame = create_test_frame("0123456789ABCDEF", now - chrono::Duration::hours(hours_ago));
+            engine.write(frame).await.unwrap();
+            engine.flush_memtable().await.unwrap();
+        }
+
+        let stats = engine.sstable_stats();
+        assert_eq!(stats.len(), 3);
+        for sstable in engine.sstables.read().iter() {
+            let on_disk = std::fs::metadata(sstable.path()).unwrap().len();
+            assert_eq!(sstable.metadata().file_size_bytes, on_disk);
+        }
+
+        // Room for the two newest SSTables only
+        let budget = stats[1].file_size_bytes + stats[2].file_size_bytes;
+        engine.retention_manager().set_max_total_bytes(Some(budget)).await.unwrap();
+        engine.enforce_retention().await.unwrap();
+
+        let remaining: Vec<u64> = engine.sstable_stats().iter().map(|s| s.id).collect();
+        assert_eq!(remaining, vec![stats[1].id, stats[2].id]);
+        let frames = engine.query(&dev_eui, None, None).await.unwrap();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|f| f.timestamp() > now - chrono::Duration::hours(3)));
+
+        // Already within budget, nothing more is deleted
+        engine.enforce_retention().await.unwrap();
+        assert_eq!(engine.sstable_stats().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retention_size_budget_orders_by_oldest_frame() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let now = Utc::now();
+
+        // The first key of the older table belongs to its newer frame
+        for (dev_eui, hours_ago) in [("0000000000000001", 1), ("FEDCBA9876543210", 5)] {
+            let frame = create_test_frame(dev_eui, now - chrono::Duration::hours(hours_ago));
+            engine.write(frame).await.unwrap();
+        }
+        engine.flush_memtable().await.unwrap();
+        let frame = create_test_frame("0000000000000001", now - chrono::Duration::hours(2));
+        engine.write(frame).await.unwrap();
+        engine.flush_memtable().await.unwrap();
+
+        let stats = engine.sstable_stats();
+        assert_eq!(stats.len(), 2);
+        engine
+            .retention_manager()
+            .set_max_total_bytes(Some(stats[1].file_size_bytes))
+            .await
+            .unwrap();
+        engine.enforce_retention().await.unwrap();
+
+        let remaining: Vec<u64> = engine.sstable_stats().iter().map(|s| s.id).collect();
+        assert_eq!(remaining, vec![stats[1].id]);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_replica_refresh() {
+        let temp_dir = TempDir::new().unwrap();
+        let primary = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        primary.write(create_test_frame("0123456789ABCDEF", now)).await.unwrap();
+        primary.flush_memtable().await.unwrap();
+
+        let mut replica_config = create_test_config(temp_dir.path());
+        replica_config.read_only = true;
+        let replica = StorageEngine::new(replica_config).await.unwrap();
+        assert_eq!(replica.query(&dev_eui, None, None).await.unwrap().len(), 1);
+        assert_eq!(replica.device_registry().device_count(), 1);
+
+        // Writes are rejected without touching the data directory
+        let err = replica
+            .write(create_test_frame("0123456789ABCDEF", now))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<LoraDbError>(), Some(LoraDbError::ReadOnly(_))));
+        assert!(replica.delete_device(&dev_eui).await.is_err());
+
+        // Frames only become visible once the primary flushes them
+        primary
+            .write(create_test_frame("0123456789ABCDEF", now + chrono::Duration::seconds(1)))
+            .await
+            .unwrap();
+        assert_eq!(replica.refresh_sstables().unwrap(), (0, 0));
+        primary.flush_memtable().await.unwrap();
+        assert_eq!(replica.refresh_sstables().unwrap(), (1, 0));
+        assert_eq!(replica.query(&dev_eui, None, None).await.unwrap().len(), 2);
+
+        // SSTables the primary compacts away are dropped in favour of the merged one
+        let paths = primary.compaction_manager.read().find_sstables().unwrap();
+        primary.compact_dir(temp_dir.path().to_path_buf(), paths).await.unwrap();
+        assert_eq!(replica.refresh_sstables().unwrap(), (1, 2));
+        assert_eq!(replica.query(&dev_eui, None, None).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_history_page_resumes_across_sstables() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let base = Utc::now() - chrono::Duration::days(365);
+
+        // Interleave timestamps across two SSTables and the memtable
+        for i in 0..10 {
+            engine
+                .write(create_test_frame("0123456789ABCDEF", base + chrono::Duration::seconds(i * 3)))
+                .await
+                .unwrap();
+        }
+        engine.flush_memtable().await.unwrap();
+        for i in 0..10 {
+            engine
+                .write(create_test_frame("0123456789ABCDEF", base + chrono::Duration::seconds(i * 3 + 1)))
+                .await
+                .unwrap();
+        }
+        engine.flush_memtable().await.unwrap();
+        for i in 0..5 {
+            engine
+                .write(create_test_frame("0123456789ABCDEF", base + chrono::Duration::seconds(i * 3 + 2)))
+                .await
+                .unwrap();
+        }
+
+        let mut exported = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = engine.history_page(&dev_eui, cursor, 4).await.unwrap();
+            assert!(page.frames.len() <= 4);
+            exported.extend(page.frames);
+            match page.resume_after {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        assert_eq!(exported.len(), 25);
+        assert!(exported.windows(2).all(|w| w[0].timestamp() < w[1].timestamp()));
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew_reject_and_clamp() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let future = Utc::now() + chrono::Duration::hours(6);
+
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        assert!(engine.write(create_test_frame("0123456789ABCDEF", future)).await.is_err());
+        assert!(engine
+            .write(create_test_frame("0123456789ABCDEF", DateTime::from_timestamp(0, 0).unwrap()))
+            .await
+            .is_err());
+        assert_eq!(engine.clock_skew_stats(), ClockSkewStats { rejected: 2, clamped: 0 });
+        assert!(engine.query(&dev_eui, None, None).await.unwrap().is_empty());
+
+        let clamp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(clamp_dir.path());
+        config.clock_skew_action = ClockSkewAction::Clamp;
+        let engine = StorageEngine::new(config).await.unwrap();
+        engine.write(create_test_frame("0123456789ABCDEF", future)).await.unwrap();
+        assert_eq!(engine.clock_skew_stats(), ClockSkewStats { rejected: 0, clamped: 1 });
+
+        let stored = engine.query(&dev_eui, None, None).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].timestamp() <= Utc::now());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_writes_during_flush_survive_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let engine = Arc::new(StorageEngine::new(config.clone()).await.unwrap());
+        let start = Utc::now() - chrono::Duration::minutes(2);
+
+        // Writers race a flush loop, every frame has its own timestamp
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let engine = engine.clone();
+                tokio::spawn(async move {
+                    for i in 0..1000 {
+                        let timestamp = start + chrono::Duration::milliseconds(writer * 10000 + i);
+                        let frame = create_test_frame("0123456789ABCDEF", timestamp);
+                        engine.write(frame).await.unwrap();
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        let done = Arc::new(AtomicBool::new(false));
+        let flusher = {
+            let (engine, done) = (engine.clone(), done.clone());
+            tokio::spawn(async move {
+                while !done.load(Ordering::Relaxed) {
+                    engine.flush().await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+        for writer in writers {
+            writer.await.unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        flusher.await.unwrap();
+
+        // Reopened without a shutdown flush, so unflushed frames come back from the WAL
+        drop(engine);
+        let engine = StorageEngine::new(config).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let frames = engine.query(&dev_eui, None, None).await.unwrap();
+        assert_eq!(frames.len(), 8000);
+
+        // Each frame is either flushed or replayed, never both
+        let flushed: u64 = engine.sstables.read().iter().map(|s| s.metadata().num_entries).sum();
+        assert_eq!(engine.recovery_report().recovered as u64 + flushed, 8000);
+    }
+
+    #[tokio::test]
+    async fn test_flush_splits_at_max_sstable_bytes() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        config.max_sstable_bytes = 300;
+        let engine = StorageEngine::new(config).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        for i in 0..10 {
+            let timestamp = now - chrono::Duration::seconds(i);
+            engine.write(create_test_frame("0123456789ABCDEF", timestamp)).await.unwrap();
+        }
+        engine.flush_memtable().await.unwrap();
+
+        let stats = engine.sstable_stats();
+        assert!(stats.len() > 1);
+        assert_eq!(stats.iter().map(|s| s.num_entries).sum::<u64>(), 10);
+        // Every table but the last is rolled over as soon as it reaches the limit
+        let entry_size = stats[0].data_size_bytes / stats[0].num_entries;
+        for table in &stats[..stats.len() - 1] {
+            assert!(table.data_size_bytes >= 300 && table.data_size_bytes < 300 + entry_size);
+        }
+
+        assert_eq!(engine.query(&dev_eui, None, None).await.unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_device_storage_usage() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        for batch in 0..2 {
+            for i in 0..3 {
+                let timestamp = now - chrono::Duration::minutes(batch * 10 + i);
+                engine.write(create_test_frame("0123456789ABCDEF", timestamp)).await.unwrap();
+            }
+            engine.flush_memtable().await.unwrap();
+        }
+        engine.write(create_test_frame("FEDCBA9876543210", now)).await.unwrap();
+        // Not flushed yet, so not counted
+        engine.write(create_test_frame("0123456789ABCDEF", now)).await.unwrap();
+
+        let usage = engine.device_storage_usage(&dev_eui).unwrap();
+        assert_eq!(usage.frames, 6);
+        assert_eq!(usage.sstables, 2);
+        assert!(usage.compressed_bytes > 0);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_application_fallback_and_reject() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let mut orphan = create_test_frame("0123456789ABCDEF", Utc::now());
+        orphan.set_application_id(ApplicationId::new(String::new()));
+
+        let mut config = create_test_config(temp_dir.path());
+        config.fallback_application_id = "orphans".to_string();
+        let engine = StorageEngine::new(config).await.unwrap();
+        engine.write(orphan.clone()).await.unwrap();
+        assert_eq!(
+            engine.unknown_application_stats(),
+            UnknownApplicationStats { rejected: 0, fallback: 1 }
+        );
+        let stored = engine.query(&dev_eui, None, None).await.unwrap();
+        assert_eq!(stored[0].application_id().unwrap().as_str(), "orphans");
+
+        let reject_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(reject_dir.path());
+        config.unknown_application_action = UnknownApplicationAction::Reject;
+        let engine = StorageEngine::new(config).await.unwrap();
+        assert!(engine.write(orphan).await.is_err());
+        engine.write(create_test_frame("0123456789ABCDEF", Utc::now())).await.unwrap();
+        assert_eq!(
+            engine.unknown_application_stats(),
+            UnknownApplicationStats { rejected: 1, fallback: 0 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_payload_reject_and_truncate() {
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let mut nested = create_test_frame("0123456789ABCDEF", Utc::now());
+        if let Frame::Uplink(uplink) = &mut nested {
+            uplink.decoded_payload = Some(DecodedPayload::from_json(
+                serde_json::json!({"temperature": 21.5, "a": {"b": {"c": 1}}}),
+            ));
+        }
+        let mut large = create_test_frame(
+            "0123456789ABCDEF",
+            Utc::now() + chrono::Duration::seconds(1),
+        );
+        if let Frame::Uplink(uplink) = &mut large {
+            uplink.decoded_payload =
+                Some(DecodedPayload::from_json(serde_json::json!({"blob": "x".repeat(200)})));
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        config.max_payload_depth = 2;
+        config.max_payload_bytes = 100;
+        let engine = StorageEngine::new(config.clone()).await.unwrap();
+        assert!(engine.write(nested.clone()).await.is_err());
+        assert!(engine.write(large.clone()).await.is_err());
+        assert_eq!(
+            engine.oversized_payload_stats(),
+            OversizedPayloadStats { rejected: 2, truncated: 0 }
+        );
+
+        let truncate_dir = TempDir::new().unwrap();
+        config.data_dir = truncate_dir.path().to_path_buf();
+        config.oversized_payload_action = OversizedPayloadAction::Truncate;
+        let engine = StorageEngine::new(config).await.unwrap();
+        engine.write(nested).await.unwrap();
+        engine.write(large).await.unwrap();
+        assert_eq!(
+            engine.oversized_payload_stats(),
+            OversizedPayloadStats { rejected: 0, truncated: 2 }
+        );
+
+        // Cut to the depth limit, and dropped when still too large
+        let stored = engine.query(&dev_eui, None, None).await.unwrap();
+        let payloads: Vec<_> = stored
+            .iter()
+            .map(|frame| match frame {
+                Frame::Uplink(uplink) => {
+                    uplink.decoded_payload.as_ref().map(|p| p.object.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        let truncated = serde_json::json!({"temperature": 21.5, "a": {"b": null}});
+        assert!(payloads.contains(&Some(truncated)));
+        assert!(payloads.contains(&None));
+    }
+
+    #[tokio::test]
+    async fn test_ingest_timestamp_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let mut config = create_test_config(temp_dir.path());
+        config.timestamp_source = TimestampSource::Ingest;
+        let engine = StorageEngine::new(config).await.unwrap();
+
+        // Network timestamps are replaced, so skewed ones aren't rejected either
+        let before = Utc::now();
+        let week_ago = before - chrono::Duration::days(7);
+        engine.write(create_test_frame("0123456789ABCDEF", week_ago)).await.unwrap();
+        let future = before + chrono::Duration::hours(6);
+        engine.write(create_test_frame("0123456789ABCDEF", future)).await.unwrap();
+        assert_eq!(engine.clock_skew_stats(), ClockSkewStats { rejected: 0, clamped: 0 });
+
+        let stored = engine.query(&dev_eui, Some(before), None).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.iter().all(|frame| frame.timestamp() >= before && frame.timestamp() <= Utc::now()));
+    }
+
+    #[tokio::test]
+    async fn test_sstable_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        let engine = StorageEngine::new(create_test_config(temp_dir.path())).await.unwrap();
+        assert!(engine.sstable_stats().is_empty());
+
+        let now = Utc::now();
+        for i in 0..3 {
+            let frame = create_test_frame("0123456789ABCDEF", now + chrono::Duration::seconds(i));
+            engine.write(frame).await.unwrap();
+        }
+        engine.flush_memtable().await.unwrap();
+
+        let stats = engine.sstable_stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].num_entries, 3);
+        assert!(stats[0].bloom_fill_ratio > 0.0 && stats[0].bloom_fill_ratio < 0.01);
+        assert!(stats[0].bloom_estimated_fp_rate < 1e-6);
+    }
+
     #[tokio::test]
     async fn test_storage_engine_recovery() {
         let temp_dir = TempDir::new().unwrap();
@@ -775,6 +3326,42 @@ mod tests {
         assert_eq!(results.len(), 3);
     }
 
+    #[tokio::test]
+    async fn test_recovery_removes_partial_sstable() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+        let now = Utc::now();
+
+        {
+            let engine = StorageEngine::new(config.clone()).await.unwrap();
+            engine.write(create_test_frame("0123456789ABCDEF", now)).await.unwrap();
+            engine.shutdown().await.unwrap();
+        }
+
+        // Simulate a crash part way through writing the next SSTable
+        let complete = std::fs::read(temp_dir.path().join("sstable-00000001.sst")).unwrap();
+        let partial = temp_dir.path().join("sstable-00000002.sst");
+        std::fs::write(&partial, &complete[..complete.len() - 10]).unwrap();
+
+        {
+            let engine = StorageEngine::new(config.clone()).await.unwrap();
+            assert!(!partial.exists());
+            assert_eq!(engine.compaction_manager.read().next_sstable_id(), 2);
+
+            engine
+                .write(create_test_frame("0123456789ABCDEF", now + chrono::Duration::seconds(1)))
+                .await
+                .unwrap();
+            engine.shutdown().await.unwrap();
+        }
+
+        let engine = StorageEngine::new(config).await.unwrap();
+        assert_eq!(engine.sstables.read().len(), 2);
+        assert_eq!(engine.compaction_manager.read().next_sstable_id(), 3);
+        assert_eq!(engine.query(&dev_eui, None, None).await.unwrap().len(), 2);
+    }
+
     #[tokio::test]
     async fn test_device_registry_persistence() {
         let temp_dir = TempDir::new().unwrap();
@@ -823,4 +3410,35 @@ mod tests {
             .get(&DevEui::new(dev_eui2.to_string()).unwrap());
         assert!(device2.is_some());
     }
+
+    #[tokio::test]
+    async fn test_self_test_canary() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_config(temp_dir.path());
+        let canary = DevEui::new(config.selftest_dev_eui.clone()).unwrap();
+
+        {
+            let engine = StorageEngine::new(config.clone()).await.unwrap();
+            let report = engine.self_test(false).await.unwrap();
+            assert!(report.success);
+            assert_eq!(report.cleaned_up, None);
+            assert_eq!(engine.device_registry().device_count(), 0);
+
+            // The canary DevEUI is reserved, whatever its casing
+            let frame = create_test_frame(&canary.as_str().to_uppercase(), Utc::now());
+            assert!(engine.write(frame).await.is_err());
+
+            engine.shutdown().await.unwrap();
+        }
+
+        // Canaries read back from SSTables stay out of the rebuilt registry
+        let engine = StorageEngine::new(config).await.unwrap();
+        assert_eq!(engine.device_registry().device_count(), 0);
+        assert_eq!(engine.query(&canary, None, None).await.unwrap().len(), 1);
+
+        let report = engine.self_test(true).await.unwrap();
+        assert!(report.success);
+        assert_eq!(report.cleaned_up, Some(2));
+        assert!(engine.query(&canary, None, None).await.unwrap().is_empty());
+    }
 }
@@ -0,0 +1,245 @@
+use crate::model::decoded::DecodedPayload;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Per-device-profile field mappings with JSON persistence
+///
+/// A payload profile renames vendor-specific decoded fields to canonical ones at
+/// ingest (e.g. `TempC_SHT` -> `temperature`). The canonical field is added next to
+/// the vendor field, so both are stored. Devices whose profile has no mapping are
+/// stored exactly as decoded.
+pub struct PayloadProfileManager {
+    profiles: RwLock<HashMap<String, PayloadProfile>>,
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
+}
+
+/// Field mapping for one device profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayloadProfile {
+    /// Vendor field path (dot notation for nested fields) -> canonical top-level field name
+    pub fields: BTreeMap<String, String>,
+    /// When this profile was created
+    pub created_at: DateTime<Utc>,
+    /// When this profile was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PayloadProfile {
+    /// Add the canonical fields to a decoded payload
+    /// A canonical field already present in the payload is never overwritten
+    pub fn apply(&self, payload: &mut DecodedPayload) {
+        let additions: Vec<(String, serde_json::Value)> = self
+            .fields
+            .iter()
+            .filter_map(|(vendor, canonical)| {
+                payload
+                    .get_field(vendor)
+                    .map(|value| (canonical.clone(), value.clone()))
+            })
+            .collect();
+
+        if let Some(object) = payload.object.as_object_mut() {
+            for (canonical, value) in additions {
+                object.entry(canonical).or_insert(value);
+            }
+        }
+    }
+}
+
+impl PayloadProfileManager {
+    /// Create a new payload profile manager, loading `payload_profiles.json` if present
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let file_path = data_dir.join("payload_profiles.json");
+
+        let profiles = if file_path.exists() {
+            match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => {
+                    match serde_json::from_str::<HashMap<String, PayloadProfile>>(&content) {
+                        Ok(profiles) => {
+                            info!("Loaded {} payload profile(s)", profiles.len());
+                            profiles
+                        }
+                        Err(e) => {
+                            warn!("Failed to parse payload profiles, payloads stored as decoded: {}", e);
+                            HashMap::new()
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to read payload profiles file, payloads stored as decoded: {}", e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            profiles: RwLock::new(profiles),
+            file_path: Some(file_path),
+        })
+    }
+
+    /// Create an empty PayloadProfileManager that keeps its state in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            profiles: RwLock::new(HashMap::new()),
+            file_path: None,
+        }
+    }
+
+    /// Profile stored under a device profile ID or name
+    pub fn get(&self, profile: &str) -> Option<PayloadProfile> {
+        self.profiles.read().get(profile.trim()).cloned()
+    }
+
+    /// All profiles, sorted by key
+    pub fn list(&self) -> Vec<(String, PayloadProfile)> {
+        let mut profiles: Vec<_> = self
+            .profiles
+            .read()
+            .iter()
+            .map(|(key, profile)| (key.clone(), profile.clone()))
+            .collect();
+        profiles.sort_by(|a, b| a.0.cmp(&b.0));
+        profiles
+    }
+
+    /// Set the field mapping for a device profile, replacing any previous mapping
+    pub async fn set(&self, profile: &str, fields: BTreeMap<String, String>) -> Result<()> {
+        let now = Utc::now();
+        let profile = profile.trim().to_string();
+        let field_count = fields.len();
+
+        {
+            let mut profiles = self.profiles.write();
+            let entry = profiles.entry(profile.clone()).or_insert_with(|| PayloadProfile {
+                fields: BTreeMap::new(),
+                created_at: now,
+                updated_at: now,
+            });
+            entry.fields = fields;
+            entry.updated_at = now;
+        }
+        self.save().await?;
+
+        info!("Updated payload profile '{}' with {} field mapping(s)", profile, field_count);
+
+        Ok(())
+    }
+
+    /// Remove the mapping for a device profile
+    /// Returns whether a profile existed
+    pub async fn remove(&self, profile: &str) -> Result<bool> {
+        let profile = profile.trim();
+        let removed = self.profiles.write().remove(profile).is_some();
+
+        if removed {
+            self.save().await?;
+            info!("Removed payload profile '{}'", profile);
+        }
+
+        Ok(removed)
+    }
+
+    /// Apply the mapping for a device, looked up by device profile ID, then by name
+    pub fn normalize(
+        &self,
+        profile_id: Option<&str>,
+        profile_name: Option<&str>,
+        payload: &mut DecodedPayload,
+    ) {
+        let profiles = self.profiles.read();
+        if profiles.is_empty() {
+            return;
+        }
+
+        let profile = [profile_id, profile_name]
+            .into_iter()
+            .flatten()
+            .find_map(|key| profiles.get(key.trim()));
+        if let Some(profile) = profile {
+            profile.apply(payload);
+        }
+    }
+
+    /// Save payload profiles to disk
+    async fn save(&self) -> Result<()> {
+        // The read guard is dropped before awaiting
+        let save = super::persist_json(self.file_path.as_deref(), &*self.profiles.read());
+        save.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn mapping(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(vendor, canonical)| (vendor.to_string(), canonical.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_normalize_keeps_vendor_fields() {
+        let manager = PayloadProfileManager::in_memory();
+        manager
+            .set(
+                "sht-sensor",
+                mapping(&[("TempC_SHT", "temperature"), ("env.Hum_SHT", "humidity"), ("BatV", "battery")]),
+            )
+            .await
+            .unwrap();
+
+        let mut payload = DecodedPayload::from_json(json!({
+            "TempC_SHT": 21.5,
+            "env": {"Hum_SHT": 40},
+            "battery": 3.1,
+            "BatV": 3.6
+        }));
+        manager.normalize(Some("unknown-id"), Some("sht-sensor"), &mut payload);
+
+        assert_eq!(payload.object["temperature"], json!(21.5));
+        assert_eq!(payload.object["TempC_SHT"], json!(21.5));
+        assert_eq!(payload.object["humidity"], json!(40));
+        // An existing canonical field wins over the mapped vendor field
+        assert_eq!(payload.object["battery"], json!(3.1));
+
+        // Without a matching profile the payload is left alone
+        let mut raw = DecodedPayload::from_json(json!({"TempC_SHT": 20.0}));
+        manager.normalize(Some("other"), None, &mut raw);
+        assert_eq!(raw.object, json!({"TempC_SHT": 20.0}));
+    }
+
+    #[tokio::test]
+    async fn test_profiles_persist() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let manager = PayloadProfileManager::new(temp_dir.path()).await.unwrap();
+            manager
+                .set("b3a1c4d2", mapping(&[("TempC_SHT", "temperature")]))
+                .await
+                .unwrap();
+            manager.set("other", mapping(&[])).await.unwrap();
+            assert!(manager.remove("other").await.unwrap());
+            assert!(!manager.remove("other").await.unwrap());
+        }
+
+        let manager = PayloadProfileManager::new(temp_dir.path()).await.unwrap();
+        let profiles = manager.list();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].0, "b3a1c4d2");
+        assert_eq!(profiles[0].1.fields.get("TempC_SHT").unwrap(), "temperature");
+    }
+}
@@ -0,0 +1,307 @@
+use crate::error::LoraDbError;
+use crate::model::frames::Frame;
+use crate::model::lorawan::ApplicationId;
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{info, warn};
+
+/// Per-application daily ingest quotas with JSON persistence
+///
+/// Usage is counted per UTC day and resets at midnight UTC. Counters are kept
+/// in memory and written with the quota settings on every change to a quota,
+/// from the periodic flush and at shutdown.
+pub struct QuotaManager {
+    quotas: Mutex<HashMap<String, ApplicationQuota>>,
+    /// Usage changed since the last save
+    dirty: AtomicBool,
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
+}
+
+/// Daily limits for one application, and its usage in the current period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplicationQuota {
+    /// Maximum frames stored per UTC day (None = unlimited)
+    pub max_frames_per_day: Option<u64>,
+    /// Maximum serialized frame bytes stored per UTC day (None = unlimited)
+    pub max_bytes_per_day: Option<u64>,
+    /// When this quota was created
+    pub created_at: DateTime<Utc>,
+    /// When this quota was last updated
+    pub updated_at: DateTime<Utc>,
+    /// Usage in the current period
+    #[serde(default = "QuotaUsage::today")]
+    pub usage: QuotaUsage,
+}
+
+/// Frames and bytes stored for an application since `period_start`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    /// UTC day the counters belong to
+    pub period_start: NaiveDate,
+    pub frames: u64,
+    pub bytes: u64,
+}
+
+impl QuotaUsage {
+    fn today() -> Self {
+        Self::starting(Utc::now().date_naive())
+    }
+
+    fn starting(period_start: NaiveDate) -> Self {
+        Self {
+            period_start,
+            frames: 0,
+            bytes: 0,
+        }
+    }
+}
+
+impl ApplicationQuota {
+    /// Start a new period if `today` is past the current one
+    fn roll_over(&mut self, today: NaiveDate) {
+        if self.usage.period_start < today {
+            self.usage = QuotaUsage::starting(today);
+        }
+    }
+
+    /// Which limit storing `bytes` more would exceed, if any
+    fn exceeded_by(&self, bytes: u64) -> Option<String> {
+        if let Some(max) = self.max_frames_per_day {
+            if self.usage.frames + 1 > max {
+                return Some(format!("{} frames per day", max));
+            }
+        }
+        if let Some(max) = self.max_bytes_per_day {
+            if self.usage.bytes + bytes > max {
+                return Some(format!("{} bytes per day", max));
+            }
+        }
+        None
+    }
+}
+
+impl QuotaManager {
+    /// Create a new quota manager, loading `quotas.json` if present
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let file_path = data_dir.join("quotas.json");
+
+        let quotas = if file_path.exists() {
+            match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => match serde_json::from_str::<HashMap<String, ApplicationQuota>>(&content) {
+                    Ok(quotas) => {
+                        info!("Loaded ingest quotas for {} application(s)", quotas.len());
+                        quotas
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse ingest quotas, no quotas enforced: {}", e);
+                        HashMap::new()
+                    }
+                },
+                Err(e) => {
+                    warn!("Failed to read ingest quotas file, no quotas enforced: {}", e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            quotas: Mutex::new(quotas),
+            dirty: AtomicBool::new(false),
+            file_path: Some(file_path),
+        })
+    }
+
+    /// Create an empty QuotaManager that keeps its state in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            quotas: Mutex::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+            file_path: None,
+        }
+    }
+
+    /// Quota and current usage for an application (matched case-insensitively)
+    pub fn get(&self, app_id: &str) -> Option<ApplicationQuota> {
+        self.get_at(app_id, Utc::now().date_naive())
+    }
+
+    fn get_at(&self, app_id: &str, today: NaiveDate) -> Option<ApplicationQuota> {
+        let mut quotas = self.quotas.lock();
+        let quota = quotas.get_mut(&ApplicationId::normalize(app_id))?;
+        quota.roll_over(today);
+        Some(quota.clone())
+    }
+
+    /// Set the daily limits for an application, keeping usage in the current period
+    pub async fn set(
+        &self,
+        app_id: &str,
+        max_frames_per_day: Option<u64>,
+        max_bytes_per_day: Option<u64>,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let app_id = ApplicationId::normalize(app_id);
+
+        {
+            let mut quotas = self.quotas.lock();
+            let quota = quotas.entry(app_id.clone()).or_insert_with(|| ApplicationQuota {
+                max_frames_per_day,
+                max_bytes_per_day,
+                created_at: now,
+                updated_at: now,
+                usage: QuotaUsage::starting(now.date_naive()),
+            });
+            quota.max_frames_per_day = max_frames_per_day;
+            quota.max_bytes_per_day = max_bytes_per_day;
+            quota.updated_at = now;
+        }
+        self.save().await?;
+
+        info!(
+            "Updated ingest quota for application '{}' (frames/day: {:?}, bytes/day: {:?})",
+            app_id, max_frames_per_day, max_bytes_per_day
+        );
+
+        Ok(())
+    }
+
+    /// Remove the quota for an application
+    /// Returns whether a quota existed
+    pub async fn remove(&self, app_id: &str) -> Result<bool> {
+        let app_id = ApplicationId::normalize(app_id);
+        let removed = self.quotas.lock().remove(&app_id).is_some();
+
+        if removed {
+            self.save().await?;
+            info!("Removed ingest quota for application '{}'", app_id);
+        }
+
+        Ok(removed)
+    }
+
+    /// Count a frame against its application's quota
+    /// Frames over quota are rejected without being counted; frames without an
+    /// application ID or quota are always accepted
+    pub fn consume(&self, frame: &Frame) -> std::result::Result<(), LoraDbError> {
+        let app_id = match frame.application_id() {
+            Some(id) => id.normalized(),
+            None => return Ok(()),
+        };
+        let bytes = bincode::serialized_size(frame).unwrap_or(0);
+        self.consume_at(&app_id, bytes, Utc::now().date_naive())
+    }
+
+    fn consume_at(
+        &self,
+        app_id: &str,
+        bytes: u64,
+        today: NaiveDate,
+    ) -> std::result::Result<(), LoraDbError> {
+        let mut quotas = self.quotas.lock();
+        let quota = match quotas.get_mut(app_id) {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+
+        quota.roll_over(today);
+        if let Some(limit) = quota.exceeded_by(bytes) {
+            return Err(LoraDbError::QuotaExceeded(format!(
+                "Application '{}' has reached its ingest quota of {}, resets at midnight UTC",
+                app_id, limit
+            )));
+        }
+
+        quota.usage.frames += 1;
+        quota.usage.bytes += bytes;
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Save usage counters if they changed since the last save
+    pub async fn save_usage(&self) -> Result<()> {
+        if self.dirty.load(Ordering::Relaxed) {
+            self.save().await?;
+        }
+        Ok(())
+    }
+
+    /// Save quotas and usage to disk
+    async fn save(&self) -> Result<()> {
+        // The lock guard is dropped before awaiting
+        let save = {
+            let quotas = self.quotas.lock();
+            self.dirty.store(false, Ordering::Relaxed);
+            super::persist_json(self.file_path.as_deref(), &*quotas)
+        };
+        save.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_quota_rejects_until_next_day() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = QuotaManager::new(temp_dir.path()).await.unwrap();
+        let today = Utc::now().date_naive();
+
+        // Applications without a quota are unlimited
+        assert!(manager.consume_at("other", 1_000, today).is_ok());
+
+        manager.set("Tenant-A", Some(2), Some(250)).await.unwrap();
+        assert!(manager.consume_at("tenant-a", 100, today).is_ok());
+        // Byte limit reached first
+        assert!(matches!(
+            manager.consume_at("tenant-a", 200, today),
+            Err(LoraDbError::QuotaExceeded(_))
+        ));
+        assert!(manager.consume_at("tenant-a", 100, today).is_ok());
+        // Frame limit reached, rejected frames are not counted
+        assert!(manager.consume_at("tenant-a", 1, today).is_err());
+
+        let quota = manager.get_at("TENANT-A", today).unwrap();
+        assert_eq!(quota.usage.frames, 2);
+        assert_eq!(quota.usage.bytes, 200);
+
+        // Counters reset with the next UTC day
+        let tomorrow = today.succ_opt().unwrap();
+        assert!(manager.consume_at("tenant-a", 100, tomorrow).is_ok());
+        let quota = manager.get_at("tenant-a", tomorrow).unwrap();
+        assert_eq!(quota.usage.period_start, tomorrow);
+        assert_eq!(quota.usage.frames, 1);
+    }
+
+    #[tokio::test]
+    async fn test_quota_usage_persistence() {
+        let temp_dir = TempDir::new().unwrap();
+        let today = Utc::now().date_naive();
+
+        {
+            let manager = QuotaManager::new(temp_dir.path()).await.unwrap();
+            manager.set("tenant-a", Some(10), None).await.unwrap();
+            manager.consume_at("tenant-a", 50, today).unwrap();
+            manager.consume_at("tenant-a", 50, today).unwrap();
+            manager.save_usage().await.unwrap();
+        }
+
+        let manager = QuotaManager::new(temp_dir.path()).await.unwrap();
+        let quota = manager.get_at("tenant-a", today).unwrap();
+        assert_eq!(quota.max_frames_per_day, Some(10));
+        assert_eq!(quota.usage.frames, 2);
+        assert_eq!(quota.usage.bytes, 100);
+
+        assert!(manager.remove("tenant-a").await.unwrap());
+        assert!(manager.get("tenant-a").is_none());
+    }
+}
@@ -1,8 +1,9 @@
+use crate::model::lorawan::ApplicationId;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use parking_lot::RwLock;
 use tracing::{info, warn};
@@ -10,7 +11,8 @@ use tracing::{info, warn};
 /// Manages retention policies with JSON persistence
 pub struct RetentionPolicyManager {
     policies: Arc<RwLock<RetentionPolicies>>,
-    file_path: PathBuf,
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
 }
 
 /// Retention policies configuration
@@ -22,6 +24,21 @@ pub struct RetentionPolicies {
     pub applications: HashMap<String, RetentionPolicy>,
     /// How often to check and enforce retention (in hours)
     pub check_interval_hours: u64,
+    /// Combined SSTable size budget; the oldest SSTables are deleted while it is
+    /// exceeded, regardless of age (None = no size limit)
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>,
+    /// Copied into a per-application policy the first time an application without
+    /// one sends data (None = such applications use the global policy)
+    #[serde(default)]
+    pub default_application_policy: Option<DefaultApplicationPolicy>,
+}
+
+/// Template for the policy given to newly seen applications
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefaultApplicationPolicy {
+    /// Retention period in days (None = "never" - keep forever)
+    pub days: Option<u32>,
 }
 
 /// Retention policy for a specific application
@@ -35,19 +52,49 @@ pub struct RetentionPolicy {
     pub updated_at: DateTime<Utc>,
 }
 
+impl RetentionPolicies {
+    /// Re-key per-application policies by normalized application ID
+    /// If two keys collide, the most recently updated policy wins
+    fn normalize_application_ids(&mut self) {
+        let mut normalized: HashMap<String, RetentionPolicy> = HashMap::new();
+        for (app_id, policy) in self.applications.drain() {
+            let key = ApplicationId::normalize(&app_id);
+            match normalized.get(&key) {
+                Some(existing) if existing.updated_at >= policy.updated_at => {}
+                _ => {
+                    normalized.insert(key, policy);
+                }
+            }
+        }
+        self.applications = normalized;
+    }
+}
+
 impl Default for RetentionPolicies {
     fn default() -> Self {
         Self {
             global_days: None,
             applications: HashMap::new(),
             check_interval_hours: 24,
+            max_total_bytes: None,
+            default_application_policy: None,
         }
     }
 }
 
 impl RetentionPolicyManager {
     /// Create a new retention policy manager
-    pub async fn new(data_dir: &PathBuf) -> Result<Self> {
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let manager = Self::load(data_dir).await;
+
+        // Save initial state
+        manager.save().await?;
+
+        Ok(manager)
+    }
+
+    /// Load existing policies (or the default) without writing anything to disk
+    pub async fn load(data_dir: &Path) -> Self {
         let file_path = data_dir.join("retention_policies.json");
 
         // Try to load existing policies, or create default
@@ -55,8 +102,10 @@ impl RetentionPolicyManager {
             match tokio::fs::read_to_string(&file_path).await {
                 Ok(content) => {
                     match serde_json::from_str::<RetentionPolicies>(&content) {
-                        Ok(policies) => {
+                        Ok(mut policies) => {
                             info!("Loaded retention policies from {}", file_path.display());
+                            // Files written before normalization may use any casing
+                            policies.normalize_application_ids();
                             policies
                         }
                         Err(e) => {
@@ -75,23 +124,20 @@ impl RetentionPolicyManager {
             RetentionPolicies::default()
         };
 
-        let manager = Self {
+        Self {
             policies: Arc::new(RwLock::new(policies)),
-            file_path,
-        };
-
-        // Save initial state
-        manager.save().await?;
-
-        Ok(manager)
+            file_path: Some(file_path),
+        }
     }
 
     /// Initialize from environment variables (for backward compatibility)
     pub async fn from_env(
-        data_dir: &PathBuf,
+        data_dir: &Path,
         retention_days: Option<u32>,
         retention_apps: HashMap<String, Option<u32>>,
         check_interval_hours: u64,
+        max_total_bytes: Option<u64>,
+        default_application_policy: Option<DefaultApplicationPolicy>,
     ) -> Result<Self> {
         let file_path = data_dir.join("retention_policies.json");
 
@@ -104,6 +150,32 @@ impl RetentionPolicyManager {
         // Otherwise, initialize from env vars
         info!("Initializing retention policies from environment variables");
 
+        let manager = Self::in_memory(
+            retention_days,
+            retention_apps,
+            check_interval_hours,
+            max_total_bytes,
+            default_application_policy,
+        );
+        let manager = Self {
+            file_path: Some(file_path),
+            ..manager
+        };
+
+        // Save initial state
+        manager.save().await?;
+
+        Ok(manager)
+    }
+
+    /// Initialize from environment variables, keeping policies in memory only
+    pub fn in_memory(
+        retention_days: Option<u32>,
+        retention_apps: HashMap<String, Option<u32>>,
+        check_interval_hours: u64,
+        max_total_bytes: Option<u64>,
+        default_application_policy: Option<DefaultApplicationPolicy>,
+    ) -> Self {
         let now = Utc::now();
         let applications = retention_apps
             .into_iter()
@@ -119,21 +191,19 @@ impl RetentionPolicyManager {
             })
             .collect();
 
-        let policies = RetentionPolicies {
+        let mut policies = RetentionPolicies {
             global_days: retention_days,
             applications,
             check_interval_hours,
+            max_total_bytes,
+            default_application_policy,
         };
+        policies.normalize_application_ids();
 
-        let manager = Self {
+        Self {
             policies: Arc::new(RwLock::new(policies)),
-            file_path,
-        };
-
-        // Save initial state
-        manager.save().await?;
-
-        Ok(manager)
+            file_path: None,
+        }
     }
 
     /// Get current policies (for internal use by storage engine)
@@ -162,14 +232,106 @@ impl RetentionPolicyManager {
         Ok(())
     }
 
-    /// Get retention policy for a specific application
+    /// Get the combined SSTable size budget in bytes
+    pub async fn get_max_total_bytes(&self) -> Option<u64> {
+        self.policies.read().max_total_bytes
+    }
+
+    /// Set the combined SSTable size budget in bytes (None = no size limit)
+    pub async fn set_max_total_bytes(&self, max_total_bytes: Option<u64>) -> Result<()> {
+        {
+            let mut policies = self.policies.write();
+            policies.max_total_bytes = max_total_bytes;
+        }
+        self.save().await?;
+
+        match max_total_bytes {
+            Some(bytes) => info!("Updated retention size budget to {} bytes", bytes),
+            None => info!("Removed retention size budget"),
+        }
+
+        Ok(())
+    }
+
+    /// Get the template for newly seen applications' policies
+    pub async fn get_default_application_policy(&self) -> Option<DefaultApplicationPolicy> {
+        self.policies.read().default_application_policy
+    }
+
+    /// Set the template for newly seen applications' policies (None = they use the global policy)
+    /// Applications that already have a policy keep it
+    pub async fn set_default_application_policy(
+        &self,
+        policy: Option<DefaultApplicationPolicy>,
+    ) -> Result<()> {
+        {
+            let mut policies = self.policies.write();
+            policies.default_application_policy = policy;
+        }
+        self.save().await?;
+
+        match policy {
+            Some(DefaultApplicationPolicy { days: Some(d) }) => {
+                info!("New applications now get a {} day retention policy", d)
+            }
+            Some(DefaultApplicationPolicy { days: None }) => {
+                info!("New applications now get a 'never' (keep forever) retention policy")
+            }
+            None => info!("New applications now use the global retention policy"),
+        }
+
+        Ok(())
+    }
+
+    /// Give an application without a policy a copy of the default application policy
+    /// Returns whether a policy was added
+    pub async fn apply_default_policy(&self, app_id: &str) -> Result<bool> {
+        let now = Utc::now();
+        let app_id = ApplicationId::normalize(app_id);
+
+        let days = {
+            let mut policies = self.policies.write();
+            let template = match policies.default_application_policy {
+                Some(template) => template,
+                None => return Ok(false),
+            };
+            if policies.applications.contains_key(&app_id) {
+                return Ok(false);
+            }
+            policies.applications.insert(
+                app_id.clone(),
+                RetentionPolicy {
+                    days: template.days,
+                    created_at: now,
+                    updated_at: now,
+                },
+            );
+            template.days
+        };
+
+        self.save().await?;
+
+        match days {
+            Some(d) => info!("New application '{}' gets the default {} day retention policy", app_id, d),
+            None => info!("New application '{}' gets the default 'never' retention policy", app_id),
+        }
+
+        Ok(true)
+    }
+
+    /// Get retention policy for a specific application (matched case-insensitively)
     pub async fn get_application(&self, app_id: &str) -> Option<RetentionPolicy> {
-        self.policies.read().applications.get(app_id).cloned()
+        self.policies
+            .read()
+            .applications
+            .get(&ApplicationId::normalize(app_id))
+            .cloned()
     }
 
-    /// Set retention policy for a specific application
+    /// Set retention policy for a specific application, keyed by its normalized ID
     pub async fn set_application(&self, app_id: String, days: Option<u32>) -> Result<()> {
         let now = Utc::now();
+        let app_id = ApplicationId::normalize(&app_id);
 
         {
             let mut policies = self.policies.write();
@@ -201,9 +363,10 @@ impl RetentionPolicyManager {
 
     /// Remove application-specific retention policy (will fall back to global)
     pub async fn remove_application(&self, app_id: &str) -> Result<bool> {
+        let app_id = ApplicationId::normalize(app_id);
         let removed = {
             let mut policies = self.policies.write();
-            policies.applications.remove(app_id).is_some()
+            policies.applications.remove(&app_id).is_some()
         };
 
         if removed {
@@ -237,20 +400,9 @@ impl RetentionPolicyManager {
 
     /// Save policies to disk
     async fn save(&self) -> Result<()> {
-        let policies = self.policies.read();
-        let json = serde_json::to_string_pretty(&*policies)?;
-
-        tokio::fs::write(&self.file_path, json).await?;
-
-        // Set strict permissions on Unix
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            tokio::fs::set_permissions(&self.file_path, std::fs::Permissions::from_mode(0o600))
-                .await?;
-        }
-
-        Ok(())
+        // The read guard is dropped before awaiting
+        let save = super::persist_json(self.file_path.as_deref(), &*self.policies.read());
+        save.await
     }
 
     /// Convert to format expected by storage engine (for backward compatibility)
@@ -296,6 +448,25 @@ mod tests {
         assert_eq!(manager.get_global().await, None);
     }
 
+    #[tokio::test]
+    async fn test_retention_manager_size_budget() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_path_buf();
+        let manager = RetentionPolicyManager::new(&data_dir).await.unwrap();
+        assert_eq!(manager.get_max_total_bytes().await, None);
+
+        manager.set_max_total_bytes(Some(10 * 1024 * 1024)).await.unwrap();
+
+        // Persisted, and files written before the field existed load without it
+        let reloaded = RetentionPolicyManager::load(&data_dir).await;
+        assert_eq!(reloaded.get_max_total_bytes().await, Some(10 * 1024 * 1024));
+        let legacy: RetentionPolicies = serde_json::from_str(
+            r#"{"global_days": 30, "applications": {}, "check_interval_hours": 24}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.max_total_bytes, None);
+    }
+
     #[tokio::test]
     async fn test_retention_manager_app_policy() {
         let temp_dir = TempDir::new().unwrap();
@@ -353,11 +524,14 @@ mod tests {
             Some(90),
             retention_apps,
             24,
+            Some(1_000_000),
+            None,
         )
         .await
         .unwrap();
 
         assert_eq!(manager.get_global().await, Some(90));
+        assert_eq!(manager.get_max_total_bytes().await, Some(1_000_000));
 
         let policy1 = manager.get_application("app1").await;
         assert_eq!(policy1.unwrap().days, Some(30));
@@ -365,4 +539,56 @@ mod tests {
         let policy2 = manager.get_application("app2").await;
         assert_eq!(policy2.unwrap().days, None);
     }
+
+    #[tokio::test]
+    async fn test_retention_manager_normalizes_app_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = RetentionPolicyManager::new(&temp_dir.path().to_path_buf())
+            .await
+            .unwrap();
+
+        manager.set_application("Env-Sensors".to_string(), Some(30))
+            .await
+            .unwrap();
+        manager.set_application(" env-sensors ".to_string(), Some(7))
+            .await
+            .unwrap();
+
+        let applications = manager.list_applications().await;
+        assert_eq!(applications.len(), 1);
+        assert_eq!(applications["env-sensors"].days, Some(7));
+        assert_eq!(manager.get_application("ENV-SENSORS").await.unwrap().days, Some(7));
+
+        assert!(manager.remove_application("Env-Sensors").await.unwrap());
+        assert!(manager.list_applications().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retention_manager_default_application_policy() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_path_buf();
+        let manager = RetentionPolicyManager::new(&data_dir).await.unwrap();
+
+        // Without a template new applications keep using the global policy
+        assert!(!manager.apply_default_policy("new-app").await.unwrap());
+        assert!(manager.list_applications().await.is_empty());
+
+        manager
+            .set_default_application_policy(Some(DefaultApplicationPolicy { days: Some(30) }))
+            .await
+            .unwrap();
+        manager.set_application("Configured".to_string(), Some(7)).await.unwrap();
+
+        assert!(manager.apply_default_policy("New-App").await.unwrap());
+        assert!(!manager.apply_default_policy("new-app").await.unwrap());
+        assert!(!manager.apply_default_policy("configured").await.unwrap());
+        assert_eq!(manager.get_application("new-app").await.unwrap().days, Some(30));
+        assert_eq!(manager.get_application("configured").await.unwrap().days, Some(7));
+
+        let reloaded = RetentionPolicyManager::load(&data_dir).await;
+        assert_eq!(
+            reloaded.get_default_application_policy().await,
+            Some(DefaultApplicationPolicy { days: Some(30) })
+        );
+    }
 }
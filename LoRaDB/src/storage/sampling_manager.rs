@@ -0,0 +1,363 @@
+use crate::model::frames::Frame;
+use crate::model::lorawan::ApplicationId;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Per-device and per-application uplink sampling with JSON persistence
+///
+/// A device rule takes precedence over its application's rule. Only uplinks are
+/// sampled, joins and status frames are always stored. Sampling state and
+/// dropped counts are kept in memory and start over on restart.
+pub struct SamplingManager {
+    rules: RwLock<SamplingRules>,
+    /// Sampling state per device (normalized DevEUI)
+    devices: DashMap<String, DeviceSampleState>,
+    /// Frames dropped since startup per rule, keyed by scope and normalized ID
+    dropped: DashMap<(SamplingScope, String), u64>,
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
+}
+
+/// What a sampling rule applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingScope {
+    Application,
+    Device,
+}
+
+/// Which uplinks to keep; when both limits are set a frame must pass both
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingRule {
+    /// Keep one frame, then drop the next N-1 (None = no count limit)
+    pub keep_one_in: Option<u32>,
+    /// Keep at most one frame per this many seconds, by frame timestamp (None = no time limit)
+    pub min_interval_secs: Option<u64>,
+    /// When this rule was created
+    pub created_at: DateTime<Utc>,
+    /// When this rule was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SamplingRules {
+    #[serde(default)]
+    applications: HashMap<String, SamplingRule>,
+    #[serde(default)]
+    devices: HashMap<String, SamplingRule>,
+}
+
+impl SamplingRules {
+    fn scope(&self, scope: SamplingScope) -> &HashMap<String, SamplingRule> {
+        match scope {
+            SamplingScope::Application => &self.applications,
+            SamplingScope::Device => &self.devices,
+        }
+    }
+
+    fn scope_mut(&mut self, scope: SamplingScope) -> &mut HashMap<String, SamplingRule> {
+        match scope {
+            SamplingScope::Application => &mut self.applications,
+            SamplingScope::Device => &mut self.devices,
+        }
+    }
+}
+
+/// Frames dropped since the device's last kept frame
+#[derive(Debug, Default)]
+struct DeviceSampleState {
+    skipped: u64,
+    last_kept: Option<DateTime<Utc>>,
+}
+
+impl SamplingRule {
+    fn keeps(&self, state: &DeviceSampleState, timestamp: DateTime<Utc>) -> bool {
+        let last_kept = match state.last_kept {
+            Some(last_kept) => last_kept,
+            // The first frame seen for a device is always kept
+            None => return true,
+        };
+
+        if let Some(n) = self.keep_one_in {
+            if state.skipped + 1 < n as u64 {
+                return false;
+            }
+        }
+        if let Some(secs) = self.min_interval_secs {
+            if timestamp - last_kept < chrono::Duration::seconds(secs as i64) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn normalize(scope: SamplingScope, id: &str) -> String {
+    match scope {
+        SamplingScope::Application => ApplicationId::normalize(id),
+        SamplingScope::Device => id.trim().to_lowercase(),
+    }
+}
+
+impl SamplingManager {
+    /// Create a new sampling manager, loading `sampling.json` if present
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let file_path = data_dir.join("sampling.json");
+
+        let rules = if file_path.exists() {
+            match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => match serde_json::from_str::<SamplingRules>(&content) {
+                    Ok(rules) => {
+                        info!(
+                            "Loaded sampling rules for {} application(s) and {} device(s)",
+                            rules.applications.len(),
+                            rules.devices.len()
+                        );
+                        rules
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse sampling rules, all frames stored: {}", e);
+                        SamplingRules::default()
+                    }
+                },
+                Err(e) => {
+                    warn!("Failed to read sampling rules file, all frames stored: {}", e);
+                    SamplingRules::default()
+                }
+            }
+        } else {
+            SamplingRules::default()
+        };
+
+        Ok(Self {
+            rules: RwLock::new(rules),
+            devices: DashMap::new(),
+            dropped: DashMap::new(),
+            file_path: Some(file_path),
+        })
+    }
+
+    /// Create an empty SamplingManager that keeps its state in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            rules: RwLock::new(SamplingRules::default()),
+            devices: DashMap::new(),
+            dropped: DashMap::new(),
+            file_path: None,
+        }
+    }
+
+    /// Rule for an application or device (matched case-insensitively)
+    pub fn get(&self, scope: SamplingScope, id: &str) -> Option<SamplingRule> {
+        self.rules.read().scope(scope).get(&normalize(scope, id)).cloned()
+    }
+
+    /// All rules, applications first, each sorted by ID
+    pub fn list(&self) -> Vec<(SamplingScope, String, SamplingRule)> {
+        let rules = self.rules.read();
+        let mut listed = Vec::new();
+        for scope in [SamplingScope::Application, SamplingScope::Device] {
+            let mut scoped: Vec<_> = rules
+                .scope(scope)
+                .iter()
+                .map(|(id, rule)| (scope, id.clone(), rule.clone()))
+                .collect();
+            scoped.sort_by(|a, b| a.1.cmp(&b.1));
+            listed.extend(scoped);
+        }
+        listed
+    }
+
+    /// Set the sampling rule for an application or device
+    pub async fn set(
+        &self,
+        scope: SamplingScope,
+        id: &str,
+        keep_one_in: Option<u32>,
+        min_interval_secs: Option<u64>,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let id = normalize(scope, id);
+
+        {
+            let mut rules = self.rules.write();
+            let rule = rules.scope_mut(scope).entry(id.clone()).or_insert_with(|| SamplingRule {
+                keep_one_in,
+                min_interval_secs,
+                created_at: now,
+                updated_at: now,
+            });
+            rule.keep_one_in = keep_one_in;
+            rule.min_interval_secs = min_interval_secs;
+            rule.updated_at = now;
+        }
+        self.save().await?;
+
+        info!(
+            "Updated sampling rule for {:?} '{}' (keep 1 in {:?}, min interval {:?}s)",
+            scope, id, keep_one_in, min_interval_secs
+        );
+
+        Ok(())
+    }
+
+    /// Remove the sampling rule for an application or device
+    /// Returns whether a rule existed
+    pub async fn remove(&self, scope: SamplingScope, id: &str) -> Result<bool> {
+        let id = normalize(scope, id);
+        let removed = self.rules.write().scope_mut(scope).remove(&id).is_some();
+
+        if removed {
+            self.save().await?;
+            info!("Removed sampling rule for {:?} '{}'", scope, id);
+        }
+
+        Ok(removed)
+    }
+
+    /// Number of frames a rule has dropped since startup
+    pub fn dropped_count(&self, scope: SamplingScope, id: &str) -> u64 {
+        self.dropped
+            .get(&(scope, normalize(scope, id)))
+            .map(|v| *v)
+            .unwrap_or(0)
+    }
+
+    /// Check whether a frame should be stored, counting it as dropped if not
+    /// Frames other than uplinks, and devices without a rule, are always kept
+    pub fn admit(&self, frame: &Frame) -> bool {
+        if !matches!(frame, Frame::Uplink(_)) {
+            return true;
+        }
+
+        let dev_eui = frame.dev_eui().normalized();
+        let (scope, id, rule) = {
+            let rules = self.rules.read();
+            if let Some(rule) = rules.devices.get(&dev_eui) {
+                (SamplingScope::Device, dev_eui.clone(), rule.clone())
+            } else if let Some((app_id, rule)) = frame.application_id().and_then(|app_id| {
+                let app_id = app_id.normalized();
+                rules.applications.get(&app_id).map(|rule| (app_id, rule.clone()))
+            }) {
+                (SamplingScope::Application, app_id, rule)
+            } else {
+                return true;
+            }
+        };
+
+        let timestamp = frame.timestamp();
+        let mut state = self.devices.entry(dev_eui).or_default();
+        if rule.keeps(&state, timestamp) {
+            state.skipped = 0;
+            state.last_kept = Some(timestamp);
+            return true;
+        }
+        state.skipped += 1;
+        drop(state);
+
+        *self.dropped.entry((scope, id)).or_insert(0) += 1;
+        false
+    }
+
+    /// Save sampling rules to disk
+    async fn save(&self) -> Result<()> {
+        // The read guard is dropped before awaiting
+        let save = super::persist_json(self.file_path.as_deref(), &*self.rules.read());
+        save.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::frames::UplinkFrame;
+    use crate::model::lorawan::*;
+    use tempfile::TempDir;
+
+    fn create_test_uplink(dev_eui: &str, app_id: &str, timestamp: DateTime<Utc>) -> Frame {
+        Frame::Uplink(UplinkFrame {
+            dev_eui: DevEui::new(dev_eui.to_string()).unwrap(),
+            application_id: ApplicationId::new(app_id.to_string()),
+            device_name: None,
+            received_at: timestamp,
+            stored_at: None,
+            f_port: 1,
+            f_cnt: 1,
+            confirmed: false,
+            adr: true,
+            dr: DataRate::new_lora(125000, 7),
+            frequency: 868100000,
+            rx_info: vec![],
+            decoded_payload: None,
+            raw_payload: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_keep_one_in_and_device_override() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = SamplingManager::new(temp_dir.path()).await.unwrap();
+        let now = Utc::now();
+
+        manager
+            .set(SamplingScope::Application, "Chatty-App", Some(3), None)
+            .await
+            .unwrap();
+
+        let kept: Vec<bool> = (0..7)
+            .map(|_| manager.admit(&create_test_uplink("0123456789ABCDEF", "chatty-app", now)))
+            .collect();
+        assert_eq!(kept, [true, false, false, true, false, false, true]);
+        assert_eq!(manager.dropped_count(SamplingScope::Application, "chatty-app"), 4);
+
+        // A device rule replaces the application rule for that device
+        manager
+            .set(SamplingScope::Device, "0123456789abcdef", Some(1), None)
+            .await
+            .unwrap();
+        assert!(manager.admit(&create_test_uplink("0123456789ABCDEF", "chatty-app", now)));
+        assert!(manager.admit(&create_test_uplink("0123456789ABCDEF", "chatty-app", now)));
+
+        // Other applications are not sampled
+        assert!(manager.admit(&create_test_uplink("FEDCBA9876543210", "other-app", now)));
+        assert!(manager.admit(&create_test_uplink("FEDCBA9876543210", "other-app", now)));
+    }
+
+    #[tokio::test]
+    async fn test_min_interval_and_persistence() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = Utc::now();
+
+        {
+            let manager = SamplingManager::new(temp_dir.path()).await.unwrap();
+            manager
+                .set(SamplingScope::Device, "0123456789ABCDEF", None, Some(60))
+                .await
+                .unwrap();
+
+            let at = |secs: i64| {
+                create_test_uplink("0123456789ABCDEF", "app", now + chrono::Duration::seconds(secs))
+            };
+            assert!(manager.admit(&at(0)));
+            assert!(!manager.admit(&at(30)));
+            assert!(!manager.admit(&at(59)));
+            assert!(manager.admit(&at(60)));
+            assert!(!manager.admit(&at(90)));
+            assert_eq!(manager.dropped_count(SamplingScope::Device, "0123456789ABCDEF"), 3);
+        }
+
+        let manager = SamplingManager::new(temp_dir.path()).await.unwrap();
+        let rule = manager.get(SamplingScope::Device, "0123456789abcdef").unwrap();
+        assert_eq!(rule.min_interval_secs, Some(60));
+        assert_eq!(manager.list().len(), 1);
+
+        assert!(manager.remove(SamplingScope::Device, "0123456789ABCDEF").await.unwrap());
+        assert!(manager.get(SamplingScope::Device, "0123456789ABCDEF").is_none());
+    }
+}
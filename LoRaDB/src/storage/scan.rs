@@ -0,0 +1,103 @@
+use crate::engine::memtable::MemtableKey;
+use crate::engine::sstable::SSTableRangeIter;
+use crate::model::frames::Frame;
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Streaming scan of one device's frames over the memtable and SSTables, in key order
+///
+/// Built by `StorageEngine::scan_iter`. Only the next frame of each source is held in
+/// memory. Frames stored twice (e.g. redelivered by MQTT and not yet compacted) are
+/// returned twice, as `query` does.
+pub struct FrameScan {
+    sources: Vec<Source>,
+    /// Next key of each source that still has one, smallest first
+    heap: BinaryHeap<Reverse<(MemtableKey, usize)>>,
+    /// Frame belonging to each source's key in the heap
+    heads: Vec<Option<Frame>>,
+    /// Errors opening a source, returned before any frame
+    errors: Vec<anyhow::Error>,
+}
+
+enum Source {
+    Memtable(std::vec::IntoIter<(MemtableKey, Frame)>),
+    SSTable(SSTableRangeIter),
+}
+
+impl Source {
+    fn next_entry(&mut self) -> Option<Result<(MemtableKey, Frame)>> {
+        match self {
+            Source::Memtable(entries) => entries.next().map(Ok),
+            Source::SSTable(entries) => entries.next(),
+        }
+    }
+}
+
+impl FrameScan {
+    /// Scan that returns nothing, e.g. for a deleted device
+    pub(crate) fn empty() -> Self {
+        Self {
+            sources: Vec::new(),
+            heap: BinaryHeap::new(),
+            heads: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Merge the device's memtable entries with per-SSTable range iterators
+    pub(crate) fn new(
+        memtable_entries: Vec<(MemtableKey, Frame)>,
+        sstables: Vec<Result<SSTableRangeIter>>,
+    ) -> Self {
+        let mut scan = Self::empty();
+        scan.push_source(Source::Memtable(memtable_entries.into_iter()));
+        for sstable in sstables {
+            match sstable {
+                Ok(entries) => scan.push_source(Source::SSTable(entries)),
+                Err(e) => scan.errors.push(e),
+            }
+        }
+        scan
+    }
+
+    fn push_source(&mut self, source: Source) {
+        self.sources.push(source);
+        self.heads.push(None);
+        // An error on the first read is reported by `next` like any other
+        if let Err(e) = self.advance(self.sources.len() - 1) {
+            self.errors.push(e);
+        }
+    }
+
+    /// Read source `i`'s next entry into its head slot and the heap
+    /// A source that fails is dropped from the merge after reporting the error
+    fn advance(&mut self, i: usize) -> Result<()> {
+        match self.sources[i].next_entry() {
+            Some(Ok((key, frame))) => {
+                self.heads[i] = Some(frame);
+                self.heap.push(Reverse((key, i)));
+                Ok(())
+            }
+            Some(Err(e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Iterator for FrameScan {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.errors.pop() {
+            return Some(Err(e));
+        }
+
+        let Reverse((_, i)) = self.heap.pop()?;
+        let frame = self.heads[i].take().expect("heap entries have a head frame");
+        if let Err(e) = self.advance(i) {
+            self.errors.push(e);
+        }
+        Some(Ok(frame))
+    }
+}
@@ -0,0 +1,343 @@
+use crate::model::frames::Frame;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use parking_lot::RwLock;
+use tracing::{info, warn};
+
+/// Manages per-application decoded payload schemas with JSON persistence
+pub struct SchemaManager {
+    schemas: Arc<RwLock<HashMap<String, ApplicationSchema>>>,
+    violations: Arc<DashMap<String, u64>>,
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
+}
+
+/// Expected JSON type of a required field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Number,
+    String,
+    Bool,
+    Object,
+    Array,
+    /// Field must be present, any type is accepted
+    Any,
+}
+
+impl FieldType {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            FieldType::Number => value.is_number(),
+            FieldType::String => value.is_string(),
+            FieldType::Bool => value.is_boolean(),
+            FieldType::Object => value.is_object(),
+            FieldType::Array => value.is_array(),
+            FieldType::Any => true,
+        }
+    }
+}
+
+/// What to do with a frame that does not conform to its application's schema
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaMode {
+    /// Refuse to store the frame
+    #[default]
+    Reject,
+    /// Store the frame but log a warning and count the violation
+    Flag,
+}
+
+/// A required field inside `decoded_payload.object`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequiredField {
+    /// Dot-separated path relative to the decoded object (e.g. "sensor.temp")
+    pub path: String,
+    #[serde(rename = "type", default = "default_field_type")]
+    pub field_type: FieldType,
+}
+
+fn default_field_type() -> FieldType {
+    FieldType::Any
+}
+
+/// Decoded payload schema for a specific application
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplicationSchema {
+    pub required_fields: Vec<RequiredField>,
+    #[serde(default)]
+    pub mode: SchemaMode,
+    /// When this schema was created
+    pub created_at: DateTime<Utc>,
+    /// When this schema was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Outcome of checking a frame against its application's schema
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaCheck {
+    /// No schema applies, or the frame conforms
+    Valid,
+    /// Frame violates the schema but should still be stored
+    Flagged(Vec<String>),
+    /// Frame violates the schema and must be rejected
+    Rejected(Vec<String>),
+}
+
+impl SchemaManager {
+    /// Create a new schema manager, loading `schemas.json` if present
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let file_path = data_dir.join("schemas.json");
+
+        let schemas = if file_path.exists() {
+            match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => match serde_json::from_str::<HashMap<String, ApplicationSchema>>(&content) {
+                    Ok(schemas) => {
+                        info!("Loaded {} application schema(s) from {}", schemas.len(), file_path.display());
+                        schemas
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse application schemas, starting empty: {}", e);
+                        HashMap::new()
+                    }
+                },
+                Err(e) => {
+                    warn!("Failed to read application schemas file, starting empty: {}", e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            schemas: Arc::new(RwLock::new(schemas)),
+            violations: Arc::new(DashMap::new()),
+            file_path: Some(file_path),
+        })
+    }
+
+    /// Create an empty SchemaManager that keeps its state in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            schemas: Arc::new(RwLock::new(HashMap::new())),
+            violations: Arc::new(DashMap::new()),
+            file_path: None,
+        }
+    }
+
+    /// List all application schemas
+    pub async fn list(&self) -> HashMap<String, ApplicationSchema> {
+        self.schemas.read().clone()
+    }
+
+    /// Get the schema for a specific application
+    pub async fn get(&self, app_id: &str) -> Option<ApplicationSchema> {
+        self.schemas.read().get(app_id).cloned()
+    }
+
+    /// Create or replace the schema for an application
+    pub async fn set(
+        &self,
+        app_id: String,
+        required_fields: Vec<RequiredField>,
+        mode: SchemaMode,
+    ) -> Result<()> {
+        let now = Utc::now();
+
+        {
+            let mut schemas = self.schemas.write();
+
+            if let Some(existing) = schemas.get_mut(&app_id) {
+                existing.required_fields = required_fields;
+                existing.mode = mode;
+                existing.updated_at = now;
+            } else {
+                schemas.insert(
+                    app_id.clone(),
+                    ApplicationSchema {
+                        required_fields,
+                        mode,
+                        created_at: now,
+                        updated_at: now,
+                    },
+                );
+            }
+        }
+
+        self.save().await?;
+        info!("Updated decoded payload schema for '{}'", app_id);
+
+        Ok(())
+    }
+
+    /// Remove the schema for an application
+    pub async fn remove(&self, app_id: &str) -> Result<bool> {
+        let removed = self.schemas.write().remove(app_id).is_some();
+
+        if removed {
+            self.save().await?;
+            self.violations.remove(app_id);
+            info!("Removed decoded payload schema for '{}'", app_id);
+        }
+
+        Ok(removed)
+    }
+
+    /// Number of non-conforming frames seen for an application since startup
+    pub fn violation_count(&self, app_id: &str) -> u64 {
+        self.violations.get(app_id).map(|v| *v).unwrap_or(0)
+    }
+
+    /// Check a frame against its application's schema
+    /// Only uplinks carry a decoded payload, so other frame types always pass
+    pub fn check(&self, frame: &Frame) -> SchemaCheck {
+        let uplink = match frame {
+            Frame::Uplink(f) => f,
+            _ => return SchemaCheck::Valid,
+        };
+
+        let app_id = uplink.application_id.as_str();
+        let schemas = self.schemas.read();
+        let schema = match schemas.get(app_id) {
+            Some(schema) if !schema.required_fields.is_empty() => schema,
+            _ => return SchemaCheck::Valid,
+        };
+
+        let mut problems = Vec::new();
+        match &uplink.decoded_payload {
+            Some(payload) => {
+                for field in &schema.required_fields {
+                    match payload.get_field(&field.path) {
+                        Some(value) if field.field_type.matches(value) => {}
+                        Some(_) => problems.push(format!(
+                            "field '{}' is not of type {:?}",
+                            field.path, field.field_type
+                        )),
+                        None => problems.push(format!("missing required field '{}'", field.path)),
+                    }
+                }
+            }
+            None => problems.push("missing decoded payload".to_string()),
+        }
+
+        if problems.is_empty() {
+            return SchemaCheck::Valid;
+        }
+
+        *self.violations.entry(app_id.to_string()).or_insert(0) += 1;
+
+        match schema.mode {
+            SchemaMode::Reject => SchemaCheck::Rejected(problems),
+            SchemaMode::Flag => SchemaCheck::Flagged(problems),
+        }
+    }
+
+    /// Save schemas to disk
+    async fn save(&self) -> Result<()> {
+        // The read guard is dropped before awaiting
+        let save = super::persist_json(self.file_path.as_deref(), &*self.schemas.read());
+        save.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::decoded::DecodedPayload;
+    use crate::model::frames::UplinkFrame;
+    use crate::model::lorawan::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn create_test_uplink(app_id: &str, object: Option<Value>) -> Frame {
+        Frame::Uplink(UplinkFrame {
+            dev_eui: DevEui::new("0123456789ABCDEF".to_string()).unwrap(),
+            application_id: ApplicationId::new(app_id.to_string()),
+            device_name: None,
+            received_at: Utc::now(),
+            stored_at: None,
+            f_port: 1,
+            f_cnt: 1,
+            confirmed: false,
+            adr: true,
+            dr: DataRate::new_lora(125000, 7),
+            frequency: 868100000,
+            rx_info: vec![],
+            decoded_payload: object.map(DecodedPayload::from_json),
+            raw_payload: None,
+        })
+    }
+
+    fn temperature_schema() -> Vec<RequiredField> {
+        vec![RequiredField {
+            path: "temperature".to_string(),
+            field_type: FieldType::Number,
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_schema_check_reject() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = SchemaManager::new(temp_dir.path()).await.unwrap();
+        manager
+            .set("env".to_string(), temperature_schema(), SchemaMode::Reject)
+            .await
+            .unwrap();
+
+        let good = create_test_uplink("env", Some(json!({"temperature": 21.5})));
+        assert_eq!(manager.check(&good), SchemaCheck::Valid);
+
+        let wrong_type = create_test_uplink("env", Some(json!({"temperature": "hot"})));
+        assert!(matches!(manager.check(&wrong_type), SchemaCheck::Rejected(_)));
+
+        let missing = create_test_uplink("env", None);
+        assert!(matches!(manager.check(&missing), SchemaCheck::Rejected(_)));
+
+        // Applications without a schema are unaffected
+        let other = create_test_uplink("other", None);
+        assert_eq!(manager.check(&other), SchemaCheck::Valid);
+
+        assert_eq!(manager.violation_count("env"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_schema_check_flag() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = SchemaManager::new(temp_dir.path()).await.unwrap();
+        manager
+            .set("env".to_string(), temperature_schema(), SchemaMode::Flag)
+            .await
+            .unwrap();
+
+        let missing = create_test_uplink("env", Some(json!({"humidity": 40})));
+        assert!(matches!(manager.check(&missing), SchemaCheck::Flagged(_)));
+    }
+
+    #[tokio::test]
+    async fn test_schema_persistence() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let manager = SchemaManager::new(temp_dir.path()).await.unwrap();
+            manager
+                .set("env".to_string(), temperature_schema(), SchemaMode::Reject)
+                .await
+                .unwrap();
+        }
+
+        let manager = SchemaManager::new(temp_dir.path()).await.unwrap();
+        let schema = manager.get("env").await.unwrap();
+        assert_eq!(schema.required_fields, temperature_schema());
+
+        assert!(manager.remove("env").await.unwrap());
+        assert!(manager.get("env").await.is_none());
+    }
+}
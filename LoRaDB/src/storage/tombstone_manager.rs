@@ -0,0 +1,166 @@
+use crate::model::lorawan::DevEui;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use parking_lot::RwLock;
+use tracing::{info, warn};
+
+/// Soft-deleted devices awaiting their physical delete, with JSON persistence
+pub struct TombstoneManager {
+    tombstones: Arc<RwLock<HashMap<String, DeviceTombstone>>>, // Key: normalized DevEUI
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
+}
+
+/// A device hidden from queries and listings until `purge_after`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceTombstone {
+    pub deleted_at: DateTime<Utc>,
+    /// After this the device's data is removed and it can no longer be undeleted
+    pub purge_after: DateTime<Utc>,
+}
+
+impl TombstoneManager {
+    /// Create a new tombstone manager, loading `tombstones.json` if present
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let file_path = data_dir.join("tombstones.json");
+
+        let tombstones = if file_path.exists() {
+            match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => match serde_json::from_str::<HashMap<String, DeviceTombstone>>(&content) {
+                    Ok(tombstones) => {
+                        if !tombstones.is_empty() {
+                            info!("{} device(s) pending deletion", tombstones.len());
+                        }
+                        tombstones
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse device tombstones, no devices pending deletion: {}", e);
+                        HashMap::new()
+                    }
+                },
+                Err(e) => {
+                    warn!("Failed to read device tombstones file, no devices pending deletion: {}", e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            tombstones: Arc::new(RwLock::new(tombstones)),
+            file_path: Some(file_path),
+        })
+    }
+
+    /// Create an empty TombstoneManager that keeps its state in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            tombstones: Arc::new(RwLock::new(HashMap::new())),
+            file_path: None,
+        }
+    }
+
+    /// Tombstone for a device, if it is pending deletion
+    pub fn get(&self, dev_eui: &DevEui) -> Option<DeviceTombstone> {
+        self.tombstones.read().get(&dev_eui.normalized()).cloned()
+    }
+
+    /// All devices pending deletion, keyed by normalized DevEUI
+    pub fn list(&self) -> HashMap<String, DeviceTombstone> {
+        self.tombstones.read().clone()
+    }
+
+    /// Mark a device deleted, to be purged after `grace`
+    /// Deleting an already deleted device keeps its original purge time
+    pub async fn add(&self, dev_eui: &DevEui, grace: Duration) -> Result<DeviceTombstone> {
+        let (tombstone, added) = {
+            let mut tombstones = self.tombstones.write();
+            match tombstones.get(&dev_eui.normalized()) {
+                Some(existing) => (existing.clone(), false),
+                None => {
+                    let now = Utc::now();
+                    let tombstone = DeviceTombstone {
+                        deleted_at: now,
+                        purge_after: now + grace,
+                    };
+                    tombstones.insert(dev_eui.normalized(), tombstone.clone());
+                    (tombstone, true)
+                }
+            }
+        };
+
+        if added {
+            self.save().await?;
+            info!(
+                "Device {} marked deleted, purging after {}",
+                dev_eui.as_str(),
+                tombstone.purge_after
+            );
+        }
+
+        Ok(tombstone)
+    }
+
+    /// Remove a device's tombstone, returns false if it had none
+    pub async fn remove(&self, dev_eui: &DevEui) -> Result<bool> {
+        let removed = self.tombstones.write().remove(&dev_eui.normalized()).is_some();
+        if removed {
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    /// Devices whose grace period has ended
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<String> {
+        self.tombstones
+            .read()
+            .iter()
+            .filter(|(_, tombstone)| tombstone.purge_after <= now)
+            .map(|(dev_eui, _)| dev_eui.clone())
+            .collect()
+    }
+
+    /// Save tombstones to disk
+    async fn save(&self) -> Result<()> {
+        // The read guard is dropped before awaiting
+        let save = super::persist_json(self.file_path.as_deref(), &*self.tombstones.read());
+        save.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_tombstone_lifecycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let dev_eui = DevEui::new("0123456789ABCDEF".to_string()).unwrap();
+
+        {
+            let manager = TombstoneManager::new(temp_dir.path()).await.unwrap();
+            let tombstone = manager.add(&dev_eui, Duration::hours(24)).await.unwrap();
+
+            // A second delete doesn't push the purge back
+            let again = manager.add(&dev_eui, Duration::hours(48)).await.unwrap();
+            assert_eq!(again.purge_after, tombstone.purge_after);
+
+            assert!(manager.due(Utc::now()).is_empty());
+            assert_eq!(manager.due(Utc::now() + Duration::hours(25)), vec![dev_eui.normalized()]);
+        }
+
+        // Tombstones survive a restart
+        let manager = TombstoneManager::new(temp_dir.path()).await.unwrap();
+        assert!(manager.get(&dev_eui).is_some());
+
+        assert!(manager.remove(&dev_eui).await.unwrap());
+        assert!(!manager.remove(&dev_eui).await.unwrap());
+        assert!(manager.list().is_empty());
+    }
+}
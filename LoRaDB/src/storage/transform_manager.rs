@@ -0,0 +1,370 @@
+use crate::model::decoded::DecodedPayload;
+use crate::model::frames::Frame;
+use crate::model::lorawan::ApplicationId;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Per-application frame enrichment pipelines with JSON persistence
+///
+/// A pipeline is an ordered list of built-in transforms that add fields to an
+/// uplink's decoded payload before it is written, so later steps can use the
+/// fields earlier ones derived. A step whose inputs are missing is skipped.
+pub struct TransformManager {
+    pipelines: RwLock<HashMap<String, TransformPipeline>>, // Key: normalized application ID
+    /// None for an in-memory engine, changes are never saved
+    file_path: Option<PathBuf>,
+}
+
+/// Ordered transforms for one application
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransformPipeline {
+    pub transforms: Vec<Transform>,
+    /// When this pipeline was created
+    pub created_at: DateTime<Utc>,
+    /// When this pipeline was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One built-in enrichment step
+///
+/// Fields are read by dot-separated path (e.g. "sensor.temp") and written as
+/// top-level fields of the decoded object, replacing any existing value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transform {
+    /// `field = left <op> right`
+    Arithmetic {
+        field: String,
+        left: Operand,
+        op: ArithmeticOp,
+        right: Operand,
+    },
+    /// Dew point in °C from a temperature in °C and a relative humidity in %
+    DewPoint {
+        field: String,
+        temperature: String,
+        humidity: String,
+    },
+    /// Tag every frame with a constant value
+    Constant { field: String, value: Value },
+    /// Site of the first receiving gateway listed in `sites` (gateway EUI -> site)
+    GatewaySite {
+        field: String,
+        sites: BTreeMap<String, String>,
+    },
+}
+
+/// Arithmetic input: a number, or the path of a numeric decoded field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Operand {
+    Number(f64),
+    Field(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArithmeticOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Operand {
+    fn resolve(&self, payload: &DecodedPayload) -> Option<f64> {
+        match self {
+            Operand::Number(n) => Some(*n),
+            Operand::Field(path) => payload.get_field(path)?.as_f64(),
+        }
+    }
+}
+
+impl Transform {
+    /// Field the transform writes
+    pub fn field(&self) -> &str {
+        match self {
+            Transform::Arithmetic { field, .. }
+            | Transform::DewPoint { field, .. }
+            | Transform::Constant { field, .. }
+            | Transform::GatewaySite { field, .. } => field,
+        }
+    }
+
+    /// Value to write, None when the frame lacks the inputs
+    fn evaluate(&self, payload: &DecodedPayload, gateways: &[&str]) -> Option<Value> {
+        match self {
+            Transform::Arithmetic { left, op, right, .. } => {
+                let (left, right) = (left.resolve(payload)?, right.resolve(payload)?);
+                let result = match op {
+                    ArithmeticOp::Add => left + right,
+                    ArithmeticOp::Sub => left - right,
+                    ArithmeticOp::Mul => left * right,
+                    ArithmeticOp::Div => left / right,
+                };
+                // Division by zero and overflow produce no field
+                serde_json::Number::from_f64(result).map(Value::Number)
+            }
+            Transform::DewPoint { temperature, humidity, .. } => {
+                let temperature = payload.get_field(temperature)?.as_f64()?;
+                let humidity = payload.get_field(humidity)?.as_f64()?;
+                if humidity <= 0.0 {
+                    return None;
+                }
+                // Magnus formula
+                const B: f64 = 17.62;
+                const C: f64 = 243.12;
+                let gamma = (humidity / 100.0).ln() + B * temperature / (C + temperature);
+                serde_json::Number::from_f64(C * gamma / (B - gamma)).map(Value::Number)
+            }
+            Transform::Constant { value, .. } => Some(value.clone()),
+            Transform::GatewaySite { sites, .. } => gateways.iter().find_map(|gateway| {
+                sites
+                    .iter()
+                    .find(|(eui, _)| eui.eq_ignore_ascii_case(gateway))
+                    .map(|(_, site)| Value::String(site.clone()))
+            }),
+        }
+    }
+}
+
+impl TransformPipeline {
+    /// Run every transform in order against an uplink's decoded payload
+    /// An uplink without a decoded payload gets an empty object first
+    pub fn apply(&self, frame: &mut Frame) {
+        let uplink = match frame {
+            Frame::Uplink(f) => f,
+            _ => return,
+        };
+
+        let gateways: Vec<&str> = uplink.rx_info.iter().map(|rx| rx.gateway_id.as_str()).collect();
+        let payload = uplink
+            .decoded_payload
+            .get_or_insert_with(|| DecodedPayload::from_json(Value::Object(Map::new())));
+
+        for transform in &self.transforms {
+            let value = match transform.evaluate(payload, &gateways) {
+                Some(value) => value,
+                None => continue,
+            };
+            match payload.object.as_object_mut() {
+                Some(object) => {
+                    object.insert(transform.field().to_string(), value);
+                }
+                // Decoders that return a scalar leave nothing to add fields to
+                None => return,
+            }
+        }
+    }
+}
+
+impl TransformManager {
+    /// Create a new transform manager, loading `transforms.json` if present
+    pub async fn new(data_dir: &Path) -> Result<Self> {
+        let file_path = data_dir.join("transforms.json");
+
+        let pipelines = if file_path.exists() {
+            match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => {
+                    match serde_json::from_str::<HashMap<String, TransformPipeline>>(&content) {
+                        Ok(pipelines) => {
+                            info!("Loaded {} transform pipeline(s)", pipelines.len());
+                            pipelines
+                        }
+                        Err(e) => {
+                            warn!("Failed to parse transform pipelines, frames stored as received: {}", e);
+                            HashMap::new()
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to read transform pipelines file, frames stored as received: {}", e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            pipelines: RwLock::new(pipelines),
+            file_path: Some(file_path),
+        })
+    }
+
+    /// Create an empty TransformManager that keeps its state in memory only
+    pub fn in_memory() -> Self {
+        Self {
+            pipelines: RwLock::new(HashMap::new()),
+            file_path: None,
+        }
+    }
+
+    /// Pipeline for an application
+    pub fn get(&self, app_id: &str) -> Option<TransformPipeline> {
+        self.pipelines.read().get(&ApplicationId::normalize(app_id)).cloned()
+    }
+
+    /// All pipelines, sorted by application ID
+    pub fn list(&self) -> Vec<(String, TransformPipeline)> {
+        let mut pipelines: Vec<_> = self
+            .pipelines
+            .read()
+            .iter()
+            .map(|(app_id, pipeline)| (app_id.clone(), pipeline.clone()))
+            .collect();
+        pipelines.sort_by(|a, b| a.0.cmp(&b.0));
+        pipelines
+    }
+
+    /// Set the transforms for an application, replacing any previous pipeline
+    pub async fn set(&self, app_id: &str, transforms: Vec<Transform>) -> Result<()> {
+        let now = Utc::now();
+        let app_id = ApplicationId::normalize(app_id);
+        let count = transforms.len();
+
+        {
+            let mut pipelines = self.pipelines.write();
+            let entry = pipelines.entry(app_id.clone()).or_insert_with(|| TransformPipeline {
+                transforms: Vec::new(),
+                created_at: now,
+                updated_at: now,
+            });
+            entry.transforms = transforms;
+            entry.updated_at = now;
+        }
+        self.save().await?;
+
+        info!("Updated transform pipeline for '{}' with {} transform(s)", app_id, count);
+
+        Ok(())
+    }
+
+    /// Remove the pipeline for an application
+    /// Returns whether a pipeline existed
+    pub async fn remove(&self, app_id: &str) -> Result<bool> {
+        let app_id = ApplicationId::normalize(app_id);
+        let removed = self.pipelines.write().remove(&app_id).is_some();
+
+        if removed {
+            self.save().await?;
+            info!("Removed transform pipeline for '{}'", app_id);
+        }
+
+        Ok(removed)
+    }
+
+    /// Enrich a frame with its application's pipeline, if it has one
+    pub fn apply(&self, frame: &mut Frame) {
+        let pipelines = self.pipelines.read();
+        if pipelines.is_empty() {
+            return;
+        }
+
+        let pipeline = frame
+            .application_id()
+            .and_then(|app_id| pipelines.get(&app_id.normalized()));
+        if let Some(pipeline) = pipeline {
+            pipeline.apply(frame);
+        }
+    }
+
+    /// Save transform pipelines to disk
+    async fn save(&self) -> Result<()> {
+        // The read guard is dropped before awaiting
+        let save = super::persist_json(self.file_path.as_deref(), &*self.pipelines.read());
+        save.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::frames::UplinkFrame;
+    use crate::model::gateway::GatewayRxInfo;
+    use crate::model::lorawan::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn create_test_uplink(object: Value, gateway: &str) -> Frame {
+        Frame::Uplink(UplinkFrame {
+            dev_eui: DevEui::new("0123456789ABCDEF".to_string()).unwrap(),
+            application_id: ApplicationId::new("Env-Sensors".to_string()),
+            device_name: None,
+            received_at: Utc::now(),
+            stored_at: None,
+            f_port: 1,
+            f_cnt: 1,
+            confirmed: false,
+            adr: true,
+            dr: DataRate::new_lora(125000, 7),
+            frequency: 868100000,
+            rx_info: vec![GatewayRxInfo {
+                gateway_id: GatewayEui::new(gateway.to_string()),
+                rssi: -80,
+                snr: 7.5,
+                channel: 0,
+                rf_chain: 0,
+                location: None,
+            }],
+            decoded_payload: Some(DecodedPayload::from_json(object)),
+            raw_payload: None,
+        })
+    }
+
+    fn decoded(frame: &Frame) -> &Value {
+        match frame {
+            Frame::Uplink(f) => &f.decoded_payload.as_ref().unwrap().object,
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_enriches_uplinks_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let transforms: Vec<Transform> = serde_json::from_value(json!([
+            {"type": "dew_point", "field": "dew_point", "temperature": "sensor.temp", "humidity": "sensor.rh"},
+            {"type": "arithmetic", "field": "spread", "left": "sensor.temp", "op": "sub", "right": "dew_point"},
+            {"type": "arithmetic", "field": "temp_f", "left": "sensor.temp", "op": "mul", "right": 1.8},
+            {"type": "arithmetic", "field": "ratio", "left": "missing", "op": "div", "right": 2},
+            {"type": "constant", "field": "building", "value": "north"},
+            {"type": "gateway_site", "field": "site", "sites": {"AA555A0000000001": "roof"}}
+        ]))
+        .unwrap();
+
+        {
+            let manager = TransformManager::new(temp_dir.path()).await.unwrap();
+            manager.set("env-sensors", transforms).await.unwrap();
+        }
+        let manager = TransformManager::new(temp_dir.path()).await.unwrap();
+
+        let mut frame = create_test_uplink(json!({"sensor": {"temp": 20.0, "rh": 50.0}}), "aa555a0000000001");
+        manager.apply(&mut frame);
+        let object = decoded(&frame);
+
+        let dew_point = object["dew_point"].as_f64().unwrap();
+        assert!((dew_point - 9.26).abs() < 0.01, "dew point {}", dew_point);
+        assert!((object["spread"].as_f64().unwrap() - (20.0 - dew_point)).abs() < 1e-9);
+        assert_eq!(object["temp_f"], json!(36.0));
+        assert!(object.get("ratio").is_none());
+        assert_eq!(object["building"], "north");
+        assert_eq!(object["site"], "roof");
+
+        // Unknown gateways get no site
+        let mut frame = create_test_uplink(json!({}), "aa555a0000000002");
+        manager.apply(&mut frame);
+        assert!(decoded(&frame).get("site").is_none());
+        assert_eq!(decoded(&frame)["building"], "north");
+
+        assert!(manager.remove("ENV-SENSORS").await.unwrap());
+        let mut frame = create_test_uplink(json!({}), "aa555a0000000001");
+        manager.apply(&mut frame);
+        assert_eq!(decoded(&frame), &json!({}));
+    }
+}
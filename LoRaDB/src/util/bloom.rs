@@ -88,6 +88,21 @@ impl BloomFilter {
     pub fn num_hash_functions(&self) -> usize {
         self.num_hash_functions
     }
+
+    /// Fraction of bits that are set (0.0 = empty, 1.0 = every lookup matches)
+    pub fn fill_ratio(&self) -> f64 {
+        if self.num_bits == 0 {
+            return 0.0;
+        }
+        let set_bits = self.bits.iter().filter(|&&bit| bit).count();
+        set_bits as f64 / self.num_bits as f64
+    }
+
+    /// False positive rate at the current load: the chance that all
+    /// `num_hash_functions` bits checked for an absent element are set
+    pub fn estimated_fp_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.num_hash_functions as i32)
+    }
 }
 
 #[cfg(test)]
@@ -113,6 +128,27 @@ mod tests {
         assert!(!bloom.contains(&"device-999"));
     }
 
+    #[test]
+    fn test_bloom_filter_fill_ratio() {
+        let mut bloom = BloomFilter::new(1000, 0.01);
+        assert_eq!(bloom.fill_ratio(), 0.0);
+        assert_eq!(bloom.estimated_fp_rate(), 0.0);
+
+        // At the expected load, roughly half the bits are set and the rate is near target
+        for i in 0..1000 {
+            bloom.insert(&format!("device-{}", i));
+        }
+        assert!((0.4..0.6).contains(&bloom.fill_ratio()));
+        assert!(bloom.estimated_fp_rate() < 0.02);
+
+        // Overloading the filter saturates it
+        for i in 1000..20_000 {
+            bloom.insert(&format!("device-{}", i));
+        }
+        assert!(bloom.fill_ratio() > 0.99);
+        assert!(bloom.estimated_fp_rate() > 0.9);
+    }
+
     #[test]
     fn test_bloom_filter_false_positive_rate() {
         let mut bloom = BloomFilter::new(1000, 0.01);
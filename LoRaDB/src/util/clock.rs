@@ -1 +1,141 @@
-// TODO: Implement clock
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+
+/// Earliest plausible frame timestamp, 2015-01-01T00:00:00Z (LoRaWAN 1.0 was published in 2015)
+/// Anything older comes from a gateway with an unset or badly broken clock
+pub const MIN_PLAUSIBLE_TIMESTAMP_SECS: i64 = 1_420_070_400;
+
+/// Result of checking a frame timestamp against the local clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampSkew {
+    /// Within tolerance
+    InRange,
+    /// Further ahead of the local clock than the tolerance, by this much
+    Future(Duration),
+    /// Before `MIN_PLAUSIBLE_TIMESTAMP_SECS`
+    TooOld,
+}
+
+/// Check `timestamp` against `now`, allowing it to be up to `max_future` ahead
+pub fn check_skew(timestamp: DateTime<Utc>, now: DateTime<Utc>, max_future: Duration) -> TimestampSkew {
+    if timestamp.timestamp() < MIN_PLAUSIBLE_TIMESTAMP_SECS {
+        return TimestampSkew::TooOld;
+    }
+
+    let ahead = timestamp - now;
+    if ahead > max_future {
+        TimestampSkew::Future(ahead)
+    } else {
+        TimestampSkew::InRange
+    }
+}
+
+/// Daily UTC time range during which heavy background work (compaction, retention) may run
+/// The end is exclusive, and a window may wrap past midnight (e.g. 22:00-02:00)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl MaintenanceWindow {
+    /// Whether `now` falls inside the window
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let time = now.time();
+        if self.start < self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// How long until the window next opens (zero if it's open now)
+    pub fn until_open(&self, now: DateTime<Utc>) -> Duration {
+        if self.contains(now) {
+            return Duration::zero();
+        }
+
+        let mut opens = now.date_naive().and_time(self.start).and_utc();
+        if opens <= now {
+            opens += Duration::days(1);
+        }
+        opens - now
+    }
+}
+
+impl std::str::FromStr for MaintenanceWindow {
+    type Err = String;
+
+    /// Parse `HH:MM-HH:MM`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("invalid maintenance window '{}', expected HH:MM-HH:MM", s))?;
+        let parse = |t: &str| {
+            NaiveTime::parse_from_str(t.trim(), "%H:%M")
+                .map_err(|e| format!("invalid maintenance window time '{}': {}", t.trim(), e))
+        };
+
+        let window = MaintenanceWindow {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if window.start == window.end {
+            return Err(format!("maintenance window '{}' is empty", s));
+        }
+        Ok(window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0)
+            .unwrap()
+            .date_naive()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+            .and_utc()
+    }
+
+    #[test]
+    fn test_maintenance_window() {
+        let window: MaintenanceWindow = "02:00-05:00".parse().unwrap();
+        assert!(!window.contains(at(1, 59)));
+        assert!(window.contains(at(2, 0)));
+        assert!(window.contains(at(4, 59)));
+        assert!(!window.contains(at(5, 0)));
+        assert_eq!(window.until_open(at(3, 0)), Duration::zero());
+        assert_eq!(window.until_open(at(1, 30)), Duration::minutes(30));
+        assert_eq!(window.until_open(at(6, 0)), Duration::hours(20));
+
+        // Wrapping past midnight
+        let window: MaintenanceWindow = "22:00-02:00".parse().unwrap();
+        assert!(window.contains(at(23, 0)));
+        assert!(window.contains(at(1, 0)));
+        assert!(!window.contains(at(12, 0)));
+
+        assert!("02:00".parse::<MaintenanceWindow>().is_err());
+        assert!("02:00-02:00".parse::<MaintenanceWindow>().is_err());
+        assert!("25:00-03:00".parse::<MaintenanceWindow>().is_err());
+    }
+
+    #[test]
+    fn test_check_skew() {
+        let now = Utc::now();
+        let tolerance = Duration::minutes(5);
+
+        assert_eq!(check_skew(now, now, tolerance), TimestampSkew::InRange);
+        assert_eq!(check_skew(now + Duration::minutes(4), now, tolerance), TimestampSkew::InRange);
+        assert_eq!(check_skew(now - Duration::days(365), now, tolerance), TimestampSkew::InRange);
+        assert_eq!(
+            check_skew(now + Duration::hours(1), now, tolerance),
+            TimestampSkew::Future(Duration::hours(1))
+        );
+        assert_eq!(
+            check_skew(DateTime::from_timestamp(0, 0).unwrap(), now, tolerance),
+            TimestampSkew::TooOld
+        );
+    }
+}
@@ -2,3 +2,4 @@ pub mod compression;
 pub mod varint;
 pub mod clock;
 pub mod bloom;
+pub mod net;
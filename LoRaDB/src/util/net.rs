@@ -0,0 +1,106 @@
+use std::net::IpAddr;
+
+/// IP network in CIDR notation, e.g. `10.0.0.0/8` or `fd00::/8`
+/// A bare address is a network holding only that address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpCidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    /// Whether `ip` is inside this network
+    /// IPv4-mapped IPv6 addresses (`::ffff:10.0.0.1`) match IPv4 networks
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = prefix_mask(self.prefix_len, 32) as u32;
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = prefix_mask(self.prefix_len, 128);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Mask with the top `prefix_len` of `bits` bits set
+fn prefix_mask(prefix_len: u8, bits: u32) -> u128 {
+    if prefix_len == 0 {
+        0
+    } else {
+        (u128::MAX << (128 - prefix_len as u32)) >> (128 - bits)
+    }
+}
+
+impl std::str::FromStr for IpCidr {
+    type Err = String;
+
+    /// Parse `ADDRESS/PREFIX` or a bare address
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let network: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid address in '{}': {}", s, e))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length in '{}', expected 0-{}", s, max_len))?,
+            None => max_len,
+        };
+
+        Ok(IpCidr {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let private: IpCidr = "10.0.0.0/8".parse().unwrap();
+        assert!(private.contains(&ip("10.1.2.3")));
+        assert!(!private.contains(&ip("11.0.0.1")));
+        assert!(private.contains(&ip("::ffff:10.0.0.1")));
+
+        let single: IpCidr = "192.168.1.10".parse().unwrap();
+        assert!(single.contains(&ip("192.168.1.10")));
+        assert!(!single.contains(&ip("192.168.1.11")));
+
+        let v6: IpCidr = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(&ip("fd12::1")));
+        assert!(!v6.contains(&ip("fe80::1")));
+        assert!(!v6.contains(&ip("10.0.0.1")));
+
+        let any: IpCidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(&ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn test_cidr_parse_errors() {
+        assert!("10.0.0.0/33".parse::<IpCidr>().is_err());
+        assert!("fd00::/129".parse::<IpCidr>().is_err());
+        assert!("proxy.local".parse::<IpCidr>().is_err());
+        assert!("10.0.0.0/x".parse::<IpCidr>().is_err());
+    }
+}
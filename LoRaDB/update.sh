@@ -76,6 +76,7 @@ echo ""
 
 # Rebuild Docker image
 echo "Rebuilding Docker image..."
+export LORADB_GIT_SHA="$(git rev-parse --short=12 HEAD 2>/dev/null || echo unknown)"
 docker compose build
 
 echo -e "${GREEN}✓${NC} Build complete"